@@ -0,0 +1,182 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter, Result};
+
+/// The largest power of 10 that fits into a limb, which decimal digits are split off in groups of
+const DECIMAL_BASE: u32 = 1_000_000_000;
+
+/// The amount of decimal digits in each group split off by `DECIMAL_BASE`
+const DECIMAL_DIGITS: usize = 9;
+
+/// A signed integer of arbitrary precision, the cell of the interpreter's `--cell-size big`
+///
+/// The magnitude is stored as 32 bit limbs, least significant first and without leading zero limbs, so zero has no
+/// limbs and is never negative.
+#[derive(Clone, Default, PartialEq)]
+pub struct Big {
+    /// Whether the value is below zero
+    negative: bool,
+    /// The limbs of the magnitude, least significant first
+    limbs: Vec<u32>,
+}
+
+impl Big {
+    /// The value of an unsigned integer
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value
+    pub fn from_u32(value: u32) -> Big {
+        let limbs = if value == 0 { Vec::new() } else { vec![value] };
+        Big { negative: false, limbs }
+    }
+
+    /// Returns whether the value is zero
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    /// The lowest byte of the value in two's complement, like the lowest byte of a wrapping cell
+    pub fn low_byte(&self) -> u8 {
+        let low = self.limbs.first().copied().unwrap_or(0) as u8;
+        if self.negative { low.wrapping_neg() } else { low }
+    }
+
+    /// The amount of bytes the value takes up, including those of its limbs on the heap
+    pub fn size(&self) -> usize {
+        size_of::<Big>() + self.limbs.capacity() * size_of::<u32>()
+    }
+
+    /// Adds a signed value
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to add
+    pub fn add(&mut self, value: i64) {
+        let magnitude = value.unsigned_abs();
+        self.add_magnitude(value < 0, &[magnitude as u32, (magnitude >> 32) as u32]);
+    }
+
+    /// Adds another value multiplied by a signed factor
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The value to multiply
+    /// * `factor` - The factor to multiply it by
+    pub fn add_product(&mut self, other: &Big, factor: i32) {
+        let mut product: Vec<u32> = Vec::with_capacity(other.limbs.len() + 1);
+        let mut carry: u64 = 0;
+        for limb in &other.limbs {
+            let value = *limb as u64 * factor.unsigned_abs() as u64 + carry;
+            product.push(value as u32);
+            carry = value >> 32;
+        }
+        product.push(carry as u32);
+
+        self.add_magnitude(other.negative != (factor < 0), &product);
+    }
+
+    /// Adds a value given as its sign and the limbs of its magnitude, which may have leading zero limbs
+    ///
+    /// # Arguments
+    ///
+    /// * `negative` - Whether the value is below zero
+    /// * `magnitude` - The limbs of the magnitude, least significant first
+    fn add_magnitude(&mut self, negative: bool, magnitude: &[u32]) {
+        if negative == self.negative {
+            // Equal signs add the magnitudes and keep the sign
+            let mut carry: u64 = 0;
+            for i in 0..self.limbs.len().max(magnitude.len()) {
+                let sum = self.limbs.get(i).copied().unwrap_or(0) as u64 + magnitude.get(i).copied().unwrap_or(0) as u64 + carry;
+                match self.limbs.get_mut(i) {
+                    Some(limb) => *limb = sum as u32,
+                    None => self.limbs.push(sum as u32),
+                }
+                carry = sum >> 32;
+            }
+            if carry != 0 {
+                self.limbs.push(carry as u32);
+            }
+        } else {
+            // Different signs subtract the smaller magnitude from the larger one, whose sign the result has
+            let mut other = magnitude.to_vec();
+            while other.last() == Some(&0) {
+                other.pop();
+            }
+            let (larger, smaller) = match compare(&self.limbs, &other) {
+                Ordering::Less => {
+                    self.negative = negative;
+                    (other, std::mem::take(&mut self.limbs))
+                },
+                _ => (std::mem::take(&mut self.limbs), other),
+            };
+
+            let mut borrow: i64 = 0;
+            self.limbs = larger.iter().enumerate()
+                .map(|(i, limb)| {
+                    let difference = *limb as i64 - smaller.get(i).copied().unwrap_or(0) as i64 - borrow;
+                    borrow = (difference < 0) as i64;
+                    difference.rem_euclid(1 << 32) as u32
+                })
+                .collect();
+        }
+
+        self.normalize();
+    }
+
+    /// Removes the leading zero limbs, which leaves zero without limbs and positive
+    fn normalize(&mut self) {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        if self.limbs.is_empty() {
+            self.negative = false;
+        }
+    }
+
+    /// The value in hex, at least 8 digits like a 32 bit cell and prefixed by `-` if it is negative
+    pub fn hex(&self) -> String {
+        let mut digits = format!("{:08x}", self.limbs.last().copied().unwrap_or(0));
+        for limb in self.limbs.iter().rev().skip(1) {
+            digits.push_str(&format!("{:08x}", limb));
+        }
+        if self.negative { format!("-{}", digits) } else { digits }
+    }
+}
+
+impl Display for Big {
+    /// Formats the value in decimal
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        // Split off groups of decimal digits, least significant first, by dividing the magnitude repeatedly
+        let mut magnitude = self.limbs.clone();
+        let mut groups: Vec<u32> = Vec::new();
+        while !magnitude.is_empty() {
+            let mut remainder: u64 = 0;
+            for limb in magnitude.iter_mut().rev() {
+                let value = (remainder << 32) | *limb as u64;
+                *limb = (value / DECIMAL_BASE as u64) as u32;
+                remainder = value % DECIMAL_BASE as u64;
+            }
+            groups.push(remainder as u32);
+            while magnitude.last() == Some(&0) {
+                magnitude.pop();
+            }
+        }
+
+        let mut digits = if self.negative { "-".to_owned() } else { String::new() };
+        digits.push_str(&groups.last().copied().unwrap_or(0).to_string());
+        for group in groups.iter().rev().skip(1) {
+            digits.push_str(&format!("{:0width$}", group, width = DECIMAL_DIGITS));
+        }
+        f.pad(&digits)
+    }
+}
+
+/// Compares two magnitudes without leading zero limbs
+///
+/// # Arguments
+///
+/// * `a` - The limbs of the first magnitude, least significant first
+/// * `b` - The limbs of the second magnitude, least significant first
+fn compare(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
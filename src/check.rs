@@ -0,0 +1,212 @@
+use std::fmt::{self, Display, Formatter};
+use std::io::ErrorKind;
+
+use crate::interpreter::{self, Machine, Step};
+use crate::optimizer::{DumpSettings, PassManager, Report};
+use crate::{Instruction, Program};
+
+/// The amount of operations each version of a program is interpreted for, so programs that never end are checked too
+pub const CHECK_STEPS: u64 = 1_000_000;
+
+/// Something a program did that can be observed from outside
+#[derive(Clone, Copy, PartialEq)]
+enum Event {
+    /// `.` wrote a byte
+    Write(u8),
+    /// `,` read the next byte of the input
+    Read,
+    /// `$` showed the data pointer and the current cell
+    Dump(isize, u32),
+    /// The program reached its end or `@`
+    End,
+    /// The program failed, e.g. by moving off the tape
+    Fail,
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Event::Write(byte) => write!(f, "writing {}", byte),
+            Event::Read => write!(f, "reading"),
+            Event::Dump(dp, cell) => write!(f, "dumping cell {} holding {}", dp, cell),
+            Event::End => write!(f, "ending"),
+            Event::Fail => write!(f, "failing"),
+        }
+    }
+}
+
+/// Where two programs behave differently
+pub struct Divergence {
+    /// The (0-based) index of the input they differ on
+    pub input: usize,
+    /// The (1-based) number of the first event that differs, reads left out
+    pub event: usize,
+    /// What the first program did then
+    pub first: String,
+    /// What the second program did then
+    pub second: String,
+}
+
+/// Runs two programs on each input and compares what they write, dump and whether they end or fail, returning whether
+/// any run reached the step limit, or the first difference
+///
+/// Reading an exhausted input leaves the cell unchanged, as the interpreter always does, so the end of the input is
+/// handled the same for both programs. A program that reached the limit only has to agree with the other one until
+/// then.
+///
+/// # Arguments
+///
+/// * `a` - The first program
+/// * `b` - The second program
+/// * `inputs` - The inputs both programs are run on, one run each
+/// * `max_steps` - The amount of operations each run is interpreted for
+pub fn programs_equivalent(a: &Program, b: &Program, inputs: &[Vec<u8>], max_steps: u64) -> Result<bool, Divergence> {
+    let options = interpreter::Options { max_steps: Some(max_steps), ..interpreter::Options::default() };
+    let mut limited = false;
+
+    for (index, input) in inputs.iter().enumerate() {
+        let traces = [a, b].map(|program| trace(program, &[], &options, input));
+        limited |= traces.iter().any(|events| !matches!(events.last(), Some(Event::End | Event::Fail)));
+
+        let [first, second] = traces.map(|events| events.into_iter().filter(|event| *event != Event::Read).collect::<Vec<Event>>());
+        let common = first.iter().zip(&second).take_while(|(a, b)| a == b).count();
+        if let (Some(a), Some(b)) = (first.get(common), second.get(common)) {
+            return Err(Divergence { input: index, event: common + 1, first: a.to_string(), second: b.to_string() });
+        }
+    }
+
+    Ok(limited)
+}
+
+/// Optimizes a program while checking after each pass that its loops are still paired up and that it still does what
+/// the program did as parsed, returning the amount of passes that ran
+///
+/// Each version is interpreted for up to a million operations on the same tape and input, recording what it writes,
+/// reads and dumps in order and whether it ended or failed. A version that reached the limit only has to agree with
+/// the events the other one recorded until then. The optimized program additionally must not keep any change by 0.
+///
+/// # Arguments
+///
+/// * `program` - The program as parsed
+/// * `passes` - The passes to run, which verify the tree after each of them as well
+/// * `init` - The bytes loaded into the first cells of the tape
+/// * `tape_size` - The amount of cells on the tape
+/// * `input` - The bytes `,` reads
+pub fn check_passes(program: Program, passes: &PassManager, init: &[u8], tape_size: usize, input: &[u8]) -> Result<usize, String> {
+    let options = interpreter::Options { max_steps: Some(CHECK_STEPS), tape_size, ..interpreter::Options::default() };
+    let expected = trace(&program, init, &options, input);
+
+    let mut count = 0;
+    let mut observe = |_: &str, program: &Program| {
+        count += 1;
+        check_loops(&program.instructions)?;
+        compare(&expected, &trace(program, init, &options, input))
+    };
+    let optimized = passes.run_observed(program, &mut Report::default(), &DumpSettings::default(), Some(&mut observe))
+        .map_err(|err| err.to_string())?;
+
+    match optimized.instructions.iter().position(|instr| matches!(instr, Instruction::AddN(0) | Instruction::MoveN(0))) {
+        Some(pc) => Err(format!("the optimized program changes by 0 at instruction {}", pc)),
+        None => Ok(count),
+    }
+}
+
+/// Checks that a version of a program pairs up its loops and does what the program did as parsed, like `check_passes`
+/// does after each pass
+///
+/// # Arguments
+///
+/// * `parsed` - The program as parsed
+/// * `version` - The version of the program to check
+/// * `input` - The bytes `,` reads
+pub fn check_version(parsed: &Program, version: &Program, input: &[u8]) -> Result<(), String> {
+    let options = interpreter::Options { max_steps: Some(CHECK_STEPS), ..interpreter::Options::default() };
+    check_loops(&version.instructions)?;
+    compare(&trace(parsed, &[], &options, input), &trace(version, &[], &options, input))
+}
+
+/// Checks that every `Jump` and `Return` names its partner and that each `PinBase` precedes a loop
+///
+/// # Arguments
+///
+/// * `instructions` - A slice of instructions that contains the program
+fn check_loops(instructions: &[Instruction]) -> Result<(), String> {
+    let mut open: Vec<usize> = Vec::new();
+
+    for (pc, instr) in instructions.iter().enumerate() {
+        match instr {
+            Instruction::Jump(end) if matches!(instructions.get(*end as usize), Some(Instruction::Return(start)) if *start as usize == pc) => open.push(pc),
+            Instruction::Jump(end) => return Err(format!("left the loop at instruction {} jumping to {}, which does not jump back", pc, end)),
+            Instruction::Return(start) if open.pop() == Some(*start as usize) => {},
+            Instruction::Return(start) => return Err(format!("left the loop end at instruction {} jumping back to {}, which does not open it", pc, start)),
+            Instruction::PinBase if !matches!(instructions.get(pc + 1), Some(Instruction::Jump(_))) => {
+                return Err(format!("left the PinBase at instruction {} in front of no loop", pc));
+            },
+            _ => {},
+        }
+    }
+
+    match open.last() {
+        Some(pc) => Err(format!("left the loop at instruction {} without an end", pc)),
+        None => Ok(()),
+    }
+}
+
+/// Interprets a program, returning what it did in order, ending in `End` or `Fail` unless it reached the step limit
+///
+/// # Arguments
+///
+/// * `program` - The program
+/// * `init` - The bytes loaded into the first cells of the tape
+/// * `options` - The settings of the run
+/// * `input` - The bytes `,` reads, after which the cell stays unchanged
+fn trace(program: &Program, init: &[u8], options: &interpreter::Options, input: &[u8]) -> Vec<Event> {
+    let mut events: Vec<Event> = Vec::new();
+    let Ok(mut machine) = Machine::<u32>::new(program, init, options, None) else { return vec![Event::Fail] };
+    let mut input = input.iter();
+
+    loop {
+        match machine.step() {
+            Ok(Step::Continued) => {},
+            Ok(Step::Output(byte)) => events.push(Event::Write(byte)),
+            Ok(Step::NeedsInput) => {
+                events.push(Event::Read);
+                let read = match input.next() {
+                    Some(byte) => machine.provide_input(*byte),
+                    None => machine.end_input(),
+                };
+                if read.is_err() {
+                    events.push(Event::Fail);
+                    return events;
+                }
+            },
+            Ok(Step::Dump(_)) => events.push(machine.cell().map_or(Event::Fail, |cell| Event::Dump(machine.dp(), cell))),
+            Ok(Step::Halted) => {
+                events.push(Event::End);
+                return events;
+            },
+            Err(err) if err.kind() == ErrorKind::QuotaExceeded => return events,
+            Err(_) => {
+                events.push(Event::Fail);
+                return events;
+            },
+        }
+    }
+}
+
+/// Compares what an optimized version of a program did with what the program did as parsed
+///
+/// Both end in the same event if neither reached the step limit, so only the events both recorded are compared.
+///
+/// # Arguments
+///
+/// * `expected` - The events of the program as parsed
+/// * `actual` - The events of the optimized version
+fn compare(expected: &[Event], actual: &[Event]) -> Result<(), String> {
+    let common = expected.iter().zip(actual).take_while(|(a, b)| a == b).count();
+
+    match (expected.get(common), actual.get(common)) {
+        (Some(old), Some(new)) => Err(format!("changed event {} of the program from {} to {}", common + 1, old, new)),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,806 @@
+use std::env::args;
+use std::fs::{read, read_to_string};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use crate::{compare_programs, compile_files, cov_report, explain_target, harness, html_report, is_symbol, lsp, optimizer, script, selftest, template, terminal, unescape, Cpu, Crt, Diagnostics, ErrorFormat, Extensions, Syntax, TapeGuard, TapeSection, LIBRARY_FUNCTION, STDIN_SOURCE};
+
+/// The entry point of the `bfasm` executable, which compiles, runs or checks programs as the arguments ask
+pub fn main() {
+    // The executable's arguments
+    let args: Vec<_> = args().collect();
+
+    // Run a subcommand instead of processing a file and fail if it failed
+    if let Some(succeeded) = args.get(1).and_then(|name| run_subcommand(name, &args[2..])) {
+        if !succeeded {
+            exit(1);
+        }
+        return;
+    }
+
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            // The format of the errors applies to those about the arguments before it as well
+            let json_errors = args.windows(2).any(|pair| pair[0] == "--error-format" && pair[1] == "json");
+            let diagnostics = Diagnostics { format: if json_errors { ErrorFormat::Json } else { ErrorFormat::Human }, ..Diagnostics::default() };
+            diagnostics.error(None, &message);
+            exit(1);
+        },
+    };
+
+    if options.list_passes {
+        let width = optimizer::PASSES.iter().map(|pass| pass.name().len()).max().unwrap_or(0);
+        for pass in optimizer::PASSES {
+            println!("{:<width$} {}", pass.name(), pass.description(), width = width);
+        }
+        return;
+    }
+
+    if options.inputs.is_empty() {
+        print_usage();
+        return;
+    }
+
+    if !compile_files(options) {
+        exit(1);
+    }
+}
+
+/// Runs a subcommand and returns whether it succeeded, or `None` if there is no subcommand of that name
+///
+/// # Arguments
+///
+/// * `name` - The first argument, which names the subcommand
+/// * `args` - The arguments after it
+fn run_subcommand(name: &str, args: &[String]) -> Option<bool> {
+    match name {
+        // Check the compiler itself
+        "selftest" => Some(selftest::selftest(args)),
+        // Run the tests of a brainfuck project
+        "test" => Some(harness::test(args)),
+        // Render a coverage file
+        "cov-report" => Some(cov_report(args)),
+        // Compare what two programs do
+        "compare" => Some(compare_programs(args)),
+        // Describe what a target needs
+        "explain-target" => Some(explain_target(args)),
+        // Write an HTML report of a program
+        "report" => Some(html_report(args)),
+        // Serve editors as a language server
+        "lsp" => Some(lsp::serve(args)),
+        _ => None,
+    }
+}
+
+/// Prints how the executable is used
+fn print_usage() {
+    println!("usage: bfasm selftest [--with-toolchain] [--fuzz N]");
+    println!("       bfasm test [--engine interpreter|compiled] [--max-steps N] [PATTERN]");
+    println!("       bfasm cov-report [--loops] FILE COVERAGE");
+    println!("       bfasm explain-target nasm|gas");
+    println!("       bfasm report [--profile COVERAGE] [-o FILE] FILE");
+    println!("       bfasm lsp [--enable-labels] [--enable-counts] [--debug-breaks] [--extensions LIST]");
+    println!("       bfasm [--syntax nasm|gas] [-O0|-O1|-O2] [--passes LIST] [--disable-passes LIST] [--strip-unreachable] [--unroll-limit N] [--verify-ir] [--check] [--cpu baseline|sse2] [--opt-report] [--time] [--opt-report-format text|json] [--opt-report-file FILE] [--dump-ir STAGE=PASS] [--dump-ir-dir DIR] [--list-passes] [--split-functions] [--enable-labels] [--enable-counts] [--enable-includes] [--debug-breaks] [--extensions LIST] [--tape-guard[=aggressive]] [--unbuffered-output] [--binary-stdio] [--fuel N] [--auto-tape] [--max-errors N] [--max-depth N] [--max-src-bytes N] [--warnings-as-errors] [--error-format human|json] [--instrument coverage] [--coverage-output FILE] [--argv-tape] [--argv-tape-at CELL] [--arg TEXT] [--const-input TEXT] [--const-input-file FILE] [--const-input-then-stdin] [--run] [--stdin-mode auto|terminal|pipe] [--raw-input] [--echo] [--no-echo] [--dump-tape-on-interrupt] [--dump-memory] [--dump-memory-file FILE] [--dump-tape-json FILE] [--count-loops-executed] [--warn-uninit] [--max-steps N] [--max-output BYTES] [--max-input BYTES] [--max-memory BYTES] [--cell-size 32|big] [--numeric-io] [--pause-on-exit] [--tape-init FILE] [--tape-section bss|data] [--label-prefix PREFIX] [--label-all] [--annotate offsets] [--emit asm|lst|fragment|tokens|ir] [--library[=NAME]] [--from-ir] [--verify ASM] [--with-build[=bat|sh|make]] [--runtime-file FILE] [--crt static|dynamic|mingw] [--template FILE] [--stream] [-o FILE] [--keep-asm] [--out-dir DIR] [--recursive] FILE|DIR...");
+}
+
+/// The options of compiling or running programs, as given by the arguments
+pub struct Options<'a> {
+    /// Whether to lift top-level loops into their own functions
+    pub split_functions: bool,
+    /// The syntax of the generated assembly
+    pub syntax: Syntax,
+    /// Whether to interpret the program instead of writing assembly
+    pub run: bool,
+    /// The enabled language extensions
+    pub extensions: Extensions,
+    /// Whether `;;include FILE` lines are replaced by the contents of `FILE` before parsing
+    pub enable_includes: bool,
+    /// Whether to write a listing file alongside the assembly
+    pub emit_listing: bool,
+    /// Whether to write the command characters recognized by the parser alongside the assembly
+    pub emit_tokens: bool,
+    /// Whether to write a fragment for NASM's `%include` instead of a complete program
+    pub emit_fragment: bool,
+    /// The name of the function the program is generated as with `--library`, which transforms a buffer into another
+    pub library: Option<&'a str>,
+    /// Whether to write the optimized instructions as IR alongside the assembly
+    pub emit_ir: bool,
+    /// Whether the input files are IR written by `--emit ir` instead of brainfuck
+    pub from_ir: bool,
+    /// The optimization level
+    pub opt_level: u8,
+    /// Whether to print how long each stage of compiling a file took
+    pub time_stages: bool,
+    /// Whether to print what the optimizer did, and where and how
+    pub opt_report: bool,
+    /// Whether the optimization report is JSON instead of text
+    pub opt_report_json: bool,
+    /// The file the optimization report is written to instead of stderr
+    pub opt_report_file: Option<&'a String>,
+    /// The comma separated passes to run instead of those of the optimization level
+    pub pass_names: Option<&'a String>,
+    /// The comma separated passes removed from those that run
+    pub disabled_passes: Option<&'a String>,
+    /// The instruction set the generated assembly may use
+    pub cpu: Cpu,
+    /// Whether to check the instructions' invariants after each pass
+    pub verify_ir: bool,
+    /// Whether to only check that each pass keeps the loops and the I/O of the programs instead of compiling them
+    pub check_passes: bool,
+    /// Whether to remove the code after `@` at any optimization level
+    pub strip_unreachable: bool,
+    /// The most instructions `known-values` may unroll a loop into
+    pub unroll_limit: usize,
+    /// The passes that run, those of the optimization level unless they are named
+    pub passes: optimizer::PassManager,
+    /// Which passes to dump the instructions before and after
+    pub dumps: optimizer::DumpSettings,
+    /// Whether to only list the passes instead of processing a file
+    pub list_passes: bool,
+    /// The prefix of every generated label
+    pub label_prefix: String,
+    /// Whether every instruction gets a label
+    pub label_all: bool,
+    /// Whether each block of the assembly is annotated with the offset of the data pointer
+    pub annotate_offsets: bool,
+    /// Whether the interpreter waits for a key press before exiting
+    pub pause_on_exit: bool,
+    /// Whether `.` bypasses the C runtime's output buffer
+    pub unbuffered_output: bool,
+    /// Whether the generated program switches stdin and stdout to binary mode
+    pub binary_stdio: bool,
+    /// The amount of loop iterations after which the generated program stops
+    pub fuel: Option<u64>,
+    /// The amount of parse errors reported before the rest is left out
+    pub max_errors: usize,
+    /// The amount of loops that may be nested in each other
+    pub max_depth: Option<usize>,
+    /// The most bytes a source file may have
+    pub max_src_bytes: Option<u64>,
+    /// Whether the tape is sized by the cells the program can reach instead of having the default size
+    pub auto_tape: bool,
+    /// The format of the errors and warnings
+    pub error_format: ErrorFormat,
+    /// Whether any warning fails the build
+    pub warnings_as_errors: bool,
+    /// Whether the entries into each block are counted
+    pub instrument_coverage: bool,
+    /// Whether the interpreter prints how often each loop ran
+    pub count_loops: bool,
+    /// Whether the interpreter warns about reads of cells that were never written
+    pub warn_uninit: bool,
+    /// The most steps the interpreter takes, if they are limited
+    pub max_steps: Option<u64>,
+    /// The most bytes the interpreted program writes, if they are limited
+    pub max_output: Option<u64>,
+    /// The most bytes the interpreted program reads, if they are limited
+    pub max_input: Option<u64>,
+    /// The most bytes the interpreter's tape takes, if they are limited
+    pub max_memory: Option<u64>,
+    /// Whether the interpreter's cells are of arbitrary precision
+    pub big_cells: bool,
+    /// Whether `.` writes the decimal values of the interpreter's cells
+    pub numeric_io: bool,
+    /// The file the counted entries are written to at exit
+    pub coverage_output: String,
+    /// Whether the generated program copies its argument to the tape
+    pub argv_tape: bool,
+    /// The argument simulated by the interpreter
+    pub argument: Option<&'a String>,
+    /// The cell the argument starts at
+    pub argv_tape_at: i32,
+    /// The input embedded into the program
+    pub const_input: Option<Vec<u8>>,
+    /// Whether stdin is read once the embedded input is exhausted
+    pub const_input_then_stdin: bool,
+    /// Whether and when to check the cells around the tape for overruns
+    pub tape_guard: TapeGuard,
+    /// Whether the interpreter dumps the whole tape when it is stopped with Ctrl-C
+    pub dump_tape_on_interrupt: bool,
+    /// Whether the interpreter dumps the tape once the program ended
+    pub dump_memory: bool,
+    /// The file the tape is dumped to instead of stderr
+    pub dump_memory_file: Option<&'a str>,
+    /// The file the tape and the state of the interpreter are written to as JSON
+    pub dump_state_file: Option<&'a str>,
+    /// Whether the interpreter reads single key presses
+    pub raw_input: bool,
+    /// Whether the single key presses are shown
+    pub echo: bool,
+    /// Whether `,` treats stdin as a terminal or a pipe
+    pub stdin_mode: terminal::StdinMode,
+    /// The file whose bytes are loaded into the start of the interpreter's tape
+    pub tape_init: Option<&'a String>,
+    /// Whether the assembly is written while the source is read instead of from the parsed program
+    pub stream: bool,
+    /// The segment the generated program's tape is placed in
+    pub tape_section: TapeSection,
+    /// The existing assembly to compare against instead of writing it
+    pub verify_file: Option<&'a String>,
+    /// The input files and directories, which are the arguments that are not flags
+    pub inputs: Vec<&'a String>,
+    /// Whether the subdirectories of input directories are searched as well
+    pub recursive: bool,
+    /// The directory the output files are written to instead of next to their input files
+    pub out_dir: Option<&'a str>,
+    /// The kind of script written alongside the assembly that builds the executable, if one is written
+    pub build_script: Option<script::Flavor>,
+    /// The C runtime the program is linked against, detected for build scripts and executables if it is not chosen
+    pub crt: Option<Crt>,
+    /// The file the output is written to instead of next to the input file, an executable unless it has the extension
+    /// of the generated file
+    pub out_file: Option<&'a str>,
+    /// Whether the assembly and object file of an executable are kept after it was built
+    pub keep_asm: bool,
+    /// The name of the file next to the assembly that holds the helpers shared by all programs, if they are shared
+    pub runtime_file: Option<&'a str>,
+    /// The contents of the file whose placeholders the generated code is filled into
+    pub template: Option<String>,
+}
+
+impl Default for Options<'_> {
+    /// The options of `bfasm` without any flags
+    fn default() -> Self {
+        Options {
+            split_functions: false,
+            syntax: Syntax::Nasm,
+            run: false,
+            extensions: Extensions::default(),
+            enable_includes: false,
+            emit_listing: false,
+            emit_tokens: false,
+            emit_fragment: false,
+            library: None,
+            emit_ir: false,
+            from_ir: false,
+            opt_level: 0,
+            time_stages: false,
+            opt_report: false,
+            opt_report_json: false,
+            opt_report_file: None,
+            pass_names: None,
+            disabled_passes: None,
+            cpu: Cpu::Baseline,
+            verify_ir: false,
+            check_passes: false,
+            strip_unreachable: false,
+            unroll_limit: optimizer::UNROLL_SIZE,
+            passes: optimizer::PassManager::for_level(0, Cpu::Baseline),
+            dumps: optimizer::DumpSettings { dir: PathBuf::from("."), ..Default::default() },
+            list_passes: false,
+            label_prefix: String::new(),
+            label_all: false,
+            annotate_offsets: false,
+            pause_on_exit: false,
+            unbuffered_output: false,
+            binary_stdio: false,
+            fuel: None,
+            max_errors: 20,
+            max_depth: None,
+            max_src_bytes: None,
+            auto_tape: false,
+            error_format: ErrorFormat::Human,
+            warnings_as_errors: false,
+            instrument_coverage: false,
+            count_loops: false,
+            warn_uninit: false,
+            max_steps: None,
+            max_output: None,
+            max_input: None,
+            max_memory: None,
+            big_cells: false,
+            numeric_io: false,
+            coverage_output: String::from("coverage.json"),
+            argv_tape: false,
+            argument: None,
+            argv_tape_at: 0,
+            const_input: None,
+            const_input_then_stdin: false,
+            tape_guard: TapeGuard::Off,
+            dump_tape_on_interrupt: false,
+            dump_memory: false,
+            dump_memory_file: None,
+            dump_state_file: None,
+            raw_input: false,
+            echo: true,
+            stdin_mode: terminal::StdinMode::Auto,
+            tape_init: None,
+            stream: false,
+            tape_section: TapeSection::Bss,
+            verify_file: None,
+            inputs: Vec::new(),
+            recursive: false,
+            out_dir: None,
+            build_script: None,
+            crt: None,
+            out_file: None,
+            keep_asm: false,
+            runtime_file: None,
+            template: None,
+        }
+    }
+}
+
+impl Options<'_> {
+    /// The extension of the generated file, `inc` for fragments
+    pub fn asm_extension(&self) -> &'static str {
+        if self.emit_fragment { "inc" } else { self.syntax.extension() }
+    }
+
+    /// Returns whether `-o` names an executable, which it does unless it has the extension of the generated file
+    pub fn builds_executable(&self) -> bool {
+        self.out_file.is_some_and(|file| Path::new(file).extension() != Some(self.asm_extension().as_ref()))
+    }
+}
+
+/// Parses the arguments that compile or run programs into their options and checks that they fit together
+///
+/// Checking the files themselves, like whether `-o` is given a single one, is left to the caller.
+///
+/// # Arguments
+///
+/// * `args` - The arguments without the executable's name
+pub fn parse_args(args: &[String]) -> Result<Options<'_>, String> {
+    let mut options = Options::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--split-functions" => options.split_functions = true,
+            "--run" => options.run = true,
+            "--pause-on-exit" => options.pause_on_exit = true,
+            "--enable-labels" => options.extensions.labels = true,
+            "--enable-counts" => options.extensions.counts = true,
+            "--debug-breaks" => options.extensions.debug_breaks = true,
+            "--enable-includes" => options.enable_includes = true,
+            "--from-ir" => options.from_ir = true,
+            "--unbuffered-output" => options.unbuffered_output = true,
+            "--binary-stdio" => options.binary_stdio = true,
+            "--const-input-then-stdin" => options.const_input_then_stdin = true,
+            "--argv-tape" => options.argv_tape = true,
+            "--auto-tape" => options.auto_tape = true,
+            "--recursive" => options.recursive = true,
+            "--stream" => options.stream = true,
+            "--keep-asm" => options.keep_asm = true,
+            "-o" => match iter.next() {
+                Some(file) => options.out_file = Some(file),
+                None => return Err("missing value for '-o'!".to_owned()),
+            },
+            "--out-dir" => match iter.next() {
+                Some(dir) => options.out_dir = Some(dir),
+                None => return Err("missing value for '--out-dir'!".to_owned()),
+            },
+            "--runtime-file" => match iter.next() {
+                Some(file) => options.runtime_file = Some(file),
+                None => return Err("missing value for '--runtime-file'!".to_owned()),
+            },
+            "--template" => match iter.next() {
+                Some(file) => match read_to_string(file) {
+                    Ok(text) => options.template = Some(text),
+                    Err(_) => return Err(format!("could not find or open '{}'!", file)),
+                },
+                None => return Err("missing value for '--template'!".to_owned()),
+            },
+            "--warnings-as-errors" => options.warnings_as_errors = true,
+            "--error-format" => match iter.next().map(|name| name.as_str()) {
+                Some("human") => options.error_format = ErrorFormat::Human,
+                Some("json") => options.error_format = ErrorFormat::Json,
+                Some(name) => return Err(format!("unknown error format '{}', expected 'human' or 'json'!", name)),
+                None => return Err("missing value for '--error-format'!".to_owned()),
+            },
+            "--label-all" => options.label_all = true,
+            "--fuel" => match iter.next().map(|amount| amount.parse::<u64>()) {
+                Some(Ok(amount)) if amount > 0 => options.fuel = Some(amount),
+                Some(_) => return Err("'--fuel' has to be a positive amount of loop iterations!".to_owned()),
+                None => return Err("missing value for '--fuel'!".to_owned()),
+            },
+            "--max-errors" => match iter.next().map(|amount| amount.parse::<usize>()) {
+                Some(Ok(amount)) if amount > 0 => options.max_errors = amount,
+                Some(_) => return Err("'--max-errors' has to be a positive amount of errors!".to_owned()),
+                None => return Err("missing value for '--max-errors'!".to_owned()),
+            },
+            "--max-steps" => match iter.next().map(|amount| amount.parse::<u64>()) {
+                Some(Ok(amount)) if amount > 0 => options.max_steps = Some(amount),
+                Some(_) => return Err("'--max-steps' has to be a positive amount of steps!".to_owned()),
+                None => return Err("missing value for '--max-steps'!".to_owned()),
+            },
+            "--max-output" => match iter.next().map(|amount| amount.parse::<u64>()) {
+                Some(Ok(amount)) => options.max_output = Some(amount),
+                Some(Err(_)) => return Err("'--max-output' has to be an amount of bytes!".to_owned()),
+                None => return Err("missing value for '--max-output'!".to_owned()),
+            },
+            "--max-input" => match iter.next().map(|amount| amount.parse::<u64>()) {
+                Some(Ok(amount)) => options.max_input = Some(amount),
+                Some(Err(_)) => return Err("'--max-input' has to be an amount of bytes!".to_owned()),
+                None => return Err("missing value for '--max-input'!".to_owned()),
+            },
+            "--max-memory" => match iter.next().map(|amount| amount.parse::<u64>()) {
+                Some(Ok(amount)) => options.max_memory = Some(amount),
+                Some(Err(_)) => return Err("'--max-memory' has to be an amount of bytes!".to_owned()),
+                None => return Err("missing value for '--max-memory'!".to_owned()),
+            },
+            "--max-depth" => match iter.next().map(|amount| amount.parse::<usize>()) {
+                Some(Ok(amount)) if amount > 0 => options.max_depth = Some(amount),
+                Some(_) => return Err("'--max-depth' has to be a positive amount of loops!".to_owned()),
+                None => return Err("missing value for '--max-depth'!".to_owned()),
+            },
+            "--max-src-bytes" => match iter.next().map(|amount| amount.parse::<u64>()) {
+                Some(Ok(amount)) => options.max_src_bytes = Some(amount),
+                Some(Err(_)) => return Err("'--max-src-bytes' has to be an amount of bytes!".to_owned()),
+                None => return Err("missing value for '--max-src-bytes'!".to_owned()),
+            },
+            "--unroll-limit" => match iter.next().map(|amount| amount.parse::<usize>()) {
+                Some(Ok(amount)) => options.unroll_limit = amount,
+                Some(_) => return Err("'--unroll-limit' has to be an amount of instructions!".to_owned()),
+                None => return Err("missing value for '--unroll-limit'!".to_owned()),
+            },
+            "--arg" => match iter.next() {
+                Some(value) => options.argument = Some(value),
+                None => return Err("missing value for '--arg'!".to_owned()),
+            },
+            "--argv-tape-at" => match iter.next().map(|cell| cell.parse::<i32>()) {
+                // Whether the cell lies on the tape is checked once its size is known
+                Some(Ok(cell)) if cell >= 0 => options.argv_tape_at = cell,
+                Some(_) => return Err("'--argv-tape-at' has to be a cell, which are counted from 0!".to_owned()),
+                None => return Err("missing value for '--argv-tape-at'!".to_owned()),
+            },
+            "--const-input" => match iter.next().map(|text| unescape(text)) {
+                Some(Ok(bytes)) => options.const_input = Some(bytes),
+                Some(Err(err)) => return Err(err.to_string()),
+                None => return Err("missing value for '--const-input'!".to_owned()),
+            },
+            "--const-input-file" => match iter.next() {
+                Some(file) => match read(file) {
+                    Ok(bytes) => options.const_input = Some(bytes),
+                    Err(_) => return Err(format!("could not find or open '{}'!", file)),
+                },
+                None => return Err("missing value for '--const-input-file'!".to_owned()),
+            },
+            "--raw-input" => options.raw_input = true,
+            "--dump-tape-on-interrupt" => options.dump_tape_on_interrupt = true,
+            "--dump-memory" => options.dump_memory = true,
+            "--dump-memory-file" => match iter.next() {
+                Some(file) => options.dump_memory_file = Some(file),
+                None => return Err("missing value for '--dump-memory-file'!".to_owned()),
+            },
+            "--dump-tape-json" => match iter.next() {
+                Some(file) => options.dump_state_file = Some(file),
+                None => return Err("missing value for '--dump-tape-json'!".to_owned()),
+            },
+            "--echo" => options.echo = true,
+            "--no-echo" => options.echo = false,
+            "--stdin-mode" => match iter.next().map(|mode| mode.as_str()) {
+                Some(name) => match terminal::StdinMode::from_name(name) {
+                    Some(mode) => options.stdin_mode = mode,
+                    None => return Err(format!("unknown stdin mode '{}', expected one of {}!", name, terminal::StdinMode::NAMES.join(", "))),
+                },
+                None => return Err("missing value for '--stdin-mode'!".to_owned()),
+            },
+            "--count-loops-executed" => options.count_loops = true,
+            "--warn-uninit" => options.warn_uninit = true,
+            "--cell-size" => match iter.next().map(|size| size.as_str()) {
+                Some("32") => options.big_cells = false,
+                Some("big") => options.big_cells = true,
+                Some(size) => return Err(format!("unknown cell size '{}', expected '32' or 'big'!", size)),
+                None => return Err("missing value for '--cell-size'!".to_owned()),
+            },
+            "--numeric-io" => options.numeric_io = true,
+            "--tape-guard" => options.tape_guard = TapeGuard::AtExit,
+            "--tape-guard=aggressive" => options.tape_guard = TapeGuard::Aggressive,
+            "--library" => options.library = Some(LIBRARY_FUNCTION),
+            flag if flag.starts_with("--library=") => {
+                let name = &flag["--library=".len()..];
+                if !is_symbol(name) {
+                    return Err(format!("invalid function name '{}', expected letters, digits and underscores not starting with a digit!", name));
+                }
+                options.library = Some(name);
+            },
+            "--with-build" | "--with-build=bat" => options.build_script = Some(script::Flavor::Batch),
+            "--with-build=sh" => options.build_script = Some(script::Flavor::Shell),
+            "--with-build=make" => options.build_script = Some(script::Flavor::Make),
+            flag if flag.starts_with("--with-build=") => return Err(format!("unknown build script '{}', expected 'bat', 'sh' or 'make'!", &flag["--with-build=".len()..])),
+            "--crt" => match iter.next().map(|name| name.as_str()) {
+                Some(name) => match Crt::ALL.into_iter().find(|candidate| candidate.name() == name) {
+                    Some(found) => options.crt = Some(found),
+                    None => return Err(format!("unknown C runtime '{}', expected 'static', 'dynamic' or 'mingw'!", name)),
+                },
+                None => return Err("missing value for '--crt'!".to_owned()),
+            },
+            "--syntax" => match iter.next().map(|name| name.as_str()) {
+                Some("nasm") => options.syntax = Syntax::Nasm,
+                Some("gas") => options.syntax = Syntax::Gas,
+                Some(name) => return Err(format!("unknown syntax '{}'!", name)),
+                None => return Err("missing value for '--syntax'!".to_owned()),
+            },
+            "--emit" => match iter.next().map(|kind| kind.as_str()) {
+                Some("asm") => {},
+                Some("lst") => options.emit_listing = true,
+                Some("tokens") => options.emit_tokens = true,
+                Some("fragment") => options.emit_fragment = true,
+                Some("ir") => options.emit_ir = true,
+                Some(kind) => return Err(format!("unknown emit kind '{}'!", kind)),
+                None => return Err("missing value for '--emit'!".to_owned()),
+            },
+            "-O0" => options.opt_level = 0,
+            "-O1" => options.opt_level = 1,
+            "-O2" => options.opt_level = 2,
+            "--passes" => match iter.next() {
+                Some(names) => options.pass_names = Some(names),
+                None => return Err("missing value for '--passes'!".to_owned()),
+            },
+            "--disable-passes" => match iter.next() {
+                Some(names) => options.disabled_passes = Some(names),
+                None => return Err("missing value for '--disable-passes'!".to_owned()),
+            },
+            "--verify-ir" => options.verify_ir = true,
+            "--check" => options.check_passes = true,
+            "--strip-unreachable" => options.strip_unreachable = true,
+            "--cpu" => match iter.next().map(|name| name.as_str()) {
+                Some("baseline") => options.cpu = Cpu::Baseline,
+                Some("sse2") => options.cpu = Cpu::Sse2,
+                Some(name) => return Err(format!("unknown cpu '{}'!", name)),
+                None => return Err("missing value for '--cpu'!".to_owned()),
+            },
+            "--opt-report" => options.opt_report = true,
+            "--time" => options.time_stages = true,
+            "--opt-report-format" => match iter.next().map(|format| format.as_str()) {
+                Some("text") => options.opt_report_json = false,
+                Some("json") => options.opt_report_json = true,
+                Some(format) => return Err(format!("unknown report format '{}'!", format)),
+                None => return Err("missing value for '--opt-report-format'!".to_owned()),
+            },
+            "--opt-report-file" => match iter.next() {
+                Some(file) => {
+                    options.opt_report = true;
+                    options.opt_report_file = Some(file);
+                },
+                None => return Err("missing value for '--opt-report-file'!".to_owned()),
+            },
+            "--dump-ir" => match iter.next().and_then(|value| value.split_once('=')) {
+                Some((stage, pass)) if pass == "all" || optimizer::find(pass).is_some() => match stage {
+                    "before" => options.dumps.before = Some(pass.to_owned()),
+                    "after" => options.dumps.after = Some(pass.to_owned()),
+                    "all" => {
+                        options.dumps.before = Some(pass.to_owned());
+                        options.dumps.after = Some(pass.to_owned());
+                    },
+                    _ => return Err(format!("unknown dump stage '{}', expected before, after or all!", stage)),
+                },
+                Some((_, pass)) => return Err(format!("unknown pass '{}', see --list-passes!", pass)),
+                None => return Err("expected '{before,after,all}=<pass|all>' for '--dump-ir'!".to_owned()),
+            },
+            "--dump-ir-dir" => match iter.next() {
+                Some(dir) => options.dumps.dir = PathBuf::from(dir),
+                None => return Err("missing value for '--dump-ir-dir'!".to_owned()),
+            },
+            "--list-passes" => options.list_passes = true,
+            "--instrument" => match iter.next().map(|kind| kind.as_str()) {
+                Some("coverage") => options.instrument_coverage = true,
+                Some(kind) => return Err(format!("unknown instrumentation '{}', expected 'coverage'!", kind)),
+                None => return Err("missing value for '--instrument'!".to_owned()),
+            },
+            "--coverage-output" => match iter.next() {
+                Some(file) => options.coverage_output = file.to_owned(),
+                None => return Err("missing value for '--coverage-output'!".to_owned()),
+            },
+            "--annotate" => match iter.next() {
+                Some(names) => {
+                    for name in names.split(',') {
+                        match name {
+                            "offsets" => options.annotate_offsets = true,
+                            _ => return Err(format!("unknown annotation '{}', expected 'offsets'!", name)),
+                        }
+                    }
+                },
+                None => return Err("missing value for '--annotate'!".to_owned()),
+            },
+            "--extensions" => match iter.next() {
+                Some(names) => {
+                    for name in names.split(',') {
+                        match name {
+                            "halt" => options.extensions.halt = true,
+                            "dump" => options.extensions.dump = true,
+                            _ => return Err(format!("unknown extension '{}', expected 'halt' or 'dump'!", name)),
+                        }
+                    }
+                },
+                None => return Err("missing value for '--extensions'!".to_owned()),
+            },
+            "--label-prefix" => match iter.next() {
+                Some(prefix) => options.label_prefix = prefix.to_owned(),
+                None => return Err("missing value for '--label-prefix'!".to_owned()),
+            },
+            "--tape-init" => match iter.next() {
+                Some(file) => options.tape_init = Some(file),
+                None => return Err("missing value for '--tape-init'!".to_owned()),
+            },
+            "--tape-section" => match iter.next().map(|name| name.as_str()) {
+                Some("bss") => options.tape_section = TapeSection::Bss,
+                Some("data") => options.tape_section = TapeSection::Data,
+                Some(name) => return Err(format!("unknown tape section '{}', expected 'bss' or 'data'!", name)),
+                None => return Err("missing value for '--tape-section'!".to_owned()),
+            },
+            "--verify" => match iter.next() {
+                Some(file) => options.verify_file = Some(file),
+                None => return Err("missing value for '--verify'!".to_owned()),
+            },
+            flag if flag.starts_with('-') && flag != STDIN_SOURCE => return Err(format!("unknown option '{}'!", flag)),
+            _ => options.inputs.push(arg),
+        }
+    }
+
+    if options.auto_tape && (options.argv_tape || options.argument.is_some()) {
+        return Err("'--auto-tape' cannot be combined with '--argv-tape' or '--arg' as the length of the argument is not known!".to_owned());
+    }
+
+    if options.extensions.halt && options.extensions.labels {
+        return Err("'--extensions halt' cannot be combined with '--enable-labels' as both use '@'!".to_owned());
+    }
+
+    // The loader zeroes the `.bss` segment, so the initial contents of a compiled tape are stored in the data segment
+    if options.tape_init.is_some() && !options.run && options.tape_section == TapeSection::Bss && options.tape_guard == TapeGuard::Off {
+        return Err("'--tape-init' requires '--run' or '--tape-section data' as a tape in the .bss segment starts zeroed!".to_owned());
+    }
+
+    if options.big_cells && !options.run {
+        return Err("'--cell-size big' requires '--run' as the generated assembly only has 32 bit cells!".to_owned());
+    }
+
+    // The optimizer computes the values it folds into the program with 32 bit cells
+    let big_conflicts = [(options.opt_level >= 2, "-O2"), (options.pass_names.is_some(), "--passes"), (options.from_ir, "--from-ir")];
+    if let Some((_, conflict)) = big_conflicts.iter().find(|(conflicts, _)| options.big_cells && *conflicts) {
+        return Err(format!("'--cell-size big' cannot be combined with '{}' as the optimizer folds values into 32 bit cells!", conflict));
+    }
+
+    // Explicitly named passes override the optimization level
+    options.passes = match options.pass_names.map(|names| optimizer::PassManager::from_names(names, options.cpu)) {
+        Some(Ok(passes)) => passes,
+        Some(Err(err)) => return Err(err.to_string()),
+        None => optimizer::PassManager::for_level(options.opt_level, options.cpu),
+    };
+    if options.strip_unreachable {
+        options.passes.ensure("strip-unreachable");
+    }
+    if let Some(Err(err)) = options.disabled_passes.map(|names| options.passes.disable(names)) {
+        return Err(err.to_string());
+    }
+    options.passes.verify = options.verify_ir || options.check_passes;
+    options.passes.limits.unroll = options.unroll_limit;
+    options.passes.zeroed_tape = !(options.argv_tape || options.argument.is_some() || options.tape_init.is_some() || options.emit_fragment);
+
+    // Streaming lowers each instruction as it is read, so nothing may need the whole program
+    let stream_conflicts = [
+        (options.run, "--run"),
+        (options.verify_file.is_some(), "--verify"),
+        (options.from_ir, "--from-ir"),
+        (options.enable_includes, "--enable-includes"),
+        (options.extensions.labels, "--enable-labels"),
+        (options.extensions.counts, "--enable-counts"),
+        (options.emit_listing, "--emit lst"),
+        (options.emit_tokens, "--emit tokens"),
+        (options.emit_ir, "--emit ir"),
+        (options.emit_fragment, "--emit fragment"),
+        (options.split_functions, "--split-functions"),
+        (options.template.is_some(), "--template"),
+        (options.instrument_coverage, "--instrument coverage"),
+        (options.annotate_offsets, "--annotate offsets"),
+        (options.auto_tape, "--auto-tape"),
+        (options.opt_report, "--opt-report"),
+    ];
+    if let Some((_, conflict)) = stream_conflicts.iter().find(|(conflicts, _)| options.stream && *conflicts) {
+        return Err(format!("'--stream' cannot be combined with '{}' as it needs the whole program!", conflict));
+    }
+    if options.stream && !options.passes.is_empty() {
+        return Err("'--stream' requires -O0 as the optimizer needs the whole program!".to_owned());
+    }
+    // Checking the passes writes nothing and only interprets the program to compare its versions
+    let check_conflicts = [
+        (options.run, "--run"),
+        (options.verify_file.is_some(), "--verify"),
+        (options.stream, "--stream"),
+        (options.out_file.is_some(), "-o"),
+        (options.emit_listing, "--emit lst"),
+        (options.emit_tokens, "--emit tokens"),
+        (options.emit_ir, "--emit ir"),
+    ];
+    if let Some((_, conflict)) = check_conflicts.iter().find(|(conflicts, _)| options.check_passes && *conflicts) {
+        return Err(format!("'--check' cannot be combined with '{}' as it writes nothing!", conflict));
+    }
+
+    if options.stream && options.inputs.iter().any(|input| *input == STDIN_SOURCE) {
+        return Err("'--stream' cannot compile a program from stdin as it reads the source twice!".to_owned());
+    }
+
+    if options.build_script.is_some() && options.emit_fragment {
+        return Err("'--with-build' cannot be combined with '--emit fragment' as fragments are not complete programs!".to_owned());
+    }
+
+    if options.crt.is_some() && options.emit_fragment {
+        return Err("'--crt' cannot be combined with '--emit fragment' as fragments call the functions the including file defines!".to_owned());
+    }
+
+    if options.build_script.is_some() && options.library.is_some() {
+        return Err("'--with-build' cannot be combined with '--library' as libraries are linked into other programs!".to_owned());
+    }
+
+    if options.crt.is_some() && options.library.is_some() {
+        return Err("'--crt' cannot be combined with '--library' as libraries call no functions of the C runtime!".to_owned());
+    }
+
+    let out_conflicts = [(options.out_dir.is_some(), "--out-dir"), (options.run, "--run"), (options.verify_file.is_some(), "--verify"), (options.emit_tokens, "--emit tokens"), (options.emit_ir, "--emit ir")];
+    if let Some((_, conflict)) = out_conflicts.iter().find(|(conflicts, _)| options.out_file.is_some() && *conflicts) {
+        return Err(format!("'-o' cannot be combined with '{}'!", conflict));
+    }
+
+    if let Some(exe) = options.out_file.filter(|_| options.builds_executable()) {
+        if options.emit_fragment {
+            return Err(format!("'-o {}' builds an executable, which '--emit fragment' cannot as fragments are not complete programs!", exe));
+        }
+        if options.library.is_some() {
+            return Err(format!("'-o {}' builds an executable, which '--library' cannot as libraries are linked into other programs!", exe));
+        }
+    }
+
+    if options.keep_asm && !options.builds_executable() {
+        return Err("'--keep-asm' requires '-o' with the name of an executable!".to_owned());
+    }
+
+    // The positions in IR refer to the source it was generated from, which is not at hand
+    let ir_conflicts = [(options.enable_includes, "--enable-includes"), (options.emit_tokens, "--emit tokens"), (options.emit_listing, "--emit lst"), (options.emit_ir, "--emit ir")];
+    if let Some((_, conflict)) = ir_conflicts.iter().find(|(conflicts, _)| options.from_ir && *conflicts) {
+        return Err(format!("'--from-ir' cannot be combined with '{}' as its input is no brainfuck source!", conflict));
+    }
+
+    if options.runtime_file.is_some() && options.emit_fragment {
+        return Err("'--runtime-file' cannot be combined with '--emit fragment' as fragments have their own exit paths!".to_owned());
+    }
+
+    if let Some(Err(err)) = options.template.as_deref().map(template::placeholders) {
+        return Err(err.to_string());
+    }
+
+    // The runtime is included by its name, so it has to be written next to the assembly
+    if options.runtime_file.is_some_and(|file| Path::new(file).file_name() != Some(file.as_ref())) {
+        return Err("'--runtime-file' takes a file name without directories, the runtime is written next to the assembly!".to_owned());
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses arguments given as string slices
+    fn parse(args: &[&str]) -> Result<Options<'static>, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(Vec::leak(args))
+    }
+
+    #[test]
+    fn inputs_are_the_arguments_that_are_no_options() {
+        let options = parse(&["-O2", "a.bf", "-", "--fuel", "10", "b.bf"]).unwrap();
+        assert_eq!(options.inputs, ["a.bf", "-", "b.bf"]);
+        assert_eq!(options.opt_level, 2);
+        assert_eq!(options.fuel, Some(10));
+        assert!(!options.passes.is_empty());
+    }
+
+    #[test]
+    fn invalid_arguments_are_errors() {
+        assert_eq!(parse(&["--bogus", "a.bf"]).err().unwrap(), "unknown option '--bogus'!");
+        assert_eq!(parse(&["--fuel", "0", "a.bf"]).err().unwrap(), "'--fuel' has to be a positive amount of loop iterations!");
+        assert_eq!(parse(&["a.bf", "--syntax"]).err().unwrap(), "missing value for '--syntax'!");
+        assert!(parse(&["--stream", "-O1", "a.bf"]).is_err());
+        assert!(parse(&["-o", "a.exe", "--library", "a.bf"]).is_err());
+    }
+
+    #[test]
+    fn out_file_names_an_executable_unless_it_has_the_extension_of_the_assembly() {
+        assert!(parse(&["-o", "a.exe", "a.bf"]).unwrap().builds_executable());
+        assert!(!parse(&["-o", "b.asm", "a.bf"]).unwrap().builds_executable());
+        assert!(!parse(&["--syntax", "gas", "-o", "b.s", "a.bf"]).unwrap().builds_executable());
+        assert!(!parse(&["--emit", "fragment", "-o", "b.inc", "a.bf"]).unwrap().builds_executable());
+    }
+}
@@ -0,0 +1,246 @@
+use std::cmp::Reverse;
+use std::io::{Result, Write};
+use std::ops::Range;
+
+use crate::{Instruction, Position, Program};
+
+/// A straight-line run of instructions whose executions are counted as a whole
+///
+/// Blocks start at the beginning of the program, at the first instruction of each loop body and after each loop, so
+/// every instruction of a block runs as often as its first one.
+pub struct Block {
+    /// The pcs of the block's instructions
+    pub pcs: Range<usize>,
+    /// The source position of the block's first instruction
+    pub start: Position,
+    /// The source position of the block's last instruction
+    pub end: Position,
+    /// How often the block was entered
+    pub count: u64,
+}
+
+/// Splits a program into its blocks, none of which has been entered yet
+///
+/// # Arguments
+///
+/// * `program` - The program to split, after optimization
+pub fn blocks(program: &Program) -> Vec<Block> {
+    let instructions = &program.instructions;
+    let mut blocks: Vec<Block> = Vec::new();
+
+    let mut start = 0;
+    for (pc, instr) in instructions.iter().enumerate() {
+        let last = pc + 1 == instructions.len();
+        if matches!(instr, Instruction::Jump(_) | Instruction::Return(_)) || last {
+            blocks.push(Block {
+                pcs: start..pc + 1,
+                start: program.positions[start],
+                end: program.positions[pc],
+                count: 0,
+            });
+            start = pc + 1;
+        }
+    }
+
+    blocks
+}
+
+/// Returns the index of the block starting at an instruction, if one does
+///
+/// # Arguments
+///
+/// * `blocks` - The blocks of the program
+/// * `pc` - The program counter of the instruction
+pub fn starting_at(blocks: &[Block], pc: usize) -> Option<usize> {
+    blocks.binary_search_by_key(&pc, |block| block.pcs.start).ok()
+}
+
+/// Formats a block as a line of the coverage file
+///
+/// # Arguments
+///
+/// * `block` - The block
+/// * `count` - How often it was entered, or a `printf` conversion in generated programs
+pub fn entry(block: &Block, count: &str) -> String {
+    format!("{{\"start\": \"{}\", \"end\": \"{}\", \"count\": {}}}", block.start, block.end, count)
+}
+
+/// The text in front of the blocks of a coverage file
+pub const HEAD: &str = "{\n  \"blocks\": [\n";
+
+/// The text after the blocks of a coverage file
+pub const TAIL: &str = "  ]\n}\n";
+
+/// Writes the coverage file of an interpreted program, in the format the generated programs write it in
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `blocks` - The blocks of the program with their counts
+pub fn write_json<W: Write>(f: &mut W, blocks: &[Block]) -> Result<()> {
+    write!(f, "{}", HEAD)?;
+    for (i, block) in blocks.iter().enumerate() {
+        let separator = if i + 1 < blocks.len() { "," } else { "" };
+        writeln!(f, "    {}{}", entry(block, &block.count.to_string()), separator)?;
+    }
+    write!(f, "{}", TAIL)
+}
+
+/// Writes how often each loop of an interpreted program was entered and how many iterations it ran, the loops with
+/// the most iterations first
+///
+/// A loop is entered each time its `[` is reached, even if its body is skipped. The counts are derived from those of
+/// the blocks, as the block ending at a `[` runs whenever the loop is entered and its body's first block on each
+/// iteration.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `program` - The interpreted program
+/// * `blocks` - The blocks of the program with their counts
+pub fn write_loop_profile<W: Write>(f: &mut W, program: &Program, blocks: &[Block]) -> Result<()> {
+    // The position of each loop with how often it was entered and iterated, in the order of the program
+    let mut loops: Vec<(Position, u64, u64)> = Vec::new();
+    for (pc, instr) in program.instructions.iter().enumerate() {
+        if let Instruction::Jump(_) = instr {
+            let entered = blocks.iter().find(|block| block.pcs.end == pc + 1).map_or(0, |block| block.count);
+            let iterations = starting_at(blocks, pc + 1).map_or(0, |index| blocks[index].count);
+            loops.push((program.positions[pc], entered, iterations));
+        }
+    }
+
+    write_loops(f, loops)
+}
+
+/// Writes the loop profile of a program from the blocks of its coverage file, in the format of the interpreter's
+///
+/// The blocks of a coverage file are in the order of the program, and only the blocks ending at a `[` end at the
+/// source position of one, as the others end at a `]` or at the program's last instruction. The block after each of
+/// them is the loop body's first, so a generated program instrumented with `--instrument coverage` yields the same
+/// profile as the interpreter at the same optimization level.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `source` - The program's source code
+/// * `blocks` - The first and last source positions and the count of each block
+pub fn write_coverage_loops<W: Write>(f: &mut W, source: &str, blocks: &[(Position, Position, u64)]) -> Result<()> {
+    let lines: Vec<&str> = source.lines().collect();
+    let char_at = |position: Position| lines.get(position.line.wrapping_sub(1)).and_then(|line| line.chars().nth(position.column.wrapping_sub(1)));
+
+    let loops = blocks.windows(2)
+        .filter(|pair| char_at(pair[0].1) == Some('['))
+        .map(|pair| (pair[0].1, pair[0].2, pair[1].2))
+        .collect();
+    write_loops(f, loops)
+}
+
+/// Writes the loops of a profile, the loops with the most iterations first
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `loops` - The position of each loop's `[` with how often it was entered and iterated, in the order of the program
+fn write_loops<W: Write>(f: &mut W, mut loops: Vec<(Position, u64, u64)>) -> Result<()> {
+    // The sort is stable, so loops with as many iterations stay in the order of the program
+    loops.sort_by_key(|(_, _, iterations)| Reverse(*iterations));
+
+    writeln!(f, "loop profile")?;
+    writeln!(f, "  {:<8} {:>12} {:>12}", "loop", "iterations", "entered")?;
+    for (position, entered, iterations) in loops {
+        writeln!(f, "  {:<8} {:>12} {:>12}", position.to_string(), iterations, entered)?;
+    }
+
+    Ok(())
+}
+
+/// Reads the blocks of a coverage file as their first and last source positions and their counts
+///
+/// Only the format written by `write_json` and the generated programs is understood, with one block per line.
+///
+/// # Arguments
+///
+/// * `text` - The contents of the coverage file
+pub fn read_json(text: &str) -> std::result::Result<Vec<(Position, Position, u64)>, String> {
+    if !text.trim_start().starts_with(HEAD.trim_end()) {
+        return Err("not a coverage file written by bfasm!".to_owned());
+    }
+
+    let mut blocks = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim().trim_end_matches(',');
+        if !line.starts_with("{\"start\"") {
+            continue;
+        }
+
+        let block = field(line, "start").and_then(position)
+            .zip(field(line, "end").and_then(position))
+            .zip(field(line, "count").and_then(|count| count.parse::<u64>().ok()))
+            .map(|((start, end), count)| (start, end, count));
+        match block {
+            Some(block) => blocks.push(block),
+            None => return Err(format!("malformed block in line {} of the coverage file!", number + 1)),
+        }
+    }
+
+    Ok(blocks)
+}
+
+/// Returns the value of a field of a block's line without its quotes
+///
+/// # Arguments
+///
+/// * `line` - The line of the block
+/// * `name` - The name of the field
+fn field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let (_, rest) = line.split_once(&format!("\"{}\": ", name))?;
+    let end = rest.find([',', '}'])?;
+    Some(rest[..end].trim_matches('"'))
+}
+
+/// Parses a source position formatted as `line:column`
+///
+/// # Arguments
+///
+/// * `text` - The formatted position
+fn position(text: &str) -> Option<Position> {
+    let (line, column) = text.split_once(':')?;
+    Some(Position { line: line.parse().ok()?, column: column.parse().ok()? })
+}
+
+/// Writes the source with the characters of blocks that were never entered marked by `^` in the line below
+///
+/// Each line is prefixed with its number, lines without unexecuted characters get no marker line. A summary of the
+/// entered blocks follows the source.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `source` - The program's source code
+/// * `blocks` - The first and last source positions and the count of each block
+pub fn write_report<W: Write>(f: &mut W, source: &str, blocks: &[(Position, Position, u64)]) -> Result<()> {
+    // Whether a (1-based) position lies within a block that was never entered
+    let unexecuted = |line: usize, column: usize| {
+        blocks.iter().any(|(start, end, count)| {
+            *count == 0
+                && (start.line, start.column) <= (line, column)
+                && (line, column) <= (end.line, end.column)
+        })
+    };
+
+    for (line, l) in source.lines().enumerate() {
+        writeln!(f, "{:>5} | {}", line + 1, l)?;
+
+        let markers: String = l.chars().enumerate()
+            .map(|(column, c)| if !c.is_whitespace() && unexecuted(line + 1, column + 1) { '^' } else { ' ' })
+            .collect();
+        if markers.contains('^') {
+            writeln!(f, "      | {}", markers.trim_end())?;
+        }
+    }
+
+    let entered = blocks.iter().filter(|(_, _, count)| *count > 0).count();
+    writeln!(f)?;
+    writeln!(f, "{} of {} blocks executed", entered, blocks.len())
+}
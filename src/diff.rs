@@ -0,0 +1,118 @@
+use std::io::{Result, Write};
+
+/// The amount of unchanged lines shown around the differing ones
+const CONTEXT: usize = 3;
+
+/// Returns the (1-based) number of the first line that differs between two texts, if any
+///
+/// # Arguments
+///
+/// * `old` - The expected text
+/// * `new` - The actual text
+pub fn first_difference(old: &str, new: &str) -> Option<usize> {
+    let (old, new) = (lines(old), lines(new));
+
+    let common = common_prefix(&old, &new);
+    if common == old.len() && common == new.len() {
+        None
+    } else {
+        Some(common + 1)
+    }
+}
+
+/// Writes the difference between two texts as a unified diff with a single hunk
+///
+/// The hunk spans from the first to the last differing line, which keeps the diff linear in the size of the texts.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `old_name` - The name of the expected text in the diff's header
+/// * `new_name` - The name of the actual text in the diff's header
+/// * `old` - The expected text
+/// * `new` - The actual text
+pub fn write_unified<W: Write>(f: &mut W, old_name: &str, new_name: &str, old: &str, new: &str) -> Result<()> {
+    let (old, new) = (lines(old), lines(new));
+
+    let prefix = common_prefix(&old, &new);
+    if prefix == old.len() && prefix == new.len() {
+        return Ok(());
+    }
+
+    // The common suffix may not overlap the common prefix
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+
+    let start = prefix.saturating_sub(CONTEXT);
+    let old_end = (old.len() - suffix + CONTEXT).min(old.len());
+    let new_end = (new.len() - suffix + CONTEXT).min(new.len());
+
+    writeln!(f, "--- {}", old_name)?;
+    writeln!(f, "+++ {}", new_name)?;
+    writeln!(f, "@@ -{} +{} @@", range(start, old_end), range(start, new_end))?;
+
+    for line in &old[start..prefix] {
+        write_line(f, ' ', line)?;
+    }
+    for line in &old[prefix..old.len() - suffix] {
+        write_line(f, '-', line)?;
+    }
+    for line in &new[prefix..new.len() - suffix] {
+        write_line(f, '+', line)?;
+    }
+    for line in &old[old.len() - suffix..old_end] {
+        write_line(f, ' ', line)?;
+    }
+
+    Ok(())
+}
+
+/// Splits a text into its lines, keeping their line endings so a missing final newline is a difference too
+///
+/// # Arguments
+///
+/// * `text` - The text to split
+fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Returns the amount of lines at the start of two texts that are equal
+///
+/// # Arguments
+///
+/// * `old` - The lines of the expected text
+/// * `new` - The lines of the actual text
+fn common_prefix(old: &[&str], new: &[&str]) -> usize {
+    old.iter().zip(new).take_while(|(o, n)| o == n).count()
+}
+
+/// Formats the lines of a hunk in the `start,count` form of unified diffs
+///
+/// # Arguments
+///
+/// * `start` - The (0-based) index of the hunk's first line
+/// * `end` - The (0-based) index after the hunk's last line
+fn range(start: usize, end: usize) -> String {
+    // Empty hunks refer to the line before them
+    let first = if end > start { start + 1 } else { start };
+    format!("{},{}", first, end - start)
+}
+
+/// Writes a single line of a hunk
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `marker` - The marker in front of the line, one of ` `, `-` or `+`
+/// * `line` - The line including its line ending
+fn write_line<W: Write>(f: &mut W, marker: char, line: &str) -> Result<()> {
+    match line.strip_suffix('\n') {
+        Some(line) => writeln!(f, "{}{}", marker, line),
+        None => {
+            writeln!(f, "{}{}", marker, line)?;
+            writeln!(f, "\\ No newline at end of file")
+        },
+    }
+}
@@ -0,0 +1,192 @@
+use std::io::{Result, Write};
+
+use crate::lowering::{Condition, Function, LowOp, Register};
+use crate::{Symbols, Syntax};
+
+/// A backend that writes lowered operations in the syntax of an assembler
+pub trait Emitter {
+    /// Writes a single operation
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The writer to write to
+    /// * `op` - The operation to write
+    fn emit(&self, f: &mut dyn Write, op: &LowOp) -> Result<()>;
+}
+
+/// Writes x86-64 assembly in Intel syntax for NASM or the GNU assembler
+///
+/// The index register is the 64 bit `rbx`, so cells are addressed as `[tape + 4 * rbx]` on tapes of any size.
+pub struct IntelEmitter<'a> {
+    /// The assembler syntax to write
+    pub syntax: Syntax,
+    /// The names the operations refer to
+    pub symbols: &'a Symbols,
+}
+
+impl IntelEmitter<'_> {
+    /// The memory operand of the cell at an offset from the current one
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset in cells
+    fn cell(&self, offset: i32) -> String {
+        match offset {
+            0 => format!("[{} + 4 * rbx]", self.symbols.tape),
+            offset if offset < 0 => format!("[{} + 4 * rbx - {}]", self.symbols.tape, -4 * offset),
+            offset => format!("[{} + 4 * rbx + {}]", self.symbols.tape, 4 * offset),
+        }
+    }
+
+    /// The operand of a call of a function, the import pointer of a C runtime function with `--crt dynamic`
+    ///
+    /// # Arguments
+    ///
+    /// * `function` - The function
+    fn function(&self, function: &Function) -> String {
+        match function {
+            Function::Putchar => self.symbols.call(self.symbols.putchar),
+            Function::Getchar => self.symbols.call(self.symbols.getchar),
+            Function::Write => self.symbols.call(self.symbols.write),
+            Function::Local(name) => name.clone(),
+        }
+    }
+}
+
+/// The name of a register
+///
+/// # Arguments
+///
+/// * `register` - The register
+fn register(register: Register) -> &'static str {
+    match register {
+        Register::Eax => "eax",
+        Register::Ecx => "ecx",
+        Register::Edx => "edx",
+        Register::R8d => "r8d",
+    }
+}
+
+/// The name of the 64 bit register that contains a 32 bit register
+///
+/// # Arguments
+///
+/// * `register` - The 32 bit register
+fn register64(register: Register) -> &'static str {
+    match register {
+        Register::Eax => "rax",
+        Register::Ecx => "rcx",
+        Register::Edx => "rdx",
+        Register::R8d => "r8",
+    }
+}
+
+/// The conditional jump instruction of a condition
+///
+/// # Arguments
+///
+/// * `condition` - The condition
+fn jump(condition: Condition) -> &'static str {
+    match condition {
+        Condition::Equal => "je",
+        Condition::NotEqual => "jne",
+    }
+}
+
+impl Emitter for IntelEmitter<'_> {
+    fn emit(&self, f: &mut dyn Write, op: &LowOp) -> Result<()> {
+        let dp = self.symbols.dp;
+        let dword = self.syntax.dword();
+        let qword = self.syntax.qword();
+
+        match op {
+            LowOp::Label(name) => writeln!(f, "{}:", name),
+            LowOp::LoadIndex => writeln!(f, "\tmov rbx, {}", dp),
+            LowOp::StoreIndex => writeln!(f, "\tmov {}, rbx", dp),
+            LowOp::AddIndex(n) => writeln!(f, "\tadd rbx, {}", n),
+            LowOp::AddDp(1) => writeln!(f, "\tinc {} {}", qword, dp),
+            LowOp::AddDp(-1) => writeln!(f, "\tdec {} {}", qword, dp),
+            LowOp::AddDp(n) => writeln!(f, "\tadd {} {}, {}", qword, dp, n),
+            LowOp::AddCell(offset, 1) => writeln!(f, "\tinc {} {}", dword, self.cell(*offset)),
+            LowOp::AddCell(offset, -1) => writeln!(f, "\tdec {} {}", dword, self.cell(*offset)),
+            LowOp::AddCell(offset, n) => writeln!(f, "\tadd {} {}, {}", dword, self.cell(*offset), n),
+            LowOp::SetCell(offset, n) => writeln!(f, "\tmov {} {}, {}", dword, self.cell(*offset), n),
+            LowOp::LoadCell(offset, r) => writeln!(f, "\tmov {}, {}", register(*r), self.cell(*offset)),
+            LowOp::StoreCell(offset, r) => writeln!(f, "\tmov {}, {}", self.cell(*offset), register(*r)),
+            LowOp::AddRegisterToCell(offset, r) => writeln!(f, "\tadd {}, {}", self.cell(*offset), register(*r)),
+            LowOp::CellAddress(offset, r) => writeln!(f, "\tlea {}, {}", register64(*r), self.cell(*offset)),
+            LowOp::SetRegister(r, n) => writeln!(f, "\tmov {}, {}", register(*r), n),
+            LowOp::Multiply(r, n) => writeln!(f, "\timul {}, {}, {}", register(*r), register(*r), n),
+            LowOp::AddVectorToCells(offset, constant) => {
+                // The tape is not necessarily aligned, but the constant is
+                let constant = match self.syntax {
+                    Syntax::Nasm => format!("[{}]", constant),
+                    Syntax::Gas => format!("[rip + {}]", constant),
+                };
+                writeln!(f, "\tmovdqu xmm0, {}", self.cell(*offset))?;
+                writeln!(f, "\tpaddd xmm0, {}", constant)?;
+                writeln!(f, "\tmovdqu {}, xmm0", self.cell(*offset))
+            },
+            LowOp::Call(function) => writeln!(f, "\tcall {}", self.function(function)),
+            LowOp::JumpIfCellZero(condition, label) => {
+                writeln!(f, "\tcmp {} {}, 0", dword, self.cell(0))?;
+                writeln!(f, "\t{} {}", jump(*condition), label)
+            },
+            LowOp::JumpIfRegister(r, 0, condition, label) => {
+                writeln!(f, "\ttest {}, {}", register(*r), register(*r))?;
+                writeln!(f, "\t{} {}", jump(*condition), label)
+            },
+            LowOp::JumpIfRegister(r, n, condition, label) => {
+                writeln!(f, "\tcmp {}, {}", register(*r), n)?;
+                writeln!(f, "\t{} {}", jump(*condition), label)
+            },
+            LowOp::Jump(label) => writeln!(f, "\tjmp {}", label),
+            LowOp::ConsumeFuel(label) => {
+                writeln!(f, "\tdec {} {}", qword, self.syntax.fuel())?;
+                writeln!(f, "\tjz {}", label)
+            },
+            LowOp::CountBlock(index) => {
+                let counter = self.syntax.rip(&format!("coverage + {}", 8 * index));
+                writeln!(f, "\tinc {} {}", qword, counter)
+            },
+            LowOp::Breakpoint => writeln!(f, "\tint3"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AsmOptions, MAX_STATIC_TAPE_CELLS};
+
+    /// The lines an emitter writes for operations on the tape of the given settings
+    fn emitted(options: &AsmOptions, ops: &[LowOp]) -> String {
+        let symbols = Symbols::new(options);
+        let emitter = IntelEmitter { syntax: options.syntax, symbols: &symbols };
+        let mut f: Vec<u8> = Vec::new();
+        for op in ops {
+            emitter.emit(&mut f, op).unwrap();
+        }
+        String::from_utf8(f).unwrap()
+    }
+
+    #[test]
+    fn the_tape_is_indexed_with_64_bit_registers() {
+        let ops = [LowOp::LoadIndex, LowOp::AddIndex(3), LowOp::AddCell(-2, 1), LowOp::StoreIndex, LowOp::AddDp(5)];
+        let nasm = AsmOptions::default();
+        assert_eq!(emitted(&nasm, &ops), "\tmov rbx, [dp]\n\tadd rbx, 3\n\tinc dword [tape + 4 * rbx - 8]\n\tmov [dp], rbx\n\tadd qword [dp], 5\n");
+
+        let gas = AsmOptions { syntax: Syntax::Gas, ..nasm };
+        assert_eq!(emitted(&gas, &ops), "\tmov rbx, [rip + dp]\n\tadd rbx, 3\n\tinc dword ptr [tape + 4 * rbx - 8]\n\tmov [rip + dp], rbx\n\tadd qword ptr [rip + dp], 5\n");
+    }
+
+    #[test]
+    fn tapes_too_large_for_the_image_are_addressed_from_r12() {
+        let ops = [LowOp::SetCell(0, 0), LowOp::LoadCell(4, Register::Eax)];
+        let allocated = AsmOptions { tape_size: MAX_STATIC_TAPE_CELLS + 1, ..AsmOptions::default() };
+        assert_eq!(emitted(&allocated, &ops), "\tmov dword [r12 + 4 * rbx], 0\n\tmov eax, [r12 + 4 * rbx + 16]\n");
+
+        let declared = AsmOptions { tape_size: MAX_STATIC_TAPE_CELLS, ..AsmOptions::default() };
+        assert_eq!(emitted(&declared, &ops), "\tmov dword [tape + 4 * rbx], 0\n\tmov eax, [tape + 4 * rbx + 16]\n");
+    }
+}
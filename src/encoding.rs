@@ -0,0 +1,137 @@
+use std::fs::{read, File};
+use std::io::{stdin, Error, ErrorKind, Read, Result};
+use std::path::Path;
+
+use crate::STDIN_SOURCE;
+
+/// The byte order mark of UTF-8, which editors on Windows like to put in front of a file
+pub const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+/// The byte order mark of UTF-16 with the least significant byte first
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+
+/// The byte order mark of UTF-16 with the most significant byte first
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// The encodings a source file is read in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// UTF-8, with or without a byte order mark
+    Utf8,
+    /// UTF-16 with the least significant byte first
+    Utf16Le,
+    /// UTF-16 with the most significant byte first
+    Utf16Be,
+    /// Anything else, whose bytes are read as the characters of the same value
+    Raw,
+}
+
+/// Reads a source file in the encoding it was saved in
+///
+/// # Arguments
+///
+/// * `path` - The path of the file
+pub fn read_source<P: AsRef<Path>>(path: P) -> Result<String> {
+    read(path).map(|bytes| decode(&bytes))
+}
+
+/// Reads a source file, or stdin if it is named `-`, in the encoding it was saved in, failing with
+/// `ErrorKind::FileTooLarge` if it has more than `max` bytes
+///
+/// At most one byte more than allowed is read, so a runaway source is rejected before it fills the memory.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file
+/// * `max` - The most bytes the file may have, unlimited if `None`
+pub fn read_source_capped(path: &str, max: Option<u64>) -> Result<String> {
+    let reader: Box<dyn Read> = match path {
+        STDIN_SOURCE => Box::new(stdin().lock()),
+        path => Box::new(File::open(path)?),
+    };
+
+    let mut bytes = Vec::new();
+    reader.take(max.map_or(u64::MAX, |max| max + 1)).read_to_end(&mut bytes)?;
+    match max {
+        Some(max) if bytes.len() as u64 > max => Err(source_too_large(max)),
+        _ => Ok(decode(&bytes)),
+    }
+}
+
+/// Returns the error of a source file that has more bytes than `--max-src-bytes` allows
+///
+/// # Arguments
+///
+/// * `max` - The most bytes the file may have
+pub fn source_too_large(max: u64) -> Error {
+    Error::new(ErrorKind::FileTooLarge, format!("the source has more than {} bytes, the limit of '--max-src-bytes'!", max))
+}
+
+/// Decodes the contents of a source file, dropping its byte order mark so it does not offset the first line's columns
+///
+/// Files with a UTF-8 or UTF-16 byte order mark are decoded accordingly. Without one, files of ascii characters that
+/// are each followed or preceded by a NUL byte are taken to be UTF-16 as well, instead of reading every other byte as
+/// a comment. Files that are no valid UTF-8 either are read byte by byte.
+///
+/// # Arguments
+///
+/// * `bytes` - The contents of the file
+pub fn decode(bytes: &[u8]) -> String {
+    let (encoding, text) = detect(bytes);
+    match encoding {
+        Encoding::Utf8 => String::from_utf8_lossy(text).into_owned(),
+        Encoding::Utf16Le => utf16(text, u16::from_le_bytes),
+        Encoding::Utf16Be => utf16(text, u16::from_be_bytes),
+        Encoding::Raw => text.iter().map(|byte| *byte as char).collect(),
+    }
+}
+
+/// Returns the encoding of the contents of a source file and the contents without their byte order mark
+///
+/// # Arguments
+///
+/// * `bytes` - The contents of the file
+pub fn detect(bytes: &[u8]) -> (Encoding, &[u8]) {
+    if let Some(text) = bytes.strip_prefix(UTF8_BOM) {
+        return (Encoding::Utf8, text);
+    }
+    if let Some(text) = bytes.strip_prefix(UTF16LE_BOM) {
+        return (Encoding::Utf16Le, text);
+    }
+    if let Some(text) = bytes.strip_prefix(UTF16BE_BOM) {
+        return (Encoding::Utf16Be, text);
+    }
+
+    // Every character of ascii text saved as UTF-16 has a NUL byte as its high byte
+    let nul_interleaved = |high: usize| {
+        bytes.len() >= 2
+            && bytes.len().is_multiple_of(2)
+            && bytes.chunks_exact(2).all(|unit| unit[high] == 0 && unit[1 - high] != 0 && unit[1 - high].is_ascii())
+    };
+    if nul_interleaved(1) {
+        return (Encoding::Utf16Le, bytes);
+    }
+    if nul_interleaved(0) {
+        return (Encoding::Utf16Be, bytes);
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(_) => (Encoding::Utf8, bytes),
+        Err(_) => (Encoding::Raw, bytes),
+    }
+}
+
+/// Decodes UTF-16, replacing unpaired surrogates and a trailing odd byte by the replacement character
+///
+/// # Arguments
+///
+/// * `bytes` - The encoded text without its byte order mark
+/// * `unit` - The function combining two bytes into a code unit in the text's byte order
+fn utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    let mut text: String = char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect();
+    if !bytes.len().is_multiple_of(2) {
+        text.push(char::REPLACEMENT_CHARACTER);
+    }
+    text
+}
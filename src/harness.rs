@@ -0,0 +1,226 @@
+use std::env::temp_dir;
+use std::fs::{read, read_dir};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::{diff, encoding, interpreter, parse, selftest, AsmOptions, Extensions, Syntax, FUEL_EXHAUSTED_EXIT_CODE};
+
+/// How the programs under test are executed
+#[derive(Clone, Copy, PartialEq)]
+enum Engine {
+    /// Run by the interpreter
+    Interpreter,
+    /// Assembled, linked and run as an executable
+    Compiled,
+}
+
+/// A program found next to the output it is expected to write
+struct Fixture {
+    /// The path of the program relative to the searched directory without `.bf`, which names the test
+    name: String,
+    /// The path of the program
+    path: PathBuf,
+}
+
+/// Runs every brainfuck program below the current directory that has a sibling `.expected` file and compares its
+/// output with that file, reading the sibling `.in` file as input if there is one
+///
+/// Prints a line per test, the differences of failed tests and a summary. Returns whether every test passed.
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `test`
+pub fn test(args: &[String]) -> bool {
+    let mut engine = Engine::Interpreter;
+    let mut max_steps: Option<u64> = None;
+    let mut pattern: Option<&str> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--engine" => match iter.next().map(|name| name.as_str()) {
+                Some("interpreter") => engine = Engine::Interpreter,
+                Some("compiled") => engine = Engine::Compiled,
+                Some(name) => {
+                    eprintln!("error: unknown engine '{}', expected 'interpreter' or 'compiled'!", name);
+                    return false;
+                },
+                None => {
+                    eprintln!("error: missing value for '--engine'!");
+                    return false;
+                },
+            },
+            "--max-steps" => match iter.next().map(|amount| amount.parse::<u64>()) {
+                Some(Ok(amount)) if amount > 0 => max_steps = Some(amount),
+                Some(_) => {
+                    eprintln!("error: '--max-steps' has to be a positive amount of steps!");
+                    return false;
+                },
+                None => {
+                    eprintln!("error: missing value for '--max-steps'!");
+                    return false;
+                },
+            },
+            _ if pattern.is_none() => pattern = Some(arg),
+            _ => {
+                eprintln!("error: only a single pattern can be given!");
+                return false;
+            },
+        }
+    }
+
+    if engine == Engine::Compiled && !selftest::toolchain_found(None) {
+        eprintln!("error: '--engine compiled' needs nasm and link, which were not found!");
+        return false;
+    }
+
+    let mut fixtures: Vec<Fixture> = Vec::new();
+    if let Err(err) = discover(Path::new("."), &mut fixtures) {
+        eprintln!("error: could not search for tests: {}", err);
+        return false;
+    }
+    fixtures.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Like cargo, a pattern selects the tests whose names contain it
+    let total = fixtures.len();
+    fixtures.retain(|fixture| pattern.is_none_or(|pattern| fixture.name.contains(pattern)));
+    let filtered = total - fixtures.len();
+
+    println!();
+    println!("running {} test{}", fixtures.len(), if fixtures.len() == 1 { "" } else { "s" });
+
+    // The names and the reasons of the failed tests
+    let mut failures: Vec<(&str, String)> = Vec::new();
+
+    for fixture in &fixtures {
+        match check(fixture, engine, max_steps) {
+            Ok(()) => println!("test {} ... ok", fixture.name),
+            Err(reason) => {
+                println!("test {} ... FAILED", fixture.name);
+                failures.push((&fixture.name, reason));
+            },
+        }
+    }
+
+    if !failures.is_empty() {
+        println!();
+        println!("failures:");
+        for (name, reason) in &failures {
+            println!();
+            println!("---- {} ----", name);
+            println!("{}", reason.trim_end());
+        }
+
+        println!();
+        println!("failures:");
+        for (name, _) in &failures {
+            println!("    {}", name);
+        }
+    }
+
+    let passed = fixtures.len() - failures.len();
+    println!();
+    println!(
+        "test result: {}. {} passed; {} failed; {} filtered out",
+        if failures.is_empty() { "ok" } else { "FAILED" }, passed, failures.len(), filtered,
+    );
+    println!();
+
+    failures.is_empty()
+}
+
+/// Collects the programs below a directory that have a sibling `.expected` file, skipping hidden directories
+///
+/// # Arguments
+///
+/// * `dir` - The directory to search
+/// * `fixtures` - The list to append the found programs to
+fn discover(dir: &Path, fixtures: &mut Vec<Fixture>) -> std::io::Result<()> {
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+
+        if path.is_dir() && !hidden {
+            discover(&path, fixtures)?;
+        } else if path.extension().is_some_and(|ext| ext == "bf") && path.with_extension("expected").is_file() {
+            let name = path.strip_prefix(".").unwrap_or(&path).with_extension("");
+            let name = name.to_string_lossy().replace('\\', "/");
+            fixtures.push(Fixture { name, path });
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs a single test, returning the differences or the error on failure
+///
+/// # Arguments
+///
+/// * `fixture` - The program to run
+/// * `engine` - How the program is executed
+/// * `max_steps` - The amount of steps, or loop iterations of compiled programs, after which the test fails
+fn check(fixture: &Fixture, engine: Engine, max_steps: Option<u64>) -> Result<(), String> {
+    let source = encoding::read_source(&fixture.path).map_err(|err| format!("could not read the program: {}", err))?;
+    let expected = read(fixture.path.with_extension("expected")).map_err(|err| format!("could not read the expected output: {}", err))?;
+    let input = read(fixture.path.with_extension("in")).unwrap_or_default();
+
+    let program = parse(&source, Extensions::default()).map_err(|err| err.to_string())?;
+
+    let output = match engine {
+        Engine::Interpreter => {
+            let options = interpreter::Options { max_steps, ..interpreter::Options::default() };
+            let mut output: Vec<u8> = Vec::new();
+            interpreter::run(&program, &[], &options, None, input.as_slice(), &mut output).map_err(|err| err.to_string())?;
+            output
+        },
+        Engine::Compiled => {
+            let options = AsmOptions { syntax: Syntax::Nasm, binary_stdio: true, fuel: max_steps, ..AsmOptions::default() };
+            let base = temp_dir().join(format!("bfasm_test_{}", fixture.name.replace('/', "_")));
+            let exe = selftest::build(&source, &program, &options, &base)?;
+            run_executable(&exe, &input, max_steps)?
+        },
+    };
+
+    if output == expected {
+        return Ok(());
+    }
+
+    let mut message: Vec<u8> = Vec::new();
+    diff::write_unified(
+        &mut message,
+        &fixture.path.with_extension("expected").to_string_lossy(),
+        "actual output",
+        &String::from_utf8_lossy(&expected),
+        &String::from_utf8_lossy(&output),
+    ).map_err(|err| err.to_string())?;
+    Err(String::from_utf8_lossy(&message).into_owned())
+}
+
+/// Runs an executable with the given input, returning what it wrote to stdout
+///
+/// # Arguments
+///
+/// * `exe` - The path of the executable
+/// * `input` - The bytes written to its stdin
+/// * `max_steps` - The fuel the executable was generated with, which it reports by exiting with its own code
+fn run_executable(exe: &Path, input: &[u8], max_steps: Option<u64>) -> Result<Vec<u8>, String> {
+    let mut child = Command::new(exe)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("could not run {}: {}", exe.display(), err))?;
+
+    // A program that does not read its whole input closes the pipe early, which is not an error
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(input);
+    }
+
+    let output = child.wait_with_output().map_err(|err| format!("could not run {}: {}", exe.display(), err))?;
+    match output.status.code() {
+        Some(0) => Ok(output.stdout),
+        Some(FUEL_EXHAUSTED_EXIT_CODE) if max_steps.is_some() => Err(format!("step limit of {} exceeded!", max_steps.unwrap_or_default())),
+        code => Err(format!("exited with {:?}: {}", code, String::from_utf8_lossy(&output.stderr))),
+    }
+}
@@ -0,0 +1,72 @@
+use std::fs::canonicalize;
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
+
+use crate::encoding;
+
+/// The directive that splices another file's contents in place of its line
+const DIRECTIVE: &str = ";;include ";
+
+/// The maximum nesting depth of includes, which keeps runaway expansions from exhausting the stack
+const MAX_DEPTH: usize = 16;
+
+/// Replaces every line starting with `;;include FILE` by the (expanded) contents of `FILE`
+///
+/// Included paths are relative to the directory of the including file. The same file may be included multiple times,
+/// but never while it is already being expanded.
+///
+/// # Arguments
+///
+/// * `filename` - The name of the file the source was read from
+/// * `source` - The source to expand
+pub fn expand(filename: &str, source: &str) -> Result<String> {
+    let path = canonicalize(filename).unwrap_or_else(|_| PathBuf::from(filename));
+
+    let mut expanded = String::with_capacity(source.len());
+    expand_into(&mut expanded, &path, source, &mut vec![path.clone()])?;
+
+    Ok(expanded)
+}
+
+/// Appends the expansion of a source to a string
+///
+/// # Arguments
+///
+/// * `expanded` - The string to append to
+/// * `path` - The path of the file the source was read from
+/// * `source` - The source to expand
+/// * `chain` - The files that are currently being expanded, ending with `path`
+fn expand_into(expanded: &mut String, path: &Path, source: &str, chain: &mut Vec<PathBuf>) -> Result<()> {
+    for (line, l) in source.lines().enumerate() {
+        let Some(name) = l.strip_prefix(DIRECTIVE).map(str::trim) else {
+            expanded.push_str(l);
+            expanded.push('\n');
+            continue;
+        };
+
+        // The location of the directive for error messages
+        let location = format!("{}:{}", path.display(), line + 1);
+
+        if chain.len() >= MAX_DEPTH {
+            return Err(Error::other(format!("includes are nested deeper than {} files at {}!", MAX_DEPTH, location)));
+        }
+
+        let included = path.parent().unwrap_or(Path::new("")).join(name);
+        let included = canonicalize(&included)
+            .map_err(|_| Error::other(format!("could not find or open included file '{}' at {}!", name, location)))?;
+
+        if chain.contains(&included) {
+            let cycle: Vec<String> = chain.iter().chain([&included]).map(|p| p.display().to_string()).collect();
+            return Err(Error::other(format!("include cycle {} at {}!", cycle.join(" -> "), location)));
+        }
+
+        let contents = encoding::read_source(&included)
+            .map_err(|_| Error::other(format!("could not read included file '{}' at {}!", name, location)))?;
+
+        chain.push(included.clone());
+        expand_into(expanded, &included, &contents, chain)?;
+        chain.pop();
+    }
+
+    Ok(())
+}
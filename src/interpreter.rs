@@ -0,0 +1,1274 @@
+use std::fmt::Display;
+use std::io::{BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::marker::PhantomData;
+
+use crate::bignum::Big;
+use crate::coverage::{self, Block};
+use crate::optimizer::{cell_delta, pointer_delta};
+use crate::{dump_message, guard_message, interrupt, Diagnostic, ErrorFormat, Instruction, Position, Program, Severity, TapeGuard, DATA_SIZE, GUARD_CELLS, GUARD_PATTERN};
+
+/// The amount of cells on each side of the current one shown when the program is interrupted
+const NEARBY_CELLS: usize = 4;
+
+/// The amount of cells in each row of a dumped tape
+const DUMP_ROW_CELLS: usize = 16;
+
+/// The amount of cells in each row of a memory dump, which shows every cell in hex
+const MEMORY_ROW_CELLS: usize = 8;
+
+/// The settings of an interpreted run
+pub struct Options {
+    /// Whether and when the guard cells around the tape are checked
+    pub tape_guard: TapeGuard,
+    /// Whether the description of an interrupted program includes every used cell of the tape
+    pub dump_tape: bool,
+    /// The amount of operations after which the program is stopped with an error, if it is limited, which are those of
+    /// the optimized program and so depend on the optimization level
+    pub max_steps: Option<u64>,
+    /// The amount of bytes `.` may write before the program is stopped with an error, if it is limited
+    pub max_output: Option<u64>,
+    /// The amount of bytes `,` may read before the program is stopped with an error, if it is limited
+    pub max_input: Option<u64>,
+    /// The amount of bytes the tape may take up including its guard cells, if it is limited
+    pub max_memory: Option<u64>,
+    /// The amount of cells on the tape, without guard cells
+    pub tape_size: usize,
+    /// Whether the bytes written by `.` are kept in the returned state as well
+    pub record_output: bool,
+    /// Whether reads of cells that were never written are warned about, once per cell
+    pub warn_uninit: bool,
+    /// Whether `.` writes the value of the current cell in decimal followed by a newline instead of its low byte
+    pub numeric_io: bool,
+    /// The format the warnings are printed in
+    pub error_format: ErrorFormat,
+}
+
+impl Default for Options {
+    /// The settings of a plain `--run` on the default tape, without limits
+    fn default() -> Self {
+        Options {
+            tape_guard: TapeGuard::Off,
+            dump_tape: false,
+            max_steps: None,
+            max_output: None,
+            max_input: None,
+            max_memory: None,
+            tape_size: DATA_SIZE as usize,
+            record_output: false,
+            warn_uninit: false,
+            numeric_io: false,
+            error_format: ErrorFormat::Human,
+        }
+    }
+}
+
+/// The value of a cell, either a wrapping 32 bit integer like in the generated assembly or an arbitrary precision one
+pub trait Cell: Clone + PartialEq + Display {
+    /// Whether the size of a cell changes with its value, which the memory limit has to keep track of then
+    const GROWS: bool;
+
+    /// The cell holding an unsigned value
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value
+    fn from_u32(value: u32) -> Self;
+
+    /// Adds a signed value given in two's complement, like the operand of a fused run of `+` and `-`
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to add
+    fn add(&mut self, value: u32);
+
+    /// Adds another cell multiplied by a signed factor given in two's complement
+    ///
+    /// # Arguments
+    ///
+    /// * `counter` - The cell to multiply
+    /// * `factor` - The factor to multiply it by
+    fn add_product(&mut self, counter: &Self, factor: u32);
+
+    /// Returns whether the cell is zero, which ends loops
+    fn is_zero(&self) -> bool;
+
+    /// The lowest byte of the cell, which `.` writes
+    fn low_byte(&self) -> u8;
+
+    /// The value of the cell as a signed decimal number, which `$` shows
+    fn signed(&self) -> String;
+
+    /// The value of the cell in hex with at least 8 digits, which memory dumps show
+    fn hex(&self) -> String;
+
+    /// The amount of bytes the cell takes up
+    fn size(&self) -> usize;
+}
+
+impl Cell for u32 {
+    const GROWS: bool = false;
+
+    fn from_u32(value: u32) -> Self {
+        value
+    }
+
+    fn add(&mut self, value: u32) {
+        *self = self.wrapping_add(value);
+    }
+
+    fn add_product(&mut self, counter: &Self, factor: u32) {
+        *self = self.wrapping_add(counter.wrapping_mul(factor));
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn low_byte(&self) -> u8 {
+        *self as u8
+    }
+
+    fn signed(&self) -> String {
+        (*self as i32).to_string()
+    }
+
+    fn hex(&self) -> String {
+        format!("{:08x}", self)
+    }
+
+    fn size(&self) -> usize {
+        size_of::<u32>()
+    }
+}
+
+impl Cell for Big {
+    const GROWS: bool = true;
+
+    fn from_u32(value: u32) -> Self {
+        Big::from_u32(value)
+    }
+
+    fn add(&mut self, value: u32) {
+        Big::add(self, value as i32 as i64);
+    }
+
+    fn add_product(&mut self, counter: &Self, factor: u32) {
+        Big::add_product(self, counter, factor as i32);
+    }
+
+    fn is_zero(&self) -> bool {
+        Big::is_zero(self)
+    }
+
+    fn low_byte(&self) -> u8 {
+        Big::low_byte(self)
+    }
+
+    fn signed(&self) -> String {
+        self.to_string()
+    }
+
+    fn hex(&self) -> String {
+        Big::hex(self)
+    }
+
+    fn size(&self) -> usize {
+        Big::size(self)
+    }
+}
+
+/// The tape of a program that ran to its end
+pub struct Memory<C = u32> {
+    /// The cells of the tape without guard cells
+    pub cells: Vec<C>,
+    /// The cell the data pointer ended at, which is outside of `cells` if it ended on a guard cell
+    pub dp: isize,
+    /// The amount of executed operations, where fused runs count once
+    pub steps: u64,
+    /// The bytes written by `.`, which are only kept if recording them was requested
+    pub output: Vec<u8>,
+}
+
+/// A pre-decoded operation executed by the interpreter
+///
+/// Runs of instructions are fused into a single operation with an embedded operand and loops store the absolute
+/// index of their partner, so no operation ever has to search the program.
+#[derive(Clone, Copy)]
+enum Op {
+    /// Add a (wrapping) value to the current cell
+    Add(u32),
+    /// Move the data pointer by an offset
+    Move(isize),
+    /// Set the current cell to zero, fused from `[-]` and `[+]`
+    Clear,
+    /// Add the current cell multiplied by a factor to the cell at an offset, fused from the body of copy loops like `[->+<]`
+    AddTo(isize, u32),
+    /// Move the data pointer by an offset until it points to a zero cell, fused from `[>]` and `[<]`
+    Scan(isize),
+    /// Add four (wrapping) values to the four cells starting at an offset
+    AddVector(isize, [u32; 4]),
+    /// Write the ascii value of the current cell to the output
+    Write,
+    /// Read an ascii value from the input to the current cell
+    Read,
+    /// Jump to the given op if the current cell is zero
+    JumpIfZero(usize),
+    /// Jump to the given op if the current cell is not zero
+    JumpIfNotZero(usize),
+    /// End the program like reaching its end does
+    Halt,
+    /// Print the data pointer and the current cell to stderr, with the pc of the `$` it was decoded from
+    Dump(usize),
+    /// Count an entry into the block with the given index
+    Count(usize),
+}
+
+/// Decodes the given instructions into the operations executed by the interpreter
+///
+/// Returns the operations and the pc of the (first) instruction each of them was decoded from.
+///
+/// # Arguments
+///
+/// * `instructions` - A slice of instructions that contains the program
+/// * `blocks` - The blocks whose entries are counted, whose loops are never fused so each body is counted exactly
+fn decode(instructions: &[Instruction], blocks: Option<&[Block]>) -> (Vec<Op>, Vec<usize>) {
+    let mut ops: Vec<Op> = Vec::new();
+    let mut pcs: Vec<usize> = Vec::new();
+
+    // The stack of op indices of the loops that have not been closed yet
+    let mut stack: Vec<usize> = Vec::new();
+
+    // The pc of the instruction the latest operations were decoded from
+    let mut start = 0;
+
+    let mut pc = 0;
+    while let Some(instruction) = instructions.get(pc) {
+        pcs.resize(ops.len(), start);
+        start = pc;
+
+        // Runs of instructions never cross the start of a block, which only follows loop instructions
+        if let Some(index) = blocks.and_then(|blocks| coverage::starting_at(blocks, pc)) {
+            ops.push(Op::Count(index));
+        }
+
+        match *instruction {
+            Instruction::Add | Instruction::Subtract => {
+                let mut value: u32 = 0;
+                while let Some(instr @ (Instruction::Add | Instruction::Subtract)) = instructions.get(pc) {
+                    value = match instr {
+                        Instruction::Add => value.wrapping_add(1),
+                        _ => value.wrapping_sub(1),
+                    };
+                    pc += 1;
+                }
+
+                if value != 0 {
+                    ops.push(Op::Add(value));
+                }
+                continue;
+            },
+            Instruction::Increment | Instruction::Decrement => {
+                let mut offset: isize = 0;
+                while let Some(instr @ (Instruction::Increment | Instruction::Decrement)) = instructions.get(pc) {
+                    offset += match instr {
+                        Instruction::Increment => 1,
+                        _ => -1,
+                    };
+                    pc += 1;
+                }
+
+                if offset != 0 {
+                    ops.push(Op::Move(offset));
+                }
+                continue;
+            },
+            // Instructions produced by the optimizer map directly onto fused operations
+            Instruction::MoveN(offset) => ops.push(Op::Move(offset as isize)),
+            Instruction::AddN(value) => ops.push(Op::Add(value as u32)),
+            Instruction::Clear => ops.push(Op::Clear),
+            Instruction::Scan(offset) => ops.push(Op::Scan(offset as isize)),
+            Instruction::MultiplyAdd(offset, factor) => ops.push(Op::AddTo(offset as isize, factor as u32)),
+            Instruction::AddVector(offset, values) => ops.push(Op::AddVector(offset as isize, values.map(|value| value as u32))),
+            // Labels, breakpoints, the end of infinite programs and hoisted loads only exist for the generated assembly
+            Instruction::Label(_) | Instruction::Break | Instruction::Unreachable | Instruction::PinBase => {},
+            Instruction::Write => ops.push(Op::Write),
+            Instruction::Read => ops.push(Op::Read),
+            Instruction::Halt => ops.push(Op::Halt),
+            Instruction::Dump => ops.push(Op::Dump(pc)),
+            Instruction::Jump(jmp_pc) => {
+                let end = jmp_pc as usize;
+
+                // Replace the whole loop by a superinstruction if its body matches a known pattern
+                if let Some(fused) = instructions.get(pc + 1..end).and_then(decode_loop).filter(|_| blocks.is_none()) {
+                    ops.extend(fused);
+                    pc = end + 1;
+                    continue;
+                }
+
+                // The target is not known yet and will be set by the corresponding JumpIfNotZero
+                stack.push(ops.len());
+                ops.push(Op::JumpIfZero(0));
+            },
+            // The parser matches the brackets, so a `]` without an open loop can only come from a broken IR file
+            Instruction::Return(_) => {
+                let Some(start) = stack.pop() else {
+                    pc += 1;
+                    continue;
+                };
+                ops[start] = Op::JumpIfZero(ops.len() + 1);
+                ops.push(Op::JumpIfNotZero(start + 1));
+            },
+        }
+
+        pc += 1;
+    }
+    pcs.resize(ops.len(), start);
+
+    (ops, pcs)
+}
+
+/// Returns the superinstructions replacing a loop if its body matches a known pattern
+///
+/// # Arguments
+///
+/// * `body` - The instructions between the loop's brackets
+fn decode_loop(body: &[Instruction]) -> Option<Vec<Op>> {
+    // `[>]` and `[<]` : Scan for a zero cell, also when the moves were merged like `[>>]` at `-O1`
+    let moves: Option<Vec<i32>> = body.iter().map(pointer_delta).collect();
+    if let Some(moves) = moves.filter(|moves| !moves.is_empty()) {
+        let offset: i64 = moves.iter().map(|n| i64::from(*n)).sum();
+        let forward = moves.iter().all(|n| *n > 0);
+        return (forward || moves.iter().all(|n| *n < 0)).then(|| vec![Op::Scan(offset as isize)]);
+    }
+
+    // Any other fusable loop may only modify cells and move the data pointer
+    let mut offset: isize = 0;
+
+    // The (offset, value) pairs that get added to the cells on each iteration
+    let mut deltas: Vec<(isize, u32)> = Vec::new();
+
+    for instr in body {
+        if let Some(n) = pointer_delta(instr) {
+            offset += n as isize;
+            continue;
+        }
+        let change = cell_delta(instr)? as u32;
+        if let Some(delta) = deltas.iter_mut().find(|(o, _)| *o == offset) {
+            delta.1 = delta.1.wrapping_add(change);
+        } else {
+            deltas.push((offset, change));
+        }
+    }
+
+    // The loop has to return to its starting cell
+    if offset != 0 {
+        return None;
+    }
+
+    // The counter has to change by exactly one on each iteration
+    let counter = deltas.iter().find(|(o, _)| *o == 0).map(|(_, v)| *v);
+    match counter {
+        // `[-]` and `[+]` : Clear the cell
+        Some(1) | Some(u32::MAX) if deltas.len() == 1 => Some(vec![Op::Clear]),
+        // `[->+<]` and friends : Add multiples of the counter to other cells
+        Some(u32::MAX) => {
+            let mut ops: Vec<Op> = deltas.iter()
+                .filter(|(o, _)| *o != 0)
+                .map(|(o, v)| Op::AddTo(*o, *v))
+                .collect();
+            ops.push(Op::Clear);
+            Some(ops)
+        },
+        _ => None,
+    }
+}
+
+/// Moves the data pointer by an offset, failing if it would leave the tape
+///
+/// # Arguments
+///
+/// * `dp` - The data pointer
+/// * `offset` - The offset to move by
+/// * `len` - The amount of cells on the tape, including guard cells
+fn moved(dp: usize, offset: isize, len: usize) -> Result<usize> {
+    dp.checked_add_signed(offset)
+        .filter(|dp| *dp < len)
+        .ok_or_else(out_of_bounds)
+}
+
+/// Returns the error of a program that exceeded a limit of `--max-steps`, `--max-output`, `--max-input` or, with cells
+/// that grow, `--max-memory`
+///
+/// # Arguments
+///
+/// * `limit` - The limit, like `output limit of 10 bytes`
+/// * `program` - The program, whose positions are reported
+/// * `pc` - The program counter of the instruction that exceeded the limit
+fn limit_exceeded(limit: &str, program: &Program, pc: usize) -> Error {
+    let position = program.positions.get(pc).map_or("?".to_owned(), |position| position.to_string());
+    Error::new(ErrorKind::QuotaExceeded, format!("{} exceeded at instruction {} ({})!", limit, pc, position))
+}
+
+/// Fails if any of the guard cells around the tape was clobbered
+///
+/// # Arguments
+///
+/// * `tape` - The tape including its guard cells
+/// * `pc` - The program counter of the last executed instruction, which is reported on failure
+fn check_guards<C: Cell, T: Tape<C>>(tape: &T, pc: usize) -> Result<()> {
+    let guard = GUARD_CELLS as usize;
+    let pattern = C::from_u32(GUARD_PATTERN);
+    let clobbered = |mut cells: std::ops::Range<usize>| cells.any(|i| tape.get(i).ok().as_ref() != Some(&pattern));
+
+    if clobbered(0..guard) {
+        return Err(Error::other(guard_message("low", &pc.to_string())));
+    }
+    if clobbered(tape.len() - guard..tape.len()) {
+        return Err(Error::other(guard_message("high", &pc.to_string())));
+    }
+
+    Ok(())
+}
+
+/// Marks a cell as written, if written cells are tracked
+///
+/// # Arguments
+///
+/// * `written` - Whether each cell of the tape including its guard cells was written, empty if they are not tracked
+/// * `cell` - The index of the cell on the tape including its guard cells
+fn mark_written(written: &mut [bool], cell: usize) {
+    if let Some(written) = written.get_mut(cell) {
+        *written = true;
+    }
+}
+
+/// Describes where an interrupted program stopped
+///
+/// # Arguments
+///
+/// * `program` - The interpreted program
+/// * `pc` - The program counter of the instruction that would have been executed next
+/// * `steps` - The amount of operations executed so far
+/// * `tape` - The tape including its guard cells
+/// * `dp` - The data pointer
+/// * `guard` - The amount of guard cells in front of the tape, cells are numbered relative to the first cell after them
+/// * `dump_tape` - Whether to append every cell of the tape up to the last non-zero one
+fn interrupted<C: Cell, T: Tape<C>>(program: &Program, pc: usize, steps: u64, tape: &T, dp: usize, guard: usize, dump_tape: bool) -> Error {
+    let size = tape.len() - 2 * guard;
+    let position = program.positions.get(pc).map(|position| position.to_string()).unwrap_or_else(|| "?".to_owned());
+    let cell = |i: usize| i as isize - guard as isize;
+    let value = |i: usize| tape.get(i).map_or("?".to_owned(), |cell| cell.to_string());
+
+    // Show the cells around the current one, which is bracketed
+    let first = dp.saturating_sub(NEARBY_CELLS);
+    let last = (dp + NEARBY_CELLS).min(tape.len().saturating_sub(1));
+    let cells: Vec<String> = (first..=last)
+        .map(|i| if i == dp { format!("[{}]", value(i)) } else { value(i) })
+        .collect();
+
+    let mut message = format!(
+        "interrupted at instruction {} ({}) after {} steps with the data pointer at cell {}, cells {}..={}: {}",
+        pc, position, steps, cell(dp), cell(first), cell(last), cells.join(" "),
+    );
+
+    // Dump the tape without its guard cells in rows, each starting with the number of its first cell
+    if dump_tape {
+        let tape: Vec<C> = (guard..guard + size).map_while(|i| tape.get(i).ok()).collect();
+        let used = tape.iter().rposition(|cell| !cell.is_zero()).map_or(0, |last| last + 1);
+        for (row, cells) in tape[..used].chunks(DUMP_ROW_CELLS).enumerate() {
+            let cells: Vec<String> = cells.iter().map(|cell| cell.to_string()).collect();
+            message.push_str(&format!("\n{:>5}: {}", row * DUMP_ROW_CELLS, cells.join(" ")));
+        }
+    }
+
+    Error::new(ErrorKind::Interrupted, message)
+}
+
+/// Writes the rows of the tape that contain a non-zero cell or the data pointer like `hexdump -C`
+///
+/// Each row starts with the range of its cells and shows every cell as a full 32 bit value in hex (or all digits of
+/// an arbitrary precision one), bracketing the one the data pointer is at, followed by the cells' low bytes as ascii.
+/// Each run of omitted rows is replaced by `*`.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `memory` - The tape of the finished program
+pub fn write_memory<C: Cell, W: Write>(f: &mut W, memory: &Memory<C>) -> Result<()> {
+    let mut skipped = false;
+
+    for (row, cells) in memory.cells.chunks(MEMORY_ROW_CELLS).enumerate() {
+        let first = row * MEMORY_ROW_CELLS;
+        let pointer = (memory.dp - first as isize).try_into().ok().filter(|i: &usize| *i < cells.len());
+
+        if pointer.is_none() && cells.iter().all(Cell::is_zero) {
+            if !skipped {
+                writeln!(f, "*")?;
+                skipped = true;
+            }
+            continue;
+        }
+        skipped = false;
+
+        let values: String = cells.iter().enumerate()
+            .map(|(i, cell)| if pointer == Some(i) { format!("[{}]", cell.hex()) } else { format!(" {} ", cell.hex()) })
+            .collect();
+        let text: String = cells.iter()
+            .map(|cell| if cell.low_byte().is_ascii_graphic() || cell.low_byte() == b' ' { cell.low_byte() as char } else { '.' })
+            .collect();
+        writeln!(f, "{:>6}..={:<6}{} |{}|", first, first + cells.len() - 1, values, text)?;
+    }
+
+    writeln!(f, "data pointer at cell {}", memory.dp)
+}
+
+/// The version of the JSON state, increased whenever a field changes its meaning or is removed
+pub const STATE_VERSION: u32 = 1;
+
+/// Writes the state of a finished program as JSON
+///
+/// The object has the fields `version` (`STATE_VERSION`), `data_pointer`, `steps`, `cells` with the values of the
+/// cells up to the last non-zero one or the data pointer, whichever is higher, and `output` with the written bytes.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `memory` - The state of the finished program, with its output recorded
+pub fn write_state_json<C: Cell, W: Write>(f: &mut W, memory: &Memory<C>) -> Result<()> {
+    let last = memory.cells.iter().rposition(|cell| !cell.is_zero()).map_or(0, |i| i + 1);
+    let used = last.max(memory.dp.saturating_add(1).clamp(0, memory.cells.len() as isize) as usize);
+
+    let join = |values: Vec<String>| values.join(", ");
+    writeln!(f, "{{")?;
+    writeln!(f, "  \"version\": {},", STATE_VERSION)?;
+    writeln!(f, "  \"data_pointer\": {},", memory.dp)?;
+    writeln!(f, "  \"steps\": {},", memory.steps)?;
+    writeln!(f, "  \"cells\": [{}],", join(memory.cells[..used].iter().map(C::to_string).collect()))?;
+    writeln!(f, "  \"output\": [{}]", join(memory.output.iter().map(u8::to_string).collect()))?;
+    writeln!(f, "}}")
+}
+
+/// The storage of the cells a `Machine` works on, from a plain vector to something like memory-mapped registers
+///
+/// Indices count from the first cell of the storage, which are guard cells if the machine placed them. Which cells
+/// exist is up to the implementation, which fails accesses to any other with an error that stops the program.
+pub trait Tape<C: Cell = u32> {
+    /// The amount of cells, which the data pointer cannot move past
+    fn len(&self) -> usize;
+
+    /// Whether there are no cells at all
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the value of a cell
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the cell
+    fn get(&self, index: usize) -> Result<C>;
+
+    /// Sets the value of a cell
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the cell
+    /// * `value` - The new value
+    fn set(&mut self, index: usize, value: C) -> Result<()>;
+}
+
+impl<C: Cell> Tape<C> for Vec<C> {
+    fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    fn get(&self, index: usize) -> Result<C> {
+        self.as_slice().get(index).cloned().ok_or_else(out_of_bounds)
+    }
+
+    fn set(&mut self, index: usize, value: C) -> Result<()> {
+        match self.get_mut(index) {
+            Some(cell) => {
+                *cell = value;
+                Ok(())
+            },
+            None => Err(out_of_bounds()),
+        }
+    }
+}
+
+/// Returns the error of a data pointer that left the tape
+#[cold]
+fn out_of_bounds() -> Error {
+    Error::other("data pointer out of bounds!")
+}
+
+/// What a single step of a `Machine` did
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    /// An operation was executed and the program goes on
+    Continued,
+    /// `,` waits for `provide_input` or `end_input`, further steps return this again until one of them was called
+    NeedsInput,
+    /// `.` wrote the current cell, whose low byte is given
+    Output(u8),
+    /// `$` at the given pc asks for the data pointer and the current cell to be shown
+    Dump(usize),
+    /// The program ended, further steps return this again
+    Halted,
+}
+
+/// A program being interpreted one operation at a time, for callers that drive it themselves
+///
+/// The machine never reads or writes by itself: `step` returns what `.` wrote and stops at `,` until the caller
+/// provided a byte or the end of the input, so it may be driven from anything that can hand out bytes. Operations are
+/// those the program was decoded into, so a fused run like `+++` or `[-]` takes a single step. The limits and the
+/// tape guard of the options are enforced like they are by `run`, which is built on the machine.
+///
+/// The cells are kept in a vector unless the machine is given a `Tape` of its own.
+pub struct Machine<'a, C: Cell = u32, T: Tape<C> = Vec<C>> {
+    /// The interpreted program, whose positions are reported
+    program: &'a Program,
+    /// The settings of the run
+    options: &'a Options,
+    /// The blocks of the program whose entries are counted, if they are
+    coverage: Option<&'a mut [Block]>,
+    /// The decoded operations
+    ops: Vec<Op>,
+    /// The pc of the (first) instruction each operation was decoded from
+    pcs: Vec<usize>,
+    /// The tape including its guard cells
+    tape: T,
+    /// The amount of guard cells on each side of the tape
+    guard: usize,
+    /// The data pointer as an index into `tape`
+    dp: usize,
+    /// The index of the operation executed next
+    ip: usize,
+    /// The amount of executed operations
+    steps: u64,
+    /// Whether each cell of the tape including its guard cells was written, empty if they are not tracked
+    written: Vec<bool>,
+    /// The warnings about reads of cells that were never written that were not taken yet
+    warnings: Vec<(Option<Position>, String)>,
+    /// The amount of bytes the tape takes up, only counting what the cells of a tape given by the caller grew by
+    used: u64,
+    /// The amount of bytes written so far
+    written_bytes: u64,
+    /// The amount of bytes read so far
+    read_bytes: u64,
+    /// Whether the program ended and its guard cells were checked
+    halted: bool,
+    /// The kind of the cells, which only the tape stores
+    cell: PhantomData<C>,
+}
+
+impl<'a, C: Cell> Machine<'a, C> {
+    /// Loads a program onto a fresh tape, failing if the initial contents or the tape itself do not fit the limits
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - The program to interpret
+    /// * `init` - The bytes loaded into the first cells of the tape before execution, the rest of the tape is zeroed
+    /// * `options` - The settings of the run
+    /// * `coverage` - The blocks of the program whose entries are counted, if they are
+    pub fn new(program: &'a Program, init: &[u8], options: &'a Options, coverage: Option<&'a mut [Block]>) -> Result<Machine<'a, C>> {
+        let size = options.tape_size;
+        if init.len() > size {
+            return Err(Error::other(format!("tape init of {} bytes does not fit on the tape of {} cells!", init.len(), size)));
+        }
+
+        // The tape starts after the low guard cells
+        let guard = if options.tape_guard == TapeGuard::Off { 0 } else { GUARD_CELLS as usize };
+
+        // The tape is allocated once, so its size is all the memory a program can make the interpreter allocate unless
+        // its cells grow, which are tracked as they change
+        let bytes = (size + 2 * guard) as u64 * C::from_u32(0).size() as u64;
+        if let Some(max) = options.max_memory.filter(|max| bytes > *max) {
+            return Err(Error::new(ErrorKind::QuotaExceeded, format!("the tape of {} cells takes {} bytes, exceeding the memory limit of {} bytes!", size, bytes, max)));
+        }
+
+        // A zeroed tape is allocated lazily by the system, so a large one only takes the memory its program touches
+        let mut tape = vec![C::from_u32(0); size + 2 * guard];
+        tape[..guard].fill(C::from_u32(GUARD_PATTERN));
+        tape[guard + size..].fill(C::from_u32(GUARD_PATTERN));
+        for (cell, byte) in tape[guard..].iter_mut().zip(init) {
+            *cell = C::from_u32(*byte as u32);
+        }
+        let used = if C::GROWS { tape.iter().map(|cell| cell.size() as u64).sum() } else { bytes };
+
+        // The guard cells and the initial contents count as written, as their values were put there on purpose
+        let mut written: Vec<bool> = Vec::new();
+        if options.warn_uninit {
+            written = vec![true; tape.len()];
+            written[guard + init.len()..guard + size].fill(false);
+        }
+
+        Ok(Machine::load(program, options, coverage, tape, guard, used, written))
+    }
+
+    /// The cells of the tape without the guard cells around it
+    pub fn tape(&self) -> &[C] {
+        &self.tape[self.guard..self.tape.len() - self.guard]
+    }
+
+    /// Returns the tape of the program without its guard cells
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - The output recorded by the caller, which is kept in the returned state
+    pub fn into_memory(mut self, output: Vec<u8>) -> Memory<C> {
+        let dp = self.dp();
+        self.tape.truncate(self.tape.len() - self.guard);
+        self.tape.drain(..self.guard);
+        Memory { cells: self.tape, dp, steps: self.steps, output }
+    }
+}
+
+impl<'a, C: Cell, T: Tape<C>> Machine<'a, C, T> {
+    /// Loads a program onto a tape given by the caller, starting at its first cell with the values it holds
+    ///
+    /// The tape has no guard cells, so the tape guard has to be off, and `tape_size` is ignored. Its values count as
+    /// written, as they were put there on purpose.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - The program to interpret
+    /// * `tape` - The tape
+    /// * `options` - The settings of the run
+    /// * `coverage` - The blocks of the program whose entries are counted, if they are
+    pub fn with_tape(program: &'a Program, tape: T, options: &'a Options, coverage: Option<&'a mut [Block]>) -> Result<Machine<'a, C, T>> {
+        if options.tape_guard != TapeGuard::Off {
+            return Err(Error::new(ErrorKind::InvalidInput, "a tape given to the interpreter has no guard cells!"));
+        }
+        let written = if options.warn_uninit { vec![true; tape.len()] } else { Vec::new() };
+        Ok(Machine::load(program, options, coverage, tape, 0, 0, written))
+    }
+
+    /// Decodes a program to run on a prepared tape
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - The program to interpret
+    /// * `options` - The settings of the run
+    /// * `coverage` - The blocks of the program whose entries are counted, if they are
+    /// * `tape` - The tape including its guard cells
+    /// * `guard` - The amount of guard cells on each side of the tape
+    /// * `used` - The amount of bytes the tape takes up
+    /// * `written` - Whether each cell of the tape was written, empty if they are not tracked
+    fn load(program: &'a Program, options: &'a Options, coverage: Option<&'a mut [Block]>, tape: T, guard: usize, used: u64, written: Vec<bool>) -> Machine<'a, C, T> {
+        let (ops, pcs) = decode(&program.instructions, coverage.as_deref());
+        Machine {
+            program, options, coverage, ops, pcs, tape, guard,
+            dp: guard, ip: 0, steps: 0, written, warnings: Vec::new(), used, written_bytes: 0, read_bytes: 0, halted: false,
+            cell: PhantomData,
+        }
+    }
+
+    /// The tape the machine works on, including its guard cells
+    pub fn storage(&self) -> &T {
+        &self.tape
+    }
+
+    /// The cell the data pointer is at, relative to the first cell of `tape`, so a guard cell is outside of it
+    pub fn dp(&self) -> isize {
+        self.dp as isize - self.guard as isize
+    }
+
+    /// The value of the cell the data pointer is at, which may be a guard cell
+    pub fn cell(&self) -> Result<C> {
+        self.tape.get(self.dp)
+    }
+
+    /// The program counter of the instruction executed by the next step, the amount of instructions once it ended
+    pub fn pc(&self) -> usize {
+        self.pcs.get(self.ip).copied().unwrap_or(self.program.instructions.len())
+    }
+
+    /// The amount of executed operations, where fused runs count once
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Takes the warnings about reads of cells that were never written, each of which is returned once with the
+    /// position of the reading instruction if it is known
+    pub fn take_warnings(&mut self) -> Vec<(Option<Position>, String)> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Executes the next operation
+    ///
+    /// Fails if the operation moved the data pointer off the tape, clobbered a guard cell or exceeded a limit. The
+    /// machine is left in the state it failed in. Always inlined, as `run` would pay a call for every operation otherwise.
+    #[inline(always)]
+    pub fn step(&mut self) -> Result<Step> {
+        let Some(op) = self.ops.get(self.ip).copied() else {
+            return self.halt(self.program.instructions.len().saturating_sub(1));
+        };
+        if self.options.max_steps.is_some_and(|max| self.steps >= max) {
+            return Err(limit_exceeded(&format!("step limit of {}", self.steps), self.program, self.pcs[self.ip]));
+        }
+        if let Op::Read = op {
+            return Ok(Step::NeedsInput);
+        }
+        self.steps += 1;
+
+        let (dp, len) = (self.dp, self.tape.len());
+        let mut step = Step::Continued;
+        match op {
+            Op::Add(value) => {
+                let mut cell = self.tape.get(dp)?;
+                let before = cell.size();
+                cell.add(value);
+                self.resized(&cell, before)?;
+                self.tape.set(dp, cell)?;
+                mark_written(&mut self.written, dp);
+            },
+            Op::Move(offset) => self.dp = moved(dp, offset, len)?,
+            // Clear loops like `[-]` are how programs initialize cells, so their test is no read
+            Op::Clear => {
+                let (cell, before) = (C::from_u32(0), if C::GROWS { self.tape.get(dp)?.size() } else { 0 });
+                self.resized(&cell, before)?;
+                self.tape.set(dp, cell)?;
+                mark_written(&mut self.written, dp);
+            },
+            Op::AddTo(offset, factor) => {
+                self.check_written(dp);
+                let counter = self.tape.get(dp)?;
+                if !counter.is_zero() {
+                    let target = moved(dp, offset, len)?;
+                    let mut cell = self.tape.get(target)?;
+                    let before = cell.size();
+                    cell.add_product(&counter, factor);
+                    self.resized(&cell, before)?;
+                    self.tape.set(target, cell)?;
+                    mark_written(&mut self.written, target);
+                }
+            },
+            Op::AddVector(offset, values) => {
+                // All four cells have to be on the tape
+                moved(dp, offset + 3, len)?;
+                let start = moved(dp, offset, len)?;
+                for (index, value) in (start..start + 4).zip(values) {
+                    let mut cell = self.tape.get(index)?;
+                    let before = cell.size();
+                    cell.add(value);
+                    self.resized(&cell, before)?;
+                    self.tape.set(index, cell)?;
+                }
+                for cell in start..start + 4 {
+                    mark_written(&mut self.written, cell);
+                }
+            },
+            Op::Scan(offset) => {
+                self.check_written(dp);
+                while !self.tape.get(self.dp)?.is_zero() {
+                    self.dp = moved(self.dp, offset, len)?;
+                    self.check_written(self.dp);
+                }
+            },
+            Op::Write => {
+                self.check_written(dp);
+                let cell = self.tape.get(dp)?;
+                let bytes = if self.options.numeric_io { cell.to_string().len() as u64 + 1 } else { 1 };
+                if let Some(max) = self.options.max_output.filter(|max| self.written_bytes + bytes > *max) {
+                    return Err(limit_exceeded(&format!("output limit of {} bytes", max), self.program, self.pcs[self.ip]));
+                }
+                self.written_bytes += bytes;
+                step = Step::Output(cell.low_byte());
+            },
+            Op::Read => unreachable!("reads wait for input before they are executed"),
+            Op::JumpIfZero(target) => {
+                self.check_written(dp);
+                if self.tape.get(dp)?.is_zero() {
+                    self.ip = target;
+                    return Ok(step);
+                }
+            },
+            Op::JumpIfNotZero(target) => {
+                self.check_written(dp);
+                if !self.tape.get(dp)?.is_zero() {
+                    self.ip = target;
+                    return Ok(step);
+                }
+            },
+            Op::Halt => return self.halt(self.pcs[self.ip]),
+            Op::Count(index) => {
+                if let Some(blocks) = &mut self.coverage {
+                    blocks[index].count += 1;
+                }
+            },
+            Op::Dump(pc) => step = Step::Dump(pc),
+        }
+
+        self.ip += 1;
+        Ok(step)
+    }
+
+    /// Completes the `,` the program waits for by storing a byte in the current cell
+    ///
+    /// # Arguments
+    ///
+    /// * `byte` - The byte read from the input
+    pub fn provide_input(&mut self, byte: u8) -> Result<()> {
+        let pc = self.waiting()?;
+        // Only bytes that were actually read count, so a program may still see the end of its input
+        if self.options.max_input.is_some_and(|max| self.read_bytes >= max) {
+            return Err(limit_exceeded(&format!("input limit of {} bytes", self.read_bytes), self.program, pc));
+        }
+        self.read_bytes += 1;
+
+        let dp = self.dp;
+        let (cell, before) = (C::from_u32(byte as u32), if C::GROWS { self.tape.get(dp)?.size() } else { 0 });
+        self.resized(&cell, before)?;
+        self.tape.set(dp, cell)?;
+        mark_written(&mut self.written, dp);
+        self.complete_read(pc)
+    }
+
+    /// Completes the `,` the program waits for at the end of the input, which leaves the current cell unchanged
+    pub fn end_input(&mut self) -> Result<()> {
+        let pc = self.waiting()?;
+        self.complete_read(pc)
+    }
+
+    /// Returns the pc of the `,` the program waits for, failing if it does not wait for input
+    fn waiting(&self) -> Result<usize> {
+        match self.ops.get(self.ip) {
+            Some(Op::Read) => Ok(self.pcs[self.ip]),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "the program does not wait for input!")),
+        }
+    }
+
+    /// Moves on past a `,` once its input was handled
+    ///
+    /// # Arguments
+    ///
+    /// * `pc` - The program counter of the `,`
+    fn complete_read(&mut self, pc: usize) -> Result<()> {
+        self.steps += 1;
+        self.ip += 1;
+        if self.options.tape_guard == TapeGuard::Aggressive {
+            check_guards(&self.tape, pc)?;
+        }
+        Ok(())
+    }
+
+    /// Ends the program, checking the guard cells once
+    ///
+    /// # Arguments
+    ///
+    /// * `pc` - The program counter of the last executed instruction, which is reported if a guard cell was clobbered
+    fn halt(&mut self, pc: usize) -> Result<Step> {
+        if !self.halted && self.options.tape_guard != TapeGuard::Off {
+            check_guards(&self.tape, pc)?;
+        }
+        self.halted = true;
+        self.ip = self.ops.len();
+        Ok(Step::Halted)
+    }
+
+    /// Keeps track of the bytes the tape takes up after a cell of a size that grows changed, failing once they exceed
+    /// the memory limit
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The changed cell
+    /// * `before` - The size of the cell before it changed
+    fn resized(&mut self, cell: &C, before: usize) -> Result<()> {
+        if !C::GROWS {
+            return Ok(());
+        }
+
+        self.used = self.used + cell.size() as u64 - before as u64;
+        match self.options.max_memory.filter(|max| self.used > *max) {
+            Some(max) => Err(limit_exceeded(&format!("memory limit of {} bytes", max), self.program, self.pcs[self.ip])),
+            None => Ok(()),
+        }
+    }
+
+    /// Warns about a read of a cell that was never written, which is then marked so each cell is warned about once
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The index of the read cell on the tape including its guard cells
+    fn check_written(&mut self, cell: usize) {
+        match self.written.get_mut(cell) {
+            Some(written) if !*written => *written = true,
+            _ => return,
+        }
+
+        let pc = self.pcs[self.ip];
+        let position = self.program.positions.get(pc).copied();
+        let message = format!(
+            "instruction {} ({}) read cell {} at step {}, which was never written and is only 0 because the tape starts that way",
+            pc, position.map_or("?".to_owned(), |position| position.to_string()), cell as isize - self.guard as isize, self.steps,
+        );
+        self.warnings.push((position, message));
+    }
+
+    /// Describes where the program stopped, for when it is interrupted
+    pub fn interrupted(&self) -> Error {
+        interrupted(self.program, self.pc(), self.steps, &self.tape, self.dp, self.guard, self.options.dump_tape)
+    }
+}
+
+/// Interprets the given program with 32 bit cells that wrap like those of the generated assembly
+///
+/// # Arguments
+///
+/// * `program` - The program to interpret
+/// * `init` - The bytes loaded into the first cells of the tape before execution, the rest of the tape is zeroed
+/// * `options` - The settings of the run
+/// * `coverage` - The blocks of the program whose entries are counted, if they are
+/// * `input` - The reader `,` reads from
+/// * `output` - The writer `.` writes to
+pub fn run<R: Read, W: Write>(program: &Program, init: &[u8], options: &Options, coverage: Option<&mut [Block]>, input: R, output: W) -> Result<Memory> {
+    execute(program, init, options, coverage, input, output)
+}
+
+/// Interprets the given program with cells of arbitrary precision, which never wrap and may become negative
+///
+/// Loops that are fused into clears and multiply-adds, like `[-]` and `[->+<]`, end like they do with wrapping cells
+/// when their counter has the sign they would never reach zero from.
+///
+/// # Arguments
+///
+/// * `program` - The program to interpret, which must not have been folded by the optimizer for 32 bit cells
+/// * `init` - The bytes loaded into the first cells of the tape before execution, the rest of the tape is zeroed
+/// * `options` - The settings of the run
+/// * `coverage` - The blocks of the program whose entries are counted, if they are
+/// * `input` - The reader `,` reads from
+/// * `output` - The writer `.` writes to
+pub fn run_big<R: Read, W: Write>(program: &Program, init: &[u8], options: &Options, coverage: Option<&mut [Block]>, input: R, output: W) -> Result<Memory<Big>> {
+    execute(program, init, options, coverage, input, output)
+}
+
+/// Interprets the given program with cells of any kind by driving a `Machine` with the input and output
+///
+/// When the input is exhausted `,` leaves the current cell unchanged. With a tape guard the data pointer may move
+/// onto the guard cells around the tape, which are checked like those of the generated assembly.
+///
+/// Once `interrupt` requested a stop, fails with `ErrorKind::Interrupted` and a description of the program's state.
+/// Returns the tape once the program ended.
+///
+/// # Arguments
+///
+/// * `program` - The program to interpret
+/// * `init` - The bytes loaded into the first cells of the tape before execution, the rest of the tape is zeroed
+/// * `options` - The settings of the run
+/// * `coverage` - The blocks of the program whose entries are counted, if they are
+/// * `input` - The reader `,` reads from
+/// * `output` - The writer `.` writes to
+fn execute<C: Cell, R: Read, W: Write>(program: &Program, init: &[u8], options: &Options, coverage: Option<&mut [Block]>, mut input: R, output: W) -> Result<Memory<C>> {
+    let mut machine: Machine<C> = Machine::new(program, init, options, coverage)?;
+    let mut out = BufWriter::new(output);
+
+    // The bytes written so far, if they are recorded
+    let mut recorded: Vec<u8> = Vec::new();
+
+    loop {
+        if interrupt::requested() {
+            out.flush()?;
+            return Err(machine.interrupted());
+        }
+
+        // Most steps need nothing from the caller, which keeps them on the fast path
+        let step = match machine.step() {
+            Ok(Step::Continued) if !options.warn_uninit => continue,
+            step => step,
+        };
+
+        // The buffered output is flushed first, so warnings appear after everything written before the read
+        let warnings = machine.take_warnings();
+        if !warnings.is_empty() {
+            out.flush()?;
+            for (position, message) in warnings {
+                eprintln!("{}", Diagnostic { severity: Severity::Warning, file: None, position, message: &message }.render(options.error_format));
+            }
+        }
+
+        match step {
+            Ok(Step::Continued) => {},
+            Ok(Step::Output(byte)) => {
+                let numeric = if options.numeric_io { Some(format!("{}\n", machine.cell()?)) } else { None };
+                let byte = [byte];
+                let bytes = numeric.as_ref().map_or(&byte[..], |numeric| numeric.as_bytes());
+                out.write_all(bytes)?;
+                if options.record_output {
+                    recorded.extend_from_slice(bytes);
+                }
+            },
+            Ok(Step::NeedsInput) => {
+                // Make sure prompts are visible before blocking on input
+                out.flush()?;
+
+                let mut byte = [0u8];
+                match input.read(&mut byte) {
+                    Ok(1) => machine.provide_input(byte[0])?,
+                    Ok(_) => machine.end_input()?,
+                    // Ctrl-C interrupts a blocked read, which is retried unless a stop was requested
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {},
+                    Err(err) => return Err(err),
+                }
+            },
+            Ok(Step::Dump(pc)) => {
+                // Show everything written before the dump first
+                out.flush()?;
+                eprintln!("{}", dump_message(&pc.to_string(), &machine.dp().to_string(), &machine.cell()?.signed()));
+            },
+            Ok(Step::Halted) => {
+                out.flush()?;
+                return Ok(machine.into_memory(recorded));
+            },
+            Err(err) => {
+                out.flush()?;
+                return Err(err);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::{DumpSettings, PassManager, Report};
+    use crate::{parse, Cpu, Extensions};
+
+    /// The programs in `tests`, with the bytes loaded onto their tape and their input
+    const PROGRAMS: [(&str, &str, &[u8], &[u8]); 6] = [
+        ("Benchmark", include_str!("../tests/Benchmark.bf"), b"", b""),
+        ("Cell_Size", include_str!("../tests/Cell_Size.bf"), b"", b""),
+        ("Factorials", include_str!("../tests/Factorials.bf"), b"", b""),
+        ("Fibonacci", include_str!("../tests/Fibonacci.bf"), b"", b""),
+        ("Hello_World", include_str!("../tests/Hello_World.bf"), b"", b""),
+        ("Uppercase", include_str!("../tests/Uppercase.bf"), b"\0\0hello, World!\0", b""),
+    ];
+
+    fn options(max_steps: Option<u64>) -> Options {
+        Options { max_steps, tape_size: 256, ..Options::default() }
+    }
+
+    fn optimized(source: &str, level: u8, init: &[u8]) -> Program {
+        let program = parse(source, Extensions::default()).unwrap();
+        let mut passes = PassManager::for_level(level, Cpu::Sse2);
+        passes.zeroed_tape = init.is_empty();
+        passes.run(program, &mut Report::default(), &DumpSettings::default()).unwrap()
+    }
+
+    /// The amount of instructions the unfused interpreter runs at most, as loops over wrapping cells can take billions
+    const UNFUSED_STEPS: u64 = 100_000_000;
+
+    /// Interprets a parsed program one instruction at a time without fusing anything, returning its output and whether
+    /// it ended before the step limit
+    fn unfused(program: &Program, init: &[u8], input: &[u8]) -> (Vec<u8>, bool) {
+        let mut tape = vec![0u32; 256];
+        for (cell, byte) in tape.iter_mut().zip(init) {
+            *cell = u32::from(*byte);
+        }
+        let (mut pc, mut dp) = (0, 0);
+        let mut input = input.iter();
+        let mut output: Vec<u8> = Vec::new();
+
+        for _ in 0..UNFUSED_STEPS {
+            let Some(instr) = program.instructions.get(pc) else { return (output, true) };
+            match instr {
+                Instruction::Increment => dp += 1,
+                Instruction::Decrement => dp -= 1,
+                Instruction::Add => tape[dp] = tape[dp].wrapping_add(1),
+                Instruction::Subtract => tape[dp] = tape[dp].wrapping_sub(1),
+                Instruction::Write => output.push(tape[dp] as u8),
+                Instruction::Read => {
+                    if let Some(byte) = input.next() {
+                        tape[dp] = u32::from(*byte);
+                    }
+                },
+                Instruction::Jump(end) if tape[dp] == 0 => pc = *end as usize,
+                Instruction::Return(start) if tape[dp] != 0 => pc = *start as usize,
+                _ => {},
+            }
+            pc += 1;
+        }
+
+        (output, false)
+    }
+
+    /// Interprets a program with the fused operations, returning its output and the amount of steps it took
+    fn fused(program: &Program, init: &[u8], input: &[u8], max_steps: Option<u64>) -> Result<(Vec<u8>, u64)> {
+        let mut output: Vec<u8> = Vec::new();
+        let memory = run(program, init, &options(max_steps), None, input, &mut output)?;
+        Ok((output, memory.steps))
+    }
+
+    #[test]
+    fn fused_matches_unfused() {
+        for (name, source, init, input) in PROGRAMS {
+            let program = parse(source, Extensions::default()).unwrap();
+            let (output, _) = fused(&program, init, input, None).unwrap();
+            match unfused(&program, init, input) {
+                (expected, true) => assert_eq!(output, expected, "{}", name),
+                // The factorials multiply by adding one at a time, so only the first ones are printed in time
+                (expected, false) => assert!(!expected.is_empty() && output.starts_with(&expected), "{}", name),
+            }
+        }
+    }
+
+    #[test]
+    fn read_wraps_and_keeps_the_cell_at_the_end_of_the_input() {
+        // The read byte wraps like any other value, the exhausted input leaves the cell unchanged
+        let cases: [(&str, &[u8], &[u8]); 4] = [
+            (",-.", b"\0", b"\xFF"),
+            (",+.", b"\xFF", b"\0"),
+            ("+++,.", b"", b"\x03"),
+            (",.,.,[-]+.", b"a", b"aa\x01"),
+        ];
+        for (source, input, expected) in cases {
+            let program = parse(source, Extensions::default()).unwrap();
+            assert_eq!(fused(&program, b"", input, None).unwrap().0, expected, "{}", source);
+            assert_eq!(unfused(&program, b"", input), (expected.to_vec(), true), "{}", source);
+        }
+    }
+
+    #[test]
+    fn loops_are_fused_after_rle() {
+        // `-O1` merges the runs of the inner loop's body, which has to be fused like at `-O0`
+        let program = optimized(">>>>>------[---+++++----[>++++<-]]++++,", 1, b"");
+        let (ops, _) = decode(&program.instructions, None);
+        assert_eq!(ops.iter().filter(|op| matches!(op, Op::JumpIfZero(_))).count(), 1);
+        assert!(ops.iter().any(|op| matches!(op, Op::AddTo(1, 4))));
+
+        let (ops, _) = decode(&optimized("+[>>>]", 1, b"").instructions, None);
+        assert!(matches!(ops[..], [Op::Add(1), Op::Scan(3)]));
+    }
+
+    #[test]
+    fn steps_depend_on_the_level() {
+        // `-O2` removes the changes the second clear overwrites, so a limit it stays within stops the parsed program
+        let steps = |level| fused(&optimized("++[-]+++[-].", level, b""), b"", b"", None).unwrap().1;
+        assert_eq!([steps(0), steps(1), steps(2)], [5, 5, 2]);
+        let result = fused(&optimized("++[-]+++[-].", 0, b""), b"", b"", Some(2));
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::QuotaExceeded);
+
+        // It unrolls copy loops with a known counter into separate moves and changes, which take more steps
+        let (_, source, _, _) = PROGRAMS[0];
+        let steps = |level| fused(&optimized(source, level, b""), b"", b"", None).unwrap().1;
+        assert!(steps(2) > steps(0));
+    }
+
+    #[test]
+    fn limits_and_output_match_across_levels() {
+        let mut programs: Vec<(&str, &str, &[u8])> = PROGRAMS.iter().map(|(name, source, init, _)| (*name, *source, *init)).collect();
+        programs.push(("rle loops", ">>>>>------[---+++++----[>++++<-]]++++,", b""));
+
+        for (name, source, init) in programs {
+            // `-O1` fuses the same loops, so it takes exactly the steps the program takes as parsed
+            let (expected, steps) = fused(&optimized(source, 0, init), init, b"", None).unwrap();
+            let result = fused(&optimized(source, 1, init), init, b"", Some(steps));
+            assert_eq!(result.unwrap(), (expected.clone(), steps), "{} at -O1", name);
+
+            // `-O2` replaces and unrolls loops, so only its output has to match
+            let result = fused(&optimized(source, 2, init), init, b"", None);
+            assert_eq!(result.unwrap().0, expected, "{} at -O2", name);
+        }
+    }
+}
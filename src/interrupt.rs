@@ -0,0 +1,98 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::terminal;
+
+/// Whether Ctrl-C was pressed since the handler was installed
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether Ctrl-C was pressed, so the interpreter should stop at the next instruction
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Marks the first Ctrl-C as a request to stop and lets every further one terminate the process
+///
+/// Returns whether this was the first Ctrl-C.
+fn request() -> bool {
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+        terminal::restore_original();
+        false
+    } else {
+        true
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    extern "C" {
+        fn signal(signum: i32, handler: usize) -> usize;
+        fn raise(signum: i32) -> i32;
+        fn siginterrupt(signum: i32, flag: i32) -> i32;
+    }
+
+    const SIGINT: i32 = 2;
+    const SIG_DFL: usize = 0;
+
+    /// Requests a stop on the first `SIGINT` and terminates the process like the default handler on the second
+    ///
+    /// # Arguments
+    ///
+    /// * `signum` - The number of the received signal
+    extern "C" fn handle(signum: i32) {
+        if !super::request() {
+            // SAFETY: `signal` and `raise` are async-signal-safe
+            unsafe {
+                signal(signum, SIG_DFL);
+                raise(signum);
+            }
+        }
+    }
+
+    /// Installs the handler of `SIGINT`, which makes blocking reads fail instead of restarting them
+    pub fn install() {
+        // SAFETY: The handler only touches an atomic and calls async-signal-safe functions
+        unsafe {
+            signal(SIGINT, handle as *const () as usize);
+            siginterrupt(SIGINT, 1);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<extern "system" fn(u32) -> i32>, add: i32) -> i32;
+    }
+
+    const CTRL_C_EVENT: u32 = 0;
+
+    /// Requests a stop on the first Ctrl-C and lets the default handler terminate the process on the second
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The kind of the received event
+    extern "system" fn handle(event: u32) -> i32 {
+        (event == CTRL_C_EVENT && super::request()) as i32
+    }
+
+    /// Installs the console control handler
+    pub fn install() {
+        // SAFETY: The handler only touches atomics and the console mode
+        unsafe {
+            SetConsoleCtrlHandler(Some(handle), 1);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    /// Ctrl-C keeps terminating the process on this platform
+    pub fn install() {}
+}
+
+/// Makes the first Ctrl-C request a stop instead of terminating the process
+///
+/// Only the interpreter polls for the request, so this must not be installed while generating assembly.
+pub fn install() {
+    platform::install();
+}
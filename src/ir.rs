@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::io::{Error, Result, Write};
+
+use crate::{optimizer, Instruction, Position, Program};
+
+/// The name that starts the first line of every IR file, followed by the version of the format
+const MAGIC: &str = "bfasm-ir";
+
+/// The version of the format, which is only increased when a line changes its meaning or an operation is removed
+const VERSION: u32 = 1;
+
+/// Writes a program as IR, a header line followed by one instruction per line
+///
+/// Each instruction is written as its source position, its name and its operands separated by spaces, like
+/// `3:7 MultiplyAdd 2 -1`. Loop instructions name the index of their partner among the instructions.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `program` - The program, optimized or not
+pub fn write<W: Write>(f: &mut W, program: &Program) -> Result<()> {
+    writeln!(f, "{} {}", MAGIC, VERSION)?;
+    for (instr, position) in program.instructions.iter().zip(&program.positions) {
+        let operands = match instr {
+            Instruction::Jump(n) | Instruction::Return(n) | Instruction::MoveN(n) | Instruction::AddN(n) | Instruction::Scan(n) => {
+                format!(" {}", n)
+            },
+            Instruction::Label(name) => format!(" {}", name),
+            Instruction::MultiplyAdd(offset, factor) => format!(" {} {}", offset, factor),
+            Instruction::AddVector(offset, values) => format!(" {} {} {} {} {}", offset, values[0], values[1], values[2], values[3]),
+            _ => String::new(),
+        };
+        writeln!(f, "{} {}{}", position, name(instr), operands)?;
+    }
+
+    Ok(())
+}
+
+/// The name of an instruction in the IR, which is that of its variant
+///
+/// # Arguments
+///
+/// * `instr` - The instruction
+fn name(instr: &Instruction) -> &'static str {
+    match instr {
+        Instruction::Increment => "Increment",
+        Instruction::Decrement => "Decrement",
+        Instruction::Add => "Add",
+        Instruction::Subtract => "Subtract",
+        Instruction::Write => "Write",
+        Instruction::Read => "Read",
+        Instruction::Jump(_) => "Jump",
+        Instruction::Return(_) => "Return",
+        Instruction::Label(_) => "Label",
+        Instruction::Break => "Break",
+        Instruction::Halt => "Halt",
+        Instruction::Dump => "Dump",
+        Instruction::MoveN(_) => "MoveN",
+        Instruction::AddN(_) => "AddN",
+        Instruction::Clear => "Clear",
+        Instruction::Scan(_) => "Scan",
+        Instruction::MultiplyAdd(..) => "MultiplyAdd",
+        Instruction::AddVector(..) => "AddVector",
+        Instruction::Unreachable => "Unreachable",
+        Instruction::PinBase => "PinBase",
+    }
+}
+
+/// Parses a program written as IR, reporting every malformed line
+///
+/// Empty lines and lines starting with `#` are ignored. The positions of the errors are those of the lines in the IR,
+/// the positions of the instructions those of the source they were generated from.
+///
+/// # Arguments
+///
+/// * `text` - The contents of the IR file
+pub fn parse(text: &str) -> std::result::Result<Program, Vec<(Position, Error)>> {
+    let mut lines = text.lines().enumerate();
+
+    let at = |line: usize| Position { line: line + 1, column: 1 };
+    let header = format!("{} {}", MAGIC, VERSION);
+    match lines.next() {
+        Some((_, first)) if first.trim_end() == header => {},
+        Some((_, first)) if first.starts_with(MAGIC) => {
+            let err = Error::other(format!("unsupported IR version '{}', expected '{}'!", first.trim_end(), header));
+            return Err(vec![(at(0), err)]);
+        },
+        _ => return Err(vec![(at(0), Error::other(format!("missing '{}' header in the first line of the IR!", header)))]),
+    }
+
+    let mut instructions: Vec<Instruction> = Vec::new();
+    let mut positions: Vec<Position> = Vec::new();
+    // The line of each instruction, which errors about loops are reported at
+    let mut lines_of: Vec<usize> = Vec::new();
+    let mut errors: Vec<(Position, Error)> = Vec::new();
+
+    for (line, text) in lines {
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        match parse_line(text) {
+            Ok((instr, position)) => {
+                instructions.push(instr);
+                positions.push(position);
+                lines_of.push(line);
+            },
+            Err(err) => errors.push((at(line), Error::other(format!("{} in line {} of the IR!", err, line + 1)))),
+        }
+    }
+
+    // The code generator relies on loops being paired, nested within each other and `PinBase` preceding a loop. The
+    // `Jump`s of the open loops are kept on a stack, and each instruction is reported once, in the order of the lines
+    let mut open: Vec<usize> = Vec::new();
+    let mut invalid: BTreeMap<usize, String> = BTreeMap::new();
+    for (pc, instr) in instructions.iter().enumerate() {
+        let line = lines_of[pc] + 1;
+        match instr {
+            Instruction::Jump(end) => {
+                if *end as usize > pc && matches!(instructions.get(*end as usize), Some(Instruction::Return(start)) if *start as usize == pc) {
+                    open.push(pc);
+                } else {
+                    invalid.insert(pc, format!("unmatched 'Jump' in line {} of the IR!", line));
+                }
+            },
+            Instruction::Return(start) => {
+                let start = *start as usize;
+                if start >= pc || !matches!(instructions.get(start), Some(Instruction::Jump(end)) if *end as usize == pc) {
+                    invalid.insert(pc, format!("unmatched 'Return' in line {} of the IR!", line));
+                } else if let Some(&inner) = open.last().filter(|inner| **inner != start) {
+                    let message = format!("'Return' in line {} closes the loop of line {} across the loop of line {} of the IR!", line, lines_of[start] + 1, lines_of[inner] + 1);
+                    invalid.insert(pc, message);
+                    open.retain(|jump| *jump != start);
+                } else {
+                    open.pop();
+                }
+            },
+            Instruction::PinBase if !matches!(instructions.get(pc + 1), Some(Instruction::Jump(_))) => {
+                invalid.insert(pc, format!("'PinBase' is not followed by a 'Jump' in line {} of the IR!", line));
+            },
+            _ => {},
+        }
+    }
+    errors.extend(invalid.into_iter().map(|(pc, message)| (at(lines_of[pc]), Error::other(message))));
+
+    if errors.is_empty() {
+        Ok(Program { instructions, positions })
+    } else {
+        errors.sort_by_key(|(position, _)| position.line);
+        Err(errors)
+    }
+}
+
+/// Parses a line of IR into an instruction and its source position
+///
+/// # Arguments
+///
+/// * `text` - The line without surrounding whitespace
+fn parse_line(text: &str) -> std::result::Result<(Instruction, Position), String> {
+    let mut words = text.split_whitespace();
+    let position = words.next().unwrap_or_default();
+    let position = position.split_once(':')
+        .and_then(|(line, column)| Some(Position { line: line.parse().ok()?, column: column.parse().ok()? }))
+        .ok_or_else(|| format!("invalid position '{}'", position))?;
+
+    let op = words.next().ok_or("missing instruction")?;
+    let operands: Vec<&str> = words.collect();
+
+    // The numbers of the operands, of which the instruction expects `count`
+    let numbers = |count: usize| -> std::result::Result<Vec<i32>, String> {
+        if operands.len() != count {
+            return Err(format!("'{}' takes {} operands but got {}", op, count, operands.len()));
+        }
+        operands.iter().map(|operand| operand.parse::<i32>().map_err(|_| format!("invalid operand '{}' of '{}'", operand, op))).collect()
+    };
+
+    // The offsets of moves and addressed cells have to be reachable by a single instruction
+    let offset = |n: i32| -> std::result::Result<i32, String> {
+        optimizer::offset_sum(0, n).ok_or_else(|| format!("offset {} of '{}' is farther than the {} cells an instruction can reach", n, op, optimizer::MAX_OFFSET))
+    };
+
+    let instr = match op {
+        "Increment" => numbers(0).map(|_| Instruction::Increment)?,
+        "Decrement" => numbers(0).map(|_| Instruction::Decrement)?,
+        "Add" => numbers(0).map(|_| Instruction::Add)?,
+        "Subtract" => numbers(0).map(|_| Instruction::Subtract)?,
+        "Write" => numbers(0).map(|_| Instruction::Write)?,
+        "Read" => numbers(0).map(|_| Instruction::Read)?,
+        "Break" => numbers(0).map(|_| Instruction::Break)?,
+        "Halt" => numbers(0).map(|_| Instruction::Halt)?,
+        "Dump" => numbers(0).map(|_| Instruction::Dump)?,
+        "Clear" => numbers(0).map(|_| Instruction::Clear)?,
+        "Unreachable" => numbers(0).map(|_| Instruction::Unreachable)?,
+        "PinBase" => numbers(0).map(|_| Instruction::PinBase)?,
+        "Jump" => Instruction::Jump(numbers(1)?[0]),
+        "Return" => Instruction::Return(numbers(1)?[0]),
+        "MoveN" => Instruction::MoveN(offset(numbers(1)?[0])?),
+        "AddN" => Instruction::AddN(numbers(1)?[0]),
+        "Scan" => Instruction::Scan(offset(numbers(1)?[0])?),
+        "MultiplyAdd" => {
+            let n = numbers(2)?;
+            Instruction::MultiplyAdd(offset(n[0])?, n[1])
+        },
+        "AddVector" => {
+            let n = numbers(5)?;
+            Instruction::AddVector(offset(n[0])?, [n[1], n[2], n[3], n[4]])
+        },
+        // Labels are emitted as `USER_name:`, so their names must be valid in both assemblers
+        "Label" => match operands.as_slice() {
+            [name] if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => Instruction::Label(name.to_string()),
+            _ => return Err("'Label' takes a name of letters, digits and '_'".to_owned()),
+        },
+        op => return Err(format!("unknown instruction '{}'", op)),
+    };
+
+    Ok((instr, position))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The messages of the errors of reading an IR file with the given lines after its header
+    fn errors(lines: &[&str]) -> Vec<String> {
+        let text = format!("{} {}\n{}\n", MAGIC, VERSION, lines.join("\n"));
+        match parse(&text) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.iter().map(|(_, err)| err.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn nested_loops_are_read() {
+        assert_eq!(errors(&["1:1 Jump 3", "1:2 Jump 2", "1:3 Return 1", "1:4 Return 0"]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn crossing_loops_are_rejected() {
+        assert_eq!(errors(&["1:1 Jump 2", "1:2 Jump 3", "1:3 Return 0", "1:4 Return 1"]), [
+            "'Return' in line 4 closes the loop of line 2 across the loop of line 3 of the IR!",
+        ]);
+    }
+
+    #[test]
+    fn errors_are_reported_once_in_the_order_of_their_lines() {
+        assert_eq!(errors(&["1:1 Return 0", "1:2 Bogus", "1:3 Jump 9", "1:4 PinBase"]), [
+            "unmatched 'Return' in line 2 of the IR!",
+            "unknown instruction 'Bogus' in line 3 of the IR!",
+            "unmatched 'Jump' in line 4 of the IR!",
+            "'PinBase' is not followed by a 'Jump' in line 5 of the IR!",
+        ]);
+    }
+}
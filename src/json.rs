@@ -0,0 +1,241 @@
+/// Quotes a string as a JSON string literal
+///
+/// # Arguments
+///
+/// * `s` - The string to quote
+pub fn string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// A parsed JSON value
+pub enum Value {
+    /// `null`
+    Null,
+    /// `true` or `false`
+    Bool(bool),
+    /// Any number, which JSON does not distinguish
+    Number(f64),
+    /// A string with its escapes resolved
+    String(String),
+    /// The elements of an array
+    Array(Vec<Value>),
+    /// The members of an object in the order they were written
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Returns the member of an object with the given name, if this is an object that has it
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the member
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(key, _)| key == name).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Returns the string, if this is one
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the number as an unsigned integer, if this is a non-negative whole number
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements, if this is an array
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(elements) => Some(elements),
+            _ => None,
+        }
+    }
+
+    /// Writes the value as JSON again
+    pub fn to_json(&self) -> String {
+        match self {
+            Value::Null => "null".to_owned(),
+            Value::Bool(b) => b.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::String(s) => string(s),
+            Value::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(Value::to_json).collect();
+                format!("[{}]", elements.join(","))
+            },
+            Value::Object(members) => {
+                let members: Vec<String> = members.iter().map(|(key, value)| format!("{}:{}", string(key), value.to_json())).collect();
+                format!("{{{}}}", members.join(","))
+            },
+        }
+    }
+}
+
+/// Parses a JSON text, returning `None` if it is malformed
+///
+/// # Arguments
+///
+/// * `text` - The text to parse
+pub fn parse(text: &str) -> Option<Value> {
+    let mut parser = Parser { chars: text.chars().collect(), i: 0 };
+    let value = parser.value()?;
+    parser.whitespace();
+    (parser.i == parser.chars.len()).then_some(value)
+}
+
+/// The state of parsing a JSON text
+struct Parser {
+    /// The characters of the text
+    chars: Vec<char>,
+    /// The index of the next character to parse
+    i: usize,
+}
+
+impl Parser {
+    /// Skips whitespace
+    fn whitespace(&mut self) {
+        while self.chars.get(self.i).is_some_and(|c| c.is_ascii_whitespace()) {
+            self.i += 1;
+        }
+    }
+
+    /// Skips whitespace and the given character, failing if another one follows
+    fn expect(&mut self, c: char) -> Option<()> {
+        self.whitespace();
+        (self.chars.get(self.i) == Some(&c)).then(|| self.i += 1)
+    }
+
+    /// Skips whitespace and the given keyword, failing if it does not follow
+    fn keyword(&mut self, word: &str, value: Value) -> Option<Value> {
+        let end = self.i + word.chars().count();
+        if self.chars.get(self.i..end)?.iter().copied().eq(word.chars()) {
+            self.i = end;
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Parses the value starting at the next non-whitespace character
+    fn value(&mut self) -> Option<Value> {
+        self.whitespace();
+        match self.chars.get(self.i)? {
+            'n' => self.keyword("null", Value::Null),
+            't' => self.keyword("true", Value::Bool(true)),
+            'f' => self.keyword("false", Value::Bool(false)),
+            '"' => self.string().map(Value::String),
+            '[' => {
+                self.i += 1;
+                let mut elements = Vec::new();
+                if self.expect(']').is_none() {
+                    loop {
+                        elements.push(self.value()?);
+                        if self.expect(']').is_some() {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Some(Value::Array(elements))
+            },
+            '{' => {
+                self.i += 1;
+                let mut members = Vec::new();
+                if self.expect('}').is_none() {
+                    loop {
+                        self.whitespace();
+                        let key = self.string()?;
+                        self.expect(':')?;
+                        members.push((key, self.value()?));
+                        if self.expect('}').is_some() {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Some(Value::Object(members))
+            },
+            _ => {
+                let start = self.i;
+                while self.chars.get(self.i).is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+                    self.i += 1;
+                }
+                let number: String = self.chars[start..self.i].iter().collect();
+                number.parse().ok().map(Value::Number)
+            },
+        }
+    }
+
+    /// Parses the string starting at the next character, which has to be its opening quote
+    fn string(&mut self) -> Option<String> {
+        if self.chars.get(self.i) != Some(&'"') {
+            return None;
+        }
+        self.i += 1;
+
+        let mut s = String::new();
+        loop {
+            let c = *self.chars.get(self.i)?;
+            self.i += 1;
+            match c {
+                '"' => return Some(s),
+                '\\' => {
+                    let escaped = *self.chars.get(self.i)?;
+                    self.i += 1;
+                    match escaped {
+                        'n' => s.push('\n'),
+                        'r' => s.push('\r'),
+                        't' => s.push('\t'),
+                        'b' => s.push('\u{8}'),
+                        'f' => s.push('\u{c}'),
+                        'u' => {
+                            let unit = self.hex4()?;
+                            // Characters outside the basic plane are written as a surrogate pair
+                            let c = if (0xD800..0xDC00).contains(&unit) && self.chars.get(self.i..self.i + 2)?.iter().eq(['\\', 'u'].iter()) {
+                                self.i += 2;
+                                let low = self.hex4()?;
+                                char::from_u32(0x10000 + ((unit - 0xD800) << 10) + (low.checked_sub(0xDC00)? & 0x3FF))
+                            } else {
+                                char::from_u32(unit)
+                            };
+                            s.push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+                        },
+                        c => s.push(c),
+                    }
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// Parses four hex digits
+    fn hex4(&mut self) -> Option<u32> {
+        let digits: String = self.chars.get(self.i..self.i + 4)?.iter().collect();
+        self.i += 4;
+        u32::from_str_radix(&digits, 16).ok()
+    }
+}
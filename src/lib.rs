@@ -0,0 +1,4007 @@
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::fs::{canonicalize, create_dir_all, metadata, read, read_dir, read_to_string, remove_file, rename, File};
+use std::io::{stderr, stdin, stdout, Error, ErrorKind, IsTerminal, Read, Result, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command};
+use std::time::{Duration, Instant};
+
+use emitter::{Emitter, IntelEmitter};
+use lowering::Helper;
+
+mod bignum;
+mod check;
+mod cli;
+pub mod coverage;
+mod diff;
+mod emitter;
+mod encoding;
+mod harness;
+mod include;
+pub mod interpreter;
+mod interrupt;
+mod ir;
+mod json;
+mod listing;
+mod lsp;
+mod lowering;
+mod optimizer;
+mod report;
+mod script;
+mod selftest;
+mod sha256;
+mod stream;
+mod template;
+mod terminal;
+
+pub use cli::main;
+
+/// A brainfuck instruction
+#[derive(Clone, Debug)]
+pub enum Instruction {
+    /// `>` : Increment data pointer
+    Increment,
+    /// `<` : Decrement data pointer
+    Decrement,
+    /// `+` : Add one to current cell
+    Add,
+    /// `-` : Subtract one from current cell
+    Subtract,
+    /// `.` : Write ascii value of current cell to stdout
+    Write,
+    /// `,` : Read ascii value from stdin to current cell
+    Read,
+    /// `[` : Beginning of loop with a `jmp_pc: i32`
+    Jump(i32),
+    /// `]` : End of loop with a `jmp_pc: i32`
+    Return(i32),
+    /// `@name` : A user defined label that generates no code (only with `--enable-labels`)
+    Label(String),
+    /// `#` : Breakpoint for debuggers (only with `--debug-breaks`)
+    Break,
+    /// `@` : Ends the program like reaching its end does (only with `--extensions halt`)
+    Halt,
+    /// `$` : Prints the data pointer and the current cell to stderr (only with `--extensions dump`)
+    Dump,
+    /// Move the data pointer by an amount (merged from `>` and `<` by the optimizer)
+    MoveN(i32),
+    /// Add an amount to the current cell (merged from `+` and `-` by the optimizer)
+    AddN(i32),
+    /// Set the current cell to zero (replaces loops like `[-]`)
+    Clear,
+    /// Move the data pointer by an amount until it points to a zero cell (replaces loops like `[>]`)
+    Scan(i32),
+    /// Add the current cell multiplied by a `factor: i32` to the cell at an `offset: i32` (replaces the body of copy loops like `[->+<]`)
+    MultiplyAdd(i32, i32),
+    /// Add four amounts to the four cells starting at an `offset: i32` without moving (only with `--cpu sse2`)
+    AddVector(i32, [i32; 4]),
+    /// Marks that the preceding loop never ends, so no epilogue is needed (inserted by the optimizer)
+    Unreachable,
+    /// Loads the data pointer once for the following loop, which never moves it (inserted by the optimizer)
+    PinBase,
+}
+
+/// The position of an instruction in the source
+#[derive(Clone, Copy)]
+pub struct Position {
+    /// The line, starting at 1
+    pub line: usize,
+    /// The column in characters, starting at 1
+    pub column: usize,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A command character recognized by the parser
+struct Token {
+    /// The character
+    c: char,
+    /// The position of the character in the source
+    position: Position,
+}
+
+/// The opt-in extensions to the brainfuck language
+#[derive(Clone, Copy, Default)]
+pub struct Extensions {
+    /// Whether `@name` defines a label instead of being a comment
+    pub labels: bool,
+    /// Whether `#` is a breakpoint instead of being a comment
+    pub debug_breaks: bool,
+    /// Whether `@` halts the program instead of being a comment
+    pub halt: bool,
+    /// Whether `$` dumps the current cell instead of being a comment
+    pub dump: bool,
+    /// Whether `{N}` repeats the preceding `+`, `-`, `<`, `>` or `.` `N` times instead of being a comment
+    counts: bool,
+}
+
+/// The largest repetition count of `--enable-counts`
+const MAX_REPEAT_COUNT: u32 = 1_000_000;
+
+/// The most instructions a program may have, as jumps address them with 32 bit signed integers
+const MAX_INSTRUCTIONS: usize = i32::MAX as usize;
+
+/// The name of the source file that stands for the program piped into stdin
+const STDIN_SOURCE: &str = "-";
+
+/// A parsed brainfuck program
+pub struct Program {
+    /// The parsed instructions
+    pub instructions: Vec<Instruction>,
+    /// The source position of each instruction
+    pub positions: Vec<Position>,
+}
+
+/// Returns the pc of the instruction a loop instruction is paired with, or `None` if it is no loop instruction
+/// 
+/// A `Jump` stores the pc of its `Return` and vice versa, so this only reads the stored pc.
+/// 
+/// # Arguments
+/// 
+/// * `instructions` - A slice of instructions that contains the program
+/// * `pc` - The program counter of the `Jump` or `Return`
+pub fn matching_bracket(instructions: &[Instruction], pc: usize) -> Option<usize> {
+    match instructions.get(pc)? {
+        Instruction::Jump(jmp_pc) | Instruction::Return(jmp_pc) => Some(*jmp_pc as usize),
+        _ => None,
+    }
+}
+
+/// The assembler syntax of the generated assembly
+#[derive(Clone, Copy, PartialEq)]
+pub enum Syntax {
+    /// NASM syntax
+    Nasm,
+    /// GNU assembler syntax using Intel operand order (`.intel_syntax noprefix`)
+    Gas,
+}
+
+impl Syntax {
+    /// Every syntax, which `explain-target` lists
+    const ALL: [Syntax; 2] = [Syntax::Nasm, Syntax::Gas];
+
+    /// The name that selects the syntax
+    fn name(self) -> &'static str {
+        match self {
+            Syntax::Nasm => "nasm",
+            Syntax::Gas => "gas",
+        }
+    }
+
+    /// The extension of generated files
+    fn extension(self) -> &'static str {
+        match self {
+            Syntax::Nasm => "asm",
+            Syntax::Gas => "s",
+        }
+    }
+
+    /// The command and its arguments that assemble a generated file into a Windows x64 object file
+    /// 
+    /// # Arguments
+    /// 
+    /// * `asm` - The path of the generated file
+    /// * `obj` - The path of the object file
+    fn assemble_command(self, asm: &str, obj: &str) -> Vec<String> {
+        match self {
+            Syntax::Nasm => vec!["nasm".to_owned(), "-fwin64".to_owned(), asm.to_owned(), "-o".to_owned(), obj.to_owned()],
+            Syntax::Gas => vec!["as".to_owned(), asm.to_owned(), "-o".to_owned(), obj.to_owned()],
+        }
+    }
+
+    /// The memory operand addressing the data pointer
+    fn dp(self) -> &'static str {
+        match self {
+            Syntax::Nasm => "[dp]",
+            Syntax::Gas => "[rip + dp]",
+        }
+    }
+
+    /// The memory operand addressing a symbol in the data segment
+    /// 
+    /// # Arguments
+    /// 
+    /// * `symbol` - The symbol, optionally followed by an offset
+    fn rip(self, symbol: &str) -> String {
+        match self {
+            Syntax::Nasm => format!("[{}]", symbol),
+            Syntax::Gas => format!("[rip + {}]", symbol),
+        }
+    }
+
+    /// The memory operand addressing the remaining fuel
+    fn fuel(self) -> &'static str {
+        match self {
+            Syntax::Nasm => "[fuel]",
+            Syntax::Gas => "[rip + fuel]",
+        }
+    }
+
+    /// The character that starts a comment
+    fn comment(self) -> &'static str {
+        match self {
+            Syntax::Nasm => ";",
+            Syntax::Gas => "#",
+        }
+    }
+
+    /// The size specifier of `DWORD` memory operands
+    fn dword(self) -> &'static str {
+        match self {
+            Syntax::Nasm => "dword",
+            Syntax::Gas => "dword ptr",
+        }
+    }
+
+    /// The size specifier of `BYTE` memory operands
+    fn byte(self) -> &'static str {
+        match self {
+            Syntax::Nasm => "byte",
+            Syntax::Gas => "byte ptr",
+        }
+    }
+
+    /// The size specifier of `QWORD` memory operands
+    fn qword(self) -> &'static str {
+        match self {
+            Syntax::Nasm => "qword",
+            Syntax::Gas => "qword ptr",
+        }
+    }
+}
+
+/// The amount of `DWORD`s to reserve for the tape in the `.bss` segment and of cells on the interpreter's tape
+const DATA_SIZE: i32 = 256;
+
+/// The most cells a tape including its guard cells may have to be reserved in the image, which has to stay below 2 GiB
+/// with `/LARGEADDRESSAWARE:NO`, larger tapes are allocated when the program starts
+const MAX_STATIC_TAPE_CELLS: i64 = 1 << 28;
+
+/// The most cells a tape may have, as many as the 128 TiB user address space of x64 Windows holds
+const MAX_TAPE_CELLS: i64 = 1 << 45;
+
+/// The exit code of generated programs and the interpreter when writing to stdout failed, e.g. because the pipe was closed
+const WRITE_FAILED_EXIT_CODE: i32 = 3;
+
+/// The exit code of generated programs when a tape guard was clobbered
+const TAPE_GUARD_EXIT_CODE: i32 = 4;
+
+/// The exit code of generated programs when they ran out of fuel
+const FUEL_EXHAUSTED_EXIT_CODE: i32 = 5;
+
+/// The message of generated programs when they ran out of fuel
+const FUEL_EXHAUSTED_MESSAGE: &str = "fuel exhausted";
+
+/// The warning of generated programs and the interpreter when the argument copied to the tape had to be truncated
+const ARGV_TRUNCATED_WARNING: &str = "warning: the argument does not fit on the tape and was truncated";
+
+/// The message of generated programs when the tape that is too large for the image could not be allocated
+const TAPE_ALLOCATION_FAILED_MESSAGE: &str = "could not allocate the tape";
+
+/// The size of the buffer the message of `$` is formatted into, which fits two 32 bit numbers and a 64 bit one
+const DUMP_BUFFER_SIZE: i32 = 128;
+
+/// The exit code of the interpreter when the program exceeded a limit of `--max-steps`, `--max-output`, `--max-input`
+/// or `--max-memory`, and of generated programs whose tape could not be allocated
+const LIMIT_EXCEEDED_EXIT_CODE: i32 = 6;
+
+/// The exit code of the interpreter when it was stopped with Ctrl-C, like that of shells for `SIGINT`
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// The amount of guard cells before and after the tape (only with `--tape-guard`)
+const GUARD_CELLS: i32 = 4;
+
+/// The value of every guard cell, checked for changes
+const GUARD_PATTERN: u32 = 0xDEADBEEF;
+
+/// The name of the function a program is generated as with `--library` if no other name is given
+const LIBRARY_FUNCTION: &str = "bf_run";
+
+/// The instruction set the generated assembly may use
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Cpu {
+    /// Only scalar instructions
+    #[default]
+    Baseline,
+    /// Additionally SSE2 vector instructions
+    Sse2,
+}
+
+/// When the guard cells around the tape are checked for overruns
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum TapeGuard {
+    /// There are no guard cells
+    #[default]
+    Off,
+    /// The guard cells are checked when the program exits
+    AtExit,
+    /// The guard cells are additionally checked after each `,`
+    Aggressive,
+}
+
+/// The segment the generated program's tape is placed in
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum TapeSection {
+    /// The segment the loader zeroes, which takes no space in the executable
+    #[default]
+    Bss,
+    /// The data segment, whose cells are stored in the executable and may start with other values than 0
+    Data,
+}
+
+/// The C runtime the generated program is linked against, which decides how its functions are called
+#[derive(Clone, Copy, PartialEq)]
+pub enum Crt {
+    /// MSVC's static runtime (`/MT`), whose functions are called directly
+    Static,
+    /// MSVC's DLL runtime (`/MD`), whose exported functions are called through the `__imp_` pointers of its import
+    /// libraries
+    Dynamic,
+    /// The `msvcrt.dll` that MinGW's `gcc` links against, whose functions are called directly
+    Mingw,
+}
+
+impl Crt {
+    /// Every runtime, which `explain-target` lists
+    const ALL: [Crt; 3] = [Crt::Static, Crt::Dynamic, Crt::Mingw];
+
+    /// The functions the DLL runtime exports, the `printf` family is inline in the UCRT's headers and comes from
+    /// `legacy_stdio_definitions.lib` and `atexit` from the static part of `msvcrt.lib`, so those are called directly
+    const IMPORTED: [&'static str; 8] = ["_getch", "putchar", "_write", "_setmode", "exit", "fflush", "fopen", "fclose"];
+
+    /// The name that selects the runtime
+    fn name(self) -> &'static str {
+        match self {
+            Crt::Static => "static",
+            Crt::Dynamic => "dynamic",
+            Crt::Mingw => "mingw",
+        }
+    }
+
+    /// Whether a function of the runtime is called through its import pointer `__imp_<function>`
+    ///
+    /// # Arguments
+    ///
+    /// * `function` - The name of the function
+    fn imports(self, function: &str) -> bool {
+        self == Crt::Dynamic && Crt::IMPORTED.contains(&function)
+    }
+
+    /// The comment of build scripts explaining how they link
+    fn description(self) -> &'static str {
+        match self {
+            Crt::Static => "Links MSVC's static C runtime (/MT), the program calls its functions directly",
+            Crt::Dynamic => "Links MSVC's DLL C runtime (/MD), the program calls its exported functions through their __imp_ pointers",
+            Crt::Mingw => "Links MinGW's msvcrt.dll with gcc, the program calls its functions directly",
+        }
+    }
+
+    /// Returns whether the linker of the runtime can be run
+    fn found(self) -> bool {
+        match self {
+            Crt::Static | Crt::Dynamic => Command::new("link").arg("/?").output().is_ok(),
+            // A native `gcc` on Linux cannot link Windows object files, only one targeting MinGW can
+            Crt::Mingw => Command::new("gcc").arg("-dumpmachine").output()
+                .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("mingw")),
+        }
+    }
+
+    /// Returns the runtime of the toolchain that was found, MSVC's `link` being preferred and linking the DLL runtime
+    /// like `cl` does by default
+    fn detect() -> Option<Crt> {
+        [Crt::Dynamic, Crt::Mingw].into_iter().find(|crt| crt.found())
+    }
+}
+
+/// Returns the symbol an external function of the C runtime is declared as
+///
+/// # Arguments
+///
+/// * `crt` - The runtime the program is linked against, if it was chosen
+/// * `function` - The name of the function
+fn extern_name(crt: Option<Crt>, function: &str) -> String {
+    match crt {
+        Some(crt) if crt.imports(function) => format!("__imp_{}", function),
+        _ => function.to_owned(),
+    }
+}
+
+/// Returns whether a name can be declared as a symbol by both NASM and the GNU assembler
+///
+/// # Arguments
+///
+/// * `name` - The name
+fn is_symbol(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The formats that errors and warnings are printed in
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum ErrorFormat {
+    /// Lines like `error: message!` for humans
+    #[default]
+    Human,
+    /// One JSON object per line for editors and other tools
+    Json,
+}
+
+/// How bad a diagnostic is
+#[derive(Clone, Copy, PartialEq)]
+enum Severity {
+    /// Processing the file or the arguments failed
+    Error,
+    /// Processing goes on, unless warnings are errors
+    Warning,
+}
+
+impl Severity {
+    /// The name that prefixes human diagnostics and is the severity of JSON ones
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// An error or warning about the arguments, a file or a program
+struct Diagnostic<'a> {
+    /// How bad it is
+    severity: Severity,
+    /// The file it is about, if it is about one
+    file: Option<&'a str>,
+    /// The position in the file it is about, if it is about one
+    position: Option<Position>,
+    /// The message, optionally followed by a line starting with `help` that suggests a fix
+    message: &'a str,
+}
+
+impl Diagnostic<'_> {
+    /// Renders the diagnostic in a format, without a trailing line break
+    ///
+    /// JSON diagnostics are objects with the fields `severity` (`"error"` or `"warning"`), `file`, `line`, `column`,
+    /// `message` and `suggestion`, of which all but the severity and the message may be `null`.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format to render it in
+    fn render(&self, format: ErrorFormat) -> String {
+        if format == ErrorFormat::Human {
+            return format!("{}: {}", self.severity.name(), self.message);
+        }
+
+        let (message, suggestion) = match self.message.split_once("\nhelp") {
+            Some((message, help)) => (message, Some(help.split_once(": ").map_or(help, |(_, suggestion)| suggestion))),
+            None => (self.message, None),
+        };
+        let nullable = |value: Option<String>| value.unwrap_or_else(|| "null".to_owned());
+        format!(
+            "{{\"severity\":{},\"file\":{},\"line\":{},\"column\":{},\"message\":{},\"suggestion\":{}}}",
+            json::string(self.severity.name()),
+            nullable(self.file.map(json::string)),
+            nullable(self.position.map(|position| position.line.to_string())),
+            nullable(self.position.map(|position| position.column.to_string())),
+            json::string(message),
+            nullable(suggestion.map(json::string)),
+        )
+    }
+}
+
+/// Prints the errors and warnings about the arguments and the given files and programs in the chosen format and counts
+/// the warnings, so they can fail the build afterwards
+#[derive(Default)]
+struct Diagnostics {
+    /// The format they are printed in
+    format: ErrorFormat,
+    /// Whether any warning fails the build
+    as_errors: bool,
+    /// The amount of warnings printed so far
+    count: Cell<usize>,
+}
+
+impl Diagnostics {
+    /// Prints a diagnostic to stderr
+    ///
+    /// # Arguments
+    ///
+    /// * `diagnostic` - The diagnostic
+    fn emit(&self, diagnostic: &Diagnostic) {
+        eprintln!("{}", diagnostic.render(self.format));
+    }
+
+    /// Prints an error
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file it is about, if it is about one
+    /// * `message` - The error without the `error: ` prefix
+    fn error(&self, file: Option<&str>, message: &str) {
+        self.emit(&Diagnostic { severity: Severity::Error, file, position: None, message });
+    }
+
+    /// Prints an error about a position in a file
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file it is about
+    /// * `position` - The position in the file
+    /// * `message` - The error without the `error: ` prefix
+    fn error_at(&self, file: &str, position: Position, message: &str) {
+        self.emit(&Diagnostic { severity: Severity::Error, file: Some(file), position: Some(position), message });
+    }
+
+    /// Prints a warning
+    /// 
+    /// # Arguments
+    /// 
+    /// * `file` - The file it is about, if it is about one
+    /// * `message` - The warning without the `warning: ` prefix
+    fn warn(&self, file: Option<&str>, message: &str) {
+        self.emit(&Diagnostic { severity: Severity::Warning, file, position: None, message });
+        self.count.set(self.count.get() + 1);
+    }
+
+    /// Returns whether warnings are errors and any was printed since the last call, so nothing may be written
+    fn fatal(&self) -> bool {
+        let count = self.count.take();
+        if self.as_errors && count > 0 {
+            let s = if count == 1 { "" } else { "s" };
+            self.error(None, &format!("{} warning{} treated as error{} because of '--warnings-as-errors'!", count, s, s));
+        }
+        self.as_errors && count > 0
+    }
+}
+
+/// Compiles or runs the input files with the parsed options and returns whether all of them succeeded
+///
+/// # Arguments
+///
+/// * `options` - The parsed options, whose C runtime is detected here if it was not chosen
+fn compile_files(mut options: cli::Options) -> bool {
+    let diagnostics = Diagnostics { format: options.error_format, as_errors: options.warnings_as_errors, ..Diagnostics::default() };
+
+    // Duplicate files only fail the build before anything is written
+    let (files, batch) = expand_inputs(&options.inputs, options.recursive, &diagnostics);
+    if diagnostics.fatal() {
+        return false;
+    }
+
+    if options.out_file.is_some() && files.len() > 1 {
+        diagnostics.error(None, "'-o' only takes a single file!");
+        return false;
+    }
+
+    // Build scripts and executables link with the toolchain that is installed
+    if options.crt.is_none() && (options.build_script.is_some() || options.builds_executable()) {
+        options.crt = Crt::detect();
+        if let Some(detected) = options.crt {
+            println!("info: linking against the {} C runtime of the detected toolchain", detected.name());
+        }
+    }
+
+    if files.len() > 1 && (options.run || options.verify_file.is_some()) {
+        diagnostics.error(None, "'--run' and '--verify' only take a single file!");
+        return false;
+    }
+
+    if let Some(dir) = options.out_dir {
+        if create_dir_all(dir).is_err() {
+            diagnostics.error(None, &format!("could not create the output directory '{}'!", dir));
+            return false;
+        }
+    }
+
+    // The initial contents of the tape
+    let init = match options.tape_init.map(read) {
+        Some(Ok(bytes)) => bytes,
+        Some(Err(_)) => {
+            diagnostics.error(None, &format!("could not find or open '{}'!", options.tape_init.unwrap()));
+            return false;
+        },
+        None => Vec::new(),
+    };
+
+    // The runtimes already written, one per output directory
+    let mut runtimes: Vec<PathBuf> = Vec::new();
+
+    // A failed file does not stop the others
+    let mut failed = 0;
+    for filename in &files {
+        if !compile_file(filename, &options, &init, &mut runtimes, &diagnostics) {
+            failed += 1;
+            if batch {
+                diagnostics.error(None, &format!("could not compile '{}'!", filename));
+            }
+        }
+    }
+    if batch {
+        println!("info: compiled {}, failed {}", files.len() - failed, failed);
+    }
+    failed == 0
+}
+
+/// Compiles or runs a single file with the parsed options and returns whether it succeeded
+///
+/// # Arguments
+///
+/// * `filename` - The name of the file
+/// * `options` - The parsed options
+/// * `init` - The initial contents of the tape
+/// * `runtimes` - The runtimes already written, one per output directory
+/// * `diagnostics` - The reporter of errors and warnings
+fn compile_file(filename: &str, options: &cli::Options, init: &[u8], runtimes: &mut Vec<PathBuf>, diagnostics: &Diagnostics) -> bool {
+    // Create the output filename from the input file's name or `-o`, GAS sources conventionally end in `.s`
+    let out_name = match options.out_file {
+        Some(file) => Path::new(file).with_extension(options.asm_extension()).to_string_lossy().into_owned(),
+        None => output_path(filename, options.out_dir, options.asm_extension()),
+    };
+
+    // Prints how long a stage of compiling a file took, one stage per line so the times can be read by other tools
+    let print_time = |stage: &str, elapsed: Duration| {
+        if options.time_stages {
+            eprintln!("time: {:<28} {:>10.3} ms  {}", stage, elapsed.as_secs_f64() * 1000.0, filename);
+        }
+    };
+
+    // Builds the executable named by `-o` from the assembly of a file, if it names one, and returns whether it succeeded
+    let build = |asm_name: &str, asm_options: &AsmOptions| -> bool {
+        let Some(exe) = options.out_file.filter(|_| options.builds_executable()) else {
+            return true;
+        };
+        let obj = Path::new(exe).with_extension("obj").to_string_lossy().into_owned();
+        let start = Instant::now();
+        let result = build_executable(asm_name, &obj, exe, options.syntax, options.crt, asm_options.heap_tape());
+        print_time("build", start.elapsed());
+
+        // The intermediate files are removed even if a command failed, `--keep-asm` keeps them to look into
+        if !options.keep_asm {
+            let _ = remove_file(asm_name);
+            let _ = remove_file(&obj);
+        }
+
+        match result {
+            Ok(()) => {
+                println!("info: successfully built {}", exe);
+                true
+            },
+            Err(err) => {
+                diagnostics.error(Some(filename), &format!("could not build {}: {}", exe, err.trim_end()));
+                false
+            },
+        }
+    };
+
+    // Writes the files that go along with the assembly and returns whether they were written
+    let mut write_companions = |out_name: &str, asm_options: &AsmOptions| -> bool {
+        // Programs written to the same directory share its runtime
+        if let Some(file) = options.runtime_file {
+            let runtime_name = Path::new(out_name).with_file_name(file);
+            if !runtimes.contains(&runtime_name) {
+                if write_runtime(&runtime_name, asm_options).is_ok() {
+                    println!("info: successfully wrote to {}", runtime_name.display());
+                    runtimes.push(runtime_name);
+                } else {
+                    diagnostics.error(Some(filename), &format!("could not write the runtime to {}!", runtime_name.display()));
+                    return false;
+                }
+            }
+        }
+
+        if let Some(flavor) = options.build_script {
+            let script_name = output_path(filename, options.out_dir, flavor.extension());
+            if write_build_script(&script_name, flavor, filename, out_name, options.syntax, options.crt, asm_options.heap_tape()).is_ok() {
+                println!("info: successfully wrote to {}", &script_name);
+            } else {
+                diagnostics.error(Some(filename), &format!("could not write build script to {}!", &script_name));
+                return false;
+            }
+        }
+
+        true
+    };
+
+    // Programs with errors or in another encoding than UTF-8 are parsed as a whole, which reports or decodes them
+    if options.stream {
+        let asm_options = AsmOptions {
+            syntax: options.syntax,
+            split_functions: options.split_functions,
+            fragment: options.emit_fragment,
+            library: options.library,
+            tape_guard: options.tape_guard,
+            unbuffered_output: options.unbuffered_output,
+            binary_stdio: options.binary_stdio,
+            argv_tape: options.argv_tape.then_some(options.argv_tape_at),
+            fuel: options.fuel,
+            const_input: options.const_input.as_deref(),
+            const_input_then_stdin: options.const_input_then_stdin,
+            coverage: None,
+            coverage_output: &options.coverage_output,
+            tape_size: DATA_SIZE.into(),
+            tape_section: options.tape_section,
+            tape_init: init,
+            label_prefix: &options.label_prefix,
+            label_all: options.label_all,
+            annotate_offsets: options.annotate_offsets,
+            offset_cells: false,
+            dp_register: false,
+            runtime_file: options.runtime_file,
+            template: None,
+            crt: options.crt,
+        };
+        // The file is not held in memory, but it is read twice
+        if let Some(max) = options.max_src_bytes.filter(|max| metadata(filename).is_ok_and(|file| file.len() > *max)) {
+            diagnostics.error(Some(filename), &encoding::source_too_large(max).to_string());
+            return false;
+        }
+        let start = Instant::now();
+        match stream::write_asm(filename, &out_name, options.extensions, options.max_depth, &asm_options) {
+            Ok(true) => {
+                print_time("stream", start.elapsed());
+                println!("info: successfully wrote to {}", &out_name);
+                return write_companions(&out_name, &asm_options) && build(&out_name, &asm_options);
+            },
+            Ok(false) => {},
+            Err(err) => {
+                diagnostics.error(Some(filename), &err.to_string());
+                return false;
+            },
+        }
+    }
+
+    let start = Instant::now();
+    let source = match encoding::read_source_capped(filename, options.max_src_bytes) {
+        Ok(source) => source,
+        Err(err) if err.kind() == ErrorKind::FileTooLarge => {
+            diagnostics.error(Some(filename), &err.to_string());
+            return false;
+        },
+        Err(_) => {
+            // Return when it could not read the file
+            diagnostics.error(Some(filename), &format!("could not find or open '{}'!", filename));
+            return false;
+        },
+    };
+
+    print_time("read", start.elapsed());
+
+    // Splice included files into the source before parsing it
+    let start = Instant::now();
+    let source = if options.enable_includes {
+        match include::expand(filename, &source) {
+            Ok(source) => source,
+            Err(err) => {
+                diagnostics.error(Some(filename), &err.to_string());
+                return false;
+            },
+        }
+    } else {
+        source
+    };
+    if options.enable_includes {
+        print_time("include", start.elapsed());
+    }
+
+    // Record the tokens only when they are written
+    let mut tokens: Vec<Token> = Vec::new();
+    let start = Instant::now();
+    let parsed = if options.from_ir {
+        ir::parse(&source)
+    } else {
+        try_parse_recording(&source, options.extensions, options.emit_tokens.then_some(&mut tokens), options.max_depth)
+    };
+    print_time("parse", start.elapsed());
+
+    // The tokens are written even if parsing failed, as they help to find out why
+    if options.emit_tokens {
+        let tok_name = output_path(filename, options.out_dir, "tok");
+
+        // Try to write the tokens and log depending on its result
+        let result = File::create(&tok_name).and_then(|mut f| write_tokens(&mut f, &tokens));
+        if let Ok(()) = result {
+            println!("info: successfully wrote to {}", &tok_name);
+        } else {
+            diagnostics.error(Some(filename), &format!("could not write tokens to {}!", &tok_name));
+        }
+    }
+
+    // Fail when the program could not be parsed, reporting all errors at once
+    let program = match parsed {
+        Ok(program) => program,
+        Err(errors) => {
+            for (position, err) in errors.iter().take(options.max_errors) {
+                diagnostics.error_at(filename, *position, &err.to_string());
+            }
+            if errors.len() > options.max_errors {
+                diagnostics.error(Some(filename), &format!("too many errors, stopping after {} of {}!", options.max_errors, errors.len()));
+            }
+            return false;
+        },
+    };
+
+    if options.check_passes {
+        let tape_size = (DATA_SIZE as usize).max(init.len());
+        return match check::check_passes(program, &options.passes, init, tape_size, options.const_input.as_deref().unwrap_or_default()) {
+            Ok(count) => {
+                println!("info: {} kept its loops and its I/O through {} passes", filename, count);
+                true
+            },
+            Err(err) => {
+                diagnostics.error(Some(filename), &format!("{}!", err));
+                false
+            },
+        };
+    }
+
+    let mut report = optimizer::Report::default();
+    let start = Instant::now();
+    let parsed_observable = observable(&program.instructions);
+    let program = match options.passes.run(program, &mut report, &options.dumps) {
+        Ok(program) => program,
+        Err(err) => {
+            diagnostics.error(Some(filename), &err.to_string());
+            return false;
+        },
+    };
+    for (pass, elapsed) in report.timings() {
+        print_time(&format!("optimize/{}", pass), elapsed);
+    }
+    print_time("optimize", start.elapsed());
+
+    if options.emit_ir {
+        let ir_name = output_path(filename, options.out_dir, "ir");
+
+        // Try to write the IR and log depending on its result
+        let result = File::create(&ir_name).and_then(|mut f| ir::write(&mut f, &program));
+        if let Ok(()) = result {
+            println!("info: successfully wrote to {}", &ir_name);
+        } else {
+            diagnostics.error(Some(filename), &format!("could not write IR to {}!", &ir_name));
+        }
+    }
+
+    if options.opt_report {
+        // Try to write the report and only log on failure
+        let result = match options.opt_report_file {
+            Some(file) => File::create(file).and_then(|mut f| write_report(&report, options.opt_report_json, &mut f)),
+            None => write_report(&report, options.opt_report_json, &mut stderr()),
+        };
+        if let Err(err) = result {
+            diagnostics.error(Some(filename), &format!("could not write optimization report: {}", err));
+        }
+    }
+
+    // A program without I/O usually means the source was misunderstood, unless its tape is looked at afterwards
+    let tape_observed = options.emit_fragment || options.run && (options.dump_memory || options.dump_state_file.is_some());
+    if !tape_observed && !observable(&program.instructions) {
+        match parsed_observable {
+            true => diagnostics.warn(Some(filename), &format!("the optimizations removed every read and write of {}, so it does nothing observable ('--opt-report' lists what they removed)", filename)),
+            false => diagnostics.warn(Some(filename), &format!("{} neither reads nor writes anything, so it does nothing observable", filename)),
+        }
+    }
+
+    // The tape only has to hold the cells the program can reach if they are known
+    let tape_size = if options.auto_tape { auto_tape_size(&program, filename, diagnostics) } else { DATA_SIZE.into() };
+
+    // Nothing is run or written once a warning failed the build
+    if diagnostics.fatal() {
+        return false;
+    }
+
+    if tape_size > MAX_TAPE_CELLS {
+        diagnostics.error(Some(filename), &format!("the tape of {} needs {} cells, more than the {} that can be addressed!", filename, tape_size, MAX_TAPE_CELLS));
+        return false;
+    }
+
+    // The blocks whose entries are counted, which the interpreter's loop profile is derived from as well
+    let mut blocks = (options.instrument_coverage || options.run && options.count_loops).then(|| coverage::blocks(&program));
+
+    if options.run {
+        let mut init = init.to_vec();
+
+        // Copy the argument to the tape like the generated program does with `argv[1]`
+        if let Some(argument) = options.argument {
+            if i64::from(options.argv_tape_at) >= tape_size {
+                diagnostics.error(None, &format!("'--argv-tape-at' has to be a cell between 0 and {}!", tape_size - 1));
+                return false;
+            }
+            copy_arg(&mut init, argument.as_bytes(), options.argv_tape_at as usize, tape_size as usize);
+        }
+
+        // Stop at the next instruction on Ctrl-C instead of losing the program's state
+        interrupt::install();
+
+        // Key presses are only read one at a time from a terminal, pipes stay buffered and are read as they are
+        let reads_stdin = options.const_input.is_none() || options.const_input_then_stdin;
+        let from_terminal = options.stdin_mode.is_terminal();
+        let raw = if options.raw_input && reads_stdin && from_terminal { terminal::RawInput::enable(options.echo) } else { None };
+        let stdin_input: Box<dyn Read> = match raw {
+            Some(raw) => Box::new(raw),
+            None => Box::new(stdin().lock()),
+        };
+        let stdin_input: Box<dyn Read> = if from_terminal { Box::new(terminal::LineEndings::new(stdin_input)) } else { stdin_input };
+
+        // The constant input is read first, like in the generated program
+        let input: Box<dyn Read> = match &options.const_input {
+            Some(bytes) if options.const_input_then_stdin => Box::new(bytes.as_slice().chain(stdin_input)),
+            Some(bytes) => Box::new(bytes.as_slice()),
+            None => stdin_input,
+        };
+
+        // Interpret the program and only log on failure, the terminal is restored once the input is dropped
+        // The initial contents have to fit on an automatically sized tape as well
+        let tape_size = if options.auto_tape { (tape_size as usize).max(init.len()) } else { tape_size as usize };
+
+        let settings = interpreter::Options {
+            tape_guard: options.tape_guard,
+            dump_tape: options.dump_tape_on_interrupt,
+            max_steps: options.max_steps,
+            max_output: options.max_output,
+            max_input: options.max_input,
+            max_memory: options.max_memory,
+            tape_size,
+            record_output: options.dump_state_file.is_some(),
+            warn_uninit: options.warn_uninit,
+            numeric_io: options.numeric_io,
+            error_format: diagnostics.format,
+        };
+        // The tape is dumped after the coverage is written, so the dumps are kept until then as bytes, which do not
+        // depend on the type of the cells
+        let result = if options.big_cells {
+            interpreter::run_big(&program, &init, &settings, blocks.as_deref_mut(), input, stdout().lock())
+                .map(|memory| memory_dumps(&memory, options.dump_memory, options.dump_state_file.is_some()))
+        } else {
+            interpreter::run(&program, &init, &settings, blocks.as_deref_mut(), input, stdout().lock())
+                .map(|memory| memory_dumps(&memory, options.dump_memory, options.dump_state_file.is_some()))
+        };
+
+        // The coverage is written however the program ended, like the generated program does at exit
+        if let Some(blocks) = blocks.as_deref().filter(|_| options.instrument_coverage) {
+            let written = File::create(&options.coverage_output).and_then(|mut f| coverage::write_json(&mut f, blocks));
+            if written.is_err() {
+                diagnostics.error(Some(filename), &format!("could not write coverage to {}!", &options.coverage_output));
+            }
+        }
+
+        // So is the loop profile, which shows the hot loops of a program that was stopped with Ctrl-C as well
+        if let Some(blocks) = blocks.as_deref().filter(|_| options.count_loops) {
+            let _ = coverage::write_loop_profile(&mut stderr().lock(), &program, blocks);
+        }
+
+        if let Ok((memory, state)) = &result {
+            if let Some(memory) = memory {
+                let written = match options.dump_memory_file {
+                    Some(file) => File::create(file).and_then(|mut f| f.write_all(memory)),
+                    None => stderr().lock().write_all(memory),
+                };
+                if written.is_err() {
+                    diagnostics.error(Some(filename), &format!("could not write the memory dump to {}!", options.dump_memory_file.unwrap_or("stderr")));
+                }
+            }
+            if let Some((file, state)) = options.dump_state_file.zip(state.as_ref()) {
+                if File::create(file).and_then(|mut f| f.write_all(state)).is_err() {
+                    diagnostics.error(Some(filename), &format!("could not write the state to {}!", file));
+                }
+            }
+        }
+
+        let failed = result.is_err();
+        if let Err(err) = result {
+            // Report where a hanging program was stopped with Ctrl-C
+            if err.kind() == ErrorKind::Interrupted {
+                diagnostics.error(Some(filename), &err.to_string());
+                exit(INTERRUPTED_EXIT_CODE);
+            }
+            // Nobody is left to read an error when the output pipe was closed
+            if err.kind() == ErrorKind::BrokenPipe {
+                exit(WRITE_FAILED_EXIT_CODE);
+            }
+            // Sandboxes tell a program that hit a limit apart from one that failed on its own
+            if err.kind() == ErrorKind::QuotaExceeded {
+                diagnostics.error(Some(filename), &err.to_string());
+                exit(LIMIT_EXCEEDED_EXIT_CODE);
+            }
+            diagnostics.error(Some(filename), &err.to_string());
+        }
+
+        // Keep the console of a double-clicked interpreter open, but never block scripts and pipes
+        if options.pause_on_exit && stdin().is_terminal() && stdout().is_terminal() {
+            pause();
+        }
+        return !failed;
+    }
+
+    // The initial contents have to fit on an automatically sized tape as well
+    let tape_size = if options.auto_tape { tape_size.max(i64::try_from(init.len()).unwrap_or(i64::MAX)) } else { tape_size };
+
+    let asm_options = AsmOptions {
+        syntax: options.syntax,
+        split_functions: options.split_functions,
+        fragment: options.emit_fragment,
+        library: options.library,
+        tape_guard: options.tape_guard,
+        unbuffered_output: options.unbuffered_output,
+        binary_stdio: options.binary_stdio,
+        argv_tape: options.argv_tape.then_some(options.argv_tape_at),
+        fuel: options.fuel,
+        const_input: options.const_input.as_deref(),
+        const_input_then_stdin: options.const_input_then_stdin,
+        coverage: blocks.as_deref(),
+        coverage_output: &options.coverage_output,
+        tape_size,
+        tape_section: options.tape_section,
+        tape_init: init,
+        label_prefix: &options.label_prefix,
+        label_all: options.label_all,
+        annotate_offsets: options.annotate_offsets,
+        offset_cells: options.opt_level >= 2,
+        // Fragments and templates leave the prologue that would save the register to the including file
+        dp_register: options.opt_level >= 2 && !options.emit_fragment && options.template.is_none(),
+        runtime_file: options.runtime_file,
+        template: options.template.as_deref(),
+        crt: options.crt,
+    };
+
+    if options.auto_tape && !options.emit_fragment {
+        println!("info: the tape of {} has {} cells", filename, tape_size);
+    }
+
+    if let Some(existing) = options.verify_file {
+        verify(existing, &source, &program.instructions, &asm_options);
+        return true;
+    }
+
+    // Try to write the assembly and log depending on its result
+    let start = Instant::now();
+    let ranges = match write_asm(&out_name, &source, &program.instructions, &asm_options) {
+        Ok(ranges) => ranges,
+        Err(err) => {
+            diagnostics.error(Some(filename), &err.to_string());
+            return false;
+        },
+    };
+    print_time("generate", start.elapsed());
+    println!("info: successfully wrote to {}", &out_name);
+
+    if !write_companions(&out_name, &asm_options) {
+        return false;
+    }
+
+    if options.emit_listing {
+        let lst_name = output_path(filename, options.out_dir, "lst");
+
+        // Try to write the listing and log depending on its result
+        let result = listing::write_listing(&lst_name, filename, &source, &program, &ranges, &asm_options);
+        if let Ok(()) = result {
+            println!("info: successfully wrote to {}", &lst_name);
+        } else if let Err(err) = result {
+            diagnostics.error(Some(filename), &err.to_string());
+        }
+    }
+
+    build(&out_name, &asm_options)
+}
+
+/// Returns the files named by the input arguments and whether more than a single file was asked for
+///
+/// Directories contribute their `.bf` and `.b` files, those of their subdirectories too if `recursive`. On Windows,
+/// whose shell does not expand wildcards, `*` and `?` in the file name of an argument are expanded here. Each file is
+/// only returned once, even if it was given multiple times.
+///
+/// # Arguments
+///
+/// * `inputs` - The arguments that are not flags
+/// * `recursive` - Whether subdirectories of directories are searched as well
+/// * `diagnostics` - The reporter of duplicate files and of directories without programs
+fn expand_inputs(inputs: &[&String], recursive: bool, diagnostics: &Diagnostics) -> (Vec<String>, bool) {
+    let mut files: Vec<String> = Vec::new();
+    let mut batch = inputs.len() > 1;
+
+    // The canonical paths of the files, used to detect duplicates
+    let mut seen: Vec<PathBuf> = Vec::new();
+
+    for input in inputs {
+        let found = if Path::new(input).is_dir() {
+            batch = true;
+            let mut found = Vec::new();
+            find_programs(Path::new(input), recursive, &mut found);
+            if found.is_empty() {
+                diagnostics.warn(Some(input), &format!("'{}' contains no .bf or .b files", input));
+            }
+            found
+        } else if cfg!(windows) && input.contains(['*', '?']) {
+            batch = true;
+            let found = expand_wildcards(input);
+            if found.is_empty() {
+                diagnostics.warn(None, &format!("no files match '{}'", input));
+            }
+            found
+        } else {
+            vec![input.to_string()]
+        };
+
+        // Only process each file once even if it was given multiple times
+        for file in found {
+            let path = canonicalize(&file).unwrap_or_else(|_| PathBuf::from(&file));
+            if seen.contains(&path) {
+                diagnostics.warn(Some(&file), &format!("'{}' was given multiple times, processing it once", file));
+            } else {
+                seen.push(path);
+                files.push(file);
+            }
+        }
+    }
+
+    (files, batch)
+}
+
+/// Collects the `.bf` and `.b` files in a directory in the order of their names, skipping hidden subdirectories
+///
+/// # Arguments
+///
+/// * `dir` - The directory to search
+/// * `recursive` - Whether subdirectories are searched as well
+/// * `found` - The list to append the found files to
+fn find_programs(dir: &Path, recursive: bool, found: &mut Vec<String>) {
+    let Ok(entries) = read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+    paths.sort();
+
+    for path in paths {
+        let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if path.is_dir() {
+            if recursive && !hidden {
+                find_programs(&path, recursive, found);
+            }
+        } else if path.extension().is_some_and(|ext| ext == "bf" || ext == "b") {
+            found.push(path.to_string_lossy().into_owned());
+        }
+    }
+}
+
+/// Returns the files whose names match the wildcards in the last component of a path, in the order of their names
+///
+/// # Arguments
+///
+/// * `pattern` - The path whose file name may contain `*` and `?`
+fn expand_wildcards(pattern: &str) -> Vec<String> {
+    let (dir, name) = match pattern.rfind(['/', '\\']) {
+        Some(i) => (&pattern[..=i], &pattern[i + 1..]),
+        None => ("", pattern),
+    };
+
+    let Ok(entries) = read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+    let mut found: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|file| wildcard_matches(name.as_bytes(), file.as_bytes()))
+        .map(|file| format!("{}{}", dir, file))
+        .collect();
+    found.sort();
+    found
+}
+
+/// Returns whether a file name matches a pattern where `*` matches any run and `?` any single character, ignoring
+/// the case of ascii letters like Windows does
+///
+/// # Arguments
+///
+/// * `pattern` - The pattern
+/// * `name` - The file name
+fn wildcard_matches(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some((b'*', rest)), _) => wildcard_matches(rest, name) || (!name.is_empty() && wildcard_matches(pattern, &name[1..])),
+        (Some((b'?', rest)), Some((_, name_rest))) => wildcard_matches(rest, name_rest),
+        (Some((p, rest)), Some((n, name_rest))) => p.eq_ignore_ascii_case(n) && wildcard_matches(rest, name_rest),
+        (Some(_), None) => false,
+    }
+}
+
+/// Returns the path of a file written for an input file, which has its extension replaced and is placed in the output
+/// directory if one was given
+///
+/// The files written for a program from stdin are named `stdin`.
+///
+/// # Arguments
+///
+/// * `filename` - The path of the input file
+/// * `out_dir` - The directory the output files are written to instead of next to their input files
+/// * `extension` - The extension of the written file
+fn output_path(filename: &str, out_dir: Option<&str>, extension: &str) -> String {
+    let filename = if filename == STDIN_SOURCE { "stdin" } else { filename };
+    let path = Path::new(filename).with_extension(extension);
+    let path = match (out_dir, path.file_name()) {
+        (Some(dir), Some(name)) => Path::new(dir).join(name),
+        _ => path,
+    };
+    path.to_string_lossy().into_owned()
+}
+
+/// Writes a script next to the assembly that assembles and links it with the commands of its syntax
+///
+/// The script refers to the files by their names relative to its own directory.
+///
+/// # Arguments
+///
+/// * `filename` - The name of the script to create
+/// * `flavor` - The kind of script
+/// * `source_name` - The name of the program's source file
+/// * `asm_name` - The name of the generated assembly
+/// * `syntax` - The syntax of the generated assembly
+/// * `crt` - The C runtime the program is linked against, if it was chosen or detected
+/// * `large_address_aware` - Whether the executable may be loaded above 2 GB, as its tape is allocated at startup
+fn write_build_script(filename: &str, flavor: script::Flavor, source_name: &str, asm_name: &str, syntax: Syntax, crt: Option<Crt>, large_address_aware: bool) -> Result<()> {
+    let asm = Path::new(asm_name).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let obj = output_path(&asm, None, "obj");
+    let exe = output_path(&asm, None, "exe");
+
+    let steps = [
+        script::Step { output: obj.clone(), input: asm.clone(), command: syntax.assemble_command(&asm, &obj) },
+        script::Step { output: exe.clone(), input: obj.clone(), command: link_command(&obj, &exe, crt, large_address_aware) },
+    ];
+
+    let mut f = File::create(filename)?;
+    script::write_script(&mut f, flavor, source_name, crt.map(Crt::description), &steps)?;
+
+    // Shell scripts can be run directly
+    #[cfg(unix)]
+    if flavor == script::Flavor::Shell {
+        use std::os::unix::fs::PermissionsExt;
+        f.set_permissions(std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(())
+}
+
+/// Returns whether a program does anything that can be observed from outside, reading or writing a byte, dumping the
+/// tape or stopping in a debugger
+///
+/// # Arguments
+///
+/// * `instructions` - A slice of instructions that contains the program
+fn observable(instructions: &[Instruction]) -> bool {
+    instructions.iter().any(|instr| matches!(instr, Instruction::Write | Instruction::Read | Instruction::Dump | Instruction::Break))
+}
+
+/// Returns the amount of cells a program can reach, or the default size with a warning if that depends on its data
+/// 
+/// # Arguments
+/// 
+/// * `program` - The program after optimization
+/// * `filename` - The name of the program's file, which is shown in the warning
+/// * `diagnostics` - The reporter of the warning
+fn auto_tape_size(program: &Program, filename: &str, diagnostics: &Diagnostics) -> i64 {
+    match optimizer::pointer_reach(&program.instructions) {
+        // Moving below cell 0 is an error anyway, so only the highest cell matters
+        Some((_, high)) => high.saturating_add(1),
+        None => {
+            diagnostics.warn(Some(filename), &format!("the cells {} can reach depend on its data, using the default tape of {} cells", filename, DATA_SIZE));
+            DATA_SIZE.into()
+        },
+    }
+}
+
+/// Returns the command and its arguments that link an object file with the C runtime into an executable
+/// 
+/// A tape in the image is addressed with 32 bit displacements, so the executable has to be loaded below 2 GB, while one
+/// allocated at startup is addressed from `r12` and needs the whole address space instead.
+/// 
+/// # Arguments
+/// 
+/// * `obj` - The path of the object file
+/// * `exe` - The path of the executable
+/// * `crt` - The C runtime to link against, if it was chosen
+/// * `large_address_aware` - Whether the executable may be loaded above 2 GB, as its tape is allocated at startup
+fn link_command(obj: &str, exe: &str, crt: Option<Crt>, large_address_aware: bool) -> Vec<String> {
+    let libraries: &[&str] = match crt {
+        None => &["msvcrt.lib"],
+        Some(Crt::Static) => &["libcmt.lib", "libucrt.lib", "libvcruntime.lib", "legacy_stdio_definitions.lib"],
+        Some(Crt::Dynamic) => &["msvcrt.lib", "ucrt.lib", "vcruntime.lib", "legacy_stdio_definitions.lib"],
+        Some(Crt::Mingw) if large_address_aware => return ["gcc", obj, "-o", exe].map(str::to_owned).to_vec(),
+        Some(Crt::Mingw) => {
+            return ["gcc", obj, "-o", exe, "-Wl,--disable-large-address-aware,--image-base,0x400000"].map(str::to_owned).to_vec();
+        },
+    };
+
+    let mut command = vec!["link".to_owned(), obj.to_owned()];
+    command.extend(libraries.iter().map(|library| library.to_string()));
+    if !large_address_aware {
+        command.push("/LARGEADDRESSAWARE:NO".to_owned());
+    }
+    command.push(format!("/OUT:{}", exe));
+    command
+}
+
+/// Assembles and links a generated file into an executable with the commands of its syntax and C runtime, failing with
+/// the output of the first command that did not succeed
+///
+/// # Arguments
+///
+/// * `asm` - The path of the generated file
+/// * `obj` - The path of the object file
+/// * `exe` - The path of the executable
+/// * `syntax` - The syntax of the generated file
+/// * `crt` - The C runtime to link against, if it was chosen or detected
+/// * `large_address_aware` - Whether the executable may be loaded above 2 GB, as its tape is allocated at startup
+fn build_executable(asm: &str, obj: &str, exe: &str, syntax: Syntax, crt: Option<Crt>, large_address_aware: bool) -> std::result::Result<(), String> {
+    run_tool(&mut tool_command(&syntax.assemble_command(asm, obj)))?;
+    run_tool(&mut tool_command(&link_command(obj, exe, crt, large_address_aware)))?;
+    Ok(())
+}
+
+/// Builds a toolchain command from its name and arguments
+///
+/// # Arguments
+///
+/// * `args` - The name of the command followed by its arguments
+fn tool_command(args: &[String]) -> Command {
+    let mut command = Command::new(&args[0]);
+    command.args(&args[1..]);
+    command
+}
+
+/// Runs a toolchain command or a built executable and returns its output, failing with it if it does not succeed
+///
+/// # Arguments
+///
+/// * `command` - The command to run
+fn run_tool(command: &mut Command) -> std::result::Result<Vec<u8>, String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command.output().map_err(|err| format!("could not run {}: {}", program, err))?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(format!("{} failed: {}", program, String::from_utf8_lossy(&[output.stdout, output.stderr].concat())))
+    }
+}
+
+/// Prints the calling convention, the I/O functions and the external symbols the assembly of a syntax relies on and
+/// the commands building an executable from it
+/// 
+/// The symbols and commands are the ones the code generator and the self test use. Returns whether the target is known.
+/// 
+/// # Arguments
+/// 
+/// * `args` - The arguments after `explain-target`, the name of the syntax
+fn explain_target(args: &[String]) -> bool {
+    let names: Vec<&str> = Syntax::ALL.iter().map(|syntax| syntax.name()).collect();
+    let [name] = args else {
+        eprintln!("error: expected 'bfasm explain-target {}'!", names.join("|"));
+        return false;
+    };
+    let Some(syntax) = Syntax::ALL.into_iter().find(|syntax| syntax.name() == name) else {
+        eprintln!("error: unknown target '{}', expected one of {}!", name, names.join(", "));
+        return false;
+    };
+
+    let base = AsmOptions { syntax, ..AsmOptions::default() };
+    let symbols = Symbols::new(&base);
+    let required = externs(&base, false);
+
+    // The flags that make the program call further functions, and whether the program contains `$`
+    let variants = [
+        ("--unbuffered-output", AsmOptions { unbuffered_output: true, ..base }, false),
+        ("--binary-stdio", AsmOptions { binary_stdio: true, ..base }, false),
+        ("--argv-tape", AsmOptions { argv_tape: Some(0), ..base }, false),
+        ("--tape-guard", AsmOptions { tape_guard: TapeGuard::AtExit, ..base }, false),
+        ("--fuel N", AsmOptions { fuel: Some(1), ..base }, false),
+        ("--extensions dump", base, true),
+        ("--instrument coverage", AsmOptions { coverage: Some(&[]), ..base }, false),
+        ("--crt dynamic", AsmOptions { crt: Some(Crt::Dynamic), ..base }, false),
+    ];
+
+    let asm = format!("FILE.{}", syntax.extension());
+    println!("target:             {}", syntax.name());
+    println!("output:             {}", asm);
+    println!("entry point:        main, called by the C runtime");
+    println!("calling convention: Microsoft x64, arguments in rcx, rdx, r8 and r9 with 32 bytes of shadow space and the stack aligned to 16 bytes at calls");
+    println!("i/o:                '.' calls {} with the cell in ecx, ',' calls {} returning the character in eax", symbols.putchar, symbols.getchar);
+    println!("                    with --unbuffered-output '.' calls {} with 1, the cell's address and 1", symbols.write);
+    println!("external symbols:   {}", required.join(", "));
+    for (flag, options, dumps) in &variants {
+        let added: Vec<String> = externs(options, *dumps).into_iter().filter(|name| !required.contains(name)).collect();
+        println!("  with {:<22} {}", format!("{}:", flag), added.join(", "));
+    }
+    println!("assemble:           {}", syntax.assemble_command(&asm, "FILE.obj").join(" "));
+    println!("link:               {}", link_command("FILE.obj", "FILE.exe", None, false).join(" "));
+    for crt in Crt::ALL {
+        println!("  with {:<22} {}", format!("--crt {}:", crt.name()), link_command("FILE.obj", "FILE.exe", Some(crt), false).join(" "));
+    }
+
+    true
+}
+
+/// Writes the source of a program to stdout with the regions that a coverage file records as never executed marked, or
+/// with `--loops` the loop profile of the coverage file
+/// 
+/// Returns whether the report could be written.
+/// 
+/// # Arguments
+/// 
+/// * `args` - The arguments after `cov-report`, optionally `--loops`, the program's source file and the coverage file
+fn cov_report(args: &[String]) -> bool {
+    // `--loops` prints the loop profile instead of the source
+    let loops = args.iter().any(|arg| arg == "--loops");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--loops").collect();
+    let [filename, coverage_file] = files[..] else {
+        eprintln!("error: expected 'bfasm cov-report [--loops] FILE COVERAGE'!");
+        return false;
+    };
+
+    let Ok(source) = encoding::read_source(filename) else {
+        eprintln!("error: could not find or open '{}'!", filename);
+        return false;
+    };
+    let Ok(text) = read_to_string(coverage_file) else {
+        eprintln!("error: could not find or open '{}'!", coverage_file);
+        return false;
+    };
+
+    let blocks = match coverage::read_json(&text) {
+        Ok(blocks) => blocks,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return false;
+        },
+    };
+
+    let written = if loops {
+        coverage::write_coverage_loops(&mut stdout().lock(), &source, &blocks)
+    } else {
+        coverage::write_report(&mut stdout().lock(), &source, &blocks)
+    };
+    match written {
+        Ok(()) => true,
+        Err(err) => {
+            exit_if_pipe_closed(&err);
+            eprintln!("error: could not write the report: {}", err);
+            false
+        },
+    }
+}
+
+/// Runs two programs through the interpreter on the same inputs and prints whether they wrote the same, or where they
+/// first differ
+///
+/// Returns whether both programs could be read and behaved the same on every input.
+///
+/// # Arguments
+///
+/// * `args` - The arguments after `compare`, the two source files, any `--input TEXT` and `--input-file FILE` and
+///   optionally `--max-steps N`
+fn compare_programs(args: &[String]) -> bool {
+    let mut files: Vec<&String> = Vec::new();
+    let mut inputs: Vec<Vec<u8>> = Vec::new();
+    let mut max_steps = check::CHECK_STEPS;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--input" => match iter.next() {
+                Some(text) => inputs.push(text.as_bytes().to_vec()),
+                None => {
+                    eprintln!("error: missing value for '--input'!");
+                    return false;
+                },
+            },
+            "--input-file" => match iter.next().map(|file| (file, read(file))) {
+                Some((_, Ok(bytes))) => inputs.push(bytes),
+                Some((file, Err(_))) => {
+                    eprintln!("error: could not find or open '{}'!", file);
+                    return false;
+                },
+                None => {
+                    eprintln!("error: missing value for '--input-file'!");
+                    return false;
+                },
+            },
+            "--max-steps" => match iter.next().map(|amount| amount.parse::<u64>()) {
+                Some(Ok(amount)) if amount > 0 => max_steps = amount,
+                Some(_) => {
+                    eprintln!("error: '--max-steps' has to be a positive amount of steps!");
+                    return false;
+                },
+                None => {
+                    eprintln!("error: missing value for '--max-steps'!");
+                    return false;
+                },
+            },
+            _ => files.push(arg),
+        }
+    }
+    let [first, second] = files[..] else {
+        eprintln!("error: expected 'bfasm compare [--input TEXT]... [--input-file FILE]... [--max-steps N] FILE FILE'!");
+        return false;
+    };
+
+    // Without any input both programs run on an empty one
+    if inputs.is_empty() {
+        inputs.push(Vec::new());
+    }
+
+    let mut programs: Vec<Program> = Vec::new();
+    for filename in [first, second] {
+        let Ok(source) = encoding::read_source(filename) else {
+            eprintln!("error: could not find or open '{}'!", filename);
+            return false;
+        };
+        match parse(&source, Extensions::default()) {
+            Ok(program) => programs.push(program),
+            Err(err) => {
+                eprintln!("error: {}: {}", filename, err);
+                return false;
+            },
+        }
+    }
+
+    match check::programs_equivalent(&programs[0], &programs[1], &inputs, max_steps) {
+        Ok(limited) => {
+            let s = if inputs.len() == 1 { "" } else { "s" };
+            println!("info: {} and {} behaved the same on {} input{}", first, second, inputs.len(), s);
+            if limited {
+                println!("info: some runs reached the limit of {} steps and were only compared until then", max_steps);
+            }
+            true
+        },
+        Err(divergence) => {
+            let input = String::from_utf8_lossy(&inputs[divergence.input]);
+            eprintln!("error: {} and {} differ on input {} ({:?}) at event {}: {} is {} and {} is {}!", first, second, divergence.input + 1, input, divergence.event, first, divergence.first, second, divergence.second);
+            false
+        },
+    }
+}
+
+/// Writes a self-contained HTML report of a program with its structure, the optimizer's findings and optionally a
+/// profile
+/// 
+/// Returns whether the report could be written.
+/// 
+/// # Arguments
+/// 
+/// * `args` - The arguments after `report`, the program's source file and optionally `--profile` with a coverage file
+///   and `-o` with the name of the report
+fn html_report(args: &[String]) -> bool {
+    let mut filename: Option<&String> = None;
+    let mut profile_file: Option<&String> = None;
+    let mut out_name: Option<&String> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let target = match arg.as_str() {
+            "--profile" => &mut profile_file,
+            "-o" => &mut out_name,
+            arg if arg.starts_with('-') => {
+                eprintln!("error: unknown argument '{}'!", arg);
+                return false;
+            },
+            _ => {
+                filename = Some(arg);
+                continue;
+            },
+        };
+        match iter.next() {
+            Some(value) => *target = Some(value),
+            None => {
+                eprintln!("error: missing value for '{}'!", arg);
+                return false;
+            },
+        }
+    }
+
+    let Some(filename) = filename else {
+        eprintln!("error: expected 'bfasm report [--profile COVERAGE] [-o FILE] FILE'!");
+        return false;
+    };
+    let Ok(source) = encoding::read_source(filename) else {
+        eprintln!("error: could not find or open '{}'!", filename);
+        return false;
+    };
+
+    let profile = match profile_file {
+        Some(profile_file) => {
+            let Ok(text) = read_to_string(profile_file) else {
+                eprintln!("error: could not find or open '{}'!", profile_file);
+                return false;
+            };
+            match coverage::read_json(&text) {
+                Ok(blocks) => Some(blocks),
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    return false;
+                },
+            }
+        },
+        None => None,
+    };
+
+    // The optimizer consumes the program, so the report's copy is parsed again
+    let parse = || try_parse_recording(&source, Extensions::default(), None, None);
+    let (program, optimized) = match (parse(), parse()) {
+        (Ok(program), Ok(optimized)) => (program, optimized),
+        (Err(errors), _) | (_, Err(errors)) => {
+            for (_, err) in errors {
+                eprintln!("error: {}", err);
+            }
+            return false;
+        },
+    };
+    let mut findings = optimizer::Report::default();
+    let optimized = match optimizer::PassManager::for_level(2, Cpu::Baseline).run(optimized, &mut findings, &optimizer::DumpSettings::default()) {
+        Ok(optimized) => optimized.instructions.len(),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return false;
+        },
+    };
+
+    let html = report::render(filename, &source, &program, &findings, optimized, profile.as_deref());
+    let out_name = out_name.map_or_else(|| Path::new(filename).with_extension("html"), PathBuf::from);
+    match write_atomically(&out_name, html.as_bytes()) {
+        Ok(()) => {
+            println!("info: successfully wrote to {}", out_name.display());
+            true
+        },
+        Err(err) => {
+            eprintln!("error: could not write to {}: {}", out_name.display(), err);
+            false
+        },
+    }
+}
+
+/// Exits with code 0 if a write to stdout failed because its reader closed the pipe, which is how a pipeline like
+/// `bfasm cov-report ... | head` normally ends
+///
+/// # Arguments
+///
+/// * `err` - The error of the write
+fn exit_if_pipe_closed(err: &Error) {
+    if err.kind() == ErrorKind::BrokenPipe {
+        exit(0);
+    }
+}
+
+/// Regenerates the assembly and compares it with an existing file, exiting with 1 and a diff when they differ
+/// 
+/// # Arguments
+/// 
+/// * `existing` - The name of the file that contains the existing assembly
+/// * `source` - The program's source code, whose hash is recorded in the header
+/// * `instructions` - A slice of instructions that contains the program
+/// * `options` - The settings of the generated assembly
+fn verify(existing: &str, source: &str, instructions: &[Instruction], options: &AsmOptions) {
+    let expected = match read_to_string(existing) {
+        Ok(expected) => expected,
+        Err(_) => {
+            eprintln!("error: could not find or open '{}'!", existing);
+            exit(2);
+        },
+    };
+
+    let actual = match generate_asm(source, instructions, options) {
+        Ok((actual, _)) => actual,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            exit(2);
+        },
+    };
+
+    let Some(line) = diff::first_difference(&expected, &actual) else {
+        println!("info: {} is up to date", existing);
+        return;
+    };
+
+    eprintln!("error: {} differs from the generated assembly starting at line {}!", existing, line);
+    // A reader that closed the pipe early has seen enough of the diff, but the assembly still differs
+    if let Err(err) = diff::write_unified(&mut stdout(), existing, "generated", &expected, &actual) {
+        if err.kind() != ErrorKind::BrokenPipe {
+            eprintln!("error: could not write diff: {}", err);
+        }
+    }
+    exit(1);
+}
+
+/// Writes an optimization report in the selected format
+/// 
+/// # Arguments
+/// 
+/// * `report` - The report to write
+/// * `json` - Whether to write JSON instead of text
+/// * `f` - The writer to write to
+fn write_report<W: Write>(report: &optimizer::Report, json: bool, f: &mut W) -> Result<()> {
+    if json {
+        report.write_json(f)
+    } else {
+        report.write_text(f)
+    }
+}
+
+/// Waits for a key press
+fn pause() {
+    #[cfg(windows)]
+    {
+        extern "C" {
+            fn _getch() -> i32;
+        }
+
+        println!();
+        println!("Press any key to exit...");
+
+        // SAFETY: `_getch` from the C runtime takes no arguments and only reads from the console
+        unsafe {
+            _getch();
+        }
+    }
+
+    #[cfg(not(windows))]
+    {
+        println!();
+        println!("Press Enter to exit...");
+
+        let mut line = String::new();
+        let _ = stdin().read_line(&mut line);
+    }
+}
+
+/// Parses a brainfuck program, failing with the first error
+/// 
+/// # Arguments
+/// 
+/// * `source` - The program's source code
+/// * `extensions` - The enabled language extensions
+pub fn parse(source: &str, extensions: Extensions) -> Result<Program> {
+    try_parse(source, extensions).map_err(|errors| errors.into_iter().next().unwrap_or_else(|| Error::other("the program could not be parsed!")))
+}
+
+/// Parses a brainfuck program, collecting all errors instead of stopping at the first one
+/// 
+/// # Arguments
+/// 
+/// * `source` - The program's source code
+/// * `extensions` - The enabled language extensions
+fn try_parse(source: &str, extensions: Extensions) -> std::result::Result<Program, Vec<Error>> {
+    try_parse_recording(source, extensions, None, None).map_err(|errors| errors.into_iter().map(|(_, err)| err).collect())
+}
+
+/// Parses a brainfuck program like `try_parse`, optionally recording each recognized command character and limiting
+/// how deeply loops are nested
+/// 
+/// The errors are ordered by their position in the source, which they are returned with.
+/// 
+/// # Arguments
+/// 
+/// * `source` - The program's source code
+/// * `extensions` - The enabled language extensions
+/// * `tokens` - The list to append the recognized command characters to, in source order
+/// * `max_depth` - The amount of loops that may be nested in each other, unlimited if `None`
+fn try_parse_recording(source: &str, extensions: Extensions, mut tokens: Option<&mut Vec<Token>>, max_depth: Option<usize>) -> std::result::Result<Program, Vec<(Position, Error)>> {
+    // The errors found so far, parsing continues after each of them
+    let mut errors: Vec<(Position, Error)> = Vec::new();
+
+    // The parsed instructions
+    let mut instructions: Vec<Instruction> = Vec::new();
+
+    // The source position of each parsed instruction
+    let mut positions: Vec<Position> = Vec::new();
+
+    // The position of each label defined so far
+    let mut labels: HashMap<String, Position> = HashMap::new();
+
+    // The name and position of the label that is currently being parsed
+    let mut label: Option<(String, Position)> = None;
+
+    // The digits and the position of the `{` of the repetition count that is currently being parsed, and the
+    // character of the instruction it repeats unless it follows none that can be repeated
+    let mut count: Option<(String, Position, Option<char>)> = None;
+
+    // The previous character if it is an instruction that a repetition count can follow
+    let mut countable: Option<char> = None;
+    
+    // The stack used to parse loops
+    let mut stack: Vec<i32> = Vec::new();
+    
+    // The program counter
+    let mut pc = 0;
+
+    // A byte order mark that an editor kept is no character of the first line, so columns start at 1 after it
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+
+    for (line, l) in source.lines().enumerate() {
+        // A shebang line makes the file executable and is skipped, while still counting for the line numbers
+        if line == 0 && l.starts_with("#!") {
+            continue;
+        }
+
+        for (column, c) in l.chars().enumerate() {
+            // Jumps address instructions with 32 bit signed integers, so a longer program cannot be compiled
+            if instructions.len() >= MAX_INSTRUCTIONS {
+                let position = Position { line: line + 1, column: column + 1 };
+                errors.push((position, too_many_instructions(position)));
+                return Err(errors);
+            }
+
+            // Identifier characters continue the name of a label
+            if let Some((name, _)) = &mut label {
+                if c.is_ascii_alphanumeric() || c == '_' {
+                    name.push(c);
+                    continue;
+                }
+            }
+
+            // Any other character ends it and is parsed as usual
+            if let Some((name, position)) = label.take() {
+                match push_label(&mut instructions, &mut positions, &mut labels, name, position) {
+                    Ok(()) => pc += 1,
+                    Err(err) => errors.push((position, err)),
+                }
+            }
+
+            if let Some((digits, _, _)) = &mut count {
+                if c.is_ascii_digit() {
+                    digits.push(c);
+                    continue;
+                }
+            }
+
+            if let Some((digits, position, repeated)) = count.take() {
+                if c == '}' {
+                    // Counts after other instructions were reported at their `{` already
+                    if let Some(repeated) = repeated {
+                        match parse_count(&digits, position) {
+                            Ok(n) if instructions.len() + n as usize > MAX_INSTRUCTIONS => errors.push((position, too_many_instructions(position))),
+                            Ok(n) => pc += repeat(&mut instructions, &mut positions, repeated, n),
+                            Err(err) => errors.push((position, err)),
+                        }
+                    }
+                    countable = None;
+                    continue;
+                }
+
+                // Any other character ends the count and is parsed as usual
+                if repeated.is_some() {
+                    errors.push((position, missing_count_end(position)));
+                }
+            }
+
+            if let Some(tokens) = &mut tokens {
+                if is_command(c, extensions) {
+                    tokens.push(Token { c, position: Position { line: line + 1, column: column + 1 } });
+                }
+            }
+
+            match c {
+                '>' => instructions.push(Instruction::Increment),
+                '<' => instructions.push(Instruction::Decrement),
+                '+' => instructions.push(Instruction::Add),
+                '-' => instructions.push(Instruction::Subtract),
+                '.' => instructions.push(Instruction::Write),
+                ',' => instructions.push(Instruction::Read),
+                '[' => {
+                    // Only the outermost loop that is nested too deeply is reported, not the loops inside it
+                    if let Some(max_depth) = max_depth.filter(|max_depth| stack.len() == *max_depth) {
+                        let position = Position { line: line + 1, column: column + 1 };
+                        let message = format!("loop at {} is nested {} deep, more than the maximum of {}!", position, max_depth + 1, max_depth);
+                        errors.push((position, Error::other(message)));
+                    }
+
+                    // The jump instruction is initialized with a jmp_pc of 0 but this will be overwritten by the corresponding Return instruction's pc later
+                    instructions.push(Instruction::Jump(0));
+                    stack.push(pc);
+                },
+                ']' => {
+                    if let Some(stack_pc) = stack.pop() {
+                        instructions.push(Instruction::Return(stack_pc));
+                        if let Some(jump) = instructions.get_mut(stack_pc as usize) {
+                            *jump = Instruction::Jump(pc);
+                        }
+                    } else {
+                        // Report when the opening and closing brackets do not match and skip the bracket
+                        let position = Position { line: line + 1, column: column + 1 };
+                        let message = format!("unmatched ']' at {}!\nhelp (a guess): remove it, or add a '[' where its loop should start", position);
+                        errors.push((position, Error::other(message)));
+                        pc -= 1;
+                    }
+                },
+                '#' if extensions.debug_breaks => instructions.push(Instruction::Break),
+                '@' if extensions.halt => instructions.push(Instruction::Halt),
+                '$' if extensions.dump => instructions.push(Instruction::Dump),
+                '@' if extensions.labels => {
+                    // The label is pushed once its name is complete
+                    label = Some((String::new(), Position { line: line + 1, column: column + 1 }));
+                    pc -= 1;
+                },
+                '{' if extensions.counts => {
+                    // The count is parsed even if it cannot repeat anything, so its digits are not reported as well
+                    let position = Position { line: line + 1, column: column + 1 };
+                    if countable.is_none() {
+                        let message = format!("repetition count at {} does not follow '+', '-', '<', '>' or '.'!", position);
+                        errors.push((position, Error::other(message)));
+                    }
+                    count = Some((String::new(), position, countable));
+                    pc -= 1;
+                },
+                // Decrement program counter when the character is not an instruction (=> comment)
+                _ => pc -= 1,
+            }
+
+            // Remember where each instruction came from
+            if positions.len() < instructions.len() {
+                positions.push(Position { line: line + 1, column: column + 1 });
+            }
+
+            // Increment program counter on each character (=> instruction)
+            pc += 1;
+
+            countable = matches!(c, '+' | '-' | '<' | '>' | '.').then_some(c);
+        }
+
+        // Counts have to end in the line they start in
+        if let Some((_, position, repeated)) = count.take() {
+            if repeated.is_some() {
+                errors.push((position, missing_count_end(position)));
+            }
+        }
+        countable = None;
+
+        // Labels also end at the end of a line
+        if let Some((name, position)) = label.take() {
+            match push_label(&mut instructions, &mut positions, &mut labels, name, position) {
+                Ok(()) => pc += 1,
+                Err(err) => errors.push((position, err)),
+            }
+        }
+    }
+
+    // Report each loop that was never closed
+    for stack_pc in stack {
+        let Some(&position) = positions.get(stack_pc as usize) else { continue };
+        let message = format!("unmatched '[' at {}!\nhelp (a guess): add a ']' after {}", position, likely_loop_end(source, position));
+        errors.push((position, Error::other(message)));
+    }
+
+    if !errors.is_empty() {
+        // Unclosed loops are only found at the end, so they are sorted in among the other errors
+        errors.sort_by_key(|(position, _)| (position.line, position.column));
+        return Err(errors);
+    }
+
+    Ok(Program { instructions, positions })
+}
+
+/// A loop that is not closed or a `]` without a loop to close, found by `check_brackets`
+#[derive(Clone, Copy)]
+pub enum BracketError {
+    /// The outermost `[` that is never closed
+    UnmatchedOpen(Position),
+    /// A `]` that follows no open `[`
+    UnmatchedClose(Position),
+}
+
+impl Display for BracketError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            BracketError::UnmatchedOpen(position) => write!(f, "unmatched '[' at {}!", position),
+            BracketError::UnmatchedClose(position) => write!(f, "unmatched ']' at {}!", position),
+        }
+    }
+}
+
+/// Checks that the brackets of a program are balanced, failing with the first bracket in the source that is not
+///
+/// Only the brackets are looked at and nothing is allocated, which makes this a cheap check whether the loops are
+/// well-formed, e.g. before an editor asks for a loop's partner. The brackets are the same with every extension and
+/// the positions are the ones the parser reports them at.
+///
+/// # Arguments
+///
+/// * `source` - The program's source code
+pub fn check_brackets(source: &str) -> std::result::Result<(), BracketError> {
+    // The amount of loops open so far and the position of the outermost one
+    let mut depth: usize = 0;
+    let mut outermost: Option<Position> = None;
+
+    // A byte order mark is skipped like the parser does
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+
+    for (line, l) in source.lines().enumerate() {
+        // The shebang line is skipped like the parser does
+        if line == 0 && l.starts_with("#!") {
+            continue;
+        }
+
+        for (column, c) in l.chars().enumerate() {
+            match c {
+                '[' => {
+                    if depth == 0 {
+                        outermost = Some(Position { line: line + 1, column: column + 1 });
+                    }
+                    depth += 1;
+                },
+                // A `]` can only be unmatched while no loop is open, so no `[` before it can be unmatched either
+                ']' if depth == 0 => return Err(BracketError::UnmatchedClose(Position { line: line + 1, column: column + 1 })),
+                ']' => depth -= 1,
+                _ => {},
+            }
+        }
+    }
+
+    match outermost {
+        Some(position) if depth > 0 => Err(BracketError::UnmatchedOpen(position)),
+        _ => Ok(()),
+    }
+}
+
+/// Parses the digits of a repetition count, failing if there are none or the count is 0 or too large
+/// 
+/// # Arguments
+/// 
+/// * `digits` - The decimal digits between `{` and `}`
+/// * `position` - The position of the count's `{`
+fn parse_count(digits: &str, position: Position) -> Result<u32> {
+    if digits.is_empty() {
+        return Err(Error::other(format!("missing repetition count between '{{' and '}}' at {}!", position)));
+    }
+
+    match digits.parse::<u32>() {
+        Ok(0) => Err(Error::other(format!("repetition count at {} is 0, counts start at 1!", position))),
+        Ok(n) if n <= MAX_REPEAT_COUNT => Ok(n),
+        _ => Err(Error::other(format!("repetition count at {} exceeds the maximum of {}!", position, MAX_REPEAT_COUNT))),
+    }
+}
+
+/// The error of a program with more instructions than jumps can address
+/// 
+/// # Arguments
+/// 
+/// * `position` - The position of the character that exceeded the limit
+fn too_many_instructions(position: Position) -> Error {
+    Error::other(format!("the program has more than {} instructions at {}, the most jumps can address!", MAX_INSTRUCTIONS, position))
+}
+
+/// The error of a repetition count whose `}` is missing
+/// 
+/// # Arguments
+/// 
+/// * `position` - The position of the count's `{`
+fn missing_count_end(position: Position) -> Error {
+    Error::other(format!("missing '}}' after the repetition count at {}!\nhelp (a guess): counts are decimal digits like '+{{3}}'", position))
+}
+
+/// Repeats the last parsed instruction, turning moves and additions into their counted instructions
+/// 
+/// Returns the amount of instructions that were added, as each repeated `.` is an instruction of its own.
+/// 
+/// # Arguments
+/// 
+/// * `instructions` - The parsed instructions, the last of which is repeated
+/// * `positions` - The source position of each parsed instruction
+/// * `repeated` - The character of the repeated instruction
+/// * `n` - How often the instruction runs in total
+fn repeat(instructions: &mut Vec<Instruction>, positions: &mut Vec<Position>, repeated: char, n: u32) -> i32 {
+    let n = n as i32;
+    let Some(last) = instructions.last_mut() else { return 0 };
+
+    match repeated {
+        '+' => *last = Instruction::AddN(n),
+        '-' => *last = Instruction::AddN(-n),
+        '>' => *last = Instruction::MoveN(n),
+        '<' => *last = Instruction::MoveN(-n),
+        _ => {
+            let Some(&position) = positions.last() else { return 0 };
+            for _ in 1..n {
+                instructions.push(Instruction::Write);
+                positions.push(position);
+            }
+            return n - 1;
+        },
+    }
+
+    0
+}
+
+/// Guesses where an unclosed loop was meant to end from the indentation of the source
+/// 
+/// The loop is assumed to span the following lines that are indented deeper than the line it starts in, so the
+/// returned position is the last character of the last such line, or of the loop's own line if there is none.
+/// 
+/// # Arguments
+/// 
+/// * `source` - The program's source code
+/// * `start` - The position of the unclosed `[`
+fn likely_loop_end(source: &str, start: Position) -> Position {
+    let indent = |l: &str| l.len() - l.trim_start().len();
+    let lines: Vec<&str> = source.lines().collect();
+    let opened = indent(lines[start.line - 1]);
+
+    let mut line = start.line;
+    for (i, l) in lines.iter().enumerate().skip(start.line) {
+        if l.trim().is_empty() {
+            continue;
+        }
+        if indent(l) <= opened {
+            break;
+        }
+        line = i + 1;
+    }
+
+    Position { line, column: lines[line - 1].trim_end().chars().count() }
+}
+
+/// Returns whether a character is a command instead of a comment
+/// 
+/// # Arguments
+/// 
+/// * `c` - The character
+/// * `extensions` - The enabled language extensions
+fn is_command(c: char, extensions: Extensions) -> bool {
+    match c {
+        '>' | '<' | '+' | '-' | '.' | ',' | '[' | ']' => true,
+        '#' => extensions.debug_breaks,
+        '@' => extensions.labels || extensions.halt,
+        '$' => extensions.dump,
+        '{' => extensions.counts,
+        _ => false,
+    }
+}
+
+/// Writes each token on its own line as its source position followed by its character
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `tokens` - The tokens to write
+fn write_tokens<W: Write>(f: &mut W, tokens: &[Token]) -> Result<()> {
+    for token in tokens {
+        writeln!(f, "{} {}", token.position, token.c)?;
+    }
+    Ok(())
+}
+
+/// Pushes a completely parsed label, failing if it is empty or was already defined
+/// 
+/// # Arguments
+/// 
+/// * `instructions` - The parsed instructions
+/// * `positions` - The source position of each parsed instruction
+/// * `labels` - The position of each label defined so far
+/// * `name` - The name of the label
+/// * `position` - The position of the label's `@`
+fn push_label(instructions: &mut Vec<Instruction>, positions: &mut Vec<Position>, labels: &mut HashMap<String, Position>, name: String, position: Position) -> Result<()> {
+    if name.is_empty() {
+        return Err(Error::other(format!("missing label name after '@' at {}!", position)));
+    }
+
+    if let Some(first) = labels.get(&name) {
+        return Err(Error::other(format!("duplicate label '{}' at {}, first defined at {}!", name, position, first)));
+    }
+
+    labels.insert(name.clone(), position);
+    instructions.push(Instruction::Label(name));
+    positions.push(position);
+
+    Ok(())
+}
+
+/// The settings of the generated assembly
+#[derive(Clone, Copy)]
+pub struct AsmOptions<'a> {
+    /// The assembler syntax to write
+    pub syntax: Syntax,
+    /// Whether each top-level loop is emitted as its own function called from `main`
+    pub split_functions: bool,
+    /// Whether to only write the instructions as a fragment for NASM's `%include`
+    pub fragment: bool,
+    /// The name of the function the program is generated as, which writes its output into a buffer and reads its input
+    /// from one instead of calling the C runtime, if it is generated as a library
+    pub library: Option<&'a str>,
+    /// Whether and when the generated program checks the guard cells around the tape
+    pub tape_guard: TapeGuard,
+    /// Whether each `.` writes its byte with an unbuffered `_write` instead of `putchar`
+    pub unbuffered_output: bool,
+    /// Whether stdin and stdout are switched to binary mode before any I/O happens
+    pub binary_stdio: bool,
+    /// The cell that `argv[1]` is copied to at startup, if it is copied
+    pub argv_tape: Option<i32>,
+    /// The amount of loop iterations after which the program stops, if it is limited
+    pub fuel: Option<u64>,
+    /// The bytes embedded into the program that `,` reads before (or instead of) stdin
+    pub const_input: Option<&'a [u8]>,
+    /// Whether `,` falls back to stdin once the constant input is exhausted instead of leaving the cell unchanged
+    pub const_input_then_stdin: bool,
+    /// The blocks whose entries are counted and written to the coverage file at exit, if they are
+    pub coverage: Option<&'a [coverage::Block]>,
+    /// The file the generated program writes its coverage to
+    pub coverage_output: &'a str,
+    /// The amount of cells on the tape, without guard cells
+    pub tape_size: i64,
+    /// The segment the tape is placed in, a guarded tape is always placed in the data segment
+    pub tape_section: TapeSection,
+    /// The bytes loaded into the first cells of the tape, one byte per cell, which need the data segment
+    pub tape_init: &'a [u8],
+    /// The prefix of every generated label
+    pub label_prefix: &'a str,
+    /// Whether every instruction gets a label named after its pc, not only those jumping
+    pub label_all: bool,
+    /// Whether the start of each block is annotated with a comment holding the offset of the data pointer from its
+    /// start, if it is known
+    pub annotate_offsets: bool,
+    /// Whether straight-line code addresses cells at offsets from a data pointer loaded once instead of storing every
+    /// move of it
+    pub offset_cells: bool,
+    /// Whether the index register holds the data pointer for the whole program instead of a copy of it in memory,
+    /// which `main` saves and restores as the calling convention requires
+    pub dp_register: bool,
+    /// The file the program-independent exit paths are included from instead of being written into the program, if
+    /// they are shared
+    pub runtime_file: Option<&'a str>,
+    /// The contents of the template that the generated code is filled into instead of the generated header and exit
+    /// code, if there is one
+    pub template: Option<&'a str>,
+    /// The C runtime the program is linked against, if it was chosen or detected, otherwise its functions are called
+    /// directly
+    pub crt: Option<Crt>,
+}
+
+impl Default for AsmOptions<'_> {
+    /// The settings of a plain program for NASM on the default tape, as `bfasm` generates it without any flags
+    fn default() -> Self {
+        AsmOptions {
+            syntax: Syntax::Nasm,
+            split_functions: false,
+            fragment: false,
+            library: None,
+            tape_guard: TapeGuard::Off,
+            unbuffered_output: false,
+            binary_stdio: false,
+            argv_tape: None,
+            fuel: None,
+            const_input: None,
+            const_input_then_stdin: false,
+            coverage: None,
+            coverage_output: "",
+            tape_size: DATA_SIZE.into(),
+            tape_section: TapeSection::Bss,
+            tape_init: &[],
+            label_prefix: "",
+            label_all: false,
+            annotate_offsets: false,
+            offset_cells: false,
+            dp_register: false,
+            runtime_file: None,
+            template: None,
+            crt: None,
+        }
+    }
+}
+
+impl AsmOptions<'_> {
+    /// Whether the tape is too large for the image and is allocated when the program starts, with its base kept in `r12`
+    fn heap_tape(&self) -> bool {
+        let guards = if self.tape_guard == TapeGuard::Off { 0 } else { 2 * i64::from(GUARD_CELLS) };
+        !self.fragment && self.tape_size + guards > MAX_STATIC_TAPE_CELLS
+    }
+
+    /// The callee-saved registers `main` saves below its frame pointer, `rbx` if it holds the data pointer for the whole
+    /// program and `r12` if it holds the base of an allocated tape
+    fn saved_registers(&self) -> Vec<&'static str> {
+        [(self.dp_register, "rbx"), (self.heap_tape(), "r12")].into_iter().filter(|(saved, _)| *saved).map(|(_, register)| register).collect()
+    }
+}
+
+/// The names the generated instructions refer to
+struct Symbols {
+    /// The base address of the tape, its symbol or the register holding it
+    tape: &'static str,
+    /// The memory operand holding the data pointer
+    dp: &'static str,
+    /// The function called with the character to write in `ecx`
+    putchar: &'static str,
+    /// The function returning the read character in `eax`
+    getchar: &'static str,
+    /// The function called with the exit code in `ecx` when writing failed
+    exit: &'static str,
+    /// The function called with a file descriptor, a buffer and its length to write unbuffered (only with `--unbuffered-output`)
+    write: &'static str,
+    /// The assembler syntax of the import pointers
+    syntax: Syntax,
+    /// The C runtime whose exported functions may have to be called through their import pointers
+    crt: Option<Crt>,
+}
+
+impl Symbols {
+    /// The symbols of the given settings, fragments refer to macros defined by the including file
+    /// 
+    /// # Arguments
+    /// 
+    /// * `options` - The settings of the generated assembly
+    fn new(options: &AsmOptions) -> Symbols {
+        if options.fragment {
+            Symbols { tape: "BF_TAPE", dp: "BF_DP", putchar: "BF_PUTCHAR", getchar: "BF_GETCHAR", exit: "BF_EXIT", write: "BF_WRITE", syntax: options.syntax, crt: None }
+        } else {
+            let tape = if options.heap_tape() { "r12" } else { "tape" };
+            Symbols { tape, dp: options.syntax.dp(), putchar: "putchar", getchar: "_getch", exit: "exit", write: "_write", syntax: options.syntax, crt: options.crt }
+        }
+    }
+
+    /// The operand of a `call` of a function of the C runtime, which is its import pointer with `--crt dynamic`
+    ///
+    /// # Arguments
+    ///
+    /// * `function` - The name of the function
+    fn call(&self, function: &str) -> String {
+        match self.crt {
+            Some(crt) if crt.imports(function) => format!("{} {}", self.syntax.qword(), self.syntax.rip(&extern_name(self.crt, function))),
+            _ => function.to_owned(),
+        }
+    }
+}
+
+/// Compiles a brainfuck program to assembly in memory, without reading or writing any file
+/// 
+/// The program is parsed without extensions and not optimized, like `bfasm` compiles a file without flags, and the
+/// assembly is generated with the given settings.
+/// 
+/// # Arguments
+/// 
+/// * `source` - The program's source code
+/// * `options` - The settings of the generated assembly
+pub fn compile_str(source: &str, options: &AsmOptions) -> Result<String> {
+    let program = parse(source, Extensions::default())?;
+    let (asm, _) = generate_asm(source, &program.instructions, options)?;
+    Ok(asm)
+}
+
+/// Writes the assembly corresponding to the given instructions to a file
+/// 
+/// # Arguments
+/// 
+/// * `filename` - The name of the file to create and write to
+/// * `source` - The program's source code, whose hash is recorded in the header
+/// * `instructions` - A slice of instructions that contains the program
+/// * `options` - The settings of the generated assembly
+/// 
+/// Returns the range of (1-based) assembly lines that was emitted for each instruction.
+fn write_asm(filename: &str, source: &str, instructions: &[Instruction], options: &AsmOptions) -> Result<Vec<Range<usize>>> {
+    let (asm, ranges) = generate_asm(source, instructions, options)?;
+
+    // Return error on failure
+    write_atomically(Path::new(filename), asm.as_bytes()).map_err(|_| Error::other("could not write to file!"))?;
+
+    Ok(ranges)
+}
+
+/// Writes a file by writing a temporary file next to it and renaming that over it, so a failed write never leaves a
+/// partially written file behind
+///
+/// If the temporary file cannot be renamed, its contents are written to the file directly instead.
+///
+/// # Arguments
+///
+/// * `filename` - The name of the file to create or replace
+/// * `contents` - The bytes to write
+fn write_atomically(filename: &Path, contents: &[u8]) -> Result<()> {
+    let mut temp_name = filename.as_os_str().to_owned();
+    temp_name.push(".tmp");
+    let temp = PathBuf::from(temp_name);
+
+    let written = File::create(&temp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    if let Err(err) = written {
+        let _ = remove_file(&temp);
+        return Err(err);
+    }
+
+    if rename(&temp, filename).is_err() {
+        let _ = remove_file(&temp);
+        let mut file = File::create(filename)?;
+        file.write_all(contents)?;
+    }
+
+    Ok(())
+}
+
+/// Generates the assembly corresponding to the given instructions
+/// 
+/// # Arguments
+/// 
+/// * `source` - The program's source code, whose hash is recorded in the header
+/// * `instructions` - A slice of instructions that contains the program
+/// * `options` - The settings of the generated assembly
+/// 
+/// Returns the assembly and the range of (1-based) assembly lines that was emitted for each instruction.
+fn generate_asm(source: &str, instructions: &[Instruction], options: &AsmOptions) -> Result<(String, Vec<Range<usize>>)> {
+    if options.fragment && options.syntax != Syntax::Nasm {
+        return Err(Error::other("fragments can only be generated for nasm!"));
+    }
+    if options.fragment && options.split_functions {
+        return Err(Error::other("fragments cannot be combined with --split-functions!"));
+    }
+    if options.fragment && options.binary_stdio {
+        return Err(Error::other("fragments cannot be combined with --binary-stdio!"));
+    }
+    if options.fragment && options.tape_guard != TapeGuard::Off {
+        return Err(Error::other("fragments cannot be combined with --tape-guard!"));
+    }
+    if options.fragment && options.fuel.is_some() {
+        return Err(Error::other("fragments cannot be combined with --fuel!"));
+    }
+    if options.fragment && options.argv_tape.is_some() {
+        return Err(Error::other("fragments cannot be combined with --argv-tape!"));
+    }
+    if options.fragment && options.const_input.is_some() {
+        return Err(Error::other("fragments cannot be combined with --const-input!"));
+    }
+    if options.fragment && options.coverage.is_some() {
+        return Err(Error::other("fragments cannot be combined with --instrument coverage!"));
+    }
+    if options.fragment && instructions.iter().any(|instr| matches!(instr, Instruction::AddVector(_, _))) {
+        return Err(Error::other("fragments cannot contain vectorized instructions, use --cpu baseline!"));
+    }
+    if options.fragment && instructions.iter().any(|instr| matches!(instr, Instruction::Dump)) {
+        return Err(Error::other("fragments cannot contain '$' dumps!"));
+    }
+    if options.fragment && options.runtime_file.is_some() {
+        return Err(Error::other("fragments cannot be combined with --runtime-file!"));
+    }
+    if options.fragment && options.tape_section == TapeSection::Data {
+        return Err(Error::other("fragments cannot be combined with --tape-section data!"));
+    }
+    if options.fragment && !options.tape_init.is_empty() {
+        return Err(Error::other("fragments cannot be combined with --tape-init!"));
+    }
+    if options.fragment && options.dp_register {
+        return Err(Error::other("fragments cannot keep the data pointer in a register!"));
+    }
+    if !options.tape_init.is_empty() && options.tape_section == TapeSection::Bss && options.tape_guard == TapeGuard::Off {
+        return Err(Error::other("the initial contents of the tape need --tape-section data, the .bss segment starts zeroed!"));
+    }
+    check_library(options, instructions.iter().any(|instr| matches!(instr, Instruction::Dump)))?;
+    check_tape_size(options)?;
+
+    // The template replaces the generated header and prologue, so it cannot declare what these features add to them
+    if options.template.is_some() {
+        let conflicts = [
+            (options.fragment, "--emit fragment"),
+            (options.split_functions, "--split-functions"),
+            (options.tape_guard != TapeGuard::Off, "--tape-guard"),
+            (options.binary_stdio, "--binary-stdio"),
+            (options.fuel.is_some(), "--fuel"),
+            (options.argv_tape.is_some(), "--argv-tape"),
+            (options.const_input.is_some(), "--const-input"),
+            (options.coverage.is_some(), "--instrument coverage"),
+            (options.runtime_file.is_some(), "--runtime-file"),
+            (options.tape_section == TapeSection::Data, "--tape-section data"),
+            (!options.tape_init.is_empty(), "--tape-init"),
+            (instructions.iter().any(|instr| matches!(instr, Instruction::Dump)), "'$' dumps"),
+            (options.dp_register, "a data pointer kept in a register"),
+        ];
+        if let Some((_, conflict)) = conflicts.iter().find(|(conflicts, _)| *conflicts) {
+            return Err(Error::other(format!("templates cannot be combined with {}!", conflict)));
+        }
+    }
+
+    // The routines the instructions refer to, of which `$` needs declarations in the header as well
+    let helpers = lowering::helpers(instructions, options);
+    let dumps = helpers.contains(&Helper::Dump);
+
+    let mut f = LineCounter { inner: Vec::new(), lines: 0 };
+
+    let syntax = options.syntax;
+    let prefix = options.label_prefix;
+    let symbols = Symbols::new(options);
+    let emitter = IntelEmitter { syntax, symbols: &symbols };
+
+    // The assembly lines of each instruction
+    let mut ranges: Vec<Range<usize>> = vec![0..0; instructions.len()];
+
+    write_prelude(&mut f, &sha256::sha256(source.as_bytes()), options, dumps, &symbols)?;
+    let body_start = f.inner.len();
+
+    // The (start, end) pcs of the top-level loops that were lifted into functions
+    let mut functions: Vec<(usize, usize)> = Vec::new();
+
+    // Append the instructions
+    // The instructions that can rely on the data pointer being loaded already
+    let pinned = lowering::pinned(instructions);
+    // The numbers naming the labels of each instruction
+    let ids = lowering::label_ids(instructions);
+    // The instructions that can reuse the cell a read or a copy loop's previous `MultiplyAdd` left in `eax`
+    let loaded = lowering::cell_loaded(instructions, options);
+    // The offsets of the data pointer the blocks are annotated with
+    let offsets = if options.annotate_offsets { lowering::dp_offsets(instructions) } else { Vec::new() };
+
+    // The offset of the data pointer from the index register while its moves are not stored yet
+    let mut cached: Option<i32> = None;
+
+    let mut pc = 0;
+    while pc < instructions.len() {
+        match instructions[pc] {
+            Instruction::Jump(jmp_pc) if options.split_functions => {
+                // Call the loop instead of inlining it and continue after its end
+                if let Some(store) = lowering::flush(&mut cached, options) {
+                    emitter.emit(&mut f, &store)?;
+                }
+                writeln!(f, "\tcall {}LOOP_{}", prefix, ids[pc])?;
+                functions.push((pc, jmp_pc as usize));
+                pc = jmp_pc as usize;
+            },
+            _ => {
+                write_offset(&mut f, syntax, instructions, &offsets, pc)?;
+                let start = f.lines + 1;
+                let ops = lowering::lower(pc, ids[pc], &instructions[pc], pinned[pc], loaded[pc], &mut cached, options);
+                write_lowered(&mut f, ops, &emitter)?;
+                ranges[pc] = start..f.lines + 1;
+            },
+        }
+
+        pc += 1;
+    }
+    if let Some(store) = lowering::flush(&mut cached, options) {
+        emitter.emit(&mut f, &store)?;
+    }
+
+    // Fragments continue in the including file, so they have to jump over their exit path
+    if options.fragment {
+        writeln!(f)?;
+        writeln!(f, "\tjmp {}FRAGMENT_END", prefix)?;
+        for helper in helpers {
+            write_helper(&mut f, helper, options, &symbols)?;
+        }
+        writeln!(f, "{}FRAGMENT_END:", prefix)?;
+        return Ok((into_string(f), ranges));
+    }
+
+    if let Some(template) = options.template {
+        let mut helper_code: Vec<u8> = Vec::new();
+        for helper in helpers {
+            write_helper(&mut helper_code, helper, options, &symbols)?;
+        }
+        write_vector_constants(&mut helper_code, syntax, instructions, prefix)?;
+
+        let externs: Vec<String> = externs(options, dumps).into_iter()
+            .map(|name| match syntax {
+                Syntax::Nasm => format!("extern {}", name),
+                Syntax::Gas => format!(".extern {}", name),
+            })
+            .collect();
+
+        let mut asm = into_string(f);
+        let body = asm.split_off(body_start);
+        let (filled, body_line) = template::fill(template, &[
+            ("ENTRY", "main"),
+            ("TAPE_SIZE", &options.tape_size.to_string()),
+            ("EXTERNS", &externs.join("\n")),
+            ("BODY", body.trim_end_matches('\n')),
+            ("HELPERS", String::from_utf8_lossy(&helper_code).trim_matches('\n')),
+        ])?;
+
+        // The body moves from right below the source hash to the line of the template that it is placed in
+        let ranges = ranges.into_iter().map(|range| range.start + body_line..range.end + body_line).collect();
+        asm.push_str(&filled);
+        return Ok((asm, ranges));
+    }
+
+    // Programs ending in an infinite loop never get here
+    let returns = !matches!(instructions.last(), Some(Instruction::Unreachable));
+    write_exit(&mut f, instructions.len(), returns, &helpers, options, &symbols)?;
+
+    // Append the lifted loops, each with its own stack frame so calls to `putchar` and `_getch` stay aligned
+    for (start, end) in functions {
+        writeln!(f)?;
+        writeln!(f, "{}LOOP_{}:", prefix, ids[start])?;
+        write_prologue(&mut f, &[])?;
+        writeln!(f)?;
+
+        for (pc, instr) in instructions.iter().enumerate().take(end + 1).skip(start) {
+            write_offset(&mut f, syntax, instructions, &offsets, pc)?;
+            let first = f.lines + 1;
+            let ops = lowering::lower(pc, ids[pc], instr, pinned[pc], loaded[pc], &mut cached, options);
+            write_lowered(&mut f, ops, &emitter)?;
+            ranges[pc] = first..f.lines + 1;
+        }
+
+        writeln!(f)?;
+        write_epilogue(&mut f, &[])?;
+        writeln!(f, "\tret")?;
+    }
+
+    // The constants switch to another section, so they have to come after all code
+    write_vector_constants(&mut f, syntax, instructions, prefix)?;
+
+    // The runtime switches sections as well
+    write_runtime_include(&mut f, options)?;
+
+    Ok((into_string(f), ranges))
+}
+
+/// Checks that a program generated as a library neither touches the process's I/O nor exits it, as the features that
+/// call the C runtime would
+///
+/// # Arguments
+///
+/// * `options` - The settings of the generated assembly
+/// * `dumps` - Whether the program contains `$` dumps, which write to stderr
+fn check_library(options: &AsmOptions, dumps: bool) -> Result<()> {
+    if options.library.is_none() {
+        return Ok(());
+    }
+
+    let conflicts = [
+        (options.fragment, "--emit fragment"),
+        (options.template.is_some(), "--template"),
+        (options.tape_guard != TapeGuard::Off, "--tape-guard"),
+        (options.unbuffered_output, "--unbuffered-output"),
+        (options.binary_stdio, "--binary-stdio"),
+        (options.fuel.is_some(), "--fuel"),
+        (options.argv_tape.is_some(), "--argv-tape"),
+        (options.const_input.is_some(), "--const-input"),
+        (options.coverage.is_some(), "--instrument coverage"),
+        (options.runtime_file.is_some(), "--runtime-file"),
+        // The tape is cleared on every call, which would erase its initial contents
+        (!options.tape_init.is_empty(), "--tape-init"),
+        (dumps, "'$' dumps"),
+    ];
+    match conflicts.iter().find(|(conflicts, _)| *conflicts) {
+        Some((_, conflict)) => Err(Error::other(format!("libraries cannot be combined with {}!", conflict))),
+        None => Ok(()),
+    }
+}
+
+/// Checks that the tape, including the guard cells around it, fits into the address space, that the cell `argv[1]` is
+/// copied to lies on it and that a tape too large for the image is only combined with features that do not need it in
+/// the image
+///
+/// # Arguments
+///
+/// * `options` - The settings of the generated assembly
+fn check_tape_size(options: &AsmOptions) -> Result<()> {
+    let guards = if options.tape_guard == TapeGuard::Off { 0 } else { 2 * GUARD_CELLS };
+    let cells = options.tape_size + i64::from(guards);
+    let including = if guards > 0 { format!(" including its {} guard cells", guards) } else { String::new() };
+    if cells > MAX_TAPE_CELLS {
+        return Err(Error::other(format!("the tape needs {} cells{}, more than the {} that fit into the address space!", cells, including, MAX_TAPE_CELLS)));
+    }
+    if options.argv_tape.is_some_and(|cell| i64::from(cell) >= options.tape_size) {
+        return Err(Error::other(format!("'--argv-tape-at' has to be a cell between 0 and {}!", options.tape_size - 1)));
+    }
+    if !options.heap_tape() {
+        return Ok(());
+    }
+
+    // The allocated tape only exists once `main` started and cannot be initialized or surrounded by the image
+    let conflicts = [
+        (options.tape_guard != TapeGuard::Off, "--tape-guard"),
+        (options.library.is_some(), "--library"),
+        (options.template.is_some(), "--template"),
+        (options.argv_tape.is_some(), "--argv-tape"),
+        (options.runtime_file.is_some(), "--runtime-file"),
+        (options.tape_section == TapeSection::Data, "--tape-section data"),
+        (!options.tape_init.is_empty(), "--tape-init"),
+    ];
+    match conflicts.iter().find(|(conflicts, _)| *conflicts) {
+        Some((_, conflict)) => Err(Error::other(format!("the tape needs {} cells{}, more than the {} the image holds, so it cannot be combined with {}!", cells, including, MAX_STATIC_TAPE_CELLS, conflict))),
+        None => Ok(()),
+    }
+}
+
+/// Writes the start of the assembly up to the code of the first instruction: the hash of the source, the header and
+/// the prologue of `main`, of which a fragment only has its header and a template brings along both itself
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `hash` - The SHA-256 hash of the program's source code in hex
+/// * `options` - The settings of the generated assembly
+/// * `dumps` - Whether the program contains `$` dumps, whose data the header declares
+/// * `symbols` - The names of the C runtime's functions
+fn write_prelude<W: Write>(f: &mut W, hash: &str, options: &AsmOptions, dumps: bool, symbols: &Symbols) -> Result<()> {
+    let syntax = options.syntax;
+    let prefix = options.label_prefix;
+
+    // Record which source the assembly was generated from
+    writeln!(f, "{} source sha256: {}", syntax.comment(), hash)?;
+    writeln!(f)?;
+
+    // Write the "header", which a template brings along itself
+    if options.fragment {
+        write_fragment_header(f, options.unbuffered_output)?;
+        writeln!(f)?;
+    } else if let Some(name) = options.library {
+        write_header(f, options, dumps)?;
+        writeln!(f)?;
+        writeln!(f, "{}:", name)?;
+        write_library_prologue(f, options)?;
+        writeln!(f)?;
+    } else if options.template.is_none() {
+        write_header(f, options, dumps)?;
+        writeln!(f)?;
+        writeln!(f, "main:")?;
+        write_prologue(f, &options.saved_registers())?;
+        if options.heap_tape() {
+            write_tape_allocation(f, options, symbols, prefix)?;
+        }
+        // The argument has to be copied before any call clobbers `argc` and `argv`
+        if let Some(cell) = options.argv_tape {
+            write_argv_tape(f, syntax, symbols, cell, options.tape_size, prefix)?;
+        }
+        if options.binary_stdio {
+            write_binary_stdio(f, symbols)?;
+        }
+        // The coverage is written by the C runtime however the program exits
+        if options.coverage.is_some() {
+            writeln!(f, "\tlea rcx, {}", syntax.rip(&Helper::WriteCoverage.label(prefix)))?;
+            writeln!(f, "\tcall {}", symbols.call("atexit"))?;
+        }
+        // The data pointer starts at the first cell, like the one in memory
+        if options.dp_register {
+            writeln!(f, "\txor ebx, ebx")?;
+        }
+        writeln!(f)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the end of `main` after the code of the last instruction and the helpers the instructions call
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `len` - The amount of instructions, the last of which the tape guard reports
+/// * `returns` - Whether the program can get to its end, which it cannot if it ends in an infinite loop
+/// * `helpers` - The routines the instructions refer to
+/// * `options` - The settings of the generated assembly
+/// * `symbols` - The names of the C runtime's functions
+fn write_exit<W: Write>(f: &mut W, len: usize, returns: bool, helpers: &BTreeSet<Helper>, options: &AsmOptions, symbols: &Symbols) -> Result<()> {
+    // Check the guards once more with the last instruction's pc
+    if returns && options.tape_guard != TapeGuard::Off {
+        writeln!(f)?;
+        writeln!(f, "\tmov edx, {}", len.saturating_sub(1))?;
+        writeln!(f, "\tcall {}", Helper::CheckGuards.label(options.label_prefix))?;
+    }
+
+    // A library returns the length of its output, also from the exit paths of programs that never get to their end
+    if options.library.is_some() {
+        write_library_epilogue(f, options.syntax, options.label_prefix)?;
+    } else if returns {
+        // Leave stack frame and return with 0
+        writeln!(f)?;
+        write_epilogue(f, &options.saved_registers())?;
+        writeln!(f)?;
+        writeln!(f, "\txor rax, rax")?;
+        writeln!(f, "\tret")?;
+    }
+
+    // A shared runtime is included at the end instead of the helpers that do not depend on the program
+    for helper in helpers {
+        if options.runtime_file.is_none() || !helper.shared() {
+            write_helper(f, *helper, options, symbols)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the inclusion of the shared runtime of `--runtime-file`, if there is one
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `options` - The settings of the generated assembly
+fn write_runtime_include<W: Write>(f: &mut W, options: &AsmOptions) -> Result<()> {
+    if let Some(file) = options.runtime_file {
+        writeln!(f)?;
+        match options.syntax {
+            Syntax::Nasm => writeln!(f, "%include \"{}\"", file)?,
+            Syntax::Gas => writeln!(f, ".include \"{}\"", file)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the offset of the data pointer from its start as a comment if an instruction starts a block
+///
+/// Blocks start with the program and after each `[` and `]`. The offset is `unknown` inside and after loops that move
+/// the data pointer and after scans.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax, which decides the comment character
+/// * `instructions` - A slice of instructions that contains the program
+/// * `offsets` - The offset of the data pointer before each instruction if it is known, empty if they are not annotated
+/// * `pc` - The program counter of the instruction about to be written
+fn write_offset<W: Write>(f: &mut W, syntax: Syntax, instructions: &[Instruction], offsets: &[Option<i64>], pc: usize) -> Result<()> {
+    let starts_block = pc == 0 || matches!(instructions[pc - 1], Instruction::Jump(_) | Instruction::Return(_));
+    match offsets.get(pc) {
+        Some(Some(offset)) if starts_block => writeln!(f, "\t{} dp offset = {:+}", syntax.comment(), offset),
+        Some(None) if starts_block => writeln!(f, "\t{} dp offset = unknown", syntax.comment()),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the text written to a line counting buffer
+/// 
+/// # Arguments
+/// 
+/// * `f` - The buffer the assembly was written to
+fn into_string(f: LineCounter<Vec<u8>>) -> String {
+    String::from_utf8(f.inner).expect("the assembly is only ever written from strings")
+}
+
+/// Writes the directives that declare the data, the tape and the external functions
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `options` - The settings of the generated assembly, which decide the declared data and functions
+/// * `dumps` - Whether the program contains `$`, which needs a message, a buffer and the functions writing them
+fn write_header<W: Write>(f: &mut W, options: &AsmOptions, dumps: bool) -> Result<()> {
+    // A guarded tape moves into the `.data` segment to be surrounded by its guard cells, one too large for the image is
+    // allocated by the prologue instead
+    let guarded = options.tape_guard != TapeGuard::Off;
+    // The messages of the shared exit paths are declared by the runtime
+    let shared = options.runtime_file.is_some();
+
+    match options.syntax {
+        Syntax::Nasm => {
+            writeln!(f, "bits 64")?;
+            writeln!(f, "default rel")?;
+            writeln!(f)?;
+            writeln!(f, "segment .data")?;
+            writeln!(f, "\tdp dq 0")?;
+            if options.library.is_some() {
+                write_library_data(f, Syntax::Nasm)?;
+            }
+            if let Some(fuel) = options.fuel {
+                writeln!(f, "\tfuel dq {}", fuel)?;
+                if !shared {
+                    write_fuel_data(f, Syntax::Nasm)?;
+                }
+            }
+            if options.argv_tape.is_some() {
+                writeln!(f, "\targv_truncated_msg db \"{}\", 10", ARGV_TRUNCATED_WARNING)?;
+            }
+            if options.heap_tape() {
+                writeln!(f, "\ttape_allocation_msg db \"{}\", 10", TAPE_ALLOCATION_FAILED_MESSAGE)?;
+            }
+            if dumps && !shared {
+                write_dump_data(f, Syntax::Nasm)?;
+            }
+            if let Some(blocks) = options.coverage {
+                writeln!(f, "\tcoverage times {} dq 0", blocks.len().max(1))?;
+                writeln!(f, "\tcoverage_path db {}, 0", join_bytes(options.coverage_output.as_bytes()))?;
+                writeln!(f, "\tcoverage_mode db \"w\", 0")?;
+                writeln!(f, "\tcoverage_head db {}, 0", join_bytes(coverage::HEAD.as_bytes()))?;
+                writeln!(f, "\tcoverage_tail db {}, 0", join_bytes(coverage::TAIL.as_bytes()))?;
+                for (i, block) in blocks.iter().enumerate() {
+                    writeln!(f, "\tcoverage_entry_{} db {}, 0", i, join_bytes(coverage_entry(blocks, i, block).as_bytes()))?;
+                }
+                writeln!(f, "coverage_entries:")?;
+                for i in 0..blocks.len() {
+                    writeln!(f, "\tdq coverage_entry_{}", i)?;
+                }
+            }
+            if let Some(input) = options.const_input {
+                writeln!(f, "\tinput_pos dd 0")?;
+                writeln!(f, "input:")?;
+                for chunk in input.chunks(16) {
+                    writeln!(f, "\tdb {}", join_bytes(chunk))?;
+                }
+            }
+            if guarded {
+                writeln!(f, "\ttimes {} dd 0x{:X}", GUARD_CELLS, GUARD_PATTERN)?;
+                write_tape(f, Syntax::Nasm, options)?;
+                writeln!(f, "\ttimes {} dd 0x{:X}", GUARD_CELLS, GUARD_PATTERN)?;
+                writeln!(f, "\tguard_low_msg db \"{}\", 10, 0", guard_message("low", "%d"))?;
+                writeln!(f, "\tguard_high_msg db \"{}\", 10, 0", guard_message("high", "%d"))?;
+            } else if options.tape_section == TapeSection::Data {
+                write_tape(f, Syntax::Nasm, options)?;
+            } else if !options.heap_tape() {
+                writeln!(f)?;
+                writeln!(f, "segment .bss")?;
+                writeln!(f, "\ttape resd {}", options.tape_size)?;
+            }
+            writeln!(f)?;
+            writeln!(f, "segment .text")?;
+            writeln!(f, "global {}", options.library.unwrap_or("main"))?;
+            let externs = externs(options, dumps);
+            if !externs.is_empty() {
+                writeln!(f)?;
+            }
+            for name in externs {
+                writeln!(f, "extern {}", name)?;
+            }
+        },
+        Syntax::Gas => {
+            writeln!(f, ".intel_syntax noprefix")?;
+            writeln!(f)?;
+            writeln!(f, ".data")?;
+            writeln!(f, "dp:")?;
+            writeln!(f, "\t.quad 0")?;
+            if options.library.is_some() {
+                write_library_data(f, Syntax::Gas)?;
+            }
+            if let Some(fuel) = options.fuel {
+                writeln!(f, "fuel:")?;
+                writeln!(f, "\t.quad {}", fuel)?;
+                if !shared {
+                    write_fuel_data(f, Syntax::Gas)?;
+                }
+            }
+            if options.argv_tape.is_some() {
+                writeln!(f, "argv_truncated_msg:")?;
+                writeln!(f, "\t.ascii \"{}\\n\"", ARGV_TRUNCATED_WARNING)?;
+            }
+            if options.heap_tape() {
+                writeln!(f, "tape_allocation_msg:")?;
+                writeln!(f, "\t.ascii \"{}\\n\"", TAPE_ALLOCATION_FAILED_MESSAGE)?;
+            }
+            if dumps && !shared {
+                write_dump_data(f, Syntax::Gas)?;
+            }
+            if let Some(blocks) = options.coverage {
+                writeln!(f, "coverage:")?;
+                writeln!(f, "\t.zero {}", 8 * blocks.len().max(1))?;
+                writeln!(f, "coverage_path:")?;
+                writeln!(f, "\t.byte {}, 0", join_bytes(options.coverage_output.as_bytes()))?;
+                writeln!(f, "coverage_mode:")?;
+                writeln!(f, "\t.asciz \"w\"")?;
+                writeln!(f, "coverage_head:")?;
+                writeln!(f, "\t.byte {}, 0", join_bytes(coverage::HEAD.as_bytes()))?;
+                writeln!(f, "coverage_tail:")?;
+                writeln!(f, "\t.byte {}, 0", join_bytes(coverage::TAIL.as_bytes()))?;
+                for (i, block) in blocks.iter().enumerate() {
+                    writeln!(f, "coverage_entry_{}:", i)?;
+                    writeln!(f, "\t.byte {}, 0", join_bytes(coverage_entry(blocks, i, block).as_bytes()))?;
+                }
+                writeln!(f, "coverage_entries:")?;
+                for i in 0..blocks.len() {
+                    writeln!(f, "\t.quad coverage_entry_{}", i)?;
+                }
+            }
+            if let Some(input) = options.const_input {
+                writeln!(f, "input_pos:")?;
+                writeln!(f, "\t.long 0")?;
+                writeln!(f, "input:")?;
+                for chunk in input.chunks(16) {
+                    writeln!(f, "\t.byte {}", join_bytes(chunk))?;
+                }
+            }
+            if guarded {
+                writeln!(f, "\t.fill {}, 4, 0x{:X}", GUARD_CELLS, GUARD_PATTERN)?;
+                write_tape(f, Syntax::Gas, options)?;
+                writeln!(f, "\t.fill {}, 4, 0x{:X}", GUARD_CELLS, GUARD_PATTERN)?;
+                writeln!(f, "guard_low_msg:")?;
+                writeln!(f, "\t.asciz \"{}\\n\"", guard_message("low", "%d"))?;
+                writeln!(f, "guard_high_msg:")?;
+                writeln!(f, "\t.asciz \"{}\\n\"", guard_message("high", "%d"))?;
+            } else if options.tape_section == TapeSection::Data {
+                write_tape(f, Syntax::Gas, options)?;
+            } else if !options.heap_tape() {
+                writeln!(f)?;
+                writeln!(f, ".bss")?;
+                writeln!(f, "tape:")?;
+                writeln!(f, "\t.zero {}", 4 * options.tape_size)?;
+            }
+            writeln!(f)?;
+            writeln!(f, ".text")?;
+            writeln!(f, ".globl {}", options.library.unwrap_or("main"))?;
+            let externs = externs(options, dumps);
+            if !externs.is_empty() {
+                writeln!(f)?;
+            }
+            for name in externs {
+                writeln!(f, ".extern {}", name)?;
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Writes the cells of the tape into the current data section, starting with the bytes of `--tape-init` and zeroed
+/// after them
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+/// * `options` - The settings of the generated assembly
+fn write_tape<W: Write>(f: &mut W, syntax: Syntax, options: &AsmOptions) -> Result<()> {
+    let init = options.tape_init;
+    let Some(zeroed) = (options.tape_size as usize).checked_sub(init.len()) else {
+        return Err(Error::other(format!("tape init of {} bytes does not fit on the tape of {} cells!", init.len(), options.tape_size)));
+    };
+
+    match syntax {
+        Syntax::Nasm if init.is_empty() => writeln!(f, "\ttape times {} dd 0", zeroed)?,
+        Syntax::Nasm => {
+            for (i, chunk) in init.chunks(16).enumerate() {
+                writeln!(f, "\t{}dd {}", if i == 0 { "tape " } else { "" }, join_bytes(chunk))?;
+            }
+            if zeroed > 0 {
+                writeln!(f, "\ttimes {} dd 0", zeroed)?;
+            }
+        },
+        Syntax::Gas => {
+            writeln!(f, "tape:")?;
+            for chunk in init.chunks(16) {
+                writeln!(f, "\t.long {}", join_bytes(chunk))?;
+            }
+            if zeroed > 0 || init.is_empty() {
+                writeln!(f, "\t.zero {}", 4 * zeroed)?;
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Writes the variables of a library into the current data section: the buffers and lengths it was called with, how
+/// far it got in each buffer and its stack frame, which the exit paths return from
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+fn write_library_data<W: Write>(f: &mut W, syntax: Syntax) -> Result<()> {
+    for name in ["out_buf", "out_len", "out_pos", "in_buf", "in_len", "in_pos", "library_frame"] {
+        match syntax {
+            Syntax::Nasm => writeln!(f, "\t{} dq 0", name)?,
+            Syntax::Gas => {
+                writeln!(f, "{}:", name)?;
+                writeln!(f, "\t.quad 0")?;
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the message of exhausted fuel into the current data section
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+fn write_fuel_data<W: Write>(f: &mut W, syntax: Syntax) -> Result<()> {
+    match syntax {
+        Syntax::Nasm => writeln!(f, "\tfuel_msg db \"{}\", 10, 0", FUEL_EXHAUSTED_MESSAGE),
+        Syntax::Gas => {
+            writeln!(f, "fuel_msg:")?;
+            writeln!(f, "\t.asciz \"{}\\n\"", FUEL_EXHAUSTED_MESSAGE)
+        },
+    }
+}
+
+/// Writes the format and the buffer of `$` dumps into the current data section
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+fn write_dump_data<W: Write>(f: &mut W, syntax: Syntax) -> Result<()> {
+    match syntax {
+        Syntax::Nasm => {
+            writeln!(f, "\tdump_msg db \"{}\", 10, 0", dump_message("%d", "%lld", "%d"))?;
+            writeln!(f, "\tdump_buf times {} db 0", DUMP_BUFFER_SIZE)
+        },
+        Syntax::Gas => {
+            writeln!(f, "dump_msg:")?;
+            writeln!(f, "\t.asciz \"{}\\n\"", dump_message("%d", "%lld", "%d"))?;
+            writeln!(f, "dump_buf:")?;
+            writeln!(f, "\t.zero {}", DUMP_BUFFER_SIZE)
+        },
+    }
+}
+
+/// Writes the runtime that programs generated with `--runtime-file` include, which holds every exit path that does
+/// not depend on the program
+/// 
+/// All of them are written whether a program uses them or not, so every program of the same syntax and label prefix
+/// can include the same file. The tape they refer to is declared by the including program.
+/// 
+/// # Arguments
+/// 
+/// * `filename` - The name of the file to create and write to
+/// * `options` - The settings of the generated assembly, of which the syntax and the label prefix are used
+fn write_runtime(filename: &Path, options: &AsmOptions) -> Result<()> {
+    let syntax = options.syntax;
+    let symbols = Symbols::new(options);
+
+    let mut f: Vec<u8> = Vec::new();
+    writeln!(f, "{} bfasm runtime, included at the end of the generated programs", syntax.comment())?;
+    writeln!(f)?;
+    match syntax {
+        Syntax::Nasm => {
+            for name in ["_write", "exit", "printf", "sprintf", "fflush"] {
+                writeln!(f, "extern {}", extern_name(options.crt, name))?;
+            }
+            writeln!(f)?;
+            writeln!(f, "segment .data")?;
+        },
+        Syntax::Gas => {
+            for name in ["_write", "exit", "printf", "sprintf", "fflush"] {
+                writeln!(f, ".extern {}", extern_name(options.crt, name))?;
+            }
+            writeln!(f)?;
+            writeln!(f, ".data")?;
+        },
+    }
+    write_fuel_data(&mut f, syntax)?;
+    write_dump_data(&mut f, syntax)?;
+    writeln!(f)?;
+    match syntax {
+        Syntax::Nasm => writeln!(f, "segment .text")?,
+        Syntax::Gas => writeln!(f, ".text")?,
+    }
+    for helper in Helper::ALL.into_iter().filter(|helper| helper.shared()) {
+        write_helper(&mut f, helper, options, &symbols)?;
+    }
+
+    write_atomically(filename, &f)
+}
+
+/// Writes a helper routine, which the generated program refers to by its label
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `helper` - The helper to write
+/// * `options` - The settings of the generated assembly
+/// * `symbols` - The names the helpers refer to
+fn write_helper<W: Write>(f: &mut W, helper: Helper, options: &AsmOptions, symbols: &Symbols) -> Result<()> {
+    let syntax = options.syntax;
+    let prefix = options.label_prefix;
+
+    match helper {
+        Helper::WriteFailed | Helper::Halt if options.library.is_some() => write_library_exit(f, helper, syntax, prefix),
+        Helper::WriteFailed => write_write_failed(f, symbols, prefix),
+        Helper::Halt => write_halt(f, symbols, prefix),
+        Helper::Dump => write_dump(f, syntax, symbols, prefix),
+        Helper::FuelExhausted => write_fuel_exhausted(f, syntax, symbols, prefix),
+        Helper::CheckGuards => write_check_guards(f, syntax, symbols, options.tape_size, prefix),
+        Helper::ReadInput if options.library.is_some() => write_read_buffer(f, syntax, prefix),
+        Helper::ReadInput => {
+            let len = options.const_input.map_or(0, <[u8]>::len);
+            write_read_input(f, syntax, symbols, len, options.const_input_then_stdin, prefix)
+        },
+        Helper::WriteOutput => write_write_output(f, syntax, prefix),
+        Helper::WriteCoverage => write_write_coverage(f, syntax, symbols, options.coverage.map_or(0, <[coverage::Block]>::len), prefix),
+    }
+}
+
+/// Returns the symbols of the functions of the C runtime that generated assembly calls, in the order they are declared
+/// 
+/// With `--crt dynamic` the exported functions are declared by their import pointers `__imp_<name>`.
+/// 
+/// # Arguments
+/// 
+/// * `options` - The settings of the generated assembly
+/// * `dumps` - Whether the program contains `$`
+fn externs(options: &AsmOptions, dumps: bool) -> Vec<String> {
+    // Libraries read and write buffers instead and return instead of exiting
+    if options.library.is_some() {
+        return Vec::new();
+    }
+
+    let mut names = vec!["_getch", "putchar"];
+
+    // The warning about a truncated argument, the failed allocation of the tape and the dumps are written unbuffered
+    // to stderr as well
+    if options.unbuffered_output || options.argv_tape.is_some() || options.heap_tape() || dumps {
+        names.push("_write");
+    }
+    if options.binary_stdio {
+        names.push("_setmode");
+    }
+    names.push("exit");
+    // The message about exhausted fuel is written with `printf` like those of the tape guard
+    if options.tape_guard != TapeGuard::Off || options.fuel.is_some() {
+        names.push("printf");
+    }
+    if dumps {
+        names.extend(["sprintf", "fflush"]);
+    }
+    if options.heap_tape() {
+        names.push("calloc");
+    }
+    if options.coverage.is_some() {
+        names.extend(["atexit", "fopen", "fprintf", "fclose"]);
+    }
+
+    names.into_iter().map(|name| extern_name(options.crt, name)).collect()
+}
+
+/// Writes the comment documenting what a file including a fragment has to define
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `unbuffered_output` - Whether `.` calls `BF_WRITE` instead of `BF_PUTCHAR`
+fn write_fragment_header<W: Write>(f: &mut W, unbuffered_output: bool) -> Result<()> {
+    writeln!(f, "; bfasm fragment, include it with %include after defining:")?;
+    writeln!(f, ";")?;
+    writeln!(f, ";   BF_TAPE     the base address of the tape, an array of DWORD cells")?;
+    writeln!(f, ";   BF_DP       the QWORD memory operand holding the data pointer, e.g. [dp]")?;
+    writeln!(f, ";   BF_PUTCHAR  the function called with the character to write in ecx")?;
+    writeln!(f, ";   BF_GETCHAR  the function returning the read character in eax")?;
+    writeln!(f, ";   BF_EXIT     the function called with the exit code in ecx when writing failed or at '@'")?;
+    if unbuffered_output {
+        writeln!(f, ";   BF_WRITE    the function called with the file descriptor in ecx, the buffer in rdx")?;
+        writeln!(f, ";               and its length in r8d, returning the amount of written bytes in eax")?;
+        writeln!(f, ";")?;
+        writeln!(f, "; The fragment clobbers rax, rbx, rcx, rdx and r8 and expects the stack to be aligned")?;
+        writeln!(f, "; with shadow space reserved so BF_WRITE and BF_GETCHAR can be called directly.")?;
+    } else {
+        writeln!(f, ";")?;
+        writeln!(f, "; The fragment clobbers rax, rbx and rcx and expects the stack to be aligned")?;
+        writeln!(f, "; with shadow space reserved so BF_PUTCHAR and BF_GETCHAR can be called directly.")?;
+    }
+
+    Ok(())
+}
+
+/// Writes the exit path that `.` jumps to when `putchar` failed, e.g. because stdout is a closed pipe
+/// 
+/// Calling `exit` instead of returning works from any stack frame, including those of lifted loops.
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `symbols` - The names the exit path refers to
+/// * `prefix` - The prefix of generated labels
+fn write_write_failed<W: Write>(f: &mut W, symbols: &Symbols, prefix: &str) -> Result<()> {
+    writeln!(f)?;
+    writeln!(f, "{}:", Helper::WriteFailed.label(prefix))?;
+    writeln!(f, "\tmov ecx, {}", WRITE_FAILED_EXIT_CODE)?;
+    writeln!(f, "\tcall {}", symbols.call(symbols.exit))?;
+
+    Ok(())
+}
+
+/// Writes the exit path that `@` jumps to, which ends the program successfully
+/// 
+/// Like the exit path of failed writes, calling `exit` works from any stack frame and flushes the buffered output.
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `symbols` - The names the exit path refers to
+/// * `prefix` - The prefix of generated labels
+fn write_halt<W: Write>(f: &mut W, symbols: &Symbols, prefix: &str) -> Result<()> {
+    writeln!(f)?;
+    writeln!(f, "{}:", Helper::Halt.label(prefix))?;
+    writeln!(f, "\txor ecx, ecx")?;
+    writeln!(f, "\tcall {}", symbols.call(symbols.exit))?;
+
+    Ok(())
+}
+
+/// The message printed by `$`
+/// 
+/// # Arguments
+/// 
+/// * `pc` - The program counter of the `$`, or a `printf` conversion in generated programs
+/// * `dp` - The data pointer, or a `printf` conversion in generated programs
+/// * `value` - The value of the current cell, or a `printf` conversion in generated programs
+fn dump_message(pc: &str, dp: &str, value: &str) -> String {
+    format!("dump at instruction {}: data pointer at cell {} with value {}", pc, dp, value)
+}
+
+/// Writes the function that `$` calls to print the data pointer and the current cell to stderr
+/// 
+/// It is called with the data pointer in `rbx` and the program counter of the `$` in `edx`. The buffered output is
+/// flushed first, so the dump appears after everything written before it.
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+/// * `symbols` - The names the dump refers to
+/// * `prefix` - The prefix of generated labels
+fn write_dump<W: Write>(f: &mut W, syntax: Syntax, symbols: &Symbols, prefix: &str) -> Result<()> {
+    let (message, buffer) = match syntax {
+        Syntax::Nasm => ("[dump_msg]", "[dump_buf]"),
+        Syntax::Gas => ("[rip + dump_msg]", "[rip + dump_buf]"),
+    };
+
+    writeln!(f)?;
+    writeln!(f, "{}:", Helper::Dump.label(prefix))?;
+    // Realign the stack and reserve shadow space and the fifth argument of `sprintf`
+    writeln!(f, "\tpush rbx")?;
+    writeln!(f, "\tsub rsp, 48")?;
+    writeln!(f, "\tmov [rsp + 40], edx")?;
+    writeln!(f, "\txor ecx, ecx")?;
+    writeln!(f, "\tcall {}", symbols.call("fflush"))?;
+    writeln!(f, "\tmov eax, [{} + 4 * rbx]", symbols.tape)?;
+    writeln!(f, "\tmov [rsp + 32], eax")?;
+    writeln!(f, "\tmov r9, rbx")?;
+    writeln!(f, "\tmov r8d, [rsp + 40]")?;
+    writeln!(f, "\tlea rdx, {}", message)?;
+    writeln!(f, "\tlea rcx, {}", buffer)?;
+    writeln!(f, "\tcall {}", symbols.call("sprintf"))?;
+    writeln!(f, "\tmov r8d, eax")?;
+    writeln!(f, "\tlea rdx, {}", buffer)?;
+    writeln!(f, "\tmov ecx, 2")?;
+    writeln!(f, "\tcall {}", symbols.call("_write"))?;
+    writeln!(f, "\tadd rsp, 48")?;
+    writeln!(f, "\tpop rbx")?;
+    writeln!(f, "\tret")?;
+
+    Ok(())
+}
+
+/// The `fprintf` format of a block's line in the coverage file, which expects its count
+/// 
+/// # Arguments
+/// 
+/// * `blocks` - The blocks of the program
+/// * `i` - The index of the block
+/// * `block` - The block
+fn coverage_entry(blocks: &[coverage::Block], i: usize, block: &coverage::Block) -> String {
+    let separator = if i + 1 < blocks.len() { "," } else { "" };
+    format!("    {}{}\n", coverage::entry(block, "%llu"), separator)
+}
+
+/// Writes the function registered with `atexit` that writes the count of each block to the coverage file
+/// 
+/// The formats of the blocks' lines are looked up in a table, so the function's size does not grow with the program.
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+/// * `symbols` - The names the function refers to
+/// * `blocks` - The amount of blocks
+/// * `prefix` - The prefix of generated labels
+fn write_write_coverage<W: Write>(f: &mut W, syntax: Syntax, symbols: &Symbols, blocks: usize, prefix: &str) -> Result<()> {
+    writeln!(f)?;
+    writeln!(f, "{}:", Helper::WriteCoverage.label(prefix))?;
+    // Keep the file in rbx and the index in rsi, which are preserved by the called functions, and realign the stack
+    writeln!(f, "\tpush rbx")?;
+    writeln!(f, "\tpush rsi")?;
+    writeln!(f, "\tsub rsp, 40")?;
+    writeln!(f, "\tlea rcx, {}", syntax.rip("coverage_path"))?;
+    writeln!(f, "\tlea rdx, {}", syntax.rip("coverage_mode"))?;
+    writeln!(f, "\tcall {}", symbols.call("fopen"))?;
+    writeln!(f, "\ttest rax, rax")?;
+    writeln!(f, "\tjz {}COVERAGE_DONE", prefix)?;
+    writeln!(f, "\tmov rbx, rax")?;
+    writeln!(f, "\tmov rcx, rbx")?;
+    writeln!(f, "\tlea rdx, {}", syntax.rip("coverage_head"))?;
+    writeln!(f, "\tcall {}", symbols.call("fprintf"))?;
+    writeln!(f, "\txor esi, esi")?;
+    if blocks > 0 {
+        writeln!(f, "{}COVERAGE_BLOCK:", prefix)?;
+        writeln!(f, "\tmov rcx, rbx")?;
+        writeln!(f, "\tlea rax, {}", syntax.rip("coverage_entries"))?;
+        writeln!(f, "\tmov rdx, [rax + 8 * rsi]")?;
+        writeln!(f, "\tlea rax, {}", syntax.rip("coverage"))?;
+        writeln!(f, "\tmov r8, [rax + 8 * rsi]")?;
+        writeln!(f, "\tcall {}", symbols.call("fprintf"))?;
+        writeln!(f, "\tinc esi")?;
+        writeln!(f, "\tcmp esi, {}", blocks)?;
+        writeln!(f, "\tjne {}COVERAGE_BLOCK", prefix)?;
+    }
+    writeln!(f, "\tmov rcx, rbx")?;
+    writeln!(f, "\tlea rdx, {}", syntax.rip("coverage_tail"))?;
+    writeln!(f, "\tcall {}", symbols.call("fprintf"))?;
+    writeln!(f, "\tmov rcx, rbx")?;
+    writeln!(f, "\tcall {}", symbols.call("fclose"))?;
+    writeln!(f, "{}COVERAGE_DONE:", prefix)?;
+    writeln!(f, "\tadd rsp, 40")?;
+    writeln!(f, "\tpop rsi")?;
+    writeln!(f, "\tpop rbx")?;
+    writeln!(f, "\tret")?;
+
+    Ok(())
+}
+
+/// Writes the exit path that loops jump to when the program ran out of fuel
+/// 
+/// The loops jump from code that keeps the stack aligned, so `printf` can be called directly. `exit` flushes the
+/// buffered output before the process ends.
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+/// * `symbols` - The names the exit path refers to
+/// * `prefix` - The prefix of generated labels
+fn write_fuel_exhausted<W: Write>(f: &mut W, syntax: Syntax, symbols: &Symbols, prefix: &str) -> Result<()> {
+    let message = match syntax {
+        Syntax::Nasm => "[fuel_msg]",
+        Syntax::Gas => "[rip + fuel_msg]",
+    };
+
+    writeln!(f)?;
+    writeln!(f, "{}:", Helper::FuelExhausted.label(prefix))?;
+    writeln!(f, "\tlea rcx, {}", message)?;
+    writeln!(f, "\tcall {}", symbols.call("printf"))?;
+    writeln!(f, "\tmov ecx, {}", FUEL_EXHAUSTED_EXIT_CODE)?;
+    writeln!(f, "\tcall {}", symbols.call("exit"))?;
+
+    Ok(())
+}
+
+/// Writes the aligned constants added by the vectorized instructions to a read-only section
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+/// * `instructions` - A slice of instructions that contains the program
+/// * `prefix` - The prefix of generated labels
+fn write_vector_constants<W: Write>(f: &mut W, syntax: Syntax, instructions: &[Instruction], prefix: &str) -> Result<()> {
+    let ids = lowering::label_ids(instructions);
+    let vectors: Vec<(usize, &[i32; 4])> = instructions.iter().enumerate()
+        .filter_map(|(pc, instr)| match instr {
+            Instruction::AddVector(_, values) => Some((ids[pc], values)),
+            _ => None,
+        })
+        .collect();
+
+    if vectors.is_empty() {
+        return Ok(());
+    }
+
+    // Each constant is 16 bytes long, so all of them stay aligned
+    writeln!(f)?;
+    match syntax {
+        Syntax::Nasm => writeln!(f, "segment .rdata align=16")?,
+        Syntax::Gas => {
+            writeln!(f, ".section .rdata")?;
+            writeln!(f, ".balign 16")?;
+        },
+    }
+
+    for (id, values) in vectors {
+        let values: Vec<String> = values.iter().map(i32::to_string).collect();
+        match syntax {
+            Syntax::Nasm => writeln!(f, "{}VECTOR_{} dd {}", prefix, id, values.join(", "))?,
+            Syntax::Gas => {
+                writeln!(f, "{}VECTOR_{}:", prefix, id)?;
+                writeln!(f, "\t.long {}", values.join(", "))?;
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// The message reported when a guard cell was clobbered
+/// 
+/// # Arguments
+/// 
+/// * `side` - The guard that was clobbered, `low` or `high`
+/// * `pc` - The program counter of the last executed instruction, or a `printf` conversion in generated programs
+fn guard_message(side: &str, pc: &str) -> String {
+    format!("tape overrun detected ({} guard) - last instruction {}", side, pc)
+}
+
+/// Writes the function that checks the guard cells around the tape and exits if any of them was clobbered
+/// 
+/// It is called with the program counter of the last executed instruction in `edx`, which is reported on failure.
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+/// * `symbols` - The names the exit path refers to
+/// * `tape_size` - The amount of cells on the tape, after which the high guard cells follow
+/// * `prefix` - The prefix of generated labels
+fn write_check_guards<W: Write>(f: &mut W, syntax: Syntax, symbols: &Symbols, tape_size: i64, prefix: &str) -> Result<()> {
+    let dword = syntax.dword();
+
+    // The guard cells are addressed relative to the tape like the cells themselves
+    writeln!(f)?;
+    writeln!(f, "{}:", Helper::CheckGuards.label(prefix))?;
+    writeln!(f, "\txor ebx, ebx")?;
+    for i in 1..=GUARD_CELLS {
+        writeln!(f, "\tcmp {} [tape + 4 * rbx - {}], 0x{:X}", dword, 4 * i, GUARD_PATTERN)?;
+        writeln!(f, "\tjne {}GUARD_LOW_FAILED", prefix)?;
+    }
+    for i in 0..GUARD_CELLS {
+        writeln!(f, "\tcmp {} [tape + 4 * rbx + {}], 0x{:X}", dword, 4 * (tape_size + i64::from(i)), GUARD_PATTERN)?;
+        writeln!(f, "\tjne {}GUARD_HIGH_FAILED", prefix)?;
+    }
+    writeln!(f, "\tret")?;
+
+    let (low, high) = match syntax {
+        Syntax::Nasm => ("[guard_low_msg]", "[guard_high_msg]"),
+        Syntax::Gas => ("[rip + guard_low_msg]", "[rip + guard_high_msg]"),
+    };
+
+    writeln!(f)?;
+    writeln!(f, "{}GUARD_LOW_FAILED:", prefix)?;
+    writeln!(f, "\tlea rcx, {}", low)?;
+    writeln!(f, "\tjmp {}GUARD_FAILED", prefix)?;
+    writeln!(f, "{}GUARD_HIGH_FAILED:", prefix)?;
+    writeln!(f, "\tlea rcx, {}", high)?;
+    writeln!(f, "{}GUARD_FAILED:", prefix)?;
+    // Realign the stack after the call to CHECK_GUARDS and reserve shadow space
+    writeln!(f, "\tsub rsp, 40")?;
+    writeln!(f, "\tcall {}", symbols.call("printf"))?;
+    writeln!(f, "\tmov ecx, {}", TAPE_GUARD_EXIT_CODE)?;
+    writeln!(f, "\tcall {}", symbols.call("exit"))?;
+
+    Ok(())
+}
+
+/// Writes the function that `,` calls to read the next byte of the constant input
+/// 
+/// It returns the byte in `eax`, or -1 once the input is exhausted unless it falls back to stdin.
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+/// * `symbols` - The names of the called functions
+/// * `len` - The length of the constant input in bytes
+/// * `then_stdin` - Whether the function reads from stdin once the input is exhausted
+/// * `prefix` - The prefix of generated labels
+fn write_read_input<W: Write>(f: &mut W, syntax: Syntax, symbols: &Symbols, len: usize, then_stdin: bool, prefix: &str) -> Result<()> {
+    let (pos, input, byte) = match syntax {
+        Syntax::Nasm => ("[input_pos]", "[input]", "byte"),
+        Syntax::Gas => ("[rip + input_pos]", "[rip + input]", "byte ptr"),
+    };
+
+    writeln!(f)?;
+    writeln!(f, "{}:", Helper::ReadInput.label(prefix))?;
+    writeln!(f, "\tmov eax, {}", pos)?;
+    writeln!(f, "\tcmp eax, {}", len)?;
+    writeln!(f, "\tjae {}READ_INPUT_EXHAUSTED", prefix)?;
+    writeln!(f, "\tinc {} {}", syntax.dword(), pos)?;
+    writeln!(f, "\tlea rcx, {}", input)?;
+    writeln!(f, "\tmovzx eax, {} [rcx + rax]", byte)?;
+    writeln!(f, "\tret")?;
+    writeln!(f, "{}READ_INPUT_EXHAUSTED:", prefix)?;
+    if then_stdin {
+        // Realign the stack after the call to READ_INPUT and reserve shadow space
+        writeln!(f, "\tsub rsp, 40")?;
+        writeln!(f, "\tcall {}", symbols.call(symbols.getchar))?;
+        writeln!(f, "\tadd rsp, 40")?;
+    } else {
+        writeln!(f, "\tmov eax, -1")?;
+    }
+    writeln!(f, "\tret")?;
+
+    Ok(())
+}
+
+/// Writes the function that `,` calls in a library to read the next byte of its input buffer into `eax`, or -1 once
+/// the buffer is exhausted
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+/// * `prefix` - The prefix of generated labels
+fn write_read_buffer<W: Write>(f: &mut W, syntax: Syntax, prefix: &str) -> Result<()> {
+    writeln!(f)?;
+    writeln!(f, "{}:", Helper::ReadInput.label(prefix))?;
+    writeln!(f, "\tmov rax, {}", syntax.rip("in_pos"))?;
+    writeln!(f, "\tcmp rax, {}", syntax.rip("in_len"))?;
+    writeln!(f, "\tjae {}READ_INPUT_EXHAUSTED", prefix)?;
+    writeln!(f, "\tinc {} {}", syntax.qword(), syntax.rip("in_pos"))?;
+    writeln!(f, "\tmov rcx, {}", syntax.rip("in_buf"))?;
+    writeln!(f, "\tmovzx eax, {} [rcx + rax]", syntax.byte())?;
+    writeln!(f, "\tret")?;
+    writeln!(f, "{}READ_INPUT_EXHAUSTED:", prefix)?;
+    writeln!(f, "\tmov eax, -1")?;
+    writeln!(f, "\tret")?;
+
+    Ok(())
+}
+
+/// Writes the function that `.` calls in a library to store the byte in `cl` in its output buffer
+///
+/// It returns 0 in `eax`, or -1 without storing the byte if the buffer is full, which `.` treats like a failed write.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+/// * `prefix` - The prefix of generated labels
+fn write_write_output<W: Write>(f: &mut W, syntax: Syntax, prefix: &str) -> Result<()> {
+    writeln!(f)?;
+    writeln!(f, "{}:", Helper::WriteOutput.label(prefix))?;
+    writeln!(f, "\tmov rax, {}", syntax.rip("out_pos"))?;
+    writeln!(f, "\tcmp rax, {}", syntax.rip("out_len"))?;
+    writeln!(f, "\tjae {}WRITE_OUTPUT_FULL", prefix)?;
+    writeln!(f, "\tinc {} {}", syntax.qword(), syntax.rip("out_pos"))?;
+    writeln!(f, "\tmov rdx, {}", syntax.rip("out_buf"))?;
+    writeln!(f, "\tmov {} [rdx + rax], cl", syntax.byte())?;
+    writeln!(f, "\txor eax, eax")?;
+    writeln!(f, "\tret")?;
+    writeln!(f, "{}WRITE_OUTPUT_FULL:", prefix)?;
+    writeln!(f, "\tmov eax, -1")?;
+    writeln!(f, "\tret")?;
+
+    Ok(())
+}
+
+/// Writes the exit path of a library that failed writes and `@` jump to, which returns from its function instead of
+/// exiting the process
+///
+/// The stack frame of the function is restored first, as the exit paths are also jumped to from lifted loops.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `helper` - The exit path to write, `WriteFailed` or `Halt`
+/// * `syntax` - The assembler syntax to write
+/// * `prefix` - The prefix of generated labels
+fn write_library_exit<W: Write>(f: &mut W, helper: Helper, syntax: Syntax, prefix: &str) -> Result<()> {
+    writeln!(f)?;
+    writeln!(f, "{}:", helper.label(prefix))?;
+    writeln!(f, "\tmov rbp, {}", syntax.rip("library_frame"))?;
+    writeln!(f, "\tjmp {}LIBRARY_RETURN", prefix)?;
+
+    Ok(())
+}
+
+/// Joins bytes into the comma separated operands of a data directive
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - The bytes to join
+fn join_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| byte.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+/// Replaces the escape sequences `\n`, `\r`, `\t`, `\0`, `\\` and `\xHH` in a command line argument by the bytes they stand for
+/// 
+/// # Arguments
+/// 
+/// * `text` - The text to unescape
+fn unescape(text: &str) -> std::result::Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len());
+
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0u8; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(byte) if hex.len() == 2 => bytes.push(byte),
+                    _ => return Err(format!("invalid escape sequence '\\x{}'!", hex)),
+                }
+            },
+            Some(c) => return Err(format!("invalid escape sequence '\\{}'!", c)),
+            None => return Err("escape sequence at the end of the input!".to_owned()),
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// A writer that counts the lines written through it
+struct LineCounter<W: Write> {
+    /// The writer to forward to
+    inner: W,
+    /// The amount of complete lines written so far
+    lines: usize,
+}
+
+impl<W: Write> Write for LineCounter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.lines += buf[..written].iter().filter(|b| **b == b'\n').count();
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the instructions that copy the bytes of `argv[1]` and its terminating NUL to the tape, one byte per cell
+/// 
+/// Without an argument the tape stays zeroed. An argument that does not fit is truncated at the end of the tape with
+/// a warning on stderr.
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+/// * `symbols` - The names of the tape and the called functions
+/// * `cell` - The cell the first byte is copied to
+/// * `tape_size` - The amount of cells on the tape, which the argument is truncated to
+/// * `prefix` - The prefix of generated labels
+fn write_argv_tape<W: Write>(f: &mut W, syntax: Syntax, symbols: &Symbols, cell: i32, tape_size: i64, prefix: &str) -> Result<()> {
+    let message = match syntax {
+        Syntax::Nasm => "[argv_truncated_msg]",
+        Syntax::Gas => "[rip + argv_truncated_msg]",
+    };
+
+    // `argc` is in ecx and `argv` in rdx, the cells are counted in ebx
+    writeln!(f)?;
+    writeln!(f, "\tcmp ecx, 2")?;
+    writeln!(f, "\tjl {}ARGV_END", prefix)?;
+    writeln!(f, "\tmov r10, [rdx + 8]")?;
+    writeln!(f, "\txor ebx, ebx")?;
+    writeln!(f, "{}ARGV_COPY:", prefix)?;
+    writeln!(f, "\tcmp ebx, {}", tape_size - i64::from(cell))?;
+    writeln!(f, "\tjae {}ARGV_TRUNCATED", prefix)?;
+    writeln!(f, "\tmovzx eax, {} [r10 + rbx]", if syntax == Syntax::Nasm { "byte" } else { "byte ptr" })?;
+    writeln!(f, "\tmov [{} + 4 * rbx + {}], eax", symbols.tape, 4 * cell)?;
+    writeln!(f, "\ttest eax, eax")?;
+    writeln!(f, "\tjz {}ARGV_END", prefix)?;
+    writeln!(f, "\tinc ebx")?;
+    writeln!(f, "\tjmp {}ARGV_COPY", prefix)?;
+    writeln!(f, "{}ARGV_TRUNCATED:", prefix)?;
+    writeln!(f, "\tmov ecx, 2")?;
+    writeln!(f, "\tlea rdx, {}", message)?;
+    writeln!(f, "\tmov r8d, {}", ARGV_TRUNCATED_WARNING.len() + 1)?;
+    writeln!(f, "\tcall {}", symbols.call(symbols.write))?;
+    writeln!(f, "{}ARGV_END:", prefix)?;
+
+    Ok(())
+}
+
+/// Writes the allocation of a tape that is too large for the image, zeroed by `calloc`, whose base is kept in `r12`
+///
+/// If it cannot be allocated, the program stops with the exit code of an exceeded limit.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `options` - The settings of the generated assembly, which decide the size of the tape
+/// * `symbols` - The names of the called functions
+/// * `prefix` - The prefix of generated labels
+fn write_tape_allocation<W: Write>(f: &mut W, options: &AsmOptions, symbols: &Symbols, prefix: &str) -> Result<()> {
+    writeln!(f)?;
+    writeln!(f, "\tmov rcx, {}", options.tape_size)?;
+    writeln!(f, "\tmov edx, 4")?;
+    writeln!(f, "\tcall {}", symbols.call("calloc"))?;
+    writeln!(f, "\ttest rax, rax")?;
+    writeln!(f, "\tjnz {}TAPE_ALLOCATED", prefix)?;
+    writeln!(f, "\tmov ecx, 2")?;
+    writeln!(f, "\tlea rdx, {}", options.syntax.rip("tape_allocation_msg"))?;
+    writeln!(f, "\tmov r8d, {}", TAPE_ALLOCATION_FAILED_MESSAGE.len() + 1)?;
+    writeln!(f, "\tcall {}", symbols.call(symbols.write))?;
+    writeln!(f, "\tmov ecx, {}", LIMIT_EXCEEDED_EXIT_CODE)?;
+    writeln!(f, "\tcall {}", symbols.call(symbols.exit))?;
+    writeln!(f, "{}TAPE_ALLOCATED:", prefix)?;
+    writeln!(f, "\tmov r12, rax")?;
+
+    Ok(())
+}
+
+/// Returns the memory dump and the JSON state of a finished program, each if it was asked for
+///
+/// # Arguments
+///
+/// * `memory` - The tape of the finished program
+/// * `dump_memory` - Whether the memory dump is returned
+/// * `dump_state` - Whether the JSON state is returned
+fn memory_dumps<C: interpreter::Cell>(memory: &interpreter::Memory<C>, dump_memory: bool, dump_state: bool) -> (Option<Vec<u8>>, Option<Vec<u8>>) {
+    let write = |dump: fn(&mut Vec<u8>, &interpreter::Memory<C>) -> Result<()>| {
+        let mut bytes: Vec<u8> = Vec::new();
+        dump(&mut bytes, memory).map(|_| bytes).ok()
+    };
+
+    (dump_memory.then(|| write(interpreter::write_memory)).flatten(), dump_state.then(|| write(interpreter::write_state_json)).flatten())
+}
+
+/// Copies the bytes of an argument and its terminating NUL to the initial contents of the interpreter's tape
+/// 
+/// An argument that does not fit is truncated at the end of the tape with a warning, like in generated programs.
+/// 
+/// # Arguments
+/// 
+/// * `init` - The initial contents of the tape, which grow as needed
+/// * `arg` - The bytes of the argument
+/// * `cell` - The cell the first byte is copied to
+/// * `tape_size` - The amount of cells on the tape, which the argument is truncated to
+fn copy_arg(init: &mut Vec<u8>, arg: &[u8], cell: usize, tape_size: usize) {
+    let mut bytes = arg.to_vec();
+    bytes.push(0);
+
+    let fits = tape_size - cell;
+    if bytes.len() > fits {
+        eprintln!("{}", ARGV_TRUNCATED_WARNING);
+        bytes.truncate(fits);
+    }
+
+    if init.len() < cell + bytes.len() {
+        init.resize(cell + bytes.len(), 0);
+    }
+    init[cell..cell + bytes.len()].copy_from_slice(&bytes);
+}
+
+/// Writes the calls that switch stdin and stdout to binary mode, so the C runtime neither turns `10` into `13, 10`
+/// on output nor stops at `26` on input
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `symbols` - The names the calls refer to
+fn write_binary_stdio<W: Write>(f: &mut W, symbols: &Symbols) -> Result<()> {
+    // The file descriptors of stdin (0) and stdout (1) are fixed, `_O_BINARY` is 0x8000
+    for fd in 0..2 {
+        writeln!(f, "\tmov ecx, {}", fd)?;
+        writeln!(f, "\tmov edx, 0x8000")?;
+        writeln!(f, "\tcall {}", symbols.call("_setmode"))?;
+    }
+
+    Ok(())
+}
+
+/// Writes the instructions that set up a stack frame with shadow space
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `saved` - The callee-saved registers that are pushed below the frame pointer, as they hold state for the whole
+///   program
+fn write_prologue<W: Write>(f: &mut W, saved: &[&str]) -> Result<()> {
+    writeln!(f, "\tpush rbp")?;
+    writeln!(f, "\tmov rbp, rsp")?;
+    for register in saved {
+        writeln!(f, "\tpush {}", register)?;
+    }
+    // The extra 8 bytes realign the stack after an odd amount of pushes
+    writeln!(f, "\tsub rsp, {}", 32 + 8 * (saved.len() % 2))?;
+
+    Ok(())
+}
+
+/// Writes the prologue of a library's function, which saves the registers the calling convention requires it to
+/// preserve, remembers its arguments and clears the tape, so every call starts on a fresh one
+///
+/// The output buffer and its length are passed in `rcx` and `rdx`, the input buffer and its length in `r8` and `r9`.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `options` - The settings of the generated assembly
+fn write_library_prologue<W: Write>(f: &mut W, options: &AsmOptions) -> Result<()> {
+    let syntax = options.syntax;
+
+    // Two pushes keep the stack aligned like the frame of `write_prologue`
+    writeln!(f, "\tpush rbp")?;
+    writeln!(f, "\tmov rbp, rsp")?;
+    writeln!(f, "\tpush rbx")?;
+    writeln!(f, "\tpush rdi")?;
+    writeln!(f, "\tsub rsp, 32")?;
+    writeln!(f, "\tmov {}, rbp", syntax.rip("library_frame"))?;
+    for (name, register) in [("out_buf", "rcx"), ("out_len", "rdx"), ("in_buf", "r8"), ("in_len", "r9")] {
+        writeln!(f, "\tmov {}, {}", syntax.rip(name), register)?;
+    }
+    writeln!(f, "\txor eax, eax")?;
+    writeln!(f, "\tmov {}, rax", syntax.rip("out_pos"))?;
+    writeln!(f, "\tmov {}, rax", syntax.rip("in_pos"))?;
+    writeln!(f, "\tmov {}, rax", syntax.dp())?;
+    if options.dp_register {
+        writeln!(f, "\txor ebx, ebx")?;
+    }
+    writeln!(f, "\tlea rdi, {}", syntax.rip("tape"))?;
+    writeln!(f, "\tmov ecx, {}", options.tape_size)?;
+    writeln!(f, "\trep stosd")?;
+
+    Ok(())
+}
+
+/// Writes the end of a library's function, which returns the amount of bytes written to the output buffer
+///
+/// The exit paths jump to it from any stack frame after restoring the one of the function.
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `syntax` - The assembler syntax to write
+/// * `prefix` - The prefix of generated labels
+fn write_library_epilogue<W: Write>(f: &mut W, syntax: Syntax, prefix: &str) -> Result<()> {
+    writeln!(f)?;
+    writeln!(f, "{}LIBRARY_RETURN:", prefix)?;
+    writeln!(f, "\tmov rax, {}", syntax.rip("out_pos"))?;
+    writeln!(f, "\tlea rsp, [rbp - 16]")?;
+    writeln!(f, "\tpop rdi")?;
+    writeln!(f, "\tpop rbx")?;
+    writeln!(f, "\tpop rbp")?;
+    writeln!(f, "\tret")?;
+
+    Ok(())
+}
+
+/// Writes the instructions that leave a stack frame set up by `write_prologue`
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `saved` - The registers the prologue saved, which are restored
+fn write_epilogue<W: Write>(f: &mut W, saved: &[&str]) -> Result<()> {
+    for (i, register) in saved.iter().enumerate() {
+        writeln!(f, "\tmov {}, [rbp - {}]", register, 8 * (i + 1))?;
+    }
+    writeln!(f, "\tmov rsp, rbp")?;
+    writeln!(f, "\tpop rbp")?;
+
+    Ok(())
+}
+
+/// Writes the assembly corresponding to a single lowered instruction
+/// 
+/// # Arguments
+/// 
+/// * `f` - The writer to write to
+/// * `ops` - The operations the instruction was lowered to
+/// * `emitter` - The backend writing the lowered instruction
+fn write_lowered<W: Write>(f: &mut W, ops: Vec<lowering::LowOp>, emitter: &dyn Emitter) -> Result<()> {
+    for op in ops {
+        emitter.emit(f, &op)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_loop_instructions_have_a_matching_bracket() {
+        let program = parse("+[>[-]<]", Extensions::default()).unwrap();
+        assert_eq!(matching_bracket(&program.instructions, 1), Some(7));
+        assert_eq!(matching_bracket(&program.instructions, 7), Some(1));
+        assert_eq!(matching_bracket(&program.instructions, 3), Some(5));
+        assert_eq!(matching_bracket(&program.instructions, 0), None);
+        assert_eq!(matching_bracket(&program.instructions, 8), None);
+    }
+
+    #[test]
+    fn programs_compile_in_memory() {
+        let asm = compile_str("+[-].", &AsmOptions::default()).unwrap();
+        assert!(asm.starts_with("; source sha256: "));
+        for line in ["main:", "\tinc dword [tape + 4 * rbx]", "JUMP_0:", "\tje RETURN_0", "\tjne JUMP_0", "\tcall putchar"] {
+            assert!(asm.lines().any(|l| l == line), "missing {:?}", line);
+        }
+
+        let gas = compile_str("+.", &AsmOptions { syntax: Syntax::Gas, ..AsmOptions::default() }).unwrap();
+        assert!(gas.lines().any(|l| l == "\tinc dword ptr [tape + 4 * rbx]"));
+
+        assert!(compile_str("[", &AsmOptions::default()).is_err());
+    }
+
+    #[test]
+    fn arguments_are_copied_up_to_the_end_of_the_tape() {
+        let options = AsmOptions { argv_tape: Some(2), tape_size: 1000, ..AsmOptions::default() };
+        assert!(compile_str(".", &options).unwrap().lines().any(|line| line == "\tcmp ebx, 998"));
+
+        let outside = AsmOptions { argv_tape: Some(1000), ..options };
+        assert_eq!(compile_str(".", &outside).err().unwrap().to_string(), "'--argv-tape-at' has to be a cell between 0 and 999!");
+
+        let mut init = Vec::new();
+        copy_arg(&mut init, b"hello", 300, 304);
+        assert_eq!(init[300..], *b"hell");
+    }
+}
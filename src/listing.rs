@@ -0,0 +1,125 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, Result, Write};
+use std::ops::Range;
+
+use crate::{lowering, AsmOptions, Instruction, Program};
+
+/// Writes a listing that correlates each source line with its instructions and the assembly lines emitted for them
+///
+/// Consecutive identical instructions on the same line are merged into one entry. Loop brackets are annotated with
+/// the labels the assembly uses for them.
+///
+/// # Arguments
+///
+/// * `filename` - The name of the file to create and write to
+/// * `source_name` - The name of the source file, used in the listing's header
+/// * `source` - The program's source code
+/// * `program` - The parsed program
+/// * `ranges` - The range of assembly lines emitted for each instruction, as returned by `write_asm`
+/// * `options` - The settings the assembly was generated with
+pub fn write_listing(filename: &str, source_name: &str, source: &str, program: &Program, ranges: &[Range<usize>], options: &AsmOptions) -> Result<()> {
+    let file = File::create(filename);
+
+    if let Ok(file) = file {
+        let mut f = BufWriter::new(file);
+
+        writeln!(f, "; bfasm listing of {}", source_name)?;
+        writeln!(f, ";")?;
+        writeln!(f, "; Each source line is followed by the instructions parsed from it and the")?;
+        writeln!(f, "; assembly lines they were emitted as. Consecutive identical instructions")?;
+        writeln!(f, "; are merged into one entry.")?;
+
+        let prefix = options.label_prefix;
+        let ids = lowering::label_ids(&program.instructions);
+
+        // The index of the next instruction to list
+        let mut pc = 0;
+
+        // The nesting depth of loops, used to tell which loops were lifted into functions
+        let mut depth = 0;
+
+        for (line, l) in source.lines().enumerate() {
+            writeln!(f)?;
+            writeln!(f, "{:>5} | {}", line + 1, l)?;
+
+            while pc < program.instructions.len() && program.positions[pc].line == line + 1 {
+                let instr = &program.instructions[pc];
+                let column = program.positions[pc].column;
+
+                // Merge following instructions of the same kind on this line
+                let mut end = pc + 1;
+                if !matches!(instr, Instruction::Jump(_) | Instruction::Return(_) | Instruction::Label(_) | Instruction::Scan(_)) {
+                    while end < program.instructions.len()
+                        && program.positions[end].line == line + 1
+                        && std::mem::discriminant(&program.instructions[end]) == std::mem::discriminant(instr)
+                        && ranges[end].start == ranges[end - 1].end {
+                        end += 1;
+                    }
+                }
+
+                let name = if end - pc > 1 {
+                    format!("{:?} x{}", instr, end - pc)
+                } else {
+                    format!("{:?}", instr)
+                };
+
+                let asm = format!("asm {}-{}", ranges[pc].start, ranges[end - 1].end - 1);
+
+                // Note the labels at brackets
+                let label = match instr {
+                    Instruction::Jump(_) => {
+                        depth += 1;
+                        if options.split_functions && depth == 1 {
+                            format!("{}LOOP_{}, {}JUMP_{}", prefix, ids[pc], prefix, ids[pc])
+                        } else {
+                            format!("{}JUMP_{}", prefix, ids[pc])
+                        }
+                    },
+                    Instruction::Return(_) => {
+                        depth -= 1;
+                        format!("{}RETURN_{}", prefix, ids[pc])
+                    },
+                    Instruction::Label(name) => format!("{}USER_{}", prefix, name),
+                    Instruction::Scan(_) => format!("{}SCAN_{}", prefix, ids[pc]),
+                    _ => String::new(),
+                };
+
+                let entry = format!("      |   col {:<4} {:<16} {:<14} {}", column, name, asm, label);
+                writeln!(f, "{}", entry.trim_end())?;
+
+                pc = end;
+            }
+        }
+
+        f.flush()
+    } else {
+        // Return error on failure
+        Err(Error::other("could not write to file!"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate_asm, parse, Extensions};
+
+    /// The listing of `tests/Hello_World.bf` at `-O0` without any flags, which `bfasm --emit lst Hello_World.bf`
+    /// writes in the `tests` directory
+    const HELLO_WORLD_LISTING: &str = include_str!("../tests/Hello_World.lst");
+
+    #[test]
+    fn hello_world_listing_matches_the_golden_file() {
+        let source = include_str!("../tests/Hello_World.bf");
+        let program = parse(source, Extensions::default()).unwrap();
+        let options = AsmOptions::default();
+        let (_, ranges) = generate_asm(source, &program.instructions, &options).unwrap();
+
+        let filename = std::env::temp_dir().join(format!("bfasm-listing-{}.lst", std::process::id()));
+        let filename = filename.to_string_lossy();
+        write_listing(&filename, "Hello_World.bf", source, &program, &ranges, &options).unwrap();
+        let listing = std::fs::read_to_string(&*filename).unwrap();
+        let _ = std::fs::remove_file(&*filename);
+
+        assert_eq!(listing, HELLO_WORLD_LISTING);
+    }
+}
@@ -0,0 +1,603 @@
+use std::collections::BTreeSet;
+
+use crate::{coverage, matching_bracket, optimizer, AsmOptions, Instruction, TapeGuard};
+
+/// A 32 bit register used by the lowered operations
+#[derive(Clone, Copy, PartialEq)]
+pub enum Register {
+    /// Holds results of calls and the value being multiplied
+    Eax,
+    /// Holds the first argument of calls
+    Ecx,
+    /// Holds the second argument of calls
+    Edx,
+    /// Holds the third argument of calls
+    R8d,
+}
+
+/// A function called by the lowered operations
+pub enum Function {
+    /// Writes the character in the first argument
+    Putchar,
+    /// Returns a read character
+    Getchar,
+    /// Writes a buffer of the length in the third argument to the file descriptor in the first argument
+    Write,
+    /// A function generated alongside the program, like a lifted loop
+    Local(String),
+}
+
+/// A routine that is written once after the program and that instructions jump to or call
+///
+/// The variants are in the order the routines are written.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Helper {
+    /// The exit path of failed writes
+    WriteFailed,
+    /// The exit path of `@`
+    Halt,
+    /// The function printing a `$` dump
+    Dump,
+    /// The exit path of loops that ran out of fuel
+    FuelExhausted,
+    /// The function checking the guard cells around the tape
+    CheckGuards,
+    /// The function reading the next byte of the constant input or of the input buffer of a library
+    ReadInput,
+    /// The function storing a written byte in the output buffer of a library
+    WriteOutput,
+    /// The function registered with `atexit` that writes the coverage file
+    WriteCoverage,
+}
+
+impl Helper {
+    /// Every helper in the order they are written
+    pub const ALL: [Helper; 8] = [
+        Helper::WriteFailed,
+        Helper::Halt,
+        Helper::Dump,
+        Helper::FuelExhausted,
+        Helper::CheckGuards,
+        Helper::ReadInput,
+        Helper::WriteOutput,
+        Helper::WriteCoverage,
+    ];
+
+    /// The label of the helper's entry
+    ///
+    /// It cannot collide with the labels of instructions, which end in their number or are prefixed with `USER_`.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of generated labels
+    pub fn label(self, prefix: &str) -> String {
+        let name = match self {
+            Helper::WriteFailed => "WRITE_FAILED",
+            Helper::Halt => "HALT",
+            Helper::Dump => "DUMP",
+            Helper::FuelExhausted => "FUEL_EXHAUSTED",
+            Helper::CheckGuards => "CHECK_GUARDS",
+            Helper::ReadInput => "READ_INPUT",
+            Helper::WriteOutput => "WRITE_OUTPUT",
+            Helper::WriteCoverage => "WRITE_COVERAGE",
+        };
+        format!("{}{}", prefix, name)
+    }
+
+    /// Whether the helper does not depend on the program, so that the shared runtime of `--runtime-file` holds it
+    pub fn shared(self) -> bool {
+        matches!(self, Helper::WriteFailed | Helper::Halt | Helper::Dump | Helper::FuelExhausted)
+    }
+}
+
+/// Returns the helpers that the lowered instructions of a program refer to
+///
+/// Each feature that needs a helper registers it here, and each helper is written once however many instructions
+/// refer to it. The exit path of failed writes is registered for every program.
+///
+/// # Arguments
+///
+/// * `instructions` - A slice of instructions that contains the program
+/// * `options` - The settings of the generated assembly
+pub fn helpers(instructions: &[Instruction], options: &AsmOptions) -> BTreeSet<Helper> {
+    let mut helpers = BTreeSet::from([Helper::WriteFailed]);
+
+    if instructions.iter().any(|instr| matches!(instr, Instruction::Halt)) {
+        helpers.insert(Helper::Halt);
+    }
+    if instructions.iter().any(|instr| matches!(instr, Instruction::Dump)) {
+        helpers.insert(Helper::Dump);
+    }
+    if options.fuel.is_some() {
+        helpers.insert(Helper::FuelExhausted);
+    }
+    if options.tape_guard != TapeGuard::Off {
+        helpers.insert(Helper::CheckGuards);
+    }
+    if options.const_input.is_some() || options.library.is_some() {
+        helpers.insert(Helper::ReadInput);
+    }
+    if options.library.is_some() {
+        helpers.insert(Helper::WriteOutput);
+    }
+    if options.coverage.is_some() {
+        helpers.insert(Helper::WriteCoverage);
+    }
+
+    helpers
+}
+
+/// The condition of a conditional jump
+#[derive(Clone, Copy, PartialEq)]
+pub enum Condition {
+    /// Jump if the compared values are equal
+    Equal,
+    /// Jump if the compared values are not equal
+    NotEqual,
+}
+
+/// An assembler-agnostic low-level operation
+///
+/// The operations work on an implicit index register that holds a copy of the data pointer, cells are addressed at
+/// offsets from it.
+pub enum LowOp {
+    /// Defines a label
+    Label(String),
+    /// Loads the data pointer into the index register
+    LoadIndex,
+    /// Stores the index register into the data pointer
+    StoreIndex,
+    /// Adds a value to the index register
+    AddIndex(i32),
+    /// Adds a value to the data pointer in memory
+    AddDp(i32),
+    /// Adds a value to the cell at an offset from the current one
+    AddCell(i32, i32),
+    /// Sets the cell at an offset from the current one to a value
+    SetCell(i32, i32),
+    /// Loads the cell at an offset from the current one into a register
+    LoadCell(i32, Register),
+    /// Stores a register into the cell at an offset from the current one
+    StoreCell(i32, Register),
+    /// Adds a register to the cell at an offset from the current one
+    AddRegisterToCell(i32, Register),
+    /// Loads the 64 bit address of the cell at an offset from the current one into a register
+    CellAddress(i32, Register),
+    /// Sets a register to a value
+    SetRegister(Register, i32),
+    /// Multiplies a register by a value
+    Multiply(Register, i32),
+    /// Adds the four values of a constant to the four cells starting at an offset from the current one
+    AddVectorToCells(i32, String),
+    /// Calls a function
+    Call(Function),
+    /// Compares the current cell with zero and jumps to a label if the condition holds
+    JumpIfCellZero(Condition, String),
+    /// Compares a register with a value and jumps to a label if the condition holds
+    JumpIfRegister(Register, i32, Condition, String),
+    /// Jumps to a label unconditionally
+    Jump(String),
+    /// Decrements the remaining fuel and jumps to a label once it is used up
+    ConsumeFuel(String),
+    /// Increments the counter of the block with an index
+    CountBlock(usize),
+    /// Stops in an attached debugger
+    Breakpoint,
+}
+
+/// Returns for each instruction whether the index register already holds the data pointer because of a `PinBase`
+///
+/// A `PinBase` pins the loop directly following it, from its `Jump` to its `Return`. The optimizer only inserts it
+/// before loops that never move the data pointer.
+///
+/// # Arguments
+///
+/// * `instructions` - A slice of instructions that contains the program
+pub fn pinned(instructions: &[Instruction]) -> Vec<bool> {
+    let mut pinned = vec![false; instructions.len()];
+
+    for (pc, instr) in instructions.iter().enumerate() {
+        // A `PinBase` always precedes a `Jump`, whose partner comes after it
+        if let (Instruction::PinBase, Some(end)) = (instr, matching_bracket(instructions, pc + 1)) {
+            pinned[pc + 1..=end].fill(true);
+        }
+    }
+
+    pinned
+}
+
+/// Returns for each instruction the offset of the data pointer from the cell it started at, if it is known without
+/// running the program
+///
+/// The offset stays known through loops whose bodies, and those of their nested loops, return to the cell they started
+/// at. It is unknown inside and after any other loop and after a scan, for the rest of the program.
+///
+/// # Arguments
+///
+/// * `instructions` - A slice of instructions that contains the program
+pub fn dp_offsets(instructions: &[Instruction]) -> Vec<Option<i64>> {
+    let mut offsets = vec![None; instructions.len()];
+    let mut offset = Some(0);
+
+    for (pc, instr) in instructions.iter().enumerate() {
+        offsets[pc] = offset;
+        offset = match instr {
+            Instruction::Increment => offset.map(|offset| offset + 1),
+            Instruction::Decrement => offset.map(|offset| offset - 1),
+            Instruction::MoveN(n) => offset.map(|offset| offset + *n as i64),
+            Instruction::Scan(_) => None,
+            Instruction::Jump(end) => offset.filter(|_| balanced(&instructions[pc..=*end as usize])),
+            _ => offset,
+        };
+    }
+
+    offsets
+}
+
+/// Returns whether a loop and every loop nested in it return to the cell they started at
+///
+/// # Arguments
+///
+/// * `instructions` - The instructions of the loop from its `Jump` to its `Return`
+fn balanced(instructions: &[Instruction]) -> bool {
+    // The net moves of the loops that are not closed yet
+    let mut moves: Vec<i64> = Vec::new();
+
+    for instr in instructions {
+        let delta = match instr {
+            Instruction::Increment => 1,
+            Instruction::Decrement => -1,
+            Instruction::MoveN(n) => *n as i64,
+            Instruction::Scan(_) => return false,
+            Instruction::Jump(_) => {
+                moves.push(0);
+                continue;
+            },
+            Instruction::Return(_) => {
+                if moves.pop() != Some(0) {
+                    return false;
+                }
+                continue;
+            },
+            _ => 0,
+        };
+        moves.iter_mut().for_each(|moved| *moved += delta);
+    }
+
+    true
+}
+
+/// Returns for each instruction whether `eax` still holds the current cell, unchanged, when it starts
+///
+/// A copy loop like `[->+>+>++<<<]` becomes a run of `MultiplyAdd`s that all read the same counter cell, so only the
+/// first of them has to load it as long as the ones before did not multiply it. A read leaves the byte it stored in
+/// `eax` as well, so the guard of a loop like `,[.,]` tests the register instead of reading the cell again.
+///
+/// # Arguments
+///
+/// * `instructions` - A slice of instructions that contains the program
+/// * `options` - The settings of the generated assembly
+pub fn cell_loaded(instructions: &[Instruction], options: &AsmOptions) -> Vec<bool> {
+    let mut loaded = vec![false; instructions.len()];
+
+    for (pc, pair) in instructions.windows(2).enumerate() {
+        loaded[pc + 1] = keeps_cell(&pair[0], &pair[1], options);
+    }
+
+    loaded
+}
+
+/// Returns whether `eax` still holds the current cell after an instruction, and the next instruction can make use of it
+///
+/// Only the plain `getchar` leaves the cell in `eax`: the embedded input and the input buffer leave the cell
+/// unchanged once they are exhausted, and the aggressive guard check clobbers the register.
+///
+/// # Arguments
+///
+/// * `instr` - The instruction
+/// * `next` - The instruction following it
+/// * `options` - The settings of the generated assembly
+pub fn keeps_cell(instr: &Instruction, next: &Instruction, options: &AsmOptions) -> bool {
+    let keeps = match instr {
+        Instruction::MultiplyAdd(_, 1) => true,
+        Instruction::Read => {
+            options.const_input.is_none() && options.library.is_none() && options.tape_guard != TapeGuard::Aggressive
+        },
+        _ => false,
+    };
+
+    keeps && matches!(next, Instruction::MultiplyAdd(..) | Instruction::Jump(_) | Instruction::Return(_))
+}
+
+/// Returns for each instruction its number among the instructions of its kind, in order of appearance, which names
+/// its labels
+///
+/// Loops, scans, reads and vectorized instructions are numbered separately and a `Return` shares the number of its
+/// `Jump`, so adding an instruction to a program only renames the labels of later instructions of the same kind.
+///
+/// # Arguments
+///
+/// * `instructions` - A slice of instructions that contains the program
+pub fn label_ids(instructions: &[Instruction]) -> Vec<usize> {
+    let mut ids = vec![0; instructions.len()];
+    let (mut loops, mut scans, mut reads, mut vectors) = (0, 0, 0, 0);
+
+    for (pc, instr) in instructions.iter().enumerate() {
+        let counter = match instr {
+            Instruction::Jump(_) => &mut loops,
+            Instruction::Return(jmp_pc) => {
+                ids[pc] = ids[*jmp_pc as usize];
+                continue;
+            },
+            Instruction::Scan(_) => &mut scans,
+            Instruction::Read => &mut reads,
+            Instruction::AddVector(..) => &mut vectors,
+            _ => continue,
+        };
+        ids[pc] = *counter;
+        *counter += 1;
+    }
+
+    ids
+}
+
+/// Returns the operation that stores the moves of the data pointer that were only applied to cell offsets so far
+///
+/// Afterwards the data pointer in memory is up to date and the index register has to be reloaded before its next use.
+/// With `options.dp_register` the moves are added to the index register itself, which is the data pointer.
+///
+/// # Arguments
+///
+/// * `cached` - The offset of the data pointer from the index register, if the index register holds the data
+///   pointer as it is stored in memory
+/// * `options` - The settings of the generated assembly
+pub fn flush(cached: &mut Option<i32>, options: &AsmOptions) -> Option<LowOp> {
+    let offset = cached.take().filter(|offset| *offset != 0);
+    match options.dp_register {
+        true => offset.map(LowOp::AddIndex),
+        false => offset.map(LowOp::AddDp),
+    }
+}
+
+/// Moves the cell an operation accesses by an offset
+///
+/// # Arguments
+///
+/// * `op` - The operation
+/// * `by` - The offset in cells
+fn shifted(op: LowOp, by: i32) -> LowOp {
+    match op {
+        LowOp::AddCell(offset, n) => LowOp::AddCell(offset + by, n),
+        LowOp::SetCell(offset, n) => LowOp::SetCell(offset + by, n),
+        LowOp::LoadCell(offset, r) => LowOp::LoadCell(offset + by, r),
+        LowOp::StoreCell(offset, r) => LowOp::StoreCell(offset + by, r),
+        LowOp::CellAddress(offset, r) => LowOp::CellAddress(offset + by, r),
+        LowOp::AddRegisterToCell(offset, r) => LowOp::AddRegisterToCell(offset + by, r),
+        LowOp::AddVectorToCells(offset, constant) => LowOp::AddVectorToCells(offset + by, constant),
+        op => op,
+    }
+}
+
+/// Returns the operations of a loop's guard, which compares the current cell with zero and jumps to a label if the
+/// condition holds
+///
+/// The guard's own label is only jumped to by the other end of the loop when the condition does not hold. If `eax`
+/// holds the current cell, the register is tested instead of reading the cell again, which is only right when
+/// falling through to the guard, so the label moves behind it.
+///
+/// # Arguments
+///
+/// * `label` - The label of the guard
+/// * `condition` - The condition under which the guard jumps
+/// * `target` - The label the guard jumps to
+/// * `loaded` - Whether `eax` holds the current cell
+fn loop_guard(label: String, condition: Condition, target: String, loaded: bool) -> Vec<LowOp> {
+    match loaded {
+        true => vec![LowOp::JumpIfRegister(Register::Eax, 0, condition, target), LowOp::Label(label)],
+        false => vec![LowOp::Label(label), LowOp::LoadIndex, LowOp::JumpIfCellZero(condition, target)],
+    }
+}
+
+/// Lowers a single instruction to the operations every backend emits for it
+///
+/// With `options.offset_cells`, a straight-line run of moves and cell accesses loads the index register once and
+/// addresses the cells at offsets from it, only storing the moved data pointer before the next instruction that
+/// needs it in memory or jumps. `cached` carries this state from one instruction to the next.
+///
+/// With `options.dp_register`, the index register holds the data pointer throughout instead, so it is never loaded or
+/// stored, except around the guard check. Combined with `options.offset_cells`, its moves are only added to it at
+/// loop edges, helper calls and the end of the program.
+///
+/// # Arguments
+///
+/// * `pc` - The program counter of the instruction, which `--label-all` and the exit paths report
+/// * `id` - The number of the instruction among those of its kind, used to name its labels
+/// * `instr` - The instruction to lower
+/// * `pinned` - Whether the index register already holds the data pointer, so it is not reloaded
+/// * `loaded` - Whether `eax` already holds the current cell, so a `MultiplyAdd` or a loop's guard does not reload it
+/// * `cached` - The offset of the data pointer from the index register, if the index register holds the data
+///   pointer as it is stored in memory
+/// * `options` - The settings of the generated assembly
+pub fn lower(pc: usize, id: usize, instr: &Instruction, pinned: bool, loaded: bool, cached: &mut Option<i32>, options: &AsmOptions) -> Vec<LowOp> {
+    let prefix = options.label_prefix;
+    let label = |name: &str, n: usize| format!("{}{}_{}", prefix, name, n);
+    let write_failed = Helper::WriteFailed.label(prefix);
+    let check_guards = Helper::CheckGuards.label(prefix);
+
+    // Every backward jump of a loop costs fuel if it is limited
+    let consume_fuel = options.fuel.map(|_| LowOp::ConsumeFuel(Helper::FuelExhausted.label(prefix)));
+
+    let mut ops = match instr {
+        Instruction::Increment => vec![LowOp::AddDp(1)],
+        Instruction::Decrement => vec![LowOp::AddDp(-1)],
+        Instruction::MoveN(n) => vec![LowOp::AddDp(*n)],
+        Instruction::Add => vec![LowOp::LoadIndex, LowOp::AddCell(0, 1)],
+        Instruction::Subtract => vec![LowOp::LoadIndex, LowOp::AddCell(0, -1)],
+        Instruction::AddN(n) => vec![LowOp::LoadIndex, LowOp::AddCell(0, *n)],
+        Instruction::Clear => vec![LowOp::LoadIndex, LowOp::SetCell(0, 0)],
+        // Write the cell's lowest byte straight to the file descriptor of stdout, bypassing the C runtime's buffer
+        Instruction::Write if options.unbuffered_output => vec![
+            LowOp::LoadIndex,
+            LowOp::SetRegister(Register::Ecx, 1),
+            LowOp::CellAddress(0, Register::Edx),
+            LowOp::SetRegister(Register::R8d, 1),
+            LowOp::Call(Function::Write),
+            LowOp::JumpIfRegister(Register::Eax, 1, Condition::NotEqual, write_failed),
+        ],
+        // A library stores the byte in its output buffer instead, which fails like `putchar` once the buffer is full
+        Instruction::Write => vec![
+            LowOp::LoadIndex,
+            LowOp::LoadCell(0, Register::Ecx),
+            LowOp::Call(match options.library {
+                Some(_) => Function::Local(Helper::WriteOutput.label(prefix)),
+                None => Function::Putchar,
+            }),
+            LowOp::JumpIfRegister(Register::Eax, -1, Condition::Equal, write_failed),
+        ],
+        Instruction::Read => {
+            let mut ops = match options.const_input.is_some() || options.library.is_some() {
+                // Read the embedded input or the input buffer and leave the cell unchanged once it is exhausted, like
+                // the interpreter does
+                true => vec![
+                    LowOp::Call(Function::Local(Helper::ReadInput.label(prefix))),
+                    LowOp::JumpIfRegister(Register::Eax, -1, Condition::Equal, label("READ_END", id)),
+                    LowOp::LoadIndex,
+                    LowOp::StoreCell(0, Register::Eax),
+                    LowOp::Label(label("READ_END", id)),
+                ],
+                false => vec![LowOp::Call(Function::Getchar), LowOp::LoadIndex, LowOp::StoreCell(0, Register::Eax)],
+            };
+            if options.tape_guard == TapeGuard::Aggressive {
+                ops.push(LowOp::SetRegister(Register::Edx, pc as i32));
+                ops.push(LowOp::Call(Function::Local(check_guards.clone())));
+            }
+            ops
+        },
+        Instruction::Jump(_) => loop_guard(label("JUMP", id), Condition::Equal, label("RETURN", id), loaded),
+        // The fuel is consumed before the label, so skipping a loop is free
+        Instruction::Return(_) => {
+            let mut ops: Vec<LowOp> = consume_fuel.into_iter().collect();
+            ops.extend(loop_guard(label("RETURN", id), Condition::NotEqual, label("JUMP", id), loaded));
+            ops
+        },
+        Instruction::Label(name) => vec![LowOp::Label(format!("{}USER_{}", prefix, name))],
+        Instruction::Break => vec![LowOp::Breakpoint],
+        // A guarded tape is checked once more like at the end of the program
+        Instruction::Halt if options.tape_guard != TapeGuard::Off => vec![
+            LowOp::SetRegister(Register::Edx, pc as i32),
+            LowOp::Call(Function::Local(check_guards.clone())),
+            LowOp::Jump(Helper::Halt.label(prefix)),
+        ],
+        Instruction::Halt => vec![LowOp::Jump(Helper::Halt.label(prefix))],
+        Instruction::Dump => vec![
+            LowOp::LoadIndex,
+            LowOp::SetRegister(Register::Edx, pc as i32),
+            LowOp::Call(Function::Local(Helper::Dump.label(prefix))),
+        ],
+        Instruction::Unreachable => vec![],
+        Instruction::PinBase => vec![LowOp::LoadIndex],
+        // Keep the pointer in the index register while scanning and store it once a zero cell was found
+        Instruction::Scan(n) => {
+            let mut ops = vec![
+                LowOp::LoadIndex,
+                LowOp::Label(label("SCAN", id)),
+                LowOp::JumpIfCellZero(Condition::Equal, label("SCAN_END", id)),
+                LowOp::AddIndex(*n),
+            ];
+            ops.extend(consume_fuel);
+            ops.push(LowOp::Jump(label("SCAN", id)));
+            ops.push(LowOp::Label(label("SCAN_END", id)));
+            ops.push(LowOp::StoreIndex);
+            ops
+        },
+        Instruction::MultiplyAdd(offset, factor) => {
+            let mut ops = vec![LowOp::LoadIndex];
+            if !loaded {
+                ops.push(LowOp::LoadCell(0, Register::Eax));
+            }
+            if *factor != 1 {
+                ops.push(LowOp::Multiply(Register::Eax, *factor));
+            }
+            ops.push(LowOp::AddRegisterToCell(*offset, Register::Eax));
+            ops
+        },
+        Instruction::AddVector(offset, _) => vec![LowOp::LoadIndex, LowOp::AddVectorToCells(*offset, label("VECTOR", id))],
+    };
+
+    // Called functions preserve the index register, except for the guard check which has to be followed by a reload
+    if pinned {
+        ops.retain(|op| !matches!(op, LowOp::LoadIndex));
+        if ops.iter().any(|op| matches!(op, LowOp::Call(Function::Local(name)) if *name == check_guards)) {
+            ops.push(LowOp::LoadIndex);
+        }
+    }
+
+    if options.offset_cells && !pinned {
+        // The index register always holds the data pointer, so cells can be addressed at offsets from it right away
+        if options.dp_register && cached.is_none() {
+            *cached = Some(0);
+        }
+
+        let moves = match instr {
+            Instruction::Increment => Some(1),
+            Instruction::Decrement => Some(-1),
+            Instruction::MoveN(n) => Some(*n),
+            _ => None,
+        };
+        // Only the guard check clobbers the index register, calls to other functions preserve it
+        let accesses_cells = match instr {
+            Instruction::Read => options.tape_guard != TapeGuard::Aggressive,
+            instr => matches!(
+                instr,
+                Instruction::Add | Instruction::Subtract | Instruction::AddN(_) | Instruction::Clear | Instruction::Write
+                    | Instruction::MultiplyAdd(..) | Instruction::AddVector(..)
+            ),
+        };
+
+        // The index register only lags behind as far as an instruction reaches, so the cells stay addressable
+        let sum = cached.zip(moves).and_then(|(offset, n)| optimizer::offset_sum(offset, n));
+        match (*cached, moves) {
+            (Some(_), Some(_)) if sum.is_some() => {
+                *cached = sum;
+                ops.clear();
+            },
+            (Some(offset), None) if accesses_cells => {
+                ops = ops.into_iter().filter(|op| !matches!(op, LowOp::LoadIndex)).map(|op| shifted(op, offset)).collect();
+            },
+            (None, None) if accesses_cells => *cached = Some(0),
+            (None, Some(_)) => {},
+            // Jumps, labels and calls that rely on the data pointer in memory see it moved
+            _ => {
+                if let Some(store) = flush(cached, options) {
+                    ops.insert(0, store);
+                }
+            },
+        }
+    }
+
+    // The index register is the data pointer, only the guard check that clobbers it needs a copy in memory
+    if options.dp_register {
+        ops = ops.into_iter()
+            .flat_map(|op| match op {
+                LowOp::LoadIndex | LowOp::StoreIndex => vec![],
+                LowOp::AddDp(n) => vec![LowOp::AddIndex(n)],
+                LowOp::Call(Function::Local(name)) if name == check_guards => {
+                    vec![LowOp::StoreIndex, LowOp::Call(Function::Local(name)), LowOp::LoadIndex]
+                },
+                op => vec![op],
+            })
+            .collect();
+    }
+
+    // Entering a block is counted before any of its code, including the labels its loops jump back to
+    if let Some(index) = options.coverage.and_then(|blocks| coverage::starting_at(blocks, pc)) {
+        ops.insert(0, LowOp::CountBlock(index));
+    }
+
+    // Labels are not declared global, so they do not clash with those of other object files
+    if options.label_all {
+        ops.insert(0, LowOp::Label(label("INSTR", pc)));
+    }
+
+    ops
+}
@@ -0,0 +1,561 @@
+use std::collections::HashMap;
+use std::io::{stdin, stdout, BufRead, BufReader, Error, Result, Write};
+use std::ops::Range;
+
+use crate::json::{self, Value};
+use crate::optimizer::{DumpSettings, PassManager, Report};
+use crate::{matching_bracket, try_parse_recording, Cpu, Extensions, Instruction, Position, Program};
+
+/// The JSON-RPC error code of messages that are no valid JSON
+const PARSE_ERROR: i32 = -32700;
+
+/// The JSON-RPC error code of requests for methods the server does not implement
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// The `SymbolKind` of loops, `Function` as `--split-functions` lifts loops into functions
+const LOOP_SYMBOL: u32 = 12;
+
+/// The `SymbolKind` of labels, `Constant`
+const LABEL_SYMBOL: u32 = 14;
+
+/// The result of `initialize`, which announces full document syncs and the supported requests
+const CAPABILITIES: &str = "{\"capabilities\":{\"textDocumentSync\":1,\"documentSymbolProvider\":true,\"definitionProvider\":true,\"hoverProvider\":true},\"serverInfo\":{\"name\":\"bfasm\"}}";
+
+/// Serves the Language Server Protocol over stdin and stdout until the client sends `exit`
+///
+/// Open documents are parsed on each change and their parse errors are published as diagnostics. Loops and labels are
+/// document symbols, the definition of a bracket is its partner and hovering a bracket shows a summary of its loop.
+/// Returns whether the client shut the server down before it exited.
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `lsp`, which enable extensions like the compiler's flags do
+pub fn serve(args: &[String]) -> bool {
+    let mut extensions = Extensions::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--enable-labels" => extensions.labels = true,
+            "--enable-counts" => extensions.counts = true,
+            "--debug-breaks" => extensions.debug_breaks = true,
+            "--extensions" => match iter.next() {
+                Some(names) => {
+                    for name in names.split(',') {
+                        match name {
+                            "halt" => extensions.halt = true,
+                            "dump" => extensions.dump = true,
+                            _ => {
+                                eprintln!("error: unknown extension '{}', expected 'halt' or 'dump'!", name);
+                                return false;
+                            },
+                        }
+                    }
+                },
+                None => {
+                    eprintln!("error: missing value for '--extensions'!");
+                    return false;
+                },
+            },
+            arg => {
+                eprintln!("error: unknown argument '{}'!", arg);
+                return false;
+            },
+        }
+    }
+
+    session(&mut BufReader::new(stdin().lock()), &mut stdout().lock(), extensions)
+}
+
+/// Answers the messages of a client until it sends `exit` or its input ends and returns whether it shut the server
+/// down before
+///
+/// # Arguments
+///
+/// * `input` - The reader of the client's messages
+/// * `output` - The writer of the responses and notifications
+/// * `extensions` - The enabled language extensions
+fn session<R: BufRead, W: Write>(input: &mut R, output: &mut W, extensions: Extensions) -> bool {
+    // The text of each open document by its uri
+    let mut documents: HashMap<String, String> = HashMap::new();
+    let mut shutdown = false;
+
+    loop {
+        let message = match read_message(input) {
+            Ok(Some(message)) => message,
+            // The client went away without `exit`
+            Ok(None) => return shutdown,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                return false;
+            },
+        };
+
+        let Some(message) = json::parse(&message) else {
+            if send_error(output, &Value::Null, PARSE_ERROR, "the message is no valid JSON").is_err() {
+                return false;
+            }
+            continue;
+        };
+
+        let method = message.get("method").and_then(Value::as_str).unwrap_or_default();
+        let params = message.get("params").unwrap_or(&Value::Null);
+        let document = params.get("textDocument");
+        let uri = document.and_then(|document| document.get("uri")).and_then(Value::as_str).unwrap_or_default();
+
+        // Notifications never get a response
+        let Some(id) = message.get("id") else {
+            let text = match method {
+                "exit" => return shutdown,
+                "textDocument/didOpen" => document.and_then(|document| document.get("text")).and_then(Value::as_str),
+                // Only full syncs are announced, so the last change holds the whole text
+                "textDocument/didChange" => params.get("contentChanges")
+                    .and_then(Value::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Value::as_str),
+                "textDocument/didClose" => {
+                    documents.remove(uri);
+                    Some("")
+                },
+                _ => continue,
+            };
+
+            let Some(text) = text else {
+                continue;
+            };
+            if method != "textDocument/didClose" {
+                documents.insert(uri.to_owned(), text.to_owned());
+            }
+            if send(output, &diagnostics(uri, text, extensions)).is_err() {
+                return false;
+            }
+            continue;
+        };
+
+        let text = documents.get(uri).map(String::as_str).unwrap_or_default();
+        let position = params.get("position").and_then(|position| {
+            let line = position.get("line").and_then(Value::as_usize)?;
+            let character = position.get("character").and_then(Value::as_usize)?;
+            Some(source_position(text, line, character))
+        });
+
+        let result = match method {
+            "initialize" => CAPABILITIES.to_owned(),
+            "shutdown" => {
+                shutdown = true;
+                "null".to_owned()
+            },
+            "textDocument/documentSymbol" => match try_parse_recording(text, extensions, None, None) {
+                Ok(program) => format!("[{}]", symbols(text, &program, 0..program.instructions.len()).join(",")),
+                Err(_) => "[]".to_owned(),
+            },
+            "textDocument/definition" => position
+                .and_then(|position| partner(text, extensions, position))
+                .map_or("null".to_owned(), |target| format!("{{\"uri\":{},\"range\":{}}}", json::string(uri), range(text, target, after(target)))),
+            "textDocument/hover" => position
+                .and_then(|position| hover(text, extensions, position))
+                .map_or("null".to_owned(), |contents| format!("{{\"contents\":{{\"kind\":\"markdown\",\"value\":{}}}}}", json::string(&contents))),
+            _ => {
+                if send_error(output, id, METHOD_NOT_FOUND, &format!("unknown method '{}'", method)).is_err() {
+                    return false;
+                }
+                continue;
+            },
+        };
+
+        if send(output, &format!("{{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":{}}}", id.to_json(), result)).is_err() {
+            return false;
+        }
+    }
+}
+
+/// Reads the content of the next message, or `None` once the input ended
+///
+/// # Arguments
+///
+/// * `input` - The reader of the messages, each of which has a `Content-Length` header
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<String>> {
+    let mut length: Option<usize> = None;
+
+    // The headers end with an empty line
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() && length.is_some() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let mut content = vec![0; length.unwrap_or_default()];
+    input.read_exact(&mut content).map_err(|_| Error::other("the input ended within a message!"))?;
+    Ok(Some(String::from_utf8_lossy(&content).into_owned()))
+}
+
+/// Writes a message with its header
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `content` - The JSON content of the message
+fn send<W: Write>(f: &mut W, content: &str) -> Result<()> {
+    write!(f, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    f.flush()
+}
+
+/// Writes the error response to a request
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `id` - The id of the request, `null` if it could not be read
+/// * `code` - The JSON-RPC error code
+/// * `message` - The description of the error
+fn send_error<W: Write>(f: &mut W, id: &Value, code: i32, message: &str) -> Result<()> {
+    send(f, &format!("{{\"jsonrpc\":\"2.0\",\"id\":{},\"error\":{{\"code\":{},\"message\":{}}}}}", id.to_json(), code, json::string(message)))
+}
+
+/// Returns the notification publishing the parse errors of a document, one diagnostic per error
+///
+/// # Arguments
+///
+/// * `uri` - The uri of the document
+/// * `text` - The text of the document
+/// * `extensions` - The enabled language extensions
+fn diagnostics(uri: &str, text: &str, extensions: Extensions) -> String {
+    let errors = try_parse_recording(text, extensions, None, None).err().unwrap_or_default();
+    let diagnostics: Vec<String> = errors.iter()
+        .map(|(position, err)| format!(
+            "{{\"range\":{},\"severity\":1,\"source\":\"bfasm\",\"message\":{}}}",
+            range(text, *position, after(*position)), json::string(&err.to_string()),
+        ))
+        .collect();
+
+    format!(
+        "{{\"jsonrpc\":\"2.0\",\"method\":\"textDocument/publishDiagnostics\",\"params\":{{\"uri\":{},\"diagnostics\":[{}]}}}}",
+        json::string(uri), diagnostics.join(","),
+    )
+}
+
+/// Returns the document symbols of the loops and labels among some instructions, with the symbols of the loops and
+/// labels inside each loop as its children
+///
+/// # Arguments
+///
+/// * `text` - The text of the document
+/// * `program` - The parsed document
+/// * `pcs` - The pcs of the instructions, which do not cut any loop in half
+fn symbols(text: &str, program: &Program, pcs: Range<usize>) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+
+    let mut pc = pcs.start;
+    while pc < pcs.end {
+        let start = program.positions[pc];
+        match &program.instructions[pc] {
+            Instruction::Jump(end) => {
+                let end = *end as usize;
+                let children = symbols(text, program, pc + 1..end);
+                found.push(format!(
+                    "{{\"name\":{},\"detail\":{},\"kind\":{},\"range\":{},\"selectionRange\":{},\"children\":[{}]}}",
+                    json::string(&format!("loop {}", start)), json::string(&format!("{} instructions", end - pc - 1)), LOOP_SYMBOL,
+                    range(text, start, after(program.positions[end])), range(text, start, after(start)), children.join(","),
+                ));
+                pc = end;
+            },
+            Instruction::Label(name) => {
+                let end = Position { line: start.line, column: start.column + name.chars().count() + 1 };
+                found.push(format!(
+                    "{{\"name\":{},\"kind\":{},\"range\":{},\"selectionRange\":{}}}",
+                    json::string(&format!("@{}", name)), LABEL_SYMBOL, range(text, start, end), range(text, start, end),
+                ));
+            },
+            _ => {},
+        }
+        pc += 1;
+    }
+
+    found
+}
+
+/// Returns the pcs of the `Jump` and the `Return` of the loop whose bracket is at a position, if there is one
+///
+/// # Arguments
+///
+/// * `program` - The parsed document
+/// * `position` - The position of the bracket
+fn loop_at(program: &Program, position: Position) -> Option<(usize, usize)> {
+    let pc = program.positions.iter().position(|p| (p.line, p.column) == (position.line, position.column))?;
+    let partner = matching_bracket(&program.instructions, pc)?;
+    Some((pc.min(partner), pc.max(partner)))
+}
+
+/// Returns the position of the bracket matching the one at a position, if there is one
+///
+/// # Arguments
+///
+/// * `text` - The text of the document
+/// * `extensions` - The enabled language extensions
+/// * `position` - The position of the bracket
+fn partner(text: &str, extensions: Extensions, position: Position) -> Option<Position> {
+    let program = try_parse_recording(text, extensions, None, None).ok()?;
+    let (start, end) = loop_at(&program, position)?;
+    let target = if (program.positions[start].line, program.positions[start].column) == (position.line, position.column) { end } else { start };
+    Some(program.positions[target])
+}
+
+/// Returns the markdown summary of the loop whose bracket is at a position, if there is one
+///
+/// It lists the size of the body, how far each iteration moves the data pointer and what the optimizer made of the
+/// loop at `-O2`.
+///
+/// # Arguments
+///
+/// * `text` - The text of the document
+/// * `extensions` - The enabled language extensions
+/// * `position` - The position of the bracket
+fn hover(text: &str, extensions: Extensions, position: Position) -> Option<String> {
+    let program = try_parse_recording(text, extensions, None, None).ok()?;
+    let (start, end) = loop_at(&program, position)?;
+    let (open, close) = (program.positions[start], program.positions[end]);
+
+    let movement = match net_movement(&program.instructions, start) {
+        Some(0) => "none, it returns to its cell".to_owned(),
+        Some(n) => format!("{:+} cells per iteration", n),
+        None => "depends on the data".to_owned(),
+    };
+
+    // The findings are reported at the position of the loop's `[`
+    let mut report = Report::default();
+    let _ = PassManager::for_level(2, Cpu::Baseline).run(program, &mut report, &DumpSettings::default());
+    let findings = report.findings_at(open);
+
+    let mut summary = format!("**loop** {} to {}\n\n", open, close);
+    summary.push_str(&format!("- body: {} instructions\n", end - start - 1));
+    summary.push_str(&format!("- data pointer movement: {}\n", movement));
+    if findings.is_empty() {
+        summary.push_str("- `-O2`: kept as a loop\n");
+    }
+    for finding in findings {
+        summary.push_str(&format!("- `-O2` {}\n", finding));
+    }
+    Some(summary)
+}
+
+/// Returns how far each iteration of a loop moves the data pointer, or `None` if that depends on the data because a
+/// loop inside it moves the data pointer
+///
+/// # Arguments
+///
+/// * `instructions` - The instructions of the program
+/// * `start` - The pc of the loop's `Jump`
+fn net_movement(instructions: &[Instruction], start: usize) -> Option<i64> {
+    let Instruction::Jump(end) = instructions[start] else {
+        return Some(0);
+    };
+
+    let mut net: i64 = 0;
+    let mut pc = start + 1;
+    while pc < end as usize {
+        match instructions[pc] {
+            Instruction::Increment => net += 1,
+            Instruction::Decrement => net -= 1,
+            Instruction::Jump(inner_end) => {
+                if net_movement(instructions, pc)? != 0 {
+                    return None;
+                }
+                pc = inner_end as usize;
+            },
+            _ => {},
+        }
+        pc += 1;
+    }
+
+    Some(net)
+}
+
+/// Returns the position of the character after the one at a position
+///
+/// # Arguments
+///
+/// * `position` - The position of the character
+fn after(position: Position) -> Position {
+    Position { line: position.line, column: position.column + 1 }
+}
+
+/// Returns an LSP range between two source positions, which count characters while LSP counts UTF-16 code units
+///
+/// # Arguments
+///
+/// * `text` - The text of the document
+/// * `start` - The position of the first character
+/// * `end` - The position after the last character
+fn range(text: &str, start: Position, end: Position) -> String {
+    let point = |position: Position| {
+        let line = text.lines().nth(position.line - 1).unwrap_or_default();
+        let character: usize = line.chars().take(position.column - 1).map(char::len_utf16).sum();
+        format!("{{\"line\":{},\"character\":{}}}", position.line - 1, character)
+    };
+    format!("{{\"start\":{},\"end\":{}}}", point(start), point(end))
+}
+
+/// Returns the source position of an LSP position
+///
+/// # Arguments
+///
+/// * `text` - The text of the document
+/// * `line` - The 0-based line
+/// * `character` - The offset in UTF-16 code units
+fn source_position(text: &str, line: usize, character: usize) -> Position {
+    let chars = text.lines().nth(line).unwrap_or_default().chars()
+        .scan(0, |units, c| {
+            *units += c.len_utf16();
+            Some(*units)
+        })
+        .take_while(|units| *units <= character)
+        .count();
+    Position { line: line + 1, column: chars + 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The uri of the document the tests open
+    const URI: &str = "file:///test.bf";
+
+    /// Returns a request with an id
+    fn request(id: u32, method: &str, params: &str) -> String {
+        format!("{{\"jsonrpc\":\"2.0\",\"id\":{},\"method\":\"{}\",\"params\":{}}}", id, method, params)
+    }
+
+    /// Returns a notification, which has no id
+    fn notification(method: &str, params: &str) -> String {
+        format!("{{\"jsonrpc\":\"2.0\",\"method\":\"{}\",\"params\":{}}}", method, params)
+    }
+
+    /// Returns the notification that opens the test document with a text
+    fn open(text: &str) -> String {
+        notification("textDocument/didOpen", &format!("{{\"textDocument\":{{\"uri\":\"{}\",\"text\":{}}}}}", URI, json::string(text)))
+    }
+
+    /// Returns a request about a position in the test document
+    fn at(id: u32, method: &str, line: usize, character: usize) -> String {
+        request(id, method, &format!("{{\"textDocument\":{{\"uri\":\"{}\"}},\"position\":{{\"line\":{},\"character\":{}}}}}", URI, line, character))
+    }
+
+    /// Sends messages to a session and returns the messages it sent back and whether it was shut down
+    fn exchange(messages: &[String]) -> (Vec<Value>, bool) {
+        let input: String = messages.iter().map(|message| format!("Content-Length: {}\r\n\r\n{}", message.len(), message)).collect();
+        let mut output: Vec<u8> = Vec::new();
+        let shutdown = session(&mut input.as_bytes(), &mut output, Extensions::default());
+
+        let mut sent: &[u8] = &output;
+        let mut responses = Vec::new();
+        while let Some(message) = read_message(&mut sent).unwrap() {
+            responses.push(json::parse(&message).unwrap());
+        }
+        (responses, shutdown)
+    }
+
+    /// Returns the result of the response to the request with an id
+    fn result(responses: &[Value], id: usize) -> String {
+        let response = responses.iter().find(|response| response.get("id").and_then(Value::as_usize) == Some(id)).unwrap();
+        response.get("result").unwrap().to_json()
+    }
+
+    #[test]
+    fn initialize_and_shutdown() {
+        let (responses, shutdown) = exchange(&[request(1, "initialize", "{}"), request(2, "shutdown", "null"), notification("exit", "null")]);
+        assert_eq!(result(&responses, 1), CAPABILITIES);
+        assert_eq!(result(&responses, 2), "null");
+        assert!(shutdown);
+
+        let (responses, shutdown) = exchange(&[request(1, "textDocument/formatting", "{}")]);
+        assert_eq!(responses[0].get("error").and_then(|error| error.get("message")).and_then(Value::as_str), Some("unknown method 'textDocument/formatting'"));
+        assert!(!shutdown);
+    }
+
+    #[test]
+    fn diagnostics_are_published_on_each_change() {
+        let change = notification("textDocument/didChange", &format!("{{\"textDocument\":{{\"uri\":\"{}\"}},\"contentChanges\":[{{\"text\":\"+[-]\"}}]}}", URI));
+        let (responses, _) = exchange(&[open("+[.\n]]"), change]);
+        assert_eq!(responses.len(), 2);
+
+        let params = responses[0].get("params").unwrap();
+        assert_eq!(responses[0].get("method").and_then(Value::as_str), Some("textDocument/publishDiagnostics"));
+        assert_eq!(params.get("uri").and_then(Value::as_str), Some(URI));
+        let diagnostics = params.get("diagnostics").and_then(Value::as_array).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].get("range").unwrap().to_json(), "{\"start\":{\"line\":1,\"character\":1},\"end\":{\"line\":1,\"character\":2}}");
+
+        let diagnostics = responses[1].get("params").and_then(|params| params.get("diagnostics")).unwrap();
+        assert_eq!(diagnostics.to_json(), "[]");
+    }
+
+    #[test]
+    fn loops_are_nested_document_symbols() {
+        let (responses, _) = exchange(&[open("+[>[-]<]"), request(1, "textDocument/documentSymbol", &format!("{{\"textDocument\":{{\"uri\":\"{}\"}}}}", URI))]);
+        let symbols = json::parse(&result(&responses, 1)).unwrap();
+        let symbols = symbols.as_array().unwrap();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].get("name").and_then(Value::as_str), Some("loop 1:2"));
+        assert_eq!(symbols[0].get("detail").and_then(Value::as_str), Some("5 instructions"));
+        let children = symbols[0].get("children").and_then(Value::as_array).unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].get("name").and_then(Value::as_str), Some("loop 1:4"));
+
+        let (responses, _) = exchange(&[open("+[>[-]<"), request(1, "textDocument/documentSymbol", &format!("{{\"textDocument\":{{\"uri\":\"{}\"}}}}", URI))]);
+        assert_eq!(result(&responses, 1), "[]");
+    }
+
+    #[test]
+    fn loops_are_only_found_at_their_brackets() {
+        let program = crate::parse("+[>\n[-]<]", Extensions::default()).unwrap();
+        assert_eq!(loop_at(&program, Position { line: 1, column: 2 }), Some((1, 7)));
+        assert_eq!(loop_at(&program, Position { line: 2, column: 5 }), Some((1, 7)));
+        assert_eq!(loop_at(&program, Position { line: 2, column: 3 }), Some((3, 5)));
+        assert_eq!(loop_at(&program, Position { line: 1, column: 3 }), None);
+        assert_eq!(loop_at(&program, Position { line: 3, column: 1 }), None);
+    }
+
+    #[test]
+    fn definition_of_a_bracket_is_its_partner() {
+        let (responses, _) = exchange(&[open("+[>\n[-]<]"), at(1, "textDocument/definition", 0, 1), at(2, "textDocument/definition", 1, 4), at(3, "textDocument/definition", 1, 2), at(4, "textDocument/definition", 0, 0)]);
+        let location = |line: usize, character: usize| format!(
+            "{{\"uri\":\"{}\",\"range\":{{\"start\":{{\"line\":{},\"character\":{}}},\"end\":{{\"line\":{},\"character\":{}}}}}}}",
+            URI, line, character, line, character + 1,
+        );
+        assert_eq!(result(&responses, 1), location(1, 4));
+        assert_eq!(result(&responses, 2), location(0, 1));
+        assert_eq!(result(&responses, 3), location(1, 0));
+        assert_eq!(result(&responses, 4), "null");
+
+        // Brackets only pair up once all of them do
+        let (responses, _) = exchange(&[open("[[-]"), at(1, "textDocument/definition", 0, 1)]);
+        assert_eq!(result(&responses, 1), "null");
+    }
+
+    #[test]
+    fn hovering_a_bracket_summarizes_its_loop() {
+        let (responses, _) = exchange(&[open("+[-]>[>]"), at(1, "textDocument/hover", 0, 3), at(2, "textDocument/hover", 0, 5), at(3, "textDocument/hover", 0, 0)]);
+        let contents = |id: usize| {
+            let hover = json::parse(&result(&responses, id)).unwrap();
+            hover.get("contents").and_then(|contents| contents.get("value")).and_then(Value::as_str).unwrap().to_owned()
+        };
+        assert_eq!(contents(1), "**loop** 1:2 to 1:4\n\n- body: 1 instructions\n- data pointer movement: none, it returns to its cell\n- `-O2` clear-loop: recognized clear loop\n");
+        assert!(contents(2).contains("- data pointer movement: +1 cells per iteration\n"), "{}", contents(2));
+        assert!(contents(2).contains("scan-loop: recognized scan loop with stride 1"), "{}", contents(2));
+        assert_eq!(result(&responses, 3), "null");
+
+        let (responses, _) = exchange(&[open("+[-]]"), at(1, "textDocument/hover", 0, 1)]);
+        assert_eq!(result(&responses, 1), "null");
+    }
+}
@@ -0,0 +1,1922 @@
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File};
+use std::io::{BufWriter, Error, Result, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::{json, BracketError, Cpu, Instruction, Position, Program};
+
+/// An instruction together with the position it was parsed from
+type Item = (Instruction, Position);
+
+/// A node of the tree the passes rewrite, in which loops contain their bodies instead of jumping to each other
+///
+/// Passes can insert and remove nodes without keeping any jump targets up to date, as those are only computed when
+/// the tree is flattened again.
+#[derive(Clone)]
+pub enum Node {
+    /// Any instruction except `Jump` and `Return`, with its position
+    Op(Instruction, Position),
+    /// A loop with the positions of its brackets and the nodes between them
+    Loop { start: Position, body: Vec<Node>, end: Position },
+}
+
+/// The most instructions the unrolled iterations of a loop may have in total, unless `--unroll-limit` sets another
+/// amount
+pub const UNROLL_SIZE: usize = 256;
+
+/// The farthest in cells an instruction may move the data pointer or address a cell from it, so that the displacement
+/// of a cell addressed from an index register that itself lags behind the data pointer by up to as much still fits into
+/// 32 bits
+pub const MAX_OFFSET: i32 = i32::MAX / 8;
+
+/// How much the passes may grow a program
+#[derive(Clone, Copy)]
+pub struct Limits {
+    /// The most instructions the unrolled iterations of a loop may have in total, 0 to never unroll a loop
+    pub unroll: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits { unroll: UNROLL_SIZE }
+    }
+}
+
+/// A function called with the name of each pass and the program it returned, failing with what the pass broke
+pub type Observer<'a> = &'a mut dyn FnMut(&str, &Program) -> std::result::Result<(), String>;
+
+/// A function returning the amount an instruction changes something by, if it only does that
+type Delta = fn(&Instruction) -> Option<i32>;
+
+/// A function adding an amount to a total, if the sum is one an instruction can hold
+type Sum = fn(i32, i32) -> Option<i32>;
+
+/// An optimization pass, which rewrites the instructions without changing what the program does
+pub trait Pass {
+    /// The name used by `--passes`, `--dump-ir` and the report
+    fn name(&self) -> &'static str;
+
+    /// A short description shown by `--list-passes`
+    fn description(&self) -> &'static str;
+
+    /// Rewrites the tree of the program
+    ///
+    /// # Arguments
+    ///
+    /// * `nodes` - The nodes to rewrite
+    /// * `limits` - How much the pass may grow the program
+    /// * `report` - The report to record the findings in
+    fn run(&self, nodes: Vec<Node>, limits: &Limits, report: &mut Report) -> Vec<Node>;
+
+    /// Whether the pass relies on the tape starting zeroed, which makes the pass manager skip it otherwise
+    fn needs_zeroed_tape(&self) -> bool {
+        false
+    }
+}
+
+/// Every optimization pass, in the order they first run at `-O2 --cpu sse2`
+pub const PASSES: &[&dyn Pass] = &[&Rle, &CommentLoop, &ClearLoop, &ScanLoop, &CopyLoop, &KnownValues, &DeadStore, &DeadTail, &StripUnreachable, &Vectorize, &HoistBase];
+
+/// The passes of `-O2 --cpu sse2`, where `rle` runs again to merge the runs that removed instructions separated
+const LEVEL_2: &[&dyn Pass] = &[&Rle, &CommentLoop, &ClearLoop, &ScanLoop, &CopyLoop, &KnownValues, &DeadStore, &DeadTail, &StripUnreachable, &Rle, &Vectorize, &HoistBase];
+
+/// Runs a sequence of passes, dumping and verifying the instructions around each of them
+pub struct PassManager {
+    /// The passes in the order they run
+    passes: Vec<&'static dyn Pass>,
+    /// Whether to check the invariants of the tree after each pass
+    pub verify: bool,
+    /// Whether the tape starts zeroed, without which the passes that rely on it are skipped
+    pub zeroed_tape: bool,
+    /// How much the passes may grow the program
+    pub limits: Limits,
+}
+
+impl PassManager {
+    /// The passes of an optimization level
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The optimization level: no passes at `0`, `rle` at `1` and those of `LEVEL_2` above
+    /// * `cpu` - The instruction set the generated assembly may use, `vectorize` only runs with SSE2
+    pub fn for_level(level: u8, cpu: Cpu) -> PassManager {
+        let passes = match level {
+            0 => Vec::new(),
+            1 => PASSES[..1].to_vec(),
+            _ => LEVEL_2.iter().copied().filter(|pass| cpu == Cpu::Sse2 || pass.name() != "vectorize").collect(),
+        };
+
+        PassManager { passes, verify: false, zeroed_tape: true, limits: Limits::default() }
+    }
+
+    /// The passes named in a comma separated list, which may name a pass multiple times
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - The comma separated names of the passes
+    /// * `cpu` - The instruction set the generated assembly may use
+    pub fn from_names(names: &str, cpu: Cpu) -> Result<PassManager> {
+        let mut passes: Vec<&'static dyn Pass> = Vec::new();
+
+        for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match find(name) {
+                Some(pass) if pass.name() == "vectorize" && cpu == Cpu::Baseline => {
+                    return Err(Error::other("the vectorize pass requires --cpu sse2!"));
+                },
+                Some(pass) => passes.push(pass),
+                None => return Err(unknown_pass(name)),
+            }
+        }
+
+        Ok(PassManager { passes, verify: false, zeroed_tape: true, limits: Limits::default() })
+    }
+
+    /// Returns whether no pass runs, which leaves the program as it was parsed
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// Appends a pass unless it runs already, for flags that add a pass to any optimization level
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the pass
+    pub fn ensure(&mut self, name: &str) {
+        if let Some(pass) = find(name).filter(|_| !self.passes.iter().any(|pass| pass.name() == name)) {
+            self.passes.push(pass);
+        }
+    }
+
+    /// Removes every run of the passes named in a comma separated list, for bisecting which pass miscompiles a program
+    ///
+    /// Passes that do not run are ignored, as which passes run depends on the optimization level.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - The comma separated names of the passes
+    pub fn disable(&mut self, names: &str) -> Result<()> {
+        for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            if find(name).is_none() {
+                return Err(unknown_pass(name));
+            }
+            self.passes.retain(|pass| pass.name() != name);
+        }
+        Ok(())
+    }
+
+    /// Optimizes a program by running the passes in order
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - The program to optimize
+    /// * `report` - The report to record the passes' findings in
+    /// * `dumps` - Which passes to dump the instructions of
+    pub fn run(&self, program: Program, report: &mut Report, dumps: &DumpSettings) -> Result<Program> {
+        self.run_observed(program, report, dumps, None)
+    }
+
+    /// Optimizes a program like `run`, handing the program to `observe` after each pass, whose error stops the passes
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - The program to optimize
+    /// * `report` - The report to record the passes' findings in
+    /// * `dumps` - Which passes to dump the instructions of
+    /// * `observe` - The function called with the name of each pass and the program it returned, if any
+    pub fn run_observed(&self, program: Program, report: &mut Report, dumps: &DumpSettings, mut observe: Option<Observer>) -> Result<Program> {
+        let mut nodes = build(program)?;
+
+        for (n, pass) in self.passes.iter().enumerate() {
+            if pass.needs_zeroed_tape() && !self.zeroed_tape {
+                continue;
+            }
+
+            dumps.dump(n + 1, pass.name(), "before", &nodes)?;
+
+            let first = report.entries.len();
+            let start = Instant::now();
+            nodes = pass.run(nodes, &self.limits, report);
+            report.passes.push((pass.name(), size(&nodes), first..report.entries.len(), start.elapsed()));
+
+            if self.verify {
+                verify(&nodes).map_err(|err| Error::other(format!("invalid IR after pass '{}': {}", pass.name(), err)))?;
+            }
+
+            dumps.dump(n + 1, pass.name(), "after", &nodes)?;
+
+            if let Some(observe) = observe.as_mut() {
+                let (instructions, positions): (Vec<Instruction>, Vec<Position>) = flatten(&nodes).into_iter().unzip();
+                observe(pass.name(), &Program { instructions, positions })
+                    .map_err(|err| Error::other(format!("pass {} '{}' {}", n + 1, pass.name(), err)))?;
+            }
+        }
+
+        let (instructions, positions): (Vec<Instruction>, Vec<Position>) = flatten(&nodes).into_iter().unzip();
+
+        Ok(Program { instructions, positions })
+    }
+}
+
+/// The pass with the given name
+///
+/// # Arguments
+///
+/// * `name` - The name of the pass
+pub fn find(name: &str) -> Option<&'static dyn Pass> {
+    PASSES.iter().copied().find(|pass| pass.name() == name)
+}
+
+/// Returns the error about a pass name that names no pass, listing the valid names
+///
+/// # Arguments
+///
+/// * `name` - The unknown name
+fn unknown_pass(name: &str) -> Error {
+    let valid: Vec<&str> = PASSES.iter().map(|pass| pass.name()).collect();
+    Error::other(format!("unknown pass '{}', expected one of {}!", name, valid.join(", ")))
+}
+
+/// Builds the tree of a program, failing on a loop instruction without a partner, which parsed programs never have
+///
+/// # Arguments
+///
+/// * `program` - The program
+fn build(program: Program) -> Result<Vec<Node>> {
+    let mut nodes: Vec<Node> = Vec::new();
+
+    // The position of the `[` of each loop that is currently open and the nodes in front of it
+    let mut outer: Vec<(Position, Vec<Node>)> = Vec::new();
+
+    for (instr, position) in program.instructions.into_iter().zip(program.positions) {
+        match instr {
+            Instruction::Jump(_) => outer.push((position, std::mem::take(&mut nodes))),
+            Instruction::Return(_) => {
+                let Some((start, mut parent)) = outer.pop() else {
+                    return Err(Error::other(BracketError::UnmatchedClose(position).to_string()));
+                };
+                parent.push(Node::Loop { start, body: nodes, end: position });
+                nodes = parent;
+            },
+            instr => nodes.push(Node::Op(instr, position)),
+        }
+    }
+
+    match outer.pop() {
+        Some((start, _)) => Err(Error::other(BracketError::UnmatchedOpen(start).to_string())),
+        None => Ok(nodes),
+    }
+}
+
+/// Flattens a tree into the instructions of the backends, computing the `jmp_pc`s of its loops
+///
+/// # Arguments
+///
+/// * `nodes` - The nodes to flatten
+fn flatten(nodes: &[Node]) -> Vec<Item> {
+    /// Appends the instructions of nodes to those of the nodes before them
+    fn append(nodes: &[Node], items: &mut Vec<Item>) {
+        for node in nodes {
+            match node {
+                Node::Op(instr, position) => items.push((instr.clone(), *position)),
+                Node::Loop { start, body, end } => {
+                    let jump = items.len();
+                    items.push((Instruction::Jump(0), *start));
+                    append(body, items);
+                    items[jump].0 = Instruction::Jump(items.len() as i32);
+                    items.push((Instruction::Return(jump as i32), *end));
+                },
+            }
+        }
+    }
+
+    let mut items: Vec<Item> = Vec::with_capacity(size(nodes));
+    append(nodes, &mut items);
+    items
+}
+
+/// The amount of instructions the nodes flatten into
+///
+/// # Arguments
+///
+/// * `nodes` - The nodes to count
+fn size(nodes: &[Node]) -> usize {
+    nodes.iter().map(|node| match node {
+        Node::Op(_, _) => 1,
+        Node::Loop { body, .. } => size(body) + 2,
+    }).sum()
+}
+
+/// The instruction of a node, if it is no loop
+///
+/// # Arguments
+///
+/// * `node` - The node
+fn instruction(node: &Node) -> Option<&Instruction> {
+    match node {
+        Node::Op(instr, _) => Some(instr),
+        Node::Loop { .. } => None,
+    }
+}
+
+/// Checks that no pass put a loop instruction into the tree instead of a loop node and that every `PinBase` precedes
+/// a loop that never moves the data pointer
+///
+/// # Arguments
+///
+/// * `nodes` - The nodes to check
+fn verify(nodes: &[Node]) -> std::result::Result<(), String> {
+    for (i, node) in nodes.iter().enumerate() {
+        match node {
+            Node::Op(Instruction::PinBase, position) => match nodes.get(i + 1) {
+                Some(Node::Loop { body, .. }) if pointer_invariant(body) => {},
+                _ => return Err(format!("PinBase at {} does not precede a loop that keeps the data pointer", position)),
+            },
+            Node::Op(Instruction::Jump(_) | Instruction::Return(_), position) => {
+                return Err(format!("loop instruction at {} is not part of a loop node", position));
+            },
+            Node::Op(_, _) => {},
+            Node::Loop { body, .. } => verify(body)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Which passes to dump the instructions before and after, and where to
+#[derive(Default)]
+pub struct DumpSettings {
+    /// The pass whose input is dumped, or `all`
+    pub before: Option<String>,
+    /// The pass whose output is dumped, or `all`
+    pub after: Option<String>,
+    /// The directory the dumps are written to
+    pub dir: PathBuf,
+}
+
+impl DumpSettings {
+    /// Writes the instructions to `ir.<n>.<pass>.<stage>.json` if the settings select the pass for the stage
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The (1-based) index of the pass in the pipeline
+    /// * `pass` - The name of the pass
+    /// * `stage` - `before` or `after`
+    /// * `nodes` - The nodes to dump as instructions
+    fn dump(&self, n: usize, pass: &str, stage: &str, nodes: &[Node]) -> Result<()> {
+        let selected = if stage == "before" { &self.before } else { &self.after };
+        if !selected.as_deref().is_some_and(|name| name == "all" || name == pass) {
+            return Ok(());
+        }
+
+        let path = self.dir.join(format!("ir.{}.{}.{}.json", n, pass, stage));
+        let result = create_dir_all(&self.dir)
+            .and_then(|()| File::create(&path))
+            .and_then(|file| {
+                let mut f = BufWriter::new(file);
+                write_ir(&mut f, pass, stage, &flatten(nodes))?;
+                f.flush()
+            });
+
+        result.map_err(|err| Error::other(format!("could not dump the IR to {}: {}", path.display(), err)))
+    }
+}
+
+/// Writes instructions as JSON, one instruction per line with its position and jump target
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `pass` - The name of the pass the instructions belong to
+/// * `stage` - Whether they are the pass's input (`before`) or output (`after`)
+/// * `items` - The instructions to write, whose jump targets are up to date
+fn write_ir<W: Write>(f: &mut W, pass: &str, stage: &str, items: &[Item]) -> Result<()> {
+    writeln!(f, "{{")?;
+    writeln!(f, "  \"pass\": {},", json::string(pass))?;
+    writeln!(f, "  \"stage\": {},", json::string(stage))?;
+    writeln!(f, "  \"instructions\": [")?;
+    for (pc, (instr, position)) in items.iter().enumerate() {
+        let separator = if pc + 1 < items.len() { "," } else { "" };
+        let (name, target) = match instr {
+            Instruction::Jump(jmp_pc) => ("Jump".to_owned(), Some(jmp_pc)),
+            Instruction::Return(jmp_pc) => ("Return".to_owned(), Some(jmp_pc)),
+            instr => (format!("{:?}", instr), None),
+        };
+        let target = target.map_or(String::new(), |target| format!(", \"target\": {}", target));
+        writeln!(
+            f,
+            "    {{ \"pc\": {}, \"instr\": {}{}, \"line\": {}, \"column\": {} }}{}",
+            pc, json::string(&name), target, position.line, position.column, separator
+        )?;
+    }
+    writeln!(f, "  ]")?;
+
+    writeln!(f, "}}")
+}
+
+/// A single finding of an optimization pass
+struct Entry {
+    /// The position of the instructions the finding is about
+    position: Position,
+    /// The name of the pass
+    pass: &'static str,
+    /// Whether the pass changed the program or rejected the pattern
+    applied: bool,
+    /// What was recognized or why it was rejected
+    message: String,
+}
+
+/// A record of what the optimization passes did to a program
+#[derive(Default)]
+pub struct Report {
+    /// The findings in the order they happened
+    entries: Vec<Entry>,
+    /// The name of each pass that ran, the amount of instructions left after it, the indices of its findings and how
+    /// long it took, as a pass can run more than once
+    passes: Vec<(&'static str, usize, Range<usize>, Duration)>,
+}
+
+impl Report {
+    /// Records that a pass changed the program
+    fn applied(&mut self, pass: &'static str, position: Position, message: String) {
+        self.entries.push(Entry { position, pass, applied: true, message });
+    }
+
+    /// Records that a pass considered a pattern but rejected it
+    fn rejected(&mut self, pass: &'static str, position: Position, message: String) {
+        self.entries.push(Entry { position, pass, applied: false, message });
+    }
+
+    /// The amount of applied and rejected findings of a run of a pass
+    fn counts(&self, findings: &Range<usize>) -> (usize, usize) {
+        let applied = self.entries[findings.clone()].iter().filter(|e| e.applied).count();
+        (applied, findings.len() - applied)
+    }
+
+    /// The findings in the order they happened with their positions and whether they changed the program, each
+    /// prefixed with the name of its pass
+    pub fn findings(&self) -> impl Iterator<Item = (Position, bool, String)> + '_ {
+        self.entries.iter().map(|entry| {
+            let outcome = if entry.applied { "" } else { "rejected: " };
+            (entry.position, entry.applied, format!("{}: {}{}", entry.pass, outcome, entry.message))
+        })
+    }
+
+    /// The name of each pass that ran with how long it took, in the order they ran
+    pub fn timings(&self) -> impl Iterator<Item = (&'static str, Duration)> + '_ {
+        self.passes.iter().map(|(pass, _, _, elapsed)| (*pass, *elapsed))
+    }
+
+    /// The findings about the instructions at a position, each prefixed with the name of its pass
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position of the instructions
+    pub fn findings_at(&self, position: Position) -> Vec<String> {
+        self.findings()
+            .filter(|(p, _, _)| (p.line, p.column) == (position.line, position.column))
+            .map(|(_, _, finding)| finding)
+            .collect()
+    }
+
+    /// Writes the report in a human readable form
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The writer to write to
+    pub fn write_text<W: Write>(&self, f: &mut W) -> Result<()> {
+        writeln!(f, "optimization report")?;
+
+        // The names of the passes line up in a column as wide as the longest of them
+        let width = PASSES.iter().map(|pass| pass.name().len()).max().unwrap_or(0);
+
+        for entry in &self.entries {
+            let outcome = if entry.applied { "" } else { "rejected: " };
+            writeln!(f, "  {:<8} {:<width$} {}{}", entry.position.to_string(), entry.pass, outcome, entry.message, width = width)?;
+        }
+
+        writeln!(f, "passes")?;
+        for (pass, instructions, findings, _) in &self.passes {
+            let (applied, rejected) = self.counts(findings);
+            writeln!(f, "  {:<width$} {} applied, {} rejected, {} instructions left", pass, applied, rejected, instructions, width = width)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the report as JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The writer to write to
+    pub fn write_json<W: Write>(&self, f: &mut W) -> Result<()> {
+        writeln!(f, "{{")?;
+
+        writeln!(f, "  \"entries\": [")?;
+        for (i, entry) in self.entries.iter().enumerate() {
+            let separator = if i + 1 < self.entries.len() { "," } else { "" };
+            writeln!(
+                f,
+                "    {{ \"line\": {}, \"column\": {}, \"pass\": {}, \"applied\": {}, \"message\": {} }}{}",
+                entry.position.line, entry.position.column, json::string(entry.pass), entry.applied, json::string(&entry.message), separator
+            )?;
+        }
+        writeln!(f, "  ],")?;
+
+        writeln!(f, "  \"passes\": [")?;
+        for (i, (pass, instructions, findings, _)) in self.passes.iter().enumerate() {
+            let (applied, rejected) = self.counts(findings);
+            let separator = if i + 1 < self.passes.len() { "," } else { "" };
+            writeln!(
+                f,
+                "    {{ \"name\": {}, \"applied\": {}, \"rejected\": {}, \"instructions\": {} }}{}",
+                json::string(pass), applied, rejected, instructions, separator
+            )?;
+        }
+        writeln!(f, "  ]")?;
+
+        writeln!(f, "}}")
+    }
+}
+
+/// The amount an instruction adds to the current cell, if it only does that
+///
+/// # Arguments
+///
+/// * `instr` - The instruction
+pub fn cell_delta(instr: &Instruction) -> Option<i32> {
+    match instr {
+        Instruction::Add => Some(1),
+        Instruction::Subtract => Some(-1),
+        Instruction::AddN(n) => Some(*n),
+        _ => None,
+    }
+}
+
+/// The amount an instruction moves the data pointer by, if it only does that
+///
+/// # Arguments
+///
+/// * `instr` - The instruction
+pub fn pointer_delta(instr: &Instruction) -> Option<i32> {
+    match instr {
+        Instruction::Increment => Some(1),
+        Instruction::Decrement => Some(-1),
+        Instruction::MoveN(n) => Some(*n),
+        _ => None,
+    }
+}
+
+/// Adds a move to an offset from the data pointer, if the sum is still within the offsets an instruction can address
+///
+/// # Arguments
+///
+/// * `offset` - The offset in cells
+/// * `n` - The amount of cells to move by
+pub fn offset_sum(offset: i32, n: i32) -> Option<i32> {
+    offset.checked_add(n).filter(|sum| (-MAX_OFFSET..=MAX_OFFSET).contains(sum))
+}
+
+/// `dead-store` : Removes changes of the current cell that a clear overwrites
+struct DeadStore;
+
+impl Pass for DeadStore {
+    fn name(&self) -> &'static str {
+        "dead-store"
+    }
+
+    fn description(&self) -> &'static str {
+        "remove changes of a cell that are cleared before the cell is read, e.g. [-]+++[-]"
+    }
+
+    fn run(&self, nodes: Vec<Node>, _: &Limits, report: &mut Report) -> Vec<Node> {
+        dead_store(nodes, report)
+    }
+}
+
+/// `known-values` : Simplifies loops whose cell has a known value
+struct KnownValues;
+
+impl Pass for KnownValues {
+    fn name(&self) -> &'static str {
+        "known-values"
+    }
+
+    fn description(&self) -> &'static str {
+        "remove, multiply out or unroll loops whose cell has a known value, e.g. [-]++[>+++.<-]"
+    }
+
+    fn run(&self, nodes: Vec<Node>, limits: &Limits, report: &mut Report) -> Vec<Node> {
+        known_values(nodes, &mut KnownCells::unknown(), limits.unroll, report)
+    }
+}
+
+/// `dead-tail` : Removes the code after an infinite top-level loop
+struct DeadTail;
+
+impl Pass for DeadTail {
+    fn name(&self) -> &'static str {
+        "dead-tail"
+    }
+
+    fn description(&self) -> &'static str {
+        "remove the code and epilogue after a top-level loop that provably never ends"
+    }
+
+    fn run(&self, nodes: Vec<Node>, _: &Limits, report: &mut Report) -> Vec<Node> {
+        dead_tail(nodes, report)
+    }
+}
+
+/// `strip-unreachable` : Removes the code after `@`, which always halts the program
+struct StripUnreachable;
+
+impl Pass for StripUnreachable {
+    fn name(&self) -> &'static str {
+        "strip-unreachable"
+    }
+
+    fn description(&self) -> &'static str {
+        "remove the code after '@', which always halts the program"
+    }
+
+    fn run(&self, nodes: Vec<Node>, _: &Limits, report: &mut Report) -> Vec<Node> {
+        strip_unreachable(nodes, true, report)
+    }
+}
+
+/// `comment-loop` : Removes the loops at the start of the program whose cell is still zero, like comment blocks
+struct CommentLoop;
+
+impl Pass for CommentLoop {
+    fn name(&self) -> &'static str {
+        "comment-loop"
+    }
+
+    fn description(&self) -> &'static str {
+        "remove the loops at the start of the program that never run, like comment blocks"
+    }
+
+    fn run(&self, nodes: Vec<Node>, _: &Limits, report: &mut Report) -> Vec<Node> {
+        comment_loop(nodes, report)
+    }
+
+    fn needs_zeroed_tape(&self) -> bool {
+        true
+    }
+}
+
+/// `hoist-base` : Loads the data pointer once before loops that never move it
+struct HoistBase;
+
+impl Pass for HoistBase {
+    fn name(&self) -> &'static str {
+        "hoist-base"
+    }
+
+    fn description(&self) -> &'static str {
+        "load the data pointer once before loops that never move it instead of in every instruction"
+    }
+
+    fn run(&self, nodes: Vec<Node>, _: &Limits, report: &mut Report) -> Vec<Node> {
+        hoist_base(nodes, report)
+    }
+}
+
+/// `vectorize` : Replaces updates of adjacent cells by vector additions
+struct Vectorize;
+
+impl Pass for Vectorize {
+    fn name(&self) -> &'static str {
+        "vectorize"
+    }
+
+    fn description(&self) -> &'static str {
+        "add to four adjacent cells at once with SSE2 (only with --cpu sse2)"
+    }
+
+    fn run(&self, nodes: Vec<Node>, _: &Limits, report: &mut Report) -> Vec<Node> {
+        vectorize(nodes, report)
+    }
+}
+
+/// `rle` : Merges runs of cell and pointer changes
+struct Rle;
+
+impl Pass for Rle {
+    fn name(&self) -> &'static str {
+        "rle"
+    }
+
+    fn description(&self) -> &'static str {
+        "merge runs of +/- and </> into single instructions"
+    }
+
+    fn run(&self, nodes: Vec<Node>, _: &Limits, report: &mut Report) -> Vec<Node> {
+        rle(nodes, report)
+    }
+}
+
+/// `clear-loop` : Replaces clear loops
+struct ClearLoop;
+
+impl Pass for ClearLoop {
+    fn name(&self) -> &'static str {
+        "clear-loop"
+    }
+
+    fn description(&self) -> &'static str {
+        "replace loops like [-] by a clear"
+    }
+
+    fn run(&self, nodes: Vec<Node>, _: &Limits, report: &mut Report) -> Vec<Node> {
+        clear_loop(nodes, report)
+    }
+}
+
+/// `scan-loop` : Replaces scan loops
+struct ScanLoop;
+
+impl Pass for ScanLoop {
+    fn name(&self) -> &'static str {
+        "scan-loop"
+    }
+
+    fn description(&self) -> &'static str {
+        "replace loops like [>] by a scan for a zero cell"
+    }
+
+    fn run(&self, nodes: Vec<Node>, _: &Limits, report: &mut Report) -> Vec<Node> {
+        scan_loop(nodes, report)
+    }
+}
+
+/// `copy-loop` : Replaces copy loops
+struct CopyLoop;
+
+impl Pass for CopyLoop {
+    fn name(&self) -> &'static str {
+        "copy-loop"
+    }
+
+    fn description(&self) -> &'static str {
+        "replace loops like [->+<] by multiply-adds and a clear"
+    }
+
+    fn run(&self, nodes: Vec<Node>, _: &Limits, report: &mut Report) -> Vec<Node> {
+        copy_loop(nodes, report)
+    }
+}
+
+/// `rle` : Merges runs of cell and pointer changes into single `AddN` and `MoveN` instructions
+///
+/// A run that cancels out leaves the runs around it adjacent, so a run continues the merged instruction before it.
+fn rle(nodes: Vec<Node>, report: &mut Report) -> Vec<Node> {
+    let mut result: Vec<Node> = Vec::with_capacity(nodes.len());
+
+    let mut iter = nodes.into_iter().peekable();
+    while let Some(node) = iter.next() {
+        let (instr, position) = match node {
+            Node::Op(instr, position) => (instr, position),
+            Node::Loop { start, body, end } => {
+                result.push(Node::Loop { start, body: rle(body, report), end });
+                continue;
+            },
+        };
+
+        // The function telling whether an instruction belongs to the run, how to build the merged instruction and how
+        // to add to it: cells wrap around, while moves end the run before they leave the offsets an instruction reaches
+        let (delta, merged, add): (Delta, fn(i32) -> Instruction, Sum) = if cell_delta(&instr).is_some() {
+            (cell_delta, Instruction::AddN, |total, n| Some(total.wrapping_add(n)))
+        } else if pointer_delta(&instr).is_some() {
+            (pointer_delta, Instruction::MoveN, offset_sum)
+        } else {
+            result.push(Node::Op(instr, position));
+            continue;
+        };
+
+        let mut total = delta(&instr).unwrap();
+        let mut length = 1;
+        let mut position = position;
+        let previous = match result.last() {
+            Some(Node::Op(last, last_position)) => delta(last).and_then(|n| add(n, total)).map(|sum| (sum, *last_position)),
+            _ => None,
+        };
+        if let Some((sum, last_position)) = previous {
+            result.pop();
+            (total, position) = (sum, last_position);
+            length += 1;
+        }
+        while let Some(sum) = iter.peek().and_then(instruction).and_then(delta).and_then(|n| add(total, n)) {
+            total = sum;
+            length += 1;
+            iter.next();
+        }
+
+        if total == 0 {
+            report.applied("rle", position, format!("removed {} instructions that cancel out", length));
+        } else {
+            if length > 1 {
+                report.applied("rle", position, format!("merged {} instructions into {:?}", length, merged(total)));
+            }
+            result.push(Node::Op(merged(total), position));
+        }
+    }
+
+    result
+}
+
+/// Replaces every innermost loop for which `replace` returns replacement instructions
+///
+/// Loops that only become innermost because their inner loops were replaced are left as they are.
+///
+/// # Arguments
+///
+/// * `nodes` - The nodes to modify
+/// * `replace` - Called with the body and position of each loop that contains no other loop
+fn replace_innermost_loops<F: FnMut(&[&Instruction], Position) -> Option<Vec<Instruction>>>(nodes: Vec<Node>, replace: &mut F) -> Vec<Node> {
+    let mut result: Vec<Node> = Vec::with_capacity(nodes.len());
+
+    for node in nodes {
+        let Node::Loop { start, body, end } = node else {
+            result.push(node);
+            continue;
+        };
+
+        // Only the bodies of innermost loops consist of instructions alone
+        let instructions: Option<Vec<&Instruction>> = body.iter().map(instruction).collect();
+        match instructions.and_then(|instructions| replace(&instructions, start)) {
+            Some(replacement) => result.extend(replacement.into_iter().map(|i| Node::Op(i, start))),
+            None => result.push(Node::Loop { start, body: replace_innermost_loops(body, replace), end }),
+        }
+    }
+
+    result
+}
+
+/// `clear-loop` : Replaces loops like `[-]` that only change the current cell by `Clear`
+fn clear_loop(nodes: Vec<Node>, report: &mut Report) -> Vec<Node> {
+    replace_innermost_loops(nodes, &mut |body, position| {
+        let deltas: Option<Vec<i32>> = body.iter().map(|i| cell_delta(i)).collect();
+        let delta = deltas.filter(|d| !d.is_empty())?.into_iter().fold(0i32, |a, b| a.wrapping_add(b));
+
+        // Only odd changes are guaranteed to reach zero with wrapping cells
+        if delta % 2 != 0 {
+            report.applied("clear-loop", position, "recognized clear loop".to_owned());
+            Some(vec![Instruction::Clear])
+        } else {
+            report.rejected("clear-loop", position, format!("cell changes by {} per iteration, so it might never reach zero", delta));
+            None
+        }
+    })
+}
+
+/// `scan-loop` : Replaces loops like `[>]` that only move the data pointer by `Scan`
+fn scan_loop(nodes: Vec<Node>, report: &mut Report) -> Vec<Node> {
+    replace_innermost_loops(nodes, &mut |body, position| {
+        let deltas: Option<Vec<i32>> = body.iter().map(|i| pointer_delta(i)).collect();
+        let delta = deltas.filter(|d| !d.is_empty())?.into_iter().try_fold(0, offset_sum)?;
+
+        if delta != 0 {
+            report.applied("scan-loop", position, format!("recognized scan loop with stride {}", delta));
+            Some(vec![Instruction::Scan(delta)])
+        } else {
+            report.rejected("scan-loop", position, "the pointer does not move, so the loop never ends once entered".to_owned());
+            None
+        }
+    })
+}
+
+/// `copy-loop` : Replaces loops like `[->+>++<<]` by `MultiplyAdd`s followed by a `Clear`
+fn copy_loop(nodes: Vec<Node>, report: &mut Report) -> Vec<Node> {
+    replace_innermost_loops(nodes, &mut |body, position| {
+        // Loops that only change the cell or only move are left to the other passes
+        let changes_cells = body.iter().any(|i| cell_delta(i).is_some());
+        let moves = body.iter().any(|i| pointer_delta(i).is_some());
+        if !changes_cells || !moves {
+            return None;
+        }
+
+        let mut offset: i32 = 0;
+
+        // The (offset, value) pairs that get added to the cells on each iteration
+        let mut deltas: Vec<(i32, i32)> = Vec::new();
+
+        for instr in body {
+            if let Some(n) = pointer_delta(instr) {
+                let Some(sum) = offset_sum(offset, n) else {
+                    report.rejected("copy-loop", position, format!("body moves farther than the {} cells an instruction can reach", MAX_OFFSET));
+                    return None;
+                };
+                offset = sum;
+            } else if let Some(n) = cell_delta(instr) {
+                if let Some(delta) = deltas.iter_mut().find(|(o, _)| *o == offset) {
+                    delta.1 = delta.1.wrapping_add(n);
+                } else {
+                    deltas.push((offset, n));
+                }
+            } else {
+                let reason = match instr {
+                    Instruction::Read => "body contains ','",
+                    Instruction::Write => "body contains '.'",
+                    Instruction::Label(_) => "body contains a label",
+                    Instruction::Break => "body contains a breakpoint",
+                    Instruction::Halt => "body contains '@'",
+                    Instruction::Dump => "body contains '$'",
+                    _ => "body contains a nested loop",
+                };
+                report.rejected("copy-loop", position, reason.to_owned());
+                return None;
+            }
+        }
+
+        if offset != 0 {
+            report.rejected("copy-loop", position, format!("loop does not return to its starting cell (moves by {})", offset));
+            return None;
+        }
+
+        let counter = deltas.iter().find(|(o, _)| *o == 0).map_or(0, |(_, n)| *n);
+        if counter != -1 {
+            report.rejected("copy-loop", position, format!("counter changes by {} per iteration instead of -1", counter));
+            return None;
+        }
+
+        let targets: Vec<(i32, i32)> = deltas.into_iter().filter(|(o, n)| *o != 0 && *n != 0).collect();
+        let factors: Vec<String> = targets.iter().map(|(o, n)| format!("[{:+}]*{}", o, n)).collect();
+        report.applied("copy-loop", position, format!("recognized copy loop with factors {}", factors.join(", ")));
+
+        let mut replacement: Vec<Instruction> = targets.into_iter().map(|(o, n)| Instruction::MultiplyAdd(o, n)).collect();
+        replacement.push(Instruction::Clear);
+        Some(replacement)
+    })
+}
+
+/// `dead-store` : Removes `Clear`s and cell changes that are followed by a `Clear` with nothing in between that reads
+/// the cell, writes it or moves the data pointer
+fn dead_store(nodes: Vec<Node>, report: &mut Report) -> Vec<Node> {
+    let mut result: Vec<Node> = Vec::with_capacity(nodes.len());
+
+    // The index into `result` of the first of the latest instructions that only change the current cell
+    let mut run = 0;
+
+    for node in nodes {
+        match node {
+            Node::Op(Instruction::Clear, position) => {
+                if let Some(Node::Op(_, first)) = result.get(run) {
+                    report.applied("dead-store", *first, format!("removed {} instructions overwritten by a clear", result.len() - run));
+                    result.truncate(run);
+                }
+                result.push(Node::Op(Instruction::Clear, position));
+            },
+            Node::Op(instr, position) if cell_delta(&instr).is_some() => result.push(Node::Op(instr, position)),
+            Node::Loop { start, body, end } => {
+                result.push(Node::Loop { start, body: dead_store(body, report), end });
+                run = result.len();
+            },
+            node => {
+                result.push(node);
+                run = result.len();
+            },
+        }
+    }
+
+    result
+}
+
+/// `vectorize` : Replaces runs of cell and pointer changes that add to four or more adjacent cells by `AddVector`s
+///
+/// The cells that are not covered by a vector are updated as before and the data pointer ends up where it did.
+fn vectorize(nodes: Vec<Node>, report: &mut Report) -> Vec<Node> {
+    let mut result: Vec<Node> = Vec::with_capacity(nodes.len());
+
+    // Whether a node changes a cell or moves the data pointer
+    let changes = |node: &Node| instruction(node).is_some_and(|i| cell_delta(i).is_some() || pointer_delta(i).is_some());
+
+    let mut iter = nodes.into_iter().peekable();
+    while let Some(node) = iter.next() {
+        let (instr, position) = match node {
+            Node::Op(instr, position) if cell_delta(&instr).is_some() || pointer_delta(&instr).is_some() => (instr, position),
+            Node::Loop { start, body, end } => {
+                result.push(Node::Loop { start, body: vectorize(body, report), end });
+                continue;
+            },
+            node => {
+                result.push(node);
+                continue;
+            },
+        };
+
+        // Collect the run and the (offset, value) pairs it adds to the cells, relative to its start
+        let mut run: Vec<Item> = vec![(instr, position)];
+        while let Some(Node::Op(next, position)) = iter.next_if(changes) {
+            run.push((next, position));
+        }
+
+        let mut offset: i32 = 0;
+        let mut reachable = true;
+        let mut deltas: Vec<(i32, i32)> = Vec::new();
+        for (instr, _) in &run {
+            if let Some(n) = pointer_delta(instr) {
+                match offset_sum(offset, n) {
+                    Some(sum) => offset = sum,
+                    None => {
+                        reachable = false;
+                        break;
+                    },
+                }
+            } else if let Some(n) = cell_delta(instr) {
+                if let Some(delta) = deltas.iter_mut().find(|(o, _)| *o == offset) {
+                    delta.1 = delta.1.wrapping_add(n);
+                } else {
+                    deltas.push((offset, n));
+                }
+            }
+        }
+        deltas.retain(|(_, n)| *n != 0);
+        deltas.sort();
+
+        // A run that moves farther than an instruction can reach is left as it is
+        if !reachable {
+            result.extend(run.into_iter().map(|(instr, position)| Node::Op(instr, position)));
+            continue;
+        }
+
+        // Greedily group four adjacent cells from the left
+        let mut vectors: Vec<(i32, [i32; 4])> = Vec::new();
+        let mut rest: Vec<(i32, i32)> = Vec::new();
+        let mut i = 0;
+        while i < deltas.len() {
+            let adjacent = deltas.len() - i >= 4 && (1..4).all(|k| deltas[i + k].0 == deltas[i].0 + k as i32);
+            if adjacent {
+                vectors.push((deltas[i].0, [deltas[i].1, deltas[i + 1].1, deltas[i + 2].1, deltas[i + 3].1]));
+                i += 4;
+            } else {
+                rest.push(deltas[i]);
+                i += 1;
+            }
+        }
+
+        if vectors.is_empty() {
+            if deltas.len() >= 4 {
+                report.rejected("vectorize", position, format!("{} cells are updated but no four of them are adjacent", deltas.len()));
+            }
+            result.extend(run.into_iter().map(|(instr, position)| Node::Op(instr, position)));
+            continue;
+        }
+
+        for (start, values) in &vectors {
+            report.applied("vectorize", position, format!("added {:?} to the cells at [{:+}..{:+}]", values, start, start + 3));
+            result.push(Node::Op(Instruction::AddVector(*start, *values), position));
+        }
+
+        // Update the remaining cells by walking to them and finally move to where the run ended
+        let mut pointer = 0;
+        for (o, n) in rest {
+            if o != pointer {
+                result.push(Node::Op(Instruction::MoveN(o - pointer), position));
+                pointer = o;
+            }
+            result.push(Node::Op(Instruction::AddN(n), position));
+        }
+        if offset != pointer {
+            result.push(Node::Op(Instruction::MoveN(offset - pointer), position));
+        }
+    }
+
+    result
+}
+
+/// The values of cells known at a point of a program, relative to where the data pointer was when tracking started
+#[derive(Clone)]
+struct KnownCells {
+    /// The known values of cells, cells that are not in the map are zero while `zeroed` holds and unknown otherwise
+    cells: HashMap<i64, Option<u32>>,
+    /// Whether cells that were never changed are known to be zero
+    zeroed: bool,
+    /// The data pointer
+    dp: i64,
+}
+
+impl KnownCells {
+    /// The cells at the start of a program on a tape that starts zeroed
+    fn zeroed() -> KnownCells {
+        KnownCells { cells: HashMap::new(), zeroed: true, dp: 0 }
+    }
+
+    /// The cells at a point where nothing is known about them
+    fn unknown() -> KnownCells {
+        KnownCells { cells: HashMap::new(), zeroed: false, dp: 0 }
+    }
+
+    /// The value of a cell, if it is known
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - The cell
+    fn value(&self, at: i64) -> Option<u32> {
+        self.cells.get(&at).copied().unwrap_or(if self.zeroed { Some(0) } else { None })
+    }
+
+    /// The value of the current cell, if it is known
+    fn current(&self) -> Option<u32> {
+        self.value(self.dp)
+    }
+
+    /// Forgets every value except the one of the current cell, which is zero once a loop ended
+    fn after_loop(&mut self) {
+        self.cells.clear();
+        self.zeroed = false;
+        self.cells.insert(self.dp, Some(0));
+    }
+
+    /// Applies the effect of an instruction other than `Jump` and `Return`
+    ///
+    /// Returns whether the data pointer is still known relative to where tracking started, which it is not after a
+    /// scan. Only the current cell is known afterwards, which is zero, relative to a new start.
+    ///
+    /// # Arguments
+    ///
+    /// * `instr` - The instruction
+    fn apply(&mut self, instr: &Instruction) -> bool {
+        match instr {
+            Instruction::Increment | Instruction::Decrement | Instruction::MoveN(_) => self.dp += pointer_delta(instr).unwrap() as i64,
+            Instruction::Add | Instruction::Subtract | Instruction::AddN(_) => {
+                let n = cell_delta(instr).unwrap();
+                let v = self.current().map(|v| v.wrapping_add(n as u32));
+                self.cells.insert(self.dp, v);
+            },
+            Instruction::Clear => {
+                self.cells.insert(self.dp, Some(0));
+            },
+            Instruction::Read => {
+                self.cells.insert(self.dp, None);
+            },
+            Instruction::MultiplyAdd(offset, factor) => {
+                let target = self.dp + *offset as i64;
+                let v = self.current()
+                    .zip(self.value(target))
+                    .map(|(v, t)| t.wrapping_add(v.wrapping_mul(*factor as u32)));
+                self.cells.insert(target, v);
+            },
+            Instruction::AddVector(offset, values) => {
+                for (k, n) in values.iter().enumerate() {
+                    let at = self.dp + *offset as i64 + k as i64;
+                    let v = self.value(at).map(|v| v.wrapping_add(*n as u32));
+                    self.cells.insert(at, v);
+                }
+            },
+            Instruction::Scan(_) => {
+                *self = KnownCells::unknown();
+                self.cells.insert(0, Some(0));
+                return false;
+            },
+            Instruction::Write | Instruction::Label(_) | Instruction::Break | Instruction::Unreachable | Instruction::PinBase => {},
+            // Code after a halt is only reached if the halt is not, so it is simulated as usual
+            Instruction::Halt | Instruction::Dump => {},
+            Instruction::Jump(_) | Instruction::Return(_) => unreachable!("loops are nodes of their own"),
+        }
+
+        true
+    }
+
+    /// Applies the effect of nodes, treating loops whose cell is not known to be zero as if they ran
+    ///
+    /// Returns whether the data pointer is still known relative to where tracking started.
+    ///
+    /// # Arguments
+    ///
+    /// * `nodes` - The nodes
+    fn apply_all(&mut self, nodes: &[Node]) -> bool {
+        for node in nodes {
+            match node {
+                Node::Op(instr, _) => {
+                    if !self.apply(instr) {
+                        return false;
+                    }
+                },
+                Node::Loop { .. } if self.current() == Some(0) => {},
+                Node::Loop { body, .. } => {
+                    if !balanced(body) {
+                        return false;
+                    }
+                    self.after_loop();
+                },
+            }
+        }
+
+        true
+    }
+}
+
+/// `dead-tail` : Replaces everything after a top-level loop that provably never ends by `Unreachable`
+///
+/// The program is simulated from its start with the tape's known values until the first top-level loop that is
+/// entered with a known non-zero cell and whose body provably leaves that cell unchanged. The simulation gives up on
+/// anything it cannot follow exactly, like loops that move the data pointer.
+fn dead_tail(mut nodes: Vec<Node>, report: &mut Report) -> Vec<Node> {
+    let mut known = KnownCells::zeroed();
+
+    for i in 0..nodes.len() {
+        match &nodes[i] {
+            Node::Loop { start, body, end } => {
+                if known.current().is_some_and(|v| v != 0) && loops_forever(body) {
+                    let removed = size(&nodes[i + 1..]);
+                    if removed > 0 {
+                        report.applied("dead-tail", *start, format!("loop never ends, removed {} instructions after it", removed));
+                    } else {
+                        report.applied("dead-tail", *start, "loop never ends, removed the epilogue".to_owned());
+                    }
+
+                    let position = *end;
+                    nodes.truncate(i + 1);
+                    nodes.push(Node::Op(Instruction::Unreachable, position));
+                    return nodes;
+                }
+
+                // A loop ends on a zero cell, but the rest of the tape is unknown afterwards
+                if !balanced(body) {
+                    return nodes;
+                }
+                known.after_loop();
+            },
+            Node::Op(instr, _) => {
+                // The data pointer is unknown after a scan
+                if !known.apply(instr) {
+                    return nodes;
+                }
+            },
+        }
+    }
+
+    nodes
+}
+
+/// `strip-unreachable` : Removes everything after a `@` up to the end of the loop body it is in
+///
+/// At the top level the epilogue is replaced by `Unreachable` as well, as the program ends through the halt helper.
+/// The code after a loop whose body halts stays, as the loop may be skipped.
+///
+/// # Arguments
+///
+/// * `nodes` - The nodes of the program or of a loop body
+/// * `top_level` - Whether the nodes are the top level of the program
+/// * `report` - The report to record the findings in
+fn strip_unreachable(mut nodes: Vec<Node>, top_level: bool, report: &mut Report) -> Vec<Node> {
+    if let Some(i) = nodes.iter().position(|node| matches!(node, Node::Op(Instruction::Halt, _))) {
+        let Node::Op(_, position) = nodes[i] else { unreachable!() };
+        let tail = &nodes[i + 1..];
+        // The top level keeps the `Unreachable` of an earlier run
+        let done = if top_level { matches!(tail, [Node::Op(Instruction::Unreachable, _)]) } else { tail.is_empty() };
+
+        if !done {
+            let removed = size(tail);
+            if top_level && removed == 0 {
+                report.applied("strip-unreachable", position, "'@' always halts, removed the epilogue".to_owned());
+            } else {
+                report.applied("strip-unreachable", position, format!("'@' always halts, removed {} instructions after it", removed));
+            }
+
+            nodes.truncate(i + 1);
+            if top_level {
+                nodes.push(Node::Op(Instruction::Unreachable, position));
+            }
+        }
+    }
+
+    let mut result = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        result.push(match node {
+            Node::Loop { start, body, end } => Node::Loop { start, body: strip_unreachable(body, false, report), end },
+            node => node,
+        });
+    }
+    result
+}
+
+/// `comment-loop` : Removes the top-level loops that are reached before their cell could have become non-zero
+///
+/// Programs often start with a loop holding a comment, which may contain instructions as `[` skips it on the zeroed
+/// tape. The cells are tracked from the start, so `+[-]>[comment]` is removed as well, until a scan or a loop that
+/// runs leaves them unknown. Only run while the tape starts zeroed, which it does not with `--argv-tape`,
+/// `--tape-init` and fragments.
+///
+/// # Arguments
+///
+/// * `nodes` - The top-level nodes of the program
+/// * `report` - The report to record the findings in
+fn comment_loop(nodes: Vec<Node>, report: &mut Report) -> Vec<Node> {
+    let mut known = Some(KnownCells::zeroed());
+    let mut result = Vec::with_capacity(nodes.len());
+
+    for node in nodes {
+        match (&node, &mut known) {
+            (Node::Loop { start, body, .. }, Some(cells)) if cells.current() == Some(0) => {
+                report.applied("comment-loop", *start, format!("loop never runs on the zeroed tape, removed it and the {} instructions of its body", size(body)));
+                continue;
+            },
+            (Node::Op(instr, _), Some(cells)) => {
+                // The data pointer is unknown after a scan
+                if !cells.apply(instr) {
+                    known = None;
+                }
+            },
+            (Node::Loop { .. }, Some(_)) => known = None,
+            (_, None) => {},
+        }
+        result.push(node);
+    }
+
+    result
+}
+
+/// `known-values` : Simplifies loops and instructions whose cell has a known value
+///
+/// Values become known through clears, the zero cell a loop or scan ends on and the changes of known cells.
+/// Loops whose cell is zero are removed, those whose body adds the same amounts on every iteration are replaced by
+/// the sum of their iterations and those whose iterations fit into the unroll limit when copied are unrolled, which
+/// removes their compares. `,`, scans and loops that are not replaced make the values unknown again. The tape is not
+/// assumed to start zeroed, as `--argv-tape`, `--tape-init` and fragments start with other values.
+///
+/// # Arguments
+///
+/// * `nodes` - The nodes to simplify
+/// * `known` - The values known before the nodes, updated to those known after them
+/// * `unroll_limit` - The most instructions the unrolled iterations of a loop may have in total
+/// * `report` - The report to record the findings in
+fn known_values(nodes: Vec<Node>, known: &mut KnownCells, unroll_limit: usize, report: &mut Report) -> Vec<Node> {
+    let mut result: Vec<Node> = Vec::with_capacity(nodes.len());
+
+    for node in nodes {
+        let (start, body, end) = match node {
+            Node::Loop { start, body, end } => (start, body, end),
+            Node::Op(Instruction::Clear, position) if known.current() == Some(0) => {
+                report.applied("known-values", position, "removed a clear of a cell that is already 0".to_owned());
+                continue;
+            },
+            Node::Op(Instruction::MultiplyAdd(offset, factor), position) if known.current().is_some() => {
+                let v = known.current().unwrap();
+                if v == 0 {
+                    report.applied("known-values", position, "removed a multiply-add from a cell that is 0".to_owned());
+                    continue;
+                }
+
+                let n = v.wrapping_mul(factor as u32) as i32;
+                report.applied("known-values", position, format!("replaced a multiply-add from a cell that is {} by adding {}", v, n));
+                let replacement = [Instruction::MoveN(offset), Instruction::AddN(n), Instruction::MoveN(-offset)];
+                for instr in replacement {
+                    known.apply(&instr);
+                    result.push(Node::Op(instr, position));
+                }
+                continue;
+            },
+            Node::Op(instr, position) => {
+                known.apply(&instr);
+                result.push(Node::Op(instr, position));
+                continue;
+            },
+        };
+
+        let replacement = match known.current() {
+            // Labels must stay where they are and must not be repeated
+            Some(_) if contains_label(&body) => {
+                report.rejected("known-values", start, "loop contains a label".to_owned());
+                None
+            },
+            Some(0) => {
+                report.applied("known-values", start, format!("loop never runs as its cell is 0, removed {} instructions", size(&body) + 2));
+                Some(Vec::new())
+            },
+            Some(v) => multiplied(&body, v, start, report).or_else(|| unrolled(&body, v, known, unroll_limit, start, report)),
+            None => None,
+        };
+
+        match replacement {
+            Some(nodes) => result.extend(known_values(nodes, known, unroll_limit, report)),
+            None => {
+                let body = known_values(body, &mut KnownCells::unknown(), unroll_limit, report);
+                result.push(Node::Loop { start, body, end });
+                known.after_loop();
+            },
+        }
+    }
+
+    result
+}
+
+/// Returns the instructions that add the sum of all iterations of a loop at once, if its body only changes cells and
+/// moves the data pointer back to the loop's cell
+///
+/// # Arguments
+///
+/// * `body` - The nodes between the loop's brackets
+/// * `value` - The known value of the loop's cell when the loop is entered, which is not zero
+/// * `position` - The position of the loop, which the instructions get
+/// * `report` - The report to record the findings in
+fn multiplied(body: &[Node], value: u32, position: Position, report: &mut Report) -> Option<Vec<Node>> {
+    let instructions: Option<Vec<&Instruction>> = body.iter().map(instruction).collect();
+    let instructions = instructions.filter(|instructions| !instructions.is_empty())?;
+
+    let mut offset: i32 = 0;
+
+    // The (offset, value) pairs that get added to the cells on each iteration
+    let mut deltas: Vec<(i32, u32)> = Vec::new();
+
+    for instr in instructions {
+        if let Some(n) = pointer_delta(instr) {
+            offset = offset_sum(offset, n)?;
+        } else if let Some(n) = cell_delta(instr) {
+            match deltas.iter_mut().find(|(o, _)| *o == offset) {
+                Some(delta) => delta.1 = delta.1.wrapping_add(n as u32),
+                None => deltas.push((offset, n as u32)),
+            }
+        } else {
+            return None;
+        }
+    }
+
+    // Only odd changes of the loop's cell reach zero from every value, and do so after a single amount of iterations
+    let counter = deltas.iter().find(|(o, _)| *o == 0).map_or(0, |(_, n)| *n);
+    if offset != 0 || counter % 2 == 0 {
+        return None;
+    }
+
+    // The iterations solve `value + iterations * counter = 0` modulo 2^32, with the inverse of the odd counter found by
+    // Newton's method, where each step doubles the amount of correct bits
+    let mut inverse = counter;
+    for _ in 0..5 {
+        inverse = inverse.wrapping_mul(2u32.wrapping_sub(counter.wrapping_mul(inverse)));
+    }
+    let iterations = value.wrapping_neg().wrapping_mul(inverse);
+
+    let mut result: Vec<Node> = Vec::new();
+    let mut pointer = 0;
+    for (o, n) in deltas.iter().filter(|(o, _)| *o != 0) {
+        let total = n.wrapping_mul(iterations) as i32;
+        if total == 0 {
+            continue;
+        }
+        if *o != pointer {
+            result.push(Node::Op(Instruction::MoveN(o - pointer), position));
+            pointer = *o;
+        }
+        result.push(Node::Op(Instruction::AddN(total), position));
+    }
+    if pointer != 0 {
+        result.push(Node::Op(Instruction::MoveN(-pointer), position));
+    }
+    result.push(Node::Op(Instruction::Clear, position));
+
+    report.applied("known-values", position, format!("replaced {} iterations by their sum", iterations));
+    Some(result)
+}
+
+/// Returns the copies of a loop's body that replace it, if the loop provably ends after few enough iterations for
+/// their copies to stay within a limit
+///
+/// # Arguments
+///
+/// * `body` - The nodes between the loop's brackets
+/// * `value` - The known value of the loop's cell when the loop is entered, which is not zero
+/// * `known` - The values known when the loop is entered
+/// * `limit` - The most instructions the copies may have in total
+/// * `position` - The position of the loop
+/// * `report` - The report to record the findings in
+fn unrolled(body: &[Node], value: u32, known: &KnownCells, limit: usize, position: Position, report: &mut Report) -> Option<Vec<Node>> {
+    let mut simulated = known.clone();
+
+    // An empty body never changes the loop's cell, so it is simulated once to find that out
+    let max_iterations = limit / size(body).max(1);
+    for iterations in 1..=max_iterations {
+        if !simulated.apply_all(body) {
+            report.rejected("known-values", position, "loop moves the data pointer by an unknown amount".to_owned());
+            return None;
+        }
+
+        match simulated.current() {
+            Some(0) if iterations == 1 => report.applied("known-values", position, "loop runs exactly once, removed its compares".to_owned()),
+            Some(0) => report.applied("known-values", position, format!("unrolled {} iterations", iterations)),
+            Some(_) => continue,
+            None => {
+                report.rejected("known-values", position, format!("loop's cell is {} when entered but unknown after an iteration", value));
+                return None;
+            },
+        }
+
+        return Some((0..iterations).flat_map(|_| body.iter().cloned()).collect());
+    }
+
+    report.rejected("known-values", position, format!("loop runs more than {} iterations, unrolling it would exceed {} instructions", max_iterations, limit));
+    None
+}
+
+/// Whether nodes contain a label, including those in their loops
+///
+/// # Arguments
+///
+/// * `nodes` - The nodes to check
+fn contains_label(nodes: &[Node]) -> bool {
+    nodes.iter().any(|node| match node {
+        Node::Op(instr, _) => matches!(instr, Instruction::Label(_)),
+        Node::Loop { body, .. } => contains_label(body),
+    })
+}
+
+/// `hoist-base` : Inserts `PinBase` before the outermost loops whose bodies never move the data pointer
+///
+/// The generated assembly then loads the data pointer into a register once instead of before every instruction of
+/// the loop. Loops that move the data pointer are not pinned, even if they return to where they started, as the
+/// pointer is kept in memory while they run.
+fn hoist_base(nodes: Vec<Node>, report: &mut Report) -> Vec<Node> {
+    let mut result: Vec<Node> = Vec::with_capacity(nodes.len());
+
+    for node in nodes {
+        match node {
+            // Inner loops of pinned loops are already covered
+            Node::Loop { start, body, end } if pointer_invariant(&body) => {
+                if !matches!(result.last(), Some(Node::Op(Instruction::PinBase, _))) {
+                    report.applied("hoist-base", start, format!("data pointer loaded once for {} instructions", size(&body) + 2));
+                    result.push(Node::Op(Instruction::PinBase, start));
+                }
+                result.push(Node::Loop { start, body, end });
+            },
+            Node::Loop { start, body, end } => result.push(Node::Loop { start, body: hoist_base(body, report), end }),
+            node => result.push(node),
+        }
+    }
+
+    result
+}
+
+/// Whether nodes never move the data pointer, including those in their loops
+///
+/// # Arguments
+///
+/// * `body` - The nodes to check
+fn pointer_invariant(body: &[Node]) -> bool {
+    body.iter().all(|node| match node {
+        Node::Op(instr, _) => !matches!(
+            instr,
+            Instruction::Increment | Instruction::Decrement | Instruction::MoveN(_) | Instruction::Scan(_) | Instruction::Unreachable
+        ),
+        Node::Loop { body, .. } => pointer_invariant(body),
+    })
+}
+
+/// Returns the lowest and the highest cell relative to the starting one that a program can ever access, or `None` if
+/// the movement of the data pointer depends on the data
+///
+/// The reach is bounded exactly when every loop moves the data pointer back to where it started, as each iteration of
+/// a loop then accesses the same cells.
+///
+/// # Arguments
+///
+/// * `instructions` - A slice of instructions that contains the program
+pub fn pointer_reach(instructions: &[Instruction]) -> Option<(i64, i64)> {
+    let mut offset: i64 = 0;
+    let (mut low, mut high) = (0, 0);
+
+    // The offset at the start of each loop that is currently open
+    let mut starts: Vec<i64> = Vec::new();
+
+    for instr in instructions {
+        // The range of cells the instruction accesses besides the current one
+        let (first, last) = match instr {
+            Instruction::Jump(_) => {
+                starts.push(offset);
+                (offset, offset)
+            },
+            Instruction::Return(_) => {
+                if starts.pop() != Some(offset) {
+                    return None;
+                }
+                (offset, offset)
+            },
+            Instruction::Scan(_) => return None,
+            Instruction::MultiplyAdd(target, _) => (offset + *target as i64, offset + *target as i64),
+            Instruction::AddVector(start, _) => (offset + *start as i64, offset + *start as i64 + 3),
+            instr => {
+                offset += pointer_delta(instr).unwrap_or(0) as i64;
+                (offset, offset)
+            },
+        };
+
+        low = low.min(first).min(offset);
+        high = high.max(last).max(offset);
+    }
+
+    Some((low, high))
+}
+
+/// Whether a loop body moves the data pointer back to where it started, no matter how often its inner loops run
+///
+/// # Arguments
+///
+/// * `body` - The nodes between the loop's brackets
+fn balanced(body: &[Node]) -> bool {
+    let mut movement: i64 = 0;
+
+    for node in body {
+        match node {
+            Node::Loop { body, .. } => {
+                if !balanced(body) {
+                    return false;
+                }
+            },
+            Node::Op(Instruction::Scan(_), _) => return false,
+            Node::Op(instr, _) => movement += i64::from(pointer_delta(instr).unwrap_or(0)),
+        }
+    }
+
+    movement == 0
+}
+
+/// Whether a loop body provably leaves the cell it starts on unchanged and returns to it, so a loop entered with a
+/// non-zero cell never ends
+///
+/// # Arguments
+///
+/// * `body` - The nodes between the loop's brackets
+fn loops_forever(body: &[Node]) -> bool {
+    let mut offset: i32 = 0;
+
+    // The amount the loop's cell changed by so far
+    let mut delta: i32 = 0;
+
+    for node in body {
+        // Inner loops could change the loop's cell in ways that are not followed
+        let Node::Op(instr, _) = node else {
+            return false;
+        };
+
+        match instr {
+            Instruction::Increment | Instruction::Decrement | Instruction::MoveN(_) => match offset_sum(offset, pointer_delta(instr).unwrap()) {
+                Some(sum) => offset = sum,
+                None => return false,
+            },
+            Instruction::Add | Instruction::Subtract | Instruction::AddN(_) => {
+                if offset == 0 {
+                    delta = delta.wrapping_add(cell_delta(instr).unwrap());
+                }
+            },
+            Instruction::AddVector(start, values) => {
+                let index = -(offset + start);
+                if (0..4).contains(&index) {
+                    delta = delta.wrapping_add(values[index as usize]);
+                }
+            },
+            Instruction::MultiplyAdd(target, _) if offset + target != 0 => {},
+            Instruction::Clear | Instruction::Read if offset != 0 => {},
+            Instruction::Write | Instruction::Label(_) | Instruction::Break | Instruction::Dump => {},
+            // Anything else, including a halt, could change the loop's cell in ways that are not followed
+            _ => return false,
+        }
+    }
+
+    offset == 0 && delta == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, Extensions};
+
+    /// Builds the tree of a source
+    fn tree(source: &str) -> Vec<Node> {
+        build(parse(source, Extensions::default()).unwrap()).unwrap()
+    }
+
+    /// The instructions of a tree, rendered to compare them
+    fn rendered(nodes: &[Node]) -> String {
+        flatten(nodes).iter().map(|(instr, _)| format!("{:?}", instr)).collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn build_and_flatten_round_trip() {
+        for source in ["", "+-<>.,", "+[->+<]>.", "[[]][[[-]>]<]", include_str!("../tests/Hello_World.bf")] {
+            let program = parse(source, Extensions::default()).unwrap();
+            let expected: Vec<String> = program.instructions.iter().map(|instr| format!("{:?}", instr)).collect();
+            let lines: Vec<(usize, usize)> = program.positions.iter().map(|position| (position.line, position.column)).collect();
+
+            let items = flatten(&build(program).unwrap());
+            assert_eq!(items.iter().map(|(instr, _)| format!("{:?}", instr)).collect::<Vec<_>>(), expected, "{}", source);
+            assert_eq!(items.iter().map(|(_, position)| (position.line, position.column)).collect::<Vec<_>>(), lines, "{}", source);
+        }
+    }
+
+    #[test]
+    fn build_rejects_unpaired_loop_instructions() {
+        let position = Position { line: 1, column: 1 };
+        let unpaired = |instructions: Vec<Instruction>| {
+            let positions = vec![position; instructions.len()];
+            build(Program { instructions, positions }).err().unwrap().to_string()
+        };
+        assert_eq!(unpaired(vec![Instruction::Add, Instruction::Return(0)]), "unmatched ']' at 1:1!");
+        assert_eq!(unpaired(vec![Instruction::Jump(1), Instruction::Add]), "unmatched '[' at 1:1!");
+    }
+
+    #[test]
+    fn loops_are_recognized_inside_nested_loops() {
+        let mut report = Report::default();
+        let nodes = tree("+[>[-]<[[>]]>[->+<]<]");
+        let nodes = clear_loop(nodes, &mut report);
+        let nodes = scan_loop(nodes, &mut report);
+        let nodes = copy_loop(nodes, &mut report);
+        assert_eq!(
+            rendered(&nodes),
+            "Add Jump(12) Increment Clear Decrement Jump(7) Scan(1) Return(5) Increment MultiplyAdd(1, 1) Clear Decrement Return(1)"
+        );
+        let applied: Vec<String> = report.findings().filter(|(_, applied, _)| *applied).map(|(_, _, message)| message).collect();
+        assert_eq!(applied, [
+            "clear-loop: recognized clear loop",
+            "scan-loop: recognized scan loop with stride 1",
+            "copy-loop: recognized copy loop with factors [+1]*1",
+        ]);
+    }
+
+    #[test]
+    fn clear_loops_with_even_changes_are_rejected() {
+        let mut report = Report::default();
+        let nodes = clear_loop(tree("+[--]+[+++-]+[---]"), &mut report);
+        assert_eq!(rendered(&nodes), "Add Jump(4) Subtract Subtract Return(1) Add Jump(11) Add Add Add Subtract Return(6) Add Clear");
+        let findings: Vec<(bool, String)> = report.findings().map(|(_, applied, message)| (applied, message)).collect();
+        assert_eq!(findings, [
+            (false, "clear-loop: rejected: cell changes by -2 per iteration, so it might never reach zero".to_owned()),
+            (false, "clear-loop: rejected: cell changes by 2 per iteration, so it might never reach zero".to_owned()),
+            (true, "clear-loop: recognized clear loop".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn changes_overwritten_by_a_clear_are_removed() {
+        let mut report = Report::default();
+        assert_eq!(rendered(&dead_store(clear_loop(tree("+++[-]"), &mut report), &mut report)), "Clear");
+        assert_eq!(rendered(&dead_store(clear_loop(tree("[-]+[-]."), &mut report), &mut report)), "Clear Write");
+        let applied: Vec<String> = report.findings().filter(|(_, applied, _)| *applied).map(|(_, _, message)| message).collect();
+        assert_eq!(applied, [
+            "clear-loop: recognized clear loop",
+            "dead-store: removed 3 instructions overwritten by a clear",
+            "clear-loop: recognized clear loop",
+            "clear-loop: recognized clear loop",
+            "dead-store: removed 2 instructions overwritten by a clear",
+        ]);
+    }
+
+    #[test]
+    fn changes_read_or_left_before_a_clear_are_kept() {
+        let mut report = Report::default();
+        for (source, expected) in [
+            ("+>[-]", "Add Increment Clear"),
+            ("+,[-]", "Add Read Clear"),
+            ("+.[-]", "Add Write Clear"),
+            ("+[>][-]", "Add Jump(3) Increment Return(1) Clear"),
+            ("+[[-]>]", "Add Jump(4) Clear Increment Return(1)"),
+        ] {
+            assert_eq!(rendered(&dead_store(clear_loop(tree(source), &mut report), &mut report)), expected, "{}", source);
+        }
+        assert!(report.findings().all(|(_, _, message)| !message.starts_with("dead-store")));
+    }
+
+    #[test]
+    fn the_code_after_a_loop_that_never_ends_is_removed() {
+        let mut report = Report::default();
+        assert_eq!(rendered(&dead_tail(tree("+[].+"), &mut report)), "Add Jump(2) Return(1) Unreachable");
+        assert_eq!(rendered(&dead_tail(tree("+[]"), &mut report)), "Add Jump(2) Return(1) Unreachable");
+        let applied: Vec<String> = report.findings().map(|(_, _, message)| message).collect();
+        assert_eq!(applied, [
+            "dead-tail: loop never ends, removed 2 instructions after it",
+            "dead-tail: loop never ends, removed the epilogue",
+        ]);
+
+        // Loops that move the data pointer or change their cell may end, and a scan leaves the data pointer unknown
+        let mut report = Report::default();
+        for (source, expected) in [
+            ("+[>].", "Add Jump(3) Increment Return(1) Write"),
+            ("+[-].", "Add Jump(3) Subtract Return(1) Write"),
+            ("[].", "Jump(1) Return(0) Write"),
+        ] {
+            assert_eq!(rendered(&dead_tail(tree(source), &mut report)), expected, "{}", source);
+        }
+        let scanned = scan_loop(tree("+[>]+[]."), &mut report);
+        assert_eq!(rendered(&dead_tail(scanned, &mut report)), "Add Scan(1) Add Jump(4) Return(3) Write");
+        assert!(report.findings().all(|(_, _, message)| !message.starts_with("dead-tail")));
+    }
+
+    #[test]
+    fn loops_changing_their_cell_through_vectors_or_multiply_adds_may_end() {
+        let position = Position { line: 1, column: 1 };
+        let body = |instructions: Vec<Instruction>| instructions.into_iter().map(|instr| Node::Op(instr, position)).collect::<Vec<_>>();
+
+        // The vector covers the cells -1 to 2, so its second value is added to the loop's cell
+        assert!(loops_forever(&body(vec![Instruction::AddVector(-1, [5, 0, 7, 7])])));
+        assert!(!loops_forever(&body(vec![Instruction::AddVector(-1, [0, 1, 0, 0])])));
+        assert!(!loops_forever(&body(vec![Instruction::MoveN(2), Instruction::AddVector(-5, [0, 0, 0, 1]), Instruction::MoveN(-2)])));
+
+        // A multiply-add only reads the loop's cell unless it targets it
+        assert!(loops_forever(&body(vec![Instruction::MultiplyAdd(1, 2)])));
+        assert!(!loops_forever(&body(vec![Instruction::MoveN(1), Instruction::MultiplyAdd(-1, 2), Instruction::MoveN(-1)])));
+    }
+
+    #[test]
+    fn four_adjacent_cells_are_updated_by_a_vector() {
+        let mut report = Report::default();
+        for (source, expected) in [
+            ("+>++>+++>++++<<<.", "AddVector(0, [1, 2, 3, 4]) Write"),
+            // Cells left over after grouping from the left are updated one by one
+            ("+>+>+>+>+>-", "AddVector(0, [1, 1, 1, 1]) MoveN(4) AddN(1) MoveN(1) AddN(-1)"),
+            ("+>+>+>+>>>", "AddVector(0, [1, 1, 1, 1]) MoveN(6)"),
+            ("<<+>+>+>+>+<", "AddVector(-2, [1, 1, 1, 1]) MoveN(2) AddN(1) MoveN(-1)"),
+            ("+>+>+>+<<<[+>+>+>+<<<]", "AddVector(0, [1, 1, 1, 1]) Jump(3) AddVector(0, [1, 1, 1, 1]) Return(1)"),
+        ] {
+            assert_eq!(rendered(&vectorize(tree(source), &mut report)), expected, "{}", source);
+        }
+
+        let mut report = Report::default();
+        assert_eq!(rendered(&vectorize(tree("+>>+>>+>>+"), &mut report)), "Add Increment Increment Add Increment Increment Add Increment Increment Add");
+        let findings: Vec<String> = report.findings().map(|(_, _, message)| message).collect();
+        assert_eq!(findings, ["vectorize: rejected: 4 cells are updated but no four of them are adjacent"]);
+    }
+
+    #[test]
+    fn the_data_pointer_is_loaded_once_for_loops_that_never_move_it() {
+        let mut report = Report::default();
+        for (source, expected) in [
+            ("+[-[+]]", "Add PinBase Jump(7) Subtract Jump(6) Add Return(4) Return(2)"),
+            ("+[>+<-]", "Add Jump(6) Increment Add Decrement Subtract Return(1)"),
+            ("+[>[-]<-]", "Add Jump(9) Increment PinBase Jump(6) Subtract Return(4) Decrement Subtract Return(1)"),
+        ] {
+            let nodes = hoist_base(tree(source), &mut report);
+            assert_eq!(rendered(&nodes), expected, "{}", source);
+            assert_eq!(verify(&nodes), Ok(()), "{}", source);
+        }
+        let applied: Vec<String> = report.findings().map(|(_, _, message)| message).collect();
+        assert_eq!(applied, [
+            "hoist-base: data pointer loaded once for 6 instructions",
+            "hoist-base: data pointer loaded once for 3 instructions",
+        ]);
+    }
+
+    #[test]
+    fn pins_of_loops_that_move_the_data_pointer_are_rejected() {
+        let position = Position { line: 1, column: 2 };
+        let pin = Node::Op(Instruction::PinBase, position);
+        let moving = tree("[>]").remove(0);
+        let error = "PinBase at 1:2 does not precede a loop that keeps the data pointer".to_owned();
+        assert_eq!(verify(&[pin.clone(), moving.clone()]), Err(error.clone()));
+        assert_eq!(verify(&[pin.clone(), Node::Op(Instruction::Add, position)]), Err(error.clone()));
+        assert_eq!(verify(&[Node::Loop { start: position, body: vec![pin], end: position }]), Err(error));
+        assert_eq!(verify(&[Node::Op(Instruction::Return(0), position)]), Err("loop instruction at 1:2 is not part of a loop node".to_owned()));
+        assert_eq!(verify(&[moving]), Ok(()));
+    }
+
+    #[test]
+    fn runs_around_a_run_that_cancels_out_are_merged() {
+        let mut report = Report::default();
+        assert_eq!(rendered(&rle(tree("+>+-<.>+-<."), &mut report)), "AddN(1) Write Write");
+        assert_eq!(rendered(&rle(tree(">>+-<[<+-<]"), &mut report)), "MoveN(1) Jump(3) MoveN(-2) Return(1)");
+
+        let mut report = Report::default();
+        assert_eq!(rendered(&rle(tree("++<>-"), &mut report)), "AddN(1)");
+        let findings: Vec<String> = report.findings().map(|(_, _, message)| message).collect();
+        assert_eq!(findings, [
+            "rle: merged 2 instructions into AddN(2)",
+            "rle: removed 2 instructions that cancel out",
+            "rle: merged 2 instructions into AddN(1)",
+        ]);
+    }
+
+    #[test]
+    fn moves_are_only_merged_as_far_as_an_instruction_reaches() {
+        let mut report = Report::default();
+        let moves = |n: i32| (0..3).map(|_| Node::Op(Instruction::MoveN(n), Position { line: 1, column: 1 })).collect::<Vec<_>>();
+        assert_eq!(rendered(&rle(moves(MAX_OFFSET / 2), &mut report)), format!("MoveN({}) MoveN({})", MAX_OFFSET / 2 * 2, MAX_OFFSET / 2));
+        assert_eq!(rendered(&rle(moves(-MAX_OFFSET), &mut report)), format!("MoveN({}) MoveN({}) MoveN({})", -MAX_OFFSET, -MAX_OFFSET, -MAX_OFFSET));
+
+        // Cells wrap around instead
+        let adds = (0..3).map(|_| Node::Op(Instruction::AddN(i32::MAX), Position { line: 1, column: 1 })).collect();
+        assert_eq!(rendered(&rle(adds, &mut report)), format!("AddN({})", i32::MAX.wrapping_mul(3)));
+
+        // Copy loops whose targets lie farther are left as they are
+        let mut report = Report::default();
+        let position = Position { line: 1, column: 1 };
+        let body = [Instruction::Subtract, Instruction::MoveN(MAX_OFFSET), Instruction::MoveN(1), Instruction::Add, Instruction::MoveN(-1), Instruction::MoveN(-MAX_OFFSET)];
+        let far = vec![Node::Loop { start: position, body: body.into_iter().map(|instr| Node::Op(instr, position)).collect(), end: position }];
+        assert_eq!(rendered(&copy_loop(far, &mut report)), format!("Jump(7) Subtract MoveN({}) MoveN(1) Add MoveN(-1) MoveN({}) Return(0)", MAX_OFFSET, -MAX_OFFSET));
+        let findings: Vec<String> = report.findings().map(|(_, _, message)| message).collect();
+        assert_eq!(findings, [format!("copy-loop: rejected: body moves farther than the {} cells an instruction can reach", MAX_OFFSET)]);
+    }
+
+    #[test]
+    fn the_text_report_lines_up_the_longest_pass_name() {
+        let mut report = Report::default();
+        let passes = PassManager::from_names("rle,strip-unreachable", Cpu::Baseline).unwrap();
+        passes.run(parse("+++.", Extensions::default()).unwrap(), &mut report, &DumpSettings::default()).unwrap();
+
+        let mut text: Vec<u8> = Vec::new();
+        report.write_text(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("\n  1:1      rle               merged 3 instructions into AddN(3)\n"), "{}", text);
+        assert!(text.contains("\n  rle               1 applied, "), "{}", text);
+        assert!(text.contains("\n  strip-unreachable 0 applied, "), "{}", text);
+    }
+}
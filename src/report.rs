@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use crate::optimizer::Report;
+use crate::{Instruction, Position, Program};
+
+/// The page the report is rendered into, whose `{{name}}` placeholders are replaced by the sections of the report
+const TEMPLATE: &str = include_str!("templates/report.html");
+
+/// The amount of heat classes of executed instructions, `heat0` being the one of instructions that never ran
+const HEAT_LEVELS: f64 = 5.0;
+
+/// Renders a self-contained HTML report of a program
+///
+/// The report holds a summary table, the source with its loops colored by depth and collapsible, the optimizer's
+/// findings both as tooltips of the instructions they are about and as a table, and with a profile how often each
+/// instruction ran as its background.
+///
+/// # Arguments
+///
+/// * `title` - The name of the program's source file
+/// * `source` - The program's source code
+/// * `program` - The parsed program, before optimization
+/// * `report` - What the optimizer did to the program
+/// * `optimized` - The amount of instructions left after optimization
+/// * `profile` - The first and last source positions and the count of each block of a coverage file, if one was given
+pub fn render(title: &str, source: &str, program: &Program, report: &Report, optimized: usize, profile: Option<&[(Position, Position, u64)]>) -> String {
+    // The pc of the instruction at each position, other characters are comments
+    let pcs: HashMap<(usize, usize), usize> = program.positions.iter()
+        .enumerate()
+        .map(|(pc, position)| ((position.line, position.column), pc))
+        .collect();
+
+    let mut findings: HashMap<(usize, usize), Vec<String>> = HashMap::new();
+    for (position, _, finding) in report.findings() {
+        findings.entry((position.line, position.column)).or_default().push(finding);
+    }
+
+    let hottest = profile.map_or(0, |blocks| blocks.iter().map(|(_, _, count)| *count).max().unwrap_or(0));
+
+    let mut html = String::new();
+    // The characters that share their attributes and are written as one span
+    let mut run = String::new();
+    let mut run_attributes = String::new();
+    let mut depth = 0;
+
+    for (line, l) in source.lines().enumerate() {
+        for (column, c) in l.chars().enumerate() {
+            let key = (line + 1, column + 1);
+            let instr = pcs.get(&key).map(|pc| &program.instructions[*pc]);
+
+            let mut classes: Vec<String> = Vec::new();
+            match instr {
+                Some(Instruction::Jump(_)) => classes.extend(["bracket".to_owned(), "open".to_owned()]),
+                Some(Instruction::Return(_)) => classes.extend(["bracket".to_owned(), "close".to_owned()]),
+                Some(_) => {},
+                None => classes.push("comment".to_owned()),
+            }
+            if let (Some(_), Some(blocks)) = (instr, profile) {
+                if let Some(count) = count_at(blocks, key) {
+                    classes.push(format!("heat{}", heat(count, hottest)));
+                }
+            }
+            let tooltip = findings.get(&key);
+            if tooltip.is_some() {
+                classes.push("optimized".to_owned());
+            }
+            let attributes = attributes(&classes, tooltip);
+
+            match instr {
+                Some(Instruction::Jump(_)) => {
+                    flush(&mut html, &mut run, &run_attributes);
+                    html.push_str(&format!(
+                        "<span class=\"loop depth{}\" id=\"loop-{}-{}\"><span{}>[</span><span class=\"body\">",
+                        1 + depth % 5, key.0, key.1, attributes,
+                    ));
+                    depth += 1;
+                },
+                Some(Instruction::Return(_)) => {
+                    flush(&mut html, &mut run, &run_attributes);
+                    html.push_str(&format!("</span><span{}>]</span></span>", attributes));
+                    depth -= 1;
+                },
+                _ => {
+                    if attributes != run_attributes {
+                        flush(&mut html, &mut run, &run_attributes);
+                        run_attributes = attributes;
+                    }
+                    run.push_str(&escape(&c.to_string()));
+                },
+            }
+        }
+
+        flush(&mut html, &mut run, &run_attributes);
+        html.push('\n');
+    }
+
+    let legend = if profile.is_some() {
+        " The background shows how often each instruction ran, from never in grey to most often in red."
+    } else {
+        ""
+    };
+
+    TEMPLATE
+        .replace("{{title}}", &escape(title))
+        .replace("{{stats}}", &stats(source, program, report, optimized, profile))
+        .replace("{{legend}}", legend)
+        .replace("{{source}}", &html)
+        .replace("{{findings}}", &findings_table(report))
+}
+
+/// Writes the pending run of characters as a span with its attributes and empties it
+///
+/// # Arguments
+///
+/// * `html` - The HTML of the source so far
+/// * `run` - The escaped characters of the run
+/// * `attributes` - The attributes of the run's span, the run is written as is if there are none
+fn flush(html: &mut String, run: &mut String, attributes: &str) {
+    if run.is_empty() {
+        return;
+    }
+    if attributes.is_empty() {
+        html.push_str(run);
+    } else {
+        html.push_str(&format!("<span{}>{}</span>", attributes, run));
+    }
+    run.clear();
+}
+
+/// Returns the `class` and `title` attributes of a character's span, or nothing if it has neither
+///
+/// # Arguments
+///
+/// * `classes` - The classes of the character
+/// * `tooltip` - The optimizer's findings about the character, if there are any
+fn attributes(classes: &[String], tooltip: Option<&Vec<String>>) -> String {
+    let mut attributes = String::new();
+    if !classes.is_empty() {
+        attributes.push_str(&format!(" class=\"{}\"", classes.join(" ")));
+    }
+    if let Some(tooltip) = tooltip {
+        attributes.push_str(&format!(" title=\"{}\"", escape(&tooltip.join("\n"))));
+    }
+    attributes
+}
+
+/// Returns how often the block containing a position was entered, if a block contains it
+///
+/// # Arguments
+///
+/// * `blocks` - The first and last source positions and the count of each block, in the order of the program
+/// * `key` - The line and column of the position
+fn count_at(blocks: &[(Position, Position, u64)], key: (usize, usize)) -> Option<u64> {
+    let index = blocks.partition_point(|(start, _, _)| (start.line, start.column) <= key);
+    let (_, end, count) = blocks.get(index.checked_sub(1)?)?;
+    ((end.line, end.column) >= key).then_some(*count)
+}
+
+/// Returns the heat class of an instruction, scaled logarithmically so hot loops do not wash out everything else
+///
+/// # Arguments
+///
+/// * `count` - How often the instruction ran
+/// * `hottest` - How often the hottest instruction ran
+fn heat(count: u64, hottest: u64) -> u64 {
+    match (count, hottest) {
+        (0, _) => 0,
+        (_, 0..=1) => HEAT_LEVELS as u64,
+        _ => 1 + ((HEAT_LEVELS - 1.0) * (count as f64).ln() / (hottest as f64).ln()).round() as u64,
+    }
+}
+
+/// Returns the summary table of a program
+///
+/// # Arguments
+///
+/// * `source` - The program's source code
+/// * `program` - The parsed program, before optimization
+/// * `report` - What the optimizer did to the program
+/// * `optimized` - The amount of instructions left after optimization
+/// * `profile` - The blocks of a coverage file, if one was given
+fn stats(source: &str, program: &Program, report: &Report, optimized: usize, profile: Option<&[(Position, Position, u64)]>) -> String {
+    let mut loops = 0;
+    let mut depth = 0;
+    let mut deepest = 0;
+    for instr in &program.instructions {
+        match instr {
+            Instruction::Jump(_) => {
+                loops += 1;
+                depth += 1;
+                deepest = deepest.max(depth);
+            },
+            Instruction::Return(_) => depth -= 1,
+            _ => {},
+        }
+    }
+    let applied = report.findings().filter(|(_, applied, _)| *applied).count();
+    let rejected = report.findings().count() - applied;
+
+    let mut rows = vec![
+        ("Lines", source.lines().count().to_string()),
+        ("Instructions", program.instructions.len().to_string()),
+        ("Loops", loops.to_string()),
+        ("Deepest nesting", deepest.to_string()),
+        ("Instructions at -O2", optimized.to_string()),
+        ("Optimizations", format!("{} applied, {} rejected", applied, rejected)),
+    ];
+    if let Some(blocks) = profile {
+        let entered = blocks.iter().filter(|(_, _, count)| *count > 0).count();
+        let hottest = blocks.iter().map(|(_, _, count)| *count).max().unwrap_or(0);
+        rows.push(("Blocks executed", format!("{} of {}", entered, blocks.len())));
+        rows.push(("Hottest block entered", format!("{} times", hottest)));
+    }
+
+    let mut table = String::from("<table>\n");
+    for (name, value) in rows {
+        table.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, value));
+    }
+    table.push_str("</table>");
+    table
+}
+
+/// Returns the table of the optimizer's findings in the order they happened
+///
+/// # Arguments
+///
+/// * `report` - What the optimizer did to the program
+fn findings_table(report: &Report) -> String {
+    if report.findings().next().is_none() {
+        return "<p>The optimizer found nothing to change.</p>".to_owned();
+    }
+
+    let mut table = String::from("<table>\n<tr><th>Position</th><th>Finding</th></tr>\n");
+    for (position, _, finding) in report.findings() {
+        table.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>\n", position, escape(&finding)));
+    }
+    table.push_str("</table>");
+    table
+}
+
+/// Escapes the characters that have a meaning in HTML text and attributes
+///
+/// # Arguments
+///
+/// * `text` - The text to escape
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,96 @@
+use std::io::{Result, Write};
+
+/// The kind of build script written alongside the assembly
+#[derive(Clone, Copy, PartialEq)]
+pub enum Flavor {
+    /// A makefile for `make -f`
+    Make,
+    /// A POSIX shell script
+    Shell,
+    /// A batch file for cmd, the default as every target is Windows
+    Batch,
+}
+
+impl Flavor {
+    /// The extension of the script's file
+    pub fn extension(self) -> &'static str {
+        match self {
+            Flavor::Make => "mk",
+            Flavor::Shell => "sh",
+            Flavor::Batch => "bat",
+        }
+    }
+}
+
+/// A command of a build script that produces a file from another one
+pub struct Step {
+    /// The file the command writes
+    pub output: String,
+    /// The file the command reads
+    pub input: String,
+    /// The command and its arguments
+    pub command: Vec<String>,
+}
+
+/// Joins a command's arguments into a line, quoting those that contain spaces
+///
+/// # Arguments
+///
+/// * `command` - The command and its arguments
+fn command_line(command: &[String]) -> String {
+    let args: Vec<String> = command.iter()
+        .map(|arg| if arg.contains(' ') { format!("\"{}\"", arg) } else { arg.to_owned() })
+        .collect();
+    args.join(" ")
+}
+
+/// Writes a build script that runs the steps in order from the directory it is in
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `flavor` - The kind of script
+/// * `source_name` - The name of the program's source file, used in the script's header
+/// * `note` - A comment below the header explaining how the executable is linked, if there is one
+/// * `steps` - The commands that build the executable, each one reading the output of the one before
+pub fn write_script<W: Write>(f: &mut W, flavor: Flavor, source_name: &str, note: Option<&str>, steps: &[Step]) -> Result<()> {
+    match flavor {
+        // The first rule builds the executable, so make's default goal is the last step
+        Flavor::Make => {
+            writeln!(f, "# Generated by bfasm from {}, run with make -f in this directory", source_name)?;
+            if let Some(note) = note {
+                writeln!(f, "# {}", note)?;
+            }
+            for step in steps.iter().rev() {
+                writeln!(f)?;
+                writeln!(f, "{}: {}", step.output, step.input)?;
+                writeln!(f, "\t{}", command_line(&step.command))?;
+            }
+        },
+        Flavor::Shell => {
+            writeln!(f, "#!/bin/sh")?;
+            writeln!(f, "# Generated by bfasm from {}", source_name)?;
+            if let Some(note) = note {
+                writeln!(f, "# {}", note)?;
+            }
+            writeln!(f, "set -e")?;
+            writeln!(f, "cd \"$(dirname \"$0\")\"")?;
+            for step in steps {
+                writeln!(f, "{}", command_line(&step.command))?;
+            }
+        },
+        Flavor::Batch => {
+            writeln!(f, "@echo off")?;
+            writeln!(f, "rem Generated by bfasm from {}", source_name)?;
+            if let Some(note) = note {
+                writeln!(f, "rem {}", note)?;
+            }
+            writeln!(f, "cd /d \"%~dp0\"")?;
+            for step in steps {
+                writeln!(f, "{} || exit /b 1", command_line(&step.command))?;
+            }
+        },
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1314 @@
+use std::cell::RefCell;
+use std::env::temp_dir;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::bignum::Big;
+use crate::json::{self, Value};
+use crate::{build_executable, check, check_brackets, coverage, diff, encoding, generate_asm, interpreter, ir, optimizer, parse, run_tool, stream, try_parse_recording, write_asm, AsmOptions, Cpu, Crt, Diagnostic, ErrorFormat, Extensions, Instruction, Program, Severity, Syntax, TapeGuard, TapeSection, DATA_SIZE, GUARD_CELLS, MAX_STATIC_TAPE_CELLS, MAX_TAPE_CELLS};
+
+/// A reference program embedded in the binary together with its expected behaviour
+struct Reference {
+    /// The name shown in the result table
+    name: &'static str,
+    /// The program's source code
+    source: &'static str,
+    /// The bytes available to `,`
+    input: &'static [u8],
+    /// The bytes the program has to write
+    expected: &'static [u8],
+}
+
+/// The reference programs checked by `bfasm selftest`
+const REFERENCES: [Reference; 6] = [
+    Reference {
+        name: "hello world",
+        source: include_str!("../tests/Hello_World.bf"),
+        input: b"",
+        expected: b"Hello, World!",
+    },
+    Reference {
+        name: "cat",
+        source: ",[.[-],]",
+        input: b"cat\n",
+        expected: b"cat\n",
+    },
+    Reference {
+        name: "cell wrapping",
+        source: include_str!("../tests/Cell_Size.bf"),
+        input: b"",
+        expected: b"32 bit cells\n",
+    },
+    Reference {
+        name: "nested multiplier",
+        source: "++[>+++[>+++++++<-]<-]>>.",
+        input: b"",
+        expected: b"*",
+    },
+    Reference {
+        name: "fan-out copy",
+        source: "+++++++++++[->++++++>+++++++<<]>.>.[>+>+<<-]>.>.",
+        input: b"",
+        expected: b"BMMM",
+    },
+    Reference {
+        name: "leading comment",
+        source: "[ A comment with instructions, which must not run: +++. ]\n>[ another, + ]++++++[<++++++++>-]<+.",
+        input: b"",
+        expected: b"1",
+    },
+];
+
+/// A reference program whose values exceed 64 bits, which is interpreted with cells of arbitrary precision and
+/// `.` writing their decimal values
+const BIG_CELLS: Reference = Reference {
+    name: "big cells",
+    source: include_str!("../tests/Factorials.bf"),
+    input: b"",
+    expected: concat!(
+        "1\n",
+        "2\n",
+        "6\n",
+        "24\n",
+        "120\n",
+        "720\n",
+        "5040\n",
+        "40320\n",
+        "362880\n",
+        "3628800\n",
+        "39916800\n",
+        "479001600\n",
+        "6227020800\n",
+        "87178291200\n",
+        "1307674368000\n",
+        "20922789888000\n",
+        "355687428096000\n",
+        "6402373705728000\n",
+        "121645100408832000\n",
+        "2432902008176640000\n",
+        "51090942171709440000\n",
+        "1124000727777607680000\n",
+        "25852016738884976640000\n",
+        "620448401733239439360000\n",
+        "15511210043330985984000000\n",
+    ).as_bytes(),
+};
+
+/// A reference program that writes two cells loaded by `--tape-init` and then a fresh cell, which has to read zero,
+/// plus 48
+const DATA_TAPE: Reference = Reference {
+    name: "data tape",
+    source: ".>.>>++++++++[<++++++>-]<.",
+    input: b"",
+    expected: b"AB0",
+};
+
+/// The initial contents of the tape of `DATA_TAPE`
+const DATA_TAPE_INIT: &[u8] = b"AB";
+
+/// A reference program whose loop guards follow reads, so they test the byte `getchar` returned in `eax`
+const ECHO: Reference = Reference {
+    name: "register guards",
+    source: ",[.,]",
+    input: b"echo\0",
+    expected: b"echo",
+};
+
+/// The real programs the fuzzed programs are mutated from, besides the sources of the reference programs
+const FUZZ_SEEDS: [&str; 3] = [
+    include_str!("../tests/Benchmark.bf"),
+    include_str!("../tests/Fibonacci.bf"),
+    include_str!("../tests/Uppercase.bf"),
+];
+
+/// The size in bytes of the program that is compiled while reading it, which spans several of the chunks it is read in
+const STREAM_SIZE: usize = 1 << 18;
+
+/// The amount of fuzzed programs unless `--fuzz N` asks for another
+const FUZZ_ITERATIONS: usize = 300;
+
+/// The state the generator of the fuzzed programs starts from, so every run checks the same programs
+const FUZZ_SEED: u64 = 0x5EED_B7A5_0F00_D1E5;
+
+/// The characters inserted into fuzzed programs, which are the commands of every extension and what follows them
+const FUZZ_ALPHABET: &[u8] = b"+-<>[].,#@$}{0123456789 _a\n";
+
+/// Runs the embedded reference programs through the parser and the interpreter and prints a table of the results
+///
+/// Each program is interpreted as parsed and once more after `-O2`, a factorial program with cells of arbitrary
+/// precision. The first program is also saved in the encodings Windows editors use, which have to parse the same.
+/// The programs are also written as IR and read back, which has to generate the same assembly at `-O2`.
+/// Mutations of the programs are fuzzed through the parser, the optimizer and the interpreter, which must not panic.
+/// With `--with-toolchain` the first program is additionally assembled, linked and run if `nasm` and `link` can be
+/// found, and once more for each C runtime of `--crt` whose linker can be found. Returns whether every check passed.
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `selftest`
+pub fn selftest(args: &[String]) -> bool {
+    let with_toolchain = args.iter().any(|arg| arg == "--with-toolchain");
+    let fuzz_iterations = match args.iter().position(|arg| arg == "--fuzz").map(|i| args.get(i + 1).and_then(|n| n.parse().ok())) {
+        Some(Some(n)) => n,
+        Some(None) => {
+            eprintln!("error: '--fuzz' expects the amount of programs to fuzz!");
+            return false;
+        },
+        None => FUZZ_ITERATIONS,
+    };
+
+    let mut passed = 0;
+    let mut failed = 0;
+
+    println!("{:<24} result", "check");
+    run_checks(fuzz_iterations, &mut |name, result| report(name, &result, &mut passed, &mut failed));
+
+    if with_toolchain {
+        let name = format!("toolchain ({})", REFERENCES[0].name);
+        match check_toolchain(&REFERENCES[0], None, &[]) {
+            Some(result) => report(&name, &result, &mut passed, &mut failed),
+            None => println!("{:<24} skipped (nasm or link not found)", name),
+        }
+
+        let name = format!("toolchain ({})", ECHO.name);
+        match check_toolchain(&ECHO, None, &[]) {
+            Some(result) => report(&name, &result, &mut passed, &mut failed),
+            None => println!("{:<24} skipped (nasm or link not found)", name),
+        }
+
+        let name = format!("toolchain ({})", DATA_TAPE.name);
+        match check_toolchain(&DATA_TAPE, None, DATA_TAPE_INIT) {
+            Some(result) => report(&name, &result, &mut passed, &mut failed),
+            None => println!("{:<24} skipped (nasm or link not found)", name),
+        }
+
+        for crt in Crt::ALL {
+            let name = format!("toolchain ({} crt)", crt.name());
+            match check_toolchain(&REFERENCES[0], Some(crt), &[]) {
+                Some(result) => report(&name, &result, &mut passed, &mut failed),
+                None => println!("{:<24} skipped (nasm or its linker not found)", name),
+            }
+        }
+    }
+
+    println!();
+    println!("selftest: {} passed, {} failed", passed, failed);
+
+    failed == 0
+}
+
+/// Runs every check that needs no toolchain, handing the name and the result of each to `record` once it finished
+///
+/// # Arguments
+///
+/// * `fuzz_iterations` - The amount of mutated programs to fuzz
+/// * `record` - Called with the name and the result of each check
+fn run_checks(fuzz_iterations: usize, record: &mut dyn FnMut(&str, Result<(), String>)) {
+    for reference in &REFERENCES {
+        record(reference.name, check(reference));
+    }
+    record(BIG_CELLS.name, check_big(&BIG_CELLS));
+    record("step-wise machine", check_stepping());
+    record("custom tape", check_tape());
+    record("strip unreachable", check_unreachable());
+    record("unrolling", check_unrolling());
+    record("pass checks", check_pass_checks());
+    record("program comparison", check_comparison());
+    record("coverage loop profile", REFERENCES.iter().try_for_each(check_loop_profile));
+    record("source encodings", check_encodings(&REFERENCES[0]));
+    record("ir round trip", REFERENCES.iter().try_for_each(check_ir));
+    record("stable labels", check_stable_labels(&BIG_CELLS));
+    record("parse errors", check_parse_errors());
+    record("json diagnostics", check_diagnostics());
+    record("bracket balance", check_bracket_balance());
+    record("streaming", check_streaming());
+    record(DATA_TAPE.name, check_data_tape(&DATA_TAPE));
+    record("library", REFERENCES.iter().try_for_each(check_library));
+    record("tape size limit", check_tape_limit(&REFERENCES[0]));
+    record("source size limit", check_source_limit(&REFERENCES[0]));
+    record(ECHO.name, check_register_guards(&ECHO));
+    record("dp register", REFERENCES.iter().try_for_each(check_dp_register));
+    record("dp register moves", check_dp_register_moves(&REFERENCES[0]));
+    record(&format!("fuzz ({} programs)", fuzz_iterations), check_fuzz(fuzz_iterations));
+}
+
+/// Prints a row of the result table and counts the result
+///
+/// # Arguments
+///
+/// * `name` - The name of the check
+/// * `result` - The result of the check, with the reason on failure
+/// * `passed` - The amount of passed checks
+/// * `failed` - The amount of failed checks
+fn report(name: &str, result: &Result<(), String>, passed: &mut usize, failed: &mut usize) {
+    match result {
+        Ok(()) => {
+            println!("{:<24} ok", name);
+            *passed += 1;
+        },
+        Err(reason) => {
+            println!("{:<24} FAILED: {}", name, reason);
+            *failed += 1;
+        },
+    }
+}
+
+/// Parses and interprets a reference program as parsed and optimized at `-O2`, comparing both outputs against the
+/// expected one
+///
+/// # Arguments
+///
+/// * `reference` - The program to check
+fn check(reference: &Reference) -> Result<(), String> {
+    let parsed = || parse(reference.source, Extensions::default()).map_err(|err| format!("parse: {}", err));
+    let program = parsed()?;
+    let optimized = optimizer::PassManager::for_level(2, Cpu::Baseline)
+        .run(parsed()?, &mut optimizer::Report::default(), &optimizer::DumpSettings::default())
+        .map_err(|err| format!("optimize: {}", err))?;
+
+    for (program, level) in [(program, "-O0"), (optimized, "-O2")] {
+        let mut output: Vec<u8> = Vec::new();
+        interpreter::run(&program, &[], &interpreter::Options::default(), None, reference.input, &mut output).map_err(|err| format!("run {}: {}", level, err))?;
+        compare(reference, &output).map_err(|err| format!("{}: {}", level, err))?;
+    }
+
+    Ok(())
+}
+
+/// Parses and interprets a reference program with cells of arbitrary precision, whose decimal values `.` writes
+///
+/// # Arguments
+///
+/// * `reference` - The program to check
+fn check_big(reference: &Reference) -> Result<(), String> {
+    let program = parse(reference.source, Extensions::default()).map_err(|err| format!("parse: {}", err))?;
+
+    let mut output: Vec<u8> = Vec::new();
+    interpreter::run_big(&program, &[], &interpreter::Options { numeric_io: true, ..interpreter::Options::default() }, None, reference.input, &mut output).map_err(|err| format!("run: {}", err))?;
+
+    compare(reference, &output)
+}
+
+/// Drives a short program through the interpreter's `Machine` one step at a time, comparing the state after each step
+/// against the expected one
+fn check_stepping() -> Result<(), String> {
+    use interpreter::Step;
+
+    let program = parse("+>,.", Extensions::default()).map_err(|err| format!("parse: {}", err))?;
+    let options = interpreter::Options { tape_size: 4, ..interpreter::Options::default() };
+    let mut machine: interpreter::Machine = interpreter::Machine::new(&program, &[], &options, None).map_err(|err| format!("load: {}", err))?;
+
+    // The result of each step and the tape, data pointer and pc after it, where `,` waits until it gets its input
+    let expected: [(Step, [u32; 4], isize, usize); 5] = [
+        (Step::Continued, [1, 0, 0, 0], 0, 1),
+        (Step::Continued, [1, 0, 0, 0], 1, 2),
+        (Step::NeedsInput, [1, 0, 0, 0], 1, 2),
+        (Step::Output(b'A'), [1, 65, 0, 0], 1, 4),
+        (Step::Halted, [1, 65, 0, 0], 1, 4),
+    ];
+    for (i, (step, tape, dp, pc)) in expected.into_iter().enumerate() {
+        let actual = machine.step().map_err(|err| format!("step {}: {}", i, err))?;
+        let state = (actual, machine.tape(), machine.dp(), machine.pc());
+        if state != (step, &tape[..], dp, pc) {
+            return Err(format!("step {}: expected {:?}, got {:?}", i, (step, tape, dp, pc), state));
+        }
+        if actual == Step::NeedsInput {
+            machine.provide_input(b'A').map_err(|err| format!("step {}: input: {}", i, err))?;
+        }
+    }
+
+    match machine.steps() {
+        4 => Ok(()),
+        steps => Err(format!("expected 4 steps, got {}", steps)),
+    }
+}
+
+/// A tape that records every access, to check which cells the interpreter touches
+struct RecordingTape {
+    /// The cells
+    cells: Vec<u32>,
+    /// The amount of cells the tape claims to have, which may be more than it has so the missing ones fail accesses
+    size: usize,
+    /// Each access as `get` or `set` with the index and the value read or written
+    accesses: RefCell<Vec<(&'static str, usize, u32)>>,
+}
+
+impl interpreter::Tape for RecordingTape {
+    fn len(&self) -> usize {
+        self.size
+    }
+
+    fn get(&self, index: usize) -> std::io::Result<u32> {
+        let value = self.cells.as_slice().get(index).copied().ok_or_else(|| std::io::Error::other(format!("no cell {}", index)))?;
+        self.accesses.borrow_mut().push(("get", index, value));
+        Ok(value)
+    }
+
+    fn set(&mut self, index: usize, value: u32) -> std::io::Result<()> {
+        *self.cells.as_mut_slice().get_mut(index).ok_or_else(|| std::io::Error::other(format!("no cell {}", index)))? = value;
+        self.accesses.borrow_mut().push(("set", index, value));
+        Ok(())
+    }
+}
+
+/// Runs a short program on a tape that records its accesses, comparing them against the expected ones, until it
+/// touches a cell the tape claims but does not have, which has to fail with the tape's own error
+fn check_tape() -> Result<(), String> {
+    use interpreter::Step;
+
+    let program = parse("++>+[<->-]<.>>>+", Extensions::default()).map_err(|err| format!("parse: {}", err))?;
+    let options = interpreter::Options { tape_size: 0, ..interpreter::Options::default() };
+    let tape = RecordingTape { cells: vec![0, 0, 0], size: 4, accesses: RefCell::new(Vec::new()) };
+    let mut machine = interpreter::Machine::with_tape(&program, tape, &options, None).map_err(|err| format!("load: {}", err))?;
+
+    let mut steps = Vec::new();
+    let error = loop {
+        match machine.step() {
+            Ok(Step::Halted) => return Err("the program did not fail at the missing cell".to_owned()),
+            Ok(step) => steps.push(step),
+            Err(err) => break err.to_string(),
+        }
+    };
+    if error != "no cell 3" || !steps.contains(&Step::Output(1)) {
+        return Err(format!("expected to write 1 and to fail at cell 3, got {:?} and {}", steps, error));
+    }
+
+    // `++` is fused into a single add, the loop into adding its counter to cell 0 and clearing it, which needs no read
+    let expected = [
+        ("get", 0, 0), ("set", 0, 2),
+        ("get", 1, 0), ("set", 1, 1),
+        ("get", 1, 1), ("get", 0, 2), ("set", 0, 1), ("set", 1, 0),
+        ("get", 0, 1),
+    ];
+    let accesses = machine.storage().accesses.borrow();
+    if accesses[..] != expected {
+        return Err(format!("expected the accesses {:?}, got {:?}", expected, accesses));
+    }
+
+    Ok(())
+}
+
+/// Checks every pass of `-O2 --cpu sse2` on the reference programs like `--check` does, then checks versions of
+/// programs that change their output, the order of their I/O or their loops, which have to fail with what changed
+fn check_pass_checks() -> Result<(), String> {
+    for reference in &REFERENCES {
+        let program = parse(reference.source, Extensions::default()).map_err(|err| format!("{}: parse: {}", reference.name, err))?;
+        let mut passes = optimizer::PassManager::for_level(2, Cpu::Sse2);
+        passes.verify = true;
+        check::check_passes(program, &passes, &[], DATA_SIZE as usize, reference.input).map_err(|err| format!("{}: {}", reference.name, err))?;
+    }
+
+    let broken_jump = |source: &str| parse(source, Extensions::default()).map(|mut program| {
+        program.instructions[1] = Instruction::Jump(4);
+        program
+    });
+    let cases = [
+        ("+.", parse("++.", Extensions::default()), "changed event 1 of the program from writing 1 to writing 2"),
+        (",.", parse(".,", Extensions::default()), "changed event 1 of the program from reading to writing 0"),
+        ("+.@.", parse("+..", Extensions::default()), "changed event 2 of the program from ending to writing 1"),
+        ("+[-].", broken_jump("+[-]."), "left the loop at instruction 1 jumping to 4, which does not jump back"),
+    ];
+    for (source, version, expected) in cases {
+        let parsed = parse(source, Extensions { halt: true, ..Extensions::default() }).map_err(|err| format!("{}: parse: {}", source, err))?;
+        let version = version.map_err(|err| format!("{}: parse: {}", source, err))?;
+        match check::check_version(&parsed, &version, b"") {
+            Ok(()) => return Err(format!("{}: a changed version passed", source)),
+            Err(err) if err != expected => return Err(format!("{}: expected '{}', got '{}'", source, expected, err)),
+            Err(_) => {},
+        }
+    }
+
+    Ok(())
+}
+
+/// Compares programs with hand-optimized versions like `bfasm compare` does, checking that equivalent ones agree on
+/// every input, and whether a run reached the step limit, and that the first input and event other ones differ on are
+/// found
+fn check_comparison() -> Result<(), String> {
+    let inputs = [b"".to_vec(), b"ab\0".to_vec(), b"xyz\0".to_vec()];
+    let cases = [
+        (",[.,]", ",[.>,]", Ok(false)),
+        ("++++++++[>++++++++<-]>+.", "+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++.", Ok(false)),
+        (",[.,]", ",[.,],", Ok(false)),
+        (",[.,]", ",[+.-,]", Err((1, 1, "writing 98"))),
+        (",[.,]", ",[..,]", Err((1, 2, "writing 97"))),
+        ("+[-]", "+[]", Ok(true)),
+    ];
+
+    for (a, b, expected) in cases {
+        let program = |source: &str| parse(source, Extensions::default()).map_err(|err| format!("{}: parse: {}", source, err));
+        let result = check::programs_equivalent(&program(a)?, &program(b)?, &inputs, 10_000);
+        match (result, expected) {
+            (Ok(limited), Ok(expected)) if limited == expected => {},
+            (Ok(limited), _) => return Err(format!("{} and {} behaved the same, {}reaching the step limit", a, b, if limited { "" } else { "not " })),
+            (Err(divergence), Err((input, event, second))) if (divergence.input, divergence.event, divergence.second.as_str()) == (input, event, second) => {},
+            (Err(divergence), _) => {
+                return Err(format!("{} and {} differ on input {} at event {}: {} and {}", a, b, divergence.input, divergence.event, divergence.first, divergence.second));
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Strips the code after `@` from programs halting at the top level and in a loop body, comparing what is left and
+/// the output of both programs
+fn check_unreachable() -> Result<(), String> {
+    let cases = [("++.@>+.[-]", "[Add, Add, Write, Halt, Unreachable]"), ("+[>.@<-]>.", "[Add, Jump(5), Increment, Write, Halt, Return(1), Increment, Write]")];
+
+    for (source, expected) in cases {
+        let program = || parse(source, Extensions { halt: true, ..Extensions::default() }).map_err(|err| format!("{}: parse: {}", source, err));
+        let parsed = program()?;
+        let stripped = optimizer::PassManager::from_names("strip-unreachable", Cpu::Baseline)
+            .and_then(|passes| passes.run(parsed, &mut optimizer::Report::default(), &optimizer::DumpSettings::default()))
+            .map_err(|err| format!("{}: optimize: {}", source, err))?;
+        if format!("{:?}", stripped.instructions) != expected {
+            return Err(format!("{}: expected {}, got {:?}", source, expected, stripped.instructions));
+        }
+
+        let run = |program: &Program| {
+            let mut output: Vec<u8> = Vec::new();
+            interpreter::run(program, &[], &interpreter::Options::default(), None, &b""[..], &mut output)
+                .map(|_| output)
+                .map_err(|err| format!("{}: run: {}", source, err))
+        };
+        if run(&program()?)? != run(&stripped)? {
+            return Err(format!("{}: the output differs after stripping", source));
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs `known-values` with several unroll limits over loops with a known amount of iterations, checking that a loop is
+/// unrolled exactly when the copies of its body fit into the limit and that the output stays the same
+fn check_unrolling() -> Result<(), String> {
+    // The programs, the amount of iterations of their last loop and the size of its body, whose cell is only known
+    // after the clear as the tape is not assumed to start zeroed
+    let cases = [("[-]+[>+.<-]", 1, 4), ("[-]+++[>++.<-]", 3, 6), ("[-]++++++++[>+++++.<-]>.", 8, 9)];
+
+    for (source, iterations, body) in cases {
+        let program = || parse(source, Extensions::default()).map_err(|err| format!("{}: parse: {}", source, err));
+        let run = |program: &Program| {
+            let mut output: Vec<u8> = Vec::new();
+            interpreter::run(program, &[], &interpreter::Options::default(), None, &b""[..], &mut output)
+                .map(|_| output)
+                .map_err(|err| format!("{}: run: {}", source, err))
+        };
+        let expected = run(&program()?)?;
+
+        for limit in [0, 6, 18, 72, optimizer::UNROLL_SIZE] {
+            let mut passes = optimizer::PassManager::from_names("known-values", Cpu::Baseline).map_err(|err| err.to_string())?;
+            passes.limits.unroll = limit;
+            let optimized = passes.run(program()?, &mut optimizer::Report::default(), &optimizer::DumpSettings::default())
+                .map_err(|err| format!("{}: optimize: {}", source, err))?;
+
+            let unrolled = optimized.instructions.iter().filter(|instr| matches!(instr, Instruction::Jump(_))).count() == 1;
+            if unrolled != (iterations * body <= limit) {
+                return Err(format!("{}: expected the loop {}to be unrolled with a limit of {}", source, if unrolled { "not " } else { "" }, limit));
+            }
+            if run(&optimized)? != expected {
+                return Err(format!("{}: the output differs with a limit of {}", source, limit));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Interprets a reference program at `-O0` and `-O2` counting its blocks, checking that the loop profile read back from
+/// its coverage file is the same as the interpreter's
+///
+/// # Arguments
+///
+/// * `reference` - The program to check
+fn check_loop_profile(reference: &Reference) -> Result<(), String> {
+    for level in [0, 2] {
+        let program = optimizer::PassManager::for_level(level, Cpu::Baseline)
+            .run(parse(reference.source, Extensions::default()).map_err(|err| format!("parse: {}", err))?, &mut optimizer::Report::default(), &optimizer::DumpSettings::default())
+            .map_err(|err| format!("optimize: {}", err))?;
+        let mut blocks = coverage::blocks(&program);
+        interpreter::run(&program, &[], &interpreter::Options::default(), Some(&mut blocks), reference.input, std::io::sink())
+            .map_err(|err| format!("run -O{}: {}", level, err))?;
+
+        let (mut expected, mut json, mut actual) = (Vec::new(), Vec::new(), Vec::new());
+        coverage::write_loop_profile(&mut expected, &program, &blocks).map_err(|err| err.to_string())?;
+        coverage::write_json(&mut json, &blocks).map_err(|err| err.to_string())?;
+        let read = coverage::read_json(&String::from_utf8_lossy(&json)).map_err(|err| format!("read -O{}: {}", level, err))?;
+        coverage::write_coverage_loops(&mut actual, reference.source, &read).map_err(|err| err.to_string())?;
+        if actual != expected {
+            return Err(format!("-O{}: expected {:?}, got {:?}", level, String::from_utf8_lossy(&expected), String::from_utf8_lossy(&actual)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Decodes a reference program saved as UTF-8 with a byte order mark and as UTF-16 with and without one, comparing the
+/// instructions and positions parsed from each against those of the program itself
+///
+/// # Arguments
+///
+/// * `reference` - The program to check
+fn check_encodings(reference: &Reference) -> Result<(), String> {
+    let parsed = |source: &str| parse(source, Extensions::default())
+        .map(|program| {
+            let positions: Vec<String> = program.positions.iter().map(|position| position.to_string()).collect();
+            format!("{:?} {:?}", program.instructions, positions)
+        })
+        .map_err(|err| format!("parse: {}", err));
+    let expected = parsed(reference.source)?;
+
+    let utf16le: Vec<u8> = reference.source.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let utf16be: Vec<u8> = reference.source.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    let saved = [
+        ("UTF-8 with BOM", [&[0xEF, 0xBB, 0xBF], reference.source.as_bytes()].concat()),
+        ("UTF-16LE with BOM", [&[0xFF, 0xFE], utf16le.as_slice()].concat()),
+        ("UTF-16BE with BOM", [&[0xFE, 0xFF], utf16be.as_slice()].concat()),
+        ("UTF-16LE", utf16le),
+        ("UTF-16BE", utf16be),
+    ];
+
+    for (name, bytes) in saved {
+        if parsed(&encoding::decode(&bytes))? != expected {
+            return Err(format!("{} parses differently", name));
+        }
+    }
+
+    Ok(())
+}
+
+/// Optimizes a reference program, writes it as IR and reads it back, comparing the assembly of both programs
+///
+/// # Arguments
+///
+/// * `reference` - The program to check
+fn check_ir(reference: &Reference) -> Result<(), String> {
+    let optimize = |program: Program| {
+        optimizer::PassManager::for_level(2, Cpu::Baseline)
+            .run(program, &mut optimizer::Report::default(), &optimizer::DumpSettings::default())
+            .map_err(|err| format!("{}: optimize: {}", reference.name, err))
+    };
+    let program = optimize(parse(reference.source, Extensions::default()).map_err(|err| format!("{}: parse: {}", reference.name, err))?)?;
+
+    let mut text: Vec<u8> = Vec::new();
+    ir::write(&mut text, &program).map_err(|err| format!("{}: write: {}", reference.name, err))?;
+    let text = String::from_utf8_lossy(&text);
+    let read = ir::parse(&text).map_err(|errors| format!("{}: read: {}", reference.name, errors[0].1))?;
+    let read = optimize(read)?;
+
+    let options = AsmOptions { offset_cells: true, ..AsmOptions::default() };
+    let generate = |program: &Program| generate_asm(reference.source, &program.instructions, &options).map(|(asm, _)| asm);
+    match (generate(&program), generate(&read)) {
+        (Ok(expected), Ok(actual)) if expected == actual => Ok(()),
+        (Ok(_), Ok(_)) => Err(format!("{}: the assembly differs after reading the IR", reference.name)),
+        (Err(err), _) | (_, Err(err)) => Err(format!("{}: generate: {}", reference.name, err)),
+    }
+}
+
+/// Inserts an instruction into the middle of a reference program and compares the assembly of both programs, which
+/// may only differ around the inserted instruction and has to define the same labels
+///
+/// # Arguments
+///
+/// * `reference` - The program to check, which has a `>` after its middle
+fn check_stable_labels(reference: &Reference) -> Result<(), String> {
+    let middle = reference.source.len() / 2;
+    let at = reference.source[middle..].find('>').map(|offset| middle + offset).ok_or_else(|| format!("{}: no '>' after the middle", reference.name))?;
+    let edited = format!("{}.{}", &reference.source[..at], &reference.source[at..]);
+
+    let options = AsmOptions { offset_cells: true, ..AsmOptions::default() };
+    let generate = |source: &str| {
+        let program = parse(source, Extensions::default()).map_err(|err| format!("parse: {}", err))?;
+        let program = optimizer::PassManager::for_level(2, Cpu::Baseline)
+            .run(program, &mut optimizer::Report::default(), &optimizer::DumpSettings::default())
+            .map_err(|err| format!("optimize: {}", err))?;
+        generate_asm(source, &program.instructions, &options).map(|(asm, _)| asm).map_err(|err| format!("generate: {}", err))
+    };
+    let (old, new) = (generate(reference.source)?, generate(&edited)?);
+
+    // The header names the hash of the source, which differs of course
+    let lines = |asm: &str| asm.lines().filter(|line| !line.starts_with("; source sha256")).map(str::to_owned).collect::<Vec<String>>();
+    let (old, new) = (lines(&old), lines(&new));
+    let labels = |lines: &[String]| lines.iter().filter(|line| line.ends_with(':')).cloned().collect::<Vec<String>>();
+    if labels(&old) != labels(&new) {
+        return Err(format!("{}: inserting an instruction renamed labels", reference.name));
+    }
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    match (old.len() - prefix - suffix, new.len() - prefix - suffix) {
+        (removed, added) if removed <= 8 && added <= 16 => Ok(()),
+        (removed, added) => Err(format!("{}: inserting an instruction changed {} lines into {}", reference.name, removed, added)),
+    }
+}
+
+/// Renders the parse errors of a program as JSON diagnostics and reads them back, checking that each field holds what
+/// the human diagnostic says
+fn check_diagnostics() -> Result<(), String> {
+    // The file name has to be escaped
+    let file = "dir\\\"quoted\".bf";
+    let errors = try_parse_recording("+]\n\"quoted\" [", Extensions::default(), None, None).err().ok_or("the program parsed")?;
+
+    let expected = [(1, 2, "unmatched ']' at 1:2!", "remove it, or add a '[' where its loop should start"), (2, 10, "unmatched '[' at 2:10!", "add a ']' after 2:10")];
+    if errors.len() != expected.len() {
+        return Err(format!("expected {} errors, got {}", expected.len(), errors.len()));
+    }
+    for ((position, err), (line, column, message, suggestion)) in errors.iter().zip(expected) {
+        let text = err.to_string();
+        let diagnostic = Diagnostic { severity: Severity::Error, file: Some(file), position: Some(*position), message: &text };
+        if diagnostic.render(ErrorFormat::Human) != format!("error: {}", text) {
+            return Err(format!("the human diagnostic of {:?} changed", message));
+        }
+
+        let rendered = diagnostic.render(ErrorFormat::Json);
+        let value = json::parse(&rendered).ok_or_else(|| format!("invalid JSON {}", rendered))?;
+        let fields = (
+            value.get("severity").and_then(Value::as_str),
+            value.get("file").and_then(Value::as_str),
+            value.get("line").and_then(Value::as_usize),
+            value.get("column").and_then(Value::as_usize),
+            value.get("message").and_then(Value::as_str),
+            value.get("suggestion").and_then(Value::as_str),
+        );
+        if fields != (Some("error"), Some(file), Some(line), Some(column), Some(message), Some(suggestion)) {
+            return Err(format!("{} does not describe {:?}", rendered, text));
+        }
+    }
+
+    // Fields that are not known are null
+    let rendered = Diagnostic { severity: Severity::Warning, file: None, position: None, message: "no files match '*.bf'" }.render(ErrorFormat::Json);
+    match json::parse(&rendered) {
+        Some(value) if ["file", "line", "column", "suggestion"].iter().all(|name| matches!(value.get(name), Some(Value::Null))) => Ok(()),
+        _ => Err(format!("{} has fields that are not null", rendered)),
+    }
+}
+
+/// Checks the brackets of balanced and unbalanced programs, which have to fail with the first unmatched bracket
+fn check_bracket_balance() -> Result<(), String> {
+    let cases = [
+        ("+[->[-]<]>.", None),
+        ("a comment without brackets", None),
+        ("+]", Some("unmatched ']' at 1:2!")),
+        ("[]]\n[", Some("unmatched ']' at 1:3!")),
+        ("[[]", Some("unmatched '[' at 1:1!")),
+        ("[]\n  [[-]\n[", Some("unmatched '[' at 2:3!")),
+        ("#!/usr/bin/env bfasm ]\n[-]", None),
+        ("\u{feff}+]", Some("unmatched ']' at 1:2!")),
+        ("\u{feff}#!/usr/bin/env bfasm ]\n[", Some("unmatched '[' at 2:1!")),
+    ];
+
+    for (source, expected) in cases {
+        let actual = check_brackets(source).err().map(|err| err.to_string());
+        if actual.as_deref() != expected {
+            return Err(format!("{:?}: expected {:?}, got {:?}", source, expected, actual));
+        }
+    }
+
+    Ok(())
+}
+
+/// Compiles a large generated program while reading it and compares the assembly with the one generated from the
+/// parsed program, with the default settings and with the extensions and the settings that add code to instructions
+fn check_streaming() -> Result<(), String> {
+    // Balanced pieces with comments in several bytes, some of which straddle the chunks the source is read in
+    let pieces = ["+++[->++<]>.", "<<-", ",[.,]", " comment é ", "@", "$", "#", ">>+++", "\n"];
+    let mut random = Random(FUZZ_SEED);
+    let mut source = String::from("#!/usr/bin/env bfasm [\n");
+    let mut depth = 0;
+    while source.len() < STREAM_SIZE {
+        match random.below(pieces.len() + 2) {
+            n if n < pieces.len() => source.push_str(pieces[n]),
+            n if n == pieces.len() && depth < 8 => {
+                source.push('[');
+                depth += 1;
+            },
+            _ if depth > 0 => {
+                source.push(']');
+                depth -= 1;
+            },
+            _ => {},
+        }
+    }
+    source.push_str(&"]".repeat(depth));
+
+    let base = temp_dir().join("bfasm_selftest_stream");
+    let (bf, asm) = (base.with_extension("bf"), base.with_extension("asm"));
+    std::fs::write(&bf, &source).map_err(|err| format!("write: {}", err))?;
+
+    let extensions = Extensions { debug_breaks: true, halt: true, dump: true, ..Extensions::default() };
+    let plain = AsmOptions::default();
+    let guarded = AsmOptions { syntax: Syntax::Gas, tape_guard: TapeGuard::Aggressive, fuel: Some(1000), const_input: Some(b"abc"), label_prefix: "S_", ..plain };
+
+    let result = [(Extensions::default(), &plain), (extensions, &guarded)].into_iter().try_for_each(|(extensions, options)| {
+        let program = try_parse_recording(&source, extensions, None, None).map_err(|errors| format!("parse: {}", errors[0].1))?;
+        let (expected, _) = generate_asm(&source, &program.instructions, options).map_err(|err| format!("generate: {}", err))?;
+
+        match stream::write_asm(&bf.to_string_lossy(), &asm.to_string_lossy(), extensions, None, options) {
+            Ok(true) => {},
+            Ok(false) => return Err("the program was not streamed".to_owned()),
+            Err(err) => return Err(format!("stream: {}", err)),
+        }
+        let actual = std::fs::read_to_string(&asm).map_err(|err| format!("read: {}", err))?;
+        match diff::first_difference(&expected, &actual) {
+            Some(line) => Err(format!("the streamed assembly differs from line {} on", line)),
+            None => Ok(()),
+        }
+    });
+
+    let _ = std::fs::remove_file(&bf);
+    let _ = std::fs::remove_file(&asm);
+    result
+}
+
+/// Interprets a reference program on a tape with initial contents and checks that the tape the assembly declares in
+/// the data segment holds them followed by zeroed cells, with both syntaxes and with guard cells around the tape
+///
+/// # Arguments
+///
+/// * `reference` - The program to check, which runs on the tape loaded with `DATA_TAPE_INIT`
+fn check_data_tape(reference: &Reference) -> Result<(), String> {
+    let program = parse(reference.source, Extensions::default()).map_err(|err| format!("parse: {}", err))?;
+
+    let mut output: Vec<u8> = Vec::new();
+    interpreter::run(&program, DATA_TAPE_INIT, &interpreter::Options::default(), None, reference.input, &mut output).map_err(|err| format!("run: {}", err))?;
+    compare(reference, &output)?;
+
+    let mut expected: Vec<u32> = DATA_TAPE_INIT.iter().map(|byte| *byte as u32).collect();
+    expected.resize(DATA_SIZE as usize, 0);
+
+    let plain = AsmOptions { tape_section: TapeSection::Data, tape_init: DATA_TAPE_INIT, ..AsmOptions::default() };
+    for options in [plain, AsmOptions { syntax: Syntax::Gas, ..plain }, AsmOptions { tape_guard: TapeGuard::AtExit, ..plain }] {
+        let (asm, _) = generate_asm(reference.source, &program.instructions, &options).map_err(|err| format!("generate: {}", err))?;
+        match declared_tape(&asm, options.syntax) {
+            Some(cells) if cells == expected => {},
+            Some(cells) => return Err(format!("{} declares a tape of {} cells starting with {:?}", options.syntax.name(), cells.len(), &cells[..cells.len().min(4)])),
+            None => return Err(format!("{} declares no tape in the data segment", options.syntax.name())),
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a reference program with `--max-src-bytes` at exactly its size and at one byte less, checking that only the
+/// latter is rejected and that its error names the flag
+///
+/// # Arguments
+///
+/// * `reference` - The program to check
+fn check_source_limit(reference: &Reference) -> Result<(), String> {
+    let bf = temp_dir().join("bfasm_selftest_limit.bf");
+    std::fs::write(&bf, reference.source).map_err(|err| format!("write: {}", err))?;
+    let size = reference.source.len() as u64;
+
+    let read = |max: u64| encoding::read_source_capped(&bf.to_string_lossy(), Some(max));
+    let result = match (read(size), read(size - 1)) {
+        (Ok(source), _) if source != reference.source => Err(format!("the source read with a limit of {} bytes differs", size)),
+        (Err(err), _) => Err(format!("a limit of {} bytes: {}", size, err)),
+        (_, Ok(_)) => Err(format!("a source of {} bytes was read with a limit of {}", size, size - 1)),
+        (_, Err(err)) if err.kind() != ErrorKind::FileTooLarge || !err.to_string().contains("'--max-src-bytes'") => {
+            Err(format!("a limit of {} bytes failed with '{}'", size - 1, err))
+        },
+        _ => Ok(()),
+    };
+
+    let _ = std::fs::remove_file(&bf);
+    result
+}
+
+/// Interprets a reference program whose loop guards follow reads and generates it, checking that exactly the guards
+/// that can rely on `eax` holding the cell test the register instead of reading the cell
+///
+/// Embedded input leaves the cell unchanged once it is exhausted and the aggressive guard check clobbers `eax`, so
+/// their guards read the cell. The toolchain check runs the program to compare it with the interpreter.
+///
+/// # Arguments
+///
+/// * `reference` - The program to check, whose reads are each followed by a loop's guard
+fn check_register_guards(reference: &Reference) -> Result<(), String> {
+    check(reference)?;
+    let program = parse(reference.source, Extensions::default()).map_err(|err| format!("parse: {}", err))?;
+    let guards = program.instructions.iter().filter(|instr| matches!(instr, Instruction::Read)).count();
+
+    let cases = [
+        ("getchar", AsmOptions::default(), guards),
+        ("gas", AsmOptions { syntax: Syntax::Gas, ..AsmOptions::default() }, guards),
+        ("offset cells", AsmOptions { offset_cells: true, ..AsmOptions::default() }, guards),
+        ("const input", AsmOptions { const_input: Some(reference.input), ..AsmOptions::default() }, 0),
+        ("aggressive guards", AsmOptions { tape_guard: TapeGuard::Aggressive, ..AsmOptions::default() }, 0),
+    ];
+    for (name, options, expected) in cases {
+        let (asm, _) = generate_asm(reference.source, &program.instructions, &options).map_err(|err| format!("{}: generate: {}", name, err))?;
+        let tested = asm.lines().filter(|line| *line == "\ttest eax, eax").count();
+        if tested != expected {
+            return Err(format!("{}: expected {} guards to test eax, got {}", name, expected, tested));
+        }
+    }
+
+    Ok(())
+}
+
+/// Generates a reference program on tapes at the most cells the image holds and one cell beyond, with and without guard
+/// cells, and at the most cells the address space holds and one cell beyond, checking that only those within the limits
+/// are generated and either declare the whole tape or allocate it
+///
+/// # Arguments
+///
+/// * `reference` - The program to check
+fn check_tape_limit(reference: &Reference) -> Result<(), String> {
+    let program = parse(reference.source, Extensions::default()).map_err(|err| format!("parse: {}", err))?;
+
+    // Whether the tape is generated at all and whether it is allocated instead of declared in the image
+    let plain = AsmOptions { tape_size: MAX_STATIC_TAPE_CELLS, ..AsmOptions::default() };
+    let guarded = MAX_STATIC_TAPE_CELLS - 2 * i64::from(GUARD_CELLS);
+    let cases = [
+        (plain, Some(false)),
+        (AsmOptions { tape_size: MAX_STATIC_TAPE_CELLS + 1, ..plain }, Some(true)),
+        (AsmOptions { syntax: Syntax::Gas, ..plain }, Some(false)),
+        (AsmOptions { syntax: Syntax::Gas, tape_size: MAX_STATIC_TAPE_CELLS + 1, ..plain }, Some(true)),
+        (AsmOptions { tape_guard: TapeGuard::AtExit, tape_size: guarded, ..plain }, Some(false)),
+        (AsmOptions { tape_guard: TapeGuard::AtExit, tape_size: guarded + 1, ..plain }, None),
+        (AsmOptions { tape_size: MAX_TAPE_CELLS, ..plain }, Some(true)),
+        (AsmOptions { tape_size: MAX_TAPE_CELLS + 1, ..plain }, None),
+    ];
+
+    for (options, expected) in cases {
+        let guards = if options.tape_guard == TapeGuard::Off { "without" } else { "with" };
+        let name = format!("{} cells {} guards with {}", options.tape_size, guards, options.syntax.name());
+        match generate_asm(reference.source, &program.instructions, &options) {
+            Ok((asm, _)) if expected == Some(true) => {
+                let allocated = format!("\tmov rcx, {}\n\tmov edx, 4\n\tcall calloc\n", options.tape_size);
+                if !asm.contains(&allocated) || !asm.contains("[r12 + 4 * rbx]") || asm.contains("tape:") || asm.contains("tape resd") {
+                    return Err(format!("{}: the tape is not allocated and addressed from r12", name));
+                }
+            },
+            Ok((asm, _)) if expected == Some(false) => {
+                // Guarded tapes are declared in the data segment between their guard cells
+                let declared = match options.syntax {
+                    Syntax::Nasm if options.tape_guard == TapeGuard::Off => format!("\ttape resd {}\n", options.tape_size),
+                    Syntax::Nasm => format!("\ttape times {} dd 0\n", options.tape_size),
+                    Syntax::Gas => format!("\t.zero {}\n", 4 * options.tape_size),
+                };
+                if !asm.contains(&declared) {
+                    return Err(format!("{}: the tape is not declared with all of its cells", name));
+                }
+            },
+            Ok(_) => return Err(format!("{}: generated a tape that cannot be addressed", name)),
+            Err(err) if expected.is_some() => return Err(format!("{}: generate: {}", name, err)),
+            Err(_) => {},
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the initial cells of the tape declared in the data segment of an assembly, if it is declared there
+///
+/// # Arguments
+///
+/// * `asm` - The assembly
+/// * `syntax` - The syntax of the assembly
+fn declared_tape(asm: &str, syntax: Syntax) -> Option<Vec<u32>> {
+    let values = |list: &str| list.split(',').map(|value| value.trim().parse::<u32>().ok()).collect::<Option<Vec<u32>>>();
+
+    // NASM declares the tape on the line of its label, GAS after it, in the last section switched to
+    let start = match syntax {
+        Syntax::Nasm => asm.lines().position(|line| line.starts_with("\ttape "))?,
+        Syntax::Gas => asm.lines().position(|line| line == "tape:")? + 1,
+    };
+    let section = asm.lines().take(start).filter(|line| line.starts_with("segment ") || matches!(*line, ".data" | ".bss")).last();
+    if !matches!(section, Some("segment .data" | ".data")) {
+        return None;
+    }
+
+    let mut cells: Vec<u32> = Vec::new();
+    for line in asm.lines().skip(start) {
+        let line = line.trim_start_matches("\ttape ").trim();
+        if let Some(list) = line.strip_prefix("dd ").or_else(|| line.strip_prefix(".long ")) {
+            cells.extend(values(list)?);
+        } else if let Some(count) = line.strip_prefix("times ").and_then(|rest| rest.strip_suffix(" dd 0")) {
+            cells.resize(cells.len() + count.parse::<usize>().ok()?, 0);
+        } else if let Some(bytes) = line.strip_prefix(".zero ") {
+            cells.resize(cells.len() + bytes.parse::<usize>().ok()? / 4, 0);
+        } else {
+            break;
+        }
+    }
+
+    (!cells.is_empty()).then_some(cells)
+}
+
+/// Generates a reference program as a library with both syntaxes, whose function has to be exported and may only call
+/// and jump to its own labels, as it must not use the C runtime
+///
+/// # Arguments
+///
+/// * `reference` - The program to check
+fn check_library(reference: &Reference) -> Result<(), String> {
+    let program = parse(reference.source, Extensions::default()).map_err(|err| format!("{}: parse: {}", reference.name, err))?;
+    let program = optimizer::PassManager::for_level(2, Cpu::Sse2)
+        .run(program, &mut optimizer::Report::default(), &optimizer::DumpSettings::default())
+        .map_err(|err| format!("{}: optimize: {}", reference.name, err))?;
+
+    let plain = AsmOptions { split_functions: true, library: Some("transform"), offset_cells: true, ..AsmOptions::default() };
+    for options in [plain, AsmOptions { syntax: Syntax::Gas, ..plain }] {
+        let name = format!("{} ({})", reference.name, options.syntax.name());
+        let (asm, _) = generate_asm(reference.source, &program.instructions, &options).map_err(|err| format!("{}: generate: {}", name, err))?;
+
+        let lines: Vec<&str> = asm.lines().collect();
+        if !lines.iter().any(|line| matches!(*line, "global transform" | ".globl transform")) {
+            return Err(format!("{}: the function is not exported", name));
+        }
+        if let Some(line) = lines.iter().find(|line| line.starts_with("extern ") || line.starts_with(".extern ")) {
+            return Err(format!("{}: declares '{}'", name, line));
+        }
+
+        let labels: Vec<&str> = lines.iter().filter_map(|line| line.strip_suffix(':')).collect();
+        let targets = lines.iter().filter_map(|line| line.trim().split_once(' ')).filter(|(op, _)| *op == "call" || op.starts_with('j'));
+        if let Some((op, target)) = targets.into_iter().find(|(_, target)| !labels.contains(target)) {
+            return Err(format!("{}: '{} {}' leaves the function", name, op, target));
+        }
+    }
+
+    Ok(())
+}
+
+/// Generates a reference program at `-O2` with the data pointer kept in the index register, checking that it is only
+/// stored and reloaded around the guard check and that `main` saves and restores `rbx`, also with both syntaxes, lifted
+/// loops, aggressive guards and as a library, and that fragments refuse it
+///
+/// # Arguments
+///
+/// * `reference` - The program to check
+fn check_dp_register(reference: &Reference) -> Result<(), String> {
+    let program = parse(reference.source, Extensions::default()).map_err(|err| format!("{}: parse: {}", reference.name, err))?;
+    let program = optimizer::PassManager::for_level(2, Cpu::Sse2)
+        .run(program, &mut optimizer::Report::default(), &optimizer::DumpSettings::default())
+        .map_err(|err| format!("{}: optimize: {}", reference.name, err))?;
+
+    let plain = AsmOptions { dp_register: true, ..AsmOptions::default() };
+    let cases = [
+        ("nasm", plain),
+        ("gas", AsmOptions { syntax: Syntax::Gas, ..plain }),
+        ("split functions", AsmOptions { split_functions: true, ..plain }),
+        ("offset cells", AsmOptions { offset_cells: true, ..plain }),
+        ("aggressive guards", AsmOptions { tape_guard: TapeGuard::Aggressive, ..plain }),
+        ("library", AsmOptions { library: Some("transform"), ..plain }),
+    ];
+    for (case, options) in cases {
+        let name = format!("{} ({})", reference.name, case);
+        let (asm, _) = generate_asm(reference.source, &program.instructions, &options).map_err(|err| format!("{}: generate: {}", name, err))?;
+
+        let lines: Vec<&str> = asm.lines().collect();
+        let dp = options.syntax.dp();
+        let check_guards = "\tcall CHECK_GUARDS";
+        for (i, line) in lines.iter().enumerate().filter(|(_, line)| line.contains(dp)) {
+            let spilled = *line == format!("\tmov {}, rbx", dp) && lines.get(i + 1) == Some(&check_guards);
+            let reloaded = *line == format!("\tmov rbx, {}", dp) && i > 0 && lines[i - 1] == check_guards;
+            // A library clears the data pointer in memory as well on every call
+            let cleared = options.library.is_some() && *line == format!("\tmov {}, rax", dp);
+            if !spilled && !reloaded && !cleared {
+                return Err(format!("{}: line {} '{}' accesses the data pointer in memory", name, i + 1, line.trim()));
+            }
+        }
+
+        if options.library.is_none() && !(asm.contains("\tpush rbx\n\tsub rsp, 40\n") && asm.contains("\tmov rbx, [rbp - 8]\n\tmov rsp, rbp\n")) {
+            return Err(format!("{}: main does not save and restore rbx", name));
+        }
+    }
+
+    let fragment = AsmOptions { fragment: true, ..plain };
+    match generate_asm(reference.source, &program.instructions, &fragment) {
+        Ok(_) => Err(format!("{}: generated a fragment keeping the data pointer in a register", reference.name)),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Generates a straight-line reference program at `-O2` with the data pointer kept in the index register, checking that
+/// its cells are addressed at offsets from it and that it is only moved once before the exit
+///
+/// # Arguments
+///
+/// * `reference` - The program to check
+fn check_dp_register_moves(reference: &Reference) -> Result<(), String> {
+    let program = parse(reference.source, Extensions::default()).map_err(|err| format!("parse: {}", err))?;
+    let program = optimizer::PassManager::for_level(2, Cpu::Baseline)
+        .run(program, &mut optimizer::Report::default(), &optimizer::DumpSettings::default())
+        .map_err(|err| format!("optimize: {}", err))?;
+
+    let options = AsmOptions { offset_cells: true, dp_register: true, ..AsmOptions::default() };
+    let (asm, _) = generate_asm(reference.source, &program.instructions, &options).map_err(|err| format!("generate: {}", err))?;
+
+    let moves = asm.lines().filter(|line| line.starts_with("\tadd rbx, ")).count();
+    if moves > 1 {
+        return Err(format!("the data pointer is moved {} times instead of once before the exit", moves));
+    }
+    if !asm.contains("[tape + 4 * rbx + 4]") {
+        return Err("no cell is addressed at an offset from the data pointer".to_owned());
+    }
+
+    Ok(())
+}
+
+/// Parses malformed programs, each of which has to fail with the expected error instead of panicking
+fn check_parse_errors() -> Result<(), String> {
+    let counts = Extensions { counts: true, ..Extensions::default() };
+    let labels = Extensions { labels: true, ..Extensions::default() };
+    let cases = [
+        ("+]", Extensions::default(), "unmatched ']' at 1:2!"),
+        ("[[]", Extensions::default(), "unmatched '[' at 1:1!"),
+        ("{3}+", counts, "repetition count at 1:1 does not follow '+', '-', '<', '>' or '.'!"),
+        ("+{3\n", counts, "missing '}' after the repetition count at 1:2!"),
+        ("+{0}", counts, "repetition count at 1:2 is 0, counts start at 1!"),
+        ("+{}", counts, "missing repetition count between '{' and '}' at 1:2!"),
+        ("@a+@a", labels, "duplicate label 'a' at 1:4, first defined at 1:1!"),
+        ("@ +", labels, "missing label name after '@' at 1:1!"),
+    ];
+
+    for (source, extensions, expected) in cases {
+        match parse(source, extensions) {
+            Ok(_) => return Err(format!("{:?} parsed", source)),
+            Err(err) if !err.to_string().starts_with(expected) => return Err(format!("{:?}: expected {:?}, got {:?}", source, expected, err.to_string())),
+            Err(_) => {},
+        }
+    }
+
+    Ok(())
+}
+
+/// A xorshift generator of the fuzzed programs, which is good enough to pick mutations
+struct Random(u64);
+
+impl Random {
+    /// Returns a number below the given bound, which has to be positive
+    ///
+    /// # Arguments
+    ///
+    /// * `bound` - The bound
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+/// Fuzzes mutations of the reference programs and of real programs through the parser, the optimizer and the
+/// interpreter, failing with the first program that made any of them panic
+///
+/// Any error they return is fine, as long as it is returned instead of panicking.
+///
+/// # Arguments
+///
+/// * `iterations` - The amount of programs to fuzz
+fn check_fuzz(iterations: usize) -> Result<(), String> {
+    let seeds: Vec<&[u8]> = REFERENCES.iter().chain([&BIG_CELLS])
+        .map(|reference| reference.source)
+        .chain(FUZZ_SEEDS)
+        .map(str::as_bytes)
+        .collect();
+    let mut random = Random(FUZZ_SEED);
+
+    // Caught panics are reported with their program instead of by the default hook
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+
+    let result = (0..iterations).try_for_each(|i| {
+        let seed = seeds[random.below(seeds.len())];
+        let bytes = mutate(&mut random, seed);
+        match std::panic::catch_unwind(|| fuzz(&bytes)) {
+            Ok(result) => result.map_err(|err| format!("program {} {:?}: {}", i, String::from_utf8_lossy(&bytes), err)),
+            Err(panic) => {
+                let message = panic.downcast_ref::<&str>().map(|message| message.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                Err(format!("program {} {:?} panicked: {}", i, String::from_utf8_lossy(&bytes), message))
+            },
+        }
+    });
+
+    std::panic::set_hook(hook);
+    result
+}
+
+/// Returns a program with a few random changes: inserted commands, inserted arbitrary bytes that may not be valid
+/// UTF-8, removed bytes and duplicated slices
+///
+/// # Arguments
+///
+/// * `random` - The generator picking the changes
+/// * `seed` - The program to change
+fn mutate(random: &mut Random, seed: &[u8]) -> Vec<u8> {
+    let mut bytes = seed.to_vec();
+
+    for _ in 0..1 + random.below(8) {
+        let at = random.below(bytes.len() + 1);
+        match random.below(4) {
+            0 => bytes.insert(at, FUZZ_ALPHABET[random.below(FUZZ_ALPHABET.len())]),
+            1 => bytes.insert(at, random.below(256) as u8),
+            2 if at < bytes.len() => {
+                bytes.remove(at);
+            },
+            _ => {
+                let start = random.below(bytes.len() + 1);
+                let slice = bytes[start..(start + random.below(32)).min(bytes.len())].to_vec();
+                bytes.splice(at..at, slice);
+            },
+        }
+    }
+
+    bytes
+}
+
+/// Parses a fuzzed program with and without every extension, then interprets it as parsed, at `-O2` and with cells of
+/// arbitrary precision on a small tape and with small limits, failing only if the optimizer broke its invariants
+///
+/// # Arguments
+///
+/// * `bytes` - The contents of the program's file
+fn fuzz(bytes: &[u8]) -> Result<(), String> {
+    let source = encoding::decode(bytes);
+
+    // The bracket check has to agree with the first unmatched bracket the parser reports
+    let unmatched = match try_parse_recording(&source, Extensions::default(), None, None) {
+        Ok(_) => None,
+        Err(errors) => errors.into_iter().map(|(_, err)| err.to_string()).find(|message| message.starts_with("unmatched")),
+    };
+    let checked = check_brackets(&source).err().map(|err| err.to_string());
+    if checked.as_deref() != unmatched.as_deref().and_then(|message| message.lines().next()) {
+        return Err(format!("checking the brackets found {:?} instead of {:?}", checked, unmatched));
+    }
+
+    let all = Extensions { labels: false, debug_breaks: true, halt: true, dump: true, counts: true };
+    let labels = Extensions { labels: true, counts: true, ..Extensions::default() };
+
+    for extensions in [Extensions::default(), all, labels] {
+        let Ok(program) = parse(&source, extensions) else { continue };
+
+        let mut passes = optimizer::PassManager::for_level(2, Cpu::Sse2);
+        passes.verify = true;
+        let optimized = passes.run(parse(&source, extensions).map_err(|err| err.to_string())?, &mut optimizer::Report::default(), &optimizer::DumpSettings::default())
+            .map_err(|err| format!("optimize: {}", err))?;
+
+        for tape_guard in [TapeGuard::Off, TapeGuard::Aggressive] {
+            let options = interpreter::Options { tape_guard, dump_tape: true, max_steps: Some(10_000), max_output: Some(1_000), max_input: Some(4), max_memory: Some(1 << 16), tape_size: 64, warn_uninit: true, ..interpreter::Options::default() };
+            drive::<u32>(&program, &options);
+            drive::<u32>(&optimized, &options);
+            drive::<Big>(&program, &options);
+        }
+    }
+
+    Ok(())
+}
+
+/// Steps a program until it ends or fails, giving it input until its limit runs out
+///
+/// # Arguments
+///
+/// * `program` - The program
+/// * `options` - The settings of the run
+fn drive<C: interpreter::Cell>(program: &Program, options: &interpreter::Options) {
+    let Ok(mut machine) = interpreter::Machine::<C>::new(program, b"init", options, None) else { return };
+
+    loop {
+        machine.take_warnings();
+        match machine.step() {
+            Ok(interpreter::Step::Halted) | Err(_) => break,
+            Ok(interpreter::Step::NeedsInput) => {
+                let _ = machine.provide_input(b'x').or_else(|_| machine.end_input());
+            },
+            Ok(_) => {},
+        }
+    }
+    let _ = machine.interrupted();
+}
+
+/// Assembles, links and runs a reference program, returning `None` if the toolchain is not available
+///
+/// # Arguments
+///
+/// * `reference` - The program to check
+/// * `crt` - The C runtime to link against, if one is chosen
+/// * `init` - The initial contents of the tape, which is placed in the data segment unless they are empty
+fn check_toolchain(reference: &Reference, crt: Option<Crt>, init: &[u8]) -> Option<Result<(), String>> {
+    if !toolchain_found(crt) {
+        return None;
+    }
+
+    let program = match parse(reference.source, Extensions::default()) {
+        Ok(program) => program,
+        Err(err) => return Some(Err(format!("parse: {}", err))),
+    };
+
+    // The input is piped into the executable from a file next to it
+    let base = temp_dir().join("bfasm_selftest");
+    let input = base.with_extension("in");
+    let options = AsmOptions { syntax: Syntax::Nasm, tape_section: if init.is_empty() { TapeSection::Bss } else { TapeSection::Data }, tape_init: init, crt, ..AsmOptions::default() };
+    let result = build(reference.source, &program, &options, &base)
+        .and_then(|exe| {
+            std::fs::write(&input, reference.input).and_then(|_| std::fs::File::open(&input)).map(|stdin| (exe, stdin)).map_err(|err| format!("input: {}", err))
+        })
+        .and_then(|(exe, stdin)| run_tool(Command::new(&exe).stdin(stdin)))
+        .and_then(|output| compare(reference, &output));
+
+    Some(result)
+}
+
+/// Returns whether `nasm` and the linker of a C runtime, `link` by default, can be run
+///
+/// # Arguments
+///
+/// * `crt` - The C runtime to link against, if one is chosen
+pub fn toolchain_found(crt: Option<Crt>) -> bool {
+    let found = |tool: &str, arg: &str| Command::new(tool).arg(arg).output().is_ok();
+    found("nasm", "-v") && crt.map_or_else(|| found("link", "/?"), Crt::found)
+}
+
+/// Writes, assembles and links a program with NASM and the linker of its C runtime, returning the path of the executable
+///
+/// # Arguments
+///
+/// * `source` - The program's source code
+/// * `program` - The parsed program
+/// * `options` - The settings of the generated assembly, which has to be NASM syntax
+/// * `base` - The path of the intermediate files and the executable without their extensions
+pub fn build(source: &str, program: &Program, options: &AsmOptions, base: &Path) -> Result<PathBuf, String> {
+    let asm = base.with_extension("asm");
+    let obj = base.with_extension("obj");
+    let exe = base.with_extension("exe");
+
+    write_asm(&asm.to_string_lossy(), source, &program.instructions, options).map_err(|err| format!("write: {}", err))?;
+
+    build_executable(&asm.to_string_lossy(), &obj.to_string_lossy(), &exe.to_string_lossy(), Syntax::Nasm, options.crt, options.heap_tape())?;
+
+    Ok(exe)
+}
+
+/// Compares the output of a reference program against the expected one
+///
+/// # Arguments
+///
+/// * `reference` - The program that was run
+/// * `output` - The bytes it wrote
+fn compare(reference: &Reference, output: &[u8]) -> Result<(), String> {
+    if output == reference.expected {
+        Ok(())
+    } else {
+        Err(format!("expected {:?}, got {:?}", String::from_utf8_lossy(reference.expected), String::from_utf8_lossy(output)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_without_a_toolchain_pass() {
+        let mut failures: Vec<String> = Vec::new();
+        run_checks(FUZZ_ITERATIONS, &mut |name, result| {
+            if let Err(reason) = result {
+                failures.push(format!("{}: {}", name, reason));
+            }
+        });
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
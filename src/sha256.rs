@@ -0,0 +1,128 @@
+/// The round constants of SHA-256
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// The initial hash values of SHA-256
+const H: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// Computes the SHA-256 hash of the given bytes as a lowercase hex string
+///
+/// # Arguments
+///
+/// * `data` - The bytes to hash
+pub fn sha256(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// A SHA-256 hash that is computed from the bytes as they are read, e.g. of a file too large to hold in memory
+pub struct Sha256 {
+    /// The hash of the complete chunks so far
+    h: [u32; 8],
+    /// The bytes of the incomplete chunk, less than 64
+    pending: Vec<u8>,
+    /// The amount of bytes hashed so far
+    length: u64,
+}
+
+impl Sha256 {
+    /// The hash of no bytes yet
+    pub fn new() -> Sha256 {
+        Sha256 { h: H, pending: Vec::with_capacity(64), length: 0 }
+    }
+
+    /// Hashes the bytes following those hashed so far
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The bytes to hash
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+
+        // Complete the pending chunk first
+        if !self.pending.is_empty() {
+            let missing = (64 - self.pending.len()).min(data.len());
+            self.pending.extend_from_slice(&data[..missing]);
+            data = &data[missing..];
+            if self.pending.len() < 64 {
+                return;
+            }
+            compress(&mut self.h, &std::mem::take(&mut self.pending));
+        }
+
+        let mut chunks = data.chunks_exact(64);
+        for chunk in &mut chunks {
+            compress(&mut self.h, chunk);
+        }
+        self.pending.extend_from_slice(chunks.remainder());
+    }
+
+    /// Returns the hash as a lowercase hex string
+    pub fn finish(mut self) -> String {
+        // Pad with a single one bit, zeros and the message length in bits to a multiple of 64 bytes
+        let mut message = std::mem::take(&mut self.pending);
+        message.push(0x80);
+        while message.len() % 64 != 56 {
+            message.push(0);
+        }
+        message.extend_from_slice(&(self.length * 8).to_be_bytes());
+
+        for chunk in message.chunks_exact(64) {
+            compress(&mut self.h, chunk);
+        }
+
+        self.h.iter().map(|word| format!("{:08x}", word)).collect()
+    }
+}
+
+/// Hashes a chunk of 64 bytes into the hash so far
+///
+/// # Arguments
+///
+/// * `h` - The hash so far
+/// * `chunk` - The 64 bytes to hash
+fn compress(h: &mut [u32; 8], chunk: &[u8]) {
+    let mut w = [0u32; 64];
+    for (i, word) in chunk.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = *h;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = hh.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+
+        hh = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+
+    for (state, value) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+        *state = state.wrapping_add(value);
+    }
+}
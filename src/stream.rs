@@ -0,0 +1,270 @@
+use std::fs::{copy, remove_file, rename, File};
+use std::io::{BufRead, BufReader, BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+
+use crate::emitter::IntelEmitter;
+use crate::sha256::Sha256;
+use crate::{check_library, check_tape_size, encoding, lowering, write_exit, write_lowered, write_prelude, write_runtime_include, AsmOptions, Extensions, Instruction, Symbols, MAX_INSTRUCTIONS};
+
+/// The amount of bytes read from the source at once
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// What the first pass over a source file found out about it
+struct Scan {
+    /// The SHA-256 hash of the source in hex
+    hash: String,
+    /// Whether the program contains `@` halts
+    halts: bool,
+    /// Whether the program contains `$` dumps
+    dumps: bool,
+}
+
+/// Compiles a source file to assembly at `-O0` while reading it, without holding its source, its instructions or
+/// the assembly in memory
+///
+/// The file is read twice: the first time to hash it and check that it parses, the second time to lower each
+/// instruction as soon as it is read. Loops are named by the number of their `[` among those before it, which a stack
+/// of the open loops' numbers remembers until their `]`, so the assembly is the same as the one generated from the
+/// parsed program and needs no back-patching. Memory only grows with the nesting depth of the loops.
+///
+/// Returns `false` without writing anything if the source has parse errors or is not plain UTF-8, which the parser
+/// has to report or decode instead.
+///
+/// # Arguments
+///
+/// * `filename` - The name of the source file
+/// * `out_name` - The name of the file to create and write the assembly to
+/// * `extensions` - The enabled language extensions, neither labels nor repetition counts
+/// * `max_depth` - The amount of loops that may be nested in each other, unlimited if `None`
+/// * `options` - The settings of the generated assembly, which needs no fragment, template, coverage, offsets or
+///   lifted loops
+pub fn write_asm(filename: &str, out_name: &str, extensions: Extensions, max_depth: Option<usize>, options: &AsmOptions) -> Result<bool> {
+    let Some(scan) = scan(filename, extensions, max_depth)? else {
+        return Ok(false);
+    };
+    check_library(options, scan.dumps)?;
+    check_tape_size(options)?;
+
+    // The assembly is written next to the output file first, so a failed write never leaves a partial file behind
+    let mut temp_name = Path::new(out_name).as_os_str().to_owned();
+    temp_name.push(".tmp");
+    let temp = PathBuf::from(temp_name);
+
+    let written = File::create(&temp).and_then(|file| {
+        let mut f = BufWriter::new(file);
+        emit(&mut f, filename, &scan, extensions, options)?;
+        f.into_inner().map_err(|err| err.into_error())?.sync_all()
+    });
+    if let Err(err) = written {
+        let _ = remove_file(&temp);
+        return Err(err);
+    }
+
+    if rename(&temp, out_name).is_err() {
+        let copied = copy(&temp, out_name);
+        let _ = remove_file(&temp);
+        copied?;
+    }
+
+    Ok(true)
+}
+
+/// Reads a source file in chunks, skipping its UTF-8 byte order mark like the parser does
+///
+/// # Arguments
+///
+/// * `filename` - The name of the source file
+/// * `visit` - The function called with each chunk and whether it belongs to a shebang line, which the parser skips
+fn read_chunks<F: FnMut(&[u8], bool)>(filename: &str, mut visit: F) -> Result<()> {
+    let mut reader = BufReader::with_capacity(CHUNK_SIZE, File::open(filename)?);
+
+    if reader.fill_buf()?.starts_with(encoding::UTF8_BOM) {
+        reader.consume(encoding::UTF8_BOM.len());
+    }
+
+    // The shebang line ends at the first line break, which may be in any later chunk
+    if reader.fill_buf()?.starts_with(b"#!") {
+        loop {
+            let chunk = reader.fill_buf()?;
+            if chunk.is_empty() {
+                break;
+            }
+            let end = chunk.iter().position(|byte| *byte == b'\n');
+            let len = end.map_or(chunk.len(), |end| end + 1);
+            visit(&chunk[..len], true);
+            reader.consume(len);
+            if end.is_some() {
+                break;
+            }
+        }
+    }
+
+    loop {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        let len = chunk.len();
+        visit(chunk, false);
+        reader.consume(len);
+    }
+}
+
+/// Hashes a source file and checks that it parses, returning `None` if it does not or is not plain UTF-8
+///
+/// Only UTF-8 without NUL bytes is compiled while reading, as other files may have to be decoded differently. The
+/// commands are all ascii, which never occurs inside the encoding of another character, so they are found byte by
+/// byte.
+///
+/// # Arguments
+///
+/// * `filename` - The name of the source file
+/// * `extensions` - The enabled language extensions
+/// * `max_depth` - The amount of loops that may be nested in each other, unlimited if `None`
+fn scan(filename: &str, extensions: Extensions, max_depth: Option<usize>) -> Result<Option<Scan>> {
+    let mut hasher = Sha256::new();
+    let mut parses = true;
+    let (mut halts, mut dumps) = (false, false);
+    let (mut instructions, mut depth) = (0, 0);
+
+    // The bytes of a character that is split between two chunks
+    let mut partial: Vec<u8> = Vec::new();
+
+    read_chunks(filename, |chunk, shebang| {
+        hasher.update(chunk);
+        if !parses {
+            return;
+        }
+
+        partial.extend_from_slice(chunk);
+        match std::str::from_utf8(&partial) {
+            Ok(_) => partial.clear(),
+            // An incomplete character at the end is completed by the next chunk
+            Err(err) if err.error_len().is_none() => {
+                partial.drain(..err.valid_up_to());
+            },
+            Err(_) => parses = false,
+        }
+        if chunk.contains(&0) {
+            parses = false;
+        }
+        if shebang {
+            return;
+        }
+
+        for byte in chunk {
+            match instruction(*byte, extensions) {
+                Some(Instruction::Jump(_)) if max_depth == Some(depth) => parses = false,
+                Some(Instruction::Jump(_)) => depth += 1,
+                Some(Instruction::Return(_)) if depth == 0 => parses = false,
+                Some(Instruction::Return(_)) => depth -= 1,
+                Some(Instruction::Halt) => halts = true,
+                Some(Instruction::Dump) => dumps = true,
+                Some(_) => {},
+                None => continue,
+            }
+            instructions += 1;
+        }
+    })?;
+
+    if !parses || !partial.is_empty() || depth > 0 || instructions >= MAX_INSTRUCTIONS {
+        return Ok(None);
+    }
+
+    Ok(Some(Scan { hash: hasher.finish(), halts, dumps }))
+}
+
+/// Writes the assembly of a source file that was scanned already, lowering each instruction as it is read
+///
+/// # Arguments
+///
+/// * `f` - The writer to write to
+/// * `filename` - The name of the source file
+/// * `scan` - What the first pass found out about the source
+/// * `extensions` - The enabled language extensions
+/// * `options` - The settings of the generated assembly
+fn emit<W: Write>(f: &mut W, filename: &str, scan: &Scan, extensions: Extensions, options: &AsmOptions) -> Result<()> {
+    let symbols = Symbols::new(options);
+    let emitter = IntelEmitter { syntax: options.syntax, symbols: &symbols };
+
+    let used: Vec<Instruction> = [(scan.halts, Instruction::Halt), (scan.dumps, Instruction::Dump)].into_iter()
+        .filter_map(|(used, instr)| used.then_some(instr))
+        .collect();
+    let helpers = lowering::helpers(&used, options);
+    write_prelude(f, &scan.hash, options, scan.dumps, &symbols)?;
+
+    // The numbers of the open loops, innermost last, and those of the next loop and read, like `label_ids` counts them
+    let mut open: Vec<usize> = Vec::new();
+    let (mut loops, mut reads) = (0, 0);
+
+    // The offset of the data pointer from the index register while its moves are not stored yet
+    let mut cached: Option<i32> = None;
+    // The previous instruction, which may leave the current cell in `eax`
+    let mut previous: Option<Instruction> = None;
+
+    let mut pc = 0;
+    let mut result = Ok(());
+    read_chunks(filename, |chunk, shebang| {
+        if shebang || result.is_err() {
+            return;
+        }
+
+        for byte in chunk {
+            let Some(instr) = instruction(*byte, extensions) else { continue };
+            let id = match instr {
+                Instruction::Jump(_) => {
+                    open.push(loops);
+                    loops += 1;
+                    loops - 1
+                },
+                Instruction::Return(_) => open.pop().unwrap_or_default(),
+                Instruction::Read => {
+                    reads += 1;
+                    reads - 1
+                },
+                _ => 0,
+            };
+
+            let loaded = previous.as_ref().is_some_and(|previous| lowering::keeps_cell(previous, &instr, options));
+            let ops = lowering::lower(pc, id, &instr, false, loaded, &mut cached, options);
+            previous = Some(instr);
+            result = write_lowered(f, ops, &emitter);
+            if result.is_err() {
+                return;
+            }
+            pc += 1;
+        }
+    })?;
+    result?;
+
+    if let Some(store) = lowering::flush(&mut cached, options) {
+        write_lowered(f, vec![store], &emitter)?;
+    }
+    write_exit(f, pc, true, &helpers, options, &symbols)?;
+    write_runtime_include(f, options)
+}
+
+/// Returns the instruction a byte of the source is parsed as at `-O0`, if it is a command
+///
+/// Loops are returned without their partners, which are not known while reading.
+///
+/// # Arguments
+///
+/// * `byte` - The byte
+/// * `extensions` - The enabled language extensions
+fn instruction(byte: u8, extensions: Extensions) -> Option<Instruction> {
+    match byte {
+        b'>' => Some(Instruction::Increment),
+        b'<' => Some(Instruction::Decrement),
+        b'+' => Some(Instruction::Add),
+        b'-' => Some(Instruction::Subtract),
+        b'.' => Some(Instruction::Write),
+        b',' => Some(Instruction::Read),
+        b'[' => Some(Instruction::Jump(0)),
+        b']' => Some(Instruction::Return(0)),
+        b'#' if extensions.debug_breaks => Some(Instruction::Break),
+        b'@' if extensions.halt => Some(Instruction::Halt),
+        b'$' if extensions.dump => Some(Instruction::Dump),
+        _ => None,
+    }
+}
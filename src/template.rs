@@ -0,0 +1,77 @@
+use std::io::{Error, Result};
+use std::ops::Range;
+
+/// The placeholders that `--template` fills in
+const PLACEHOLDERS: [&str; 5] = ["ENTRY", "TAPE_SIZE", "EXTERNS", "BODY", "HELPERS"];
+
+/// The placeholders every template has to contain, as the generated program cannot run without them
+const REQUIRED: [&str; 2] = ["BODY", "HELPERS"];
+
+/// Returns the byte range and the name of each placeholder in a template, in order
+///
+/// Placeholders are names of uppercase letters and `_` in double braces like `{{BODY}}`, other text in double braces
+/// is copied as is. Fails if a placeholder is unknown, a required one is missing or one of them that holds code
+/// appears more than once, as its labels would be defined twice.
+///
+/// # Arguments
+///
+/// * `template` - The contents of the template file
+pub fn placeholders(template: &str) -> Result<Vec<(Range<usize>, &str)>> {
+    let mut found: Vec<(Range<usize>, &str)> = Vec::new();
+
+    let mut offset = 0;
+    while let Some(start) = template[offset..].find("{{").map(|start| offset + start) {
+        let Some(end) = template[start + 2..].find("}}").map(|end| start + 2 + end) else {
+            break;
+        };
+        let name = &template[start + 2..end];
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_uppercase() || c == '_') {
+            offset = start + 2;
+            continue;
+        }
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(Error::other(format!("unknown placeholder '{{{{{}}}}}' in the template, expected one of {}!", name, PLACEHOLDERS.join(", "))));
+        }
+
+        found.push((start..end + 2, name));
+        offset = end + 2;
+    }
+
+    for name in REQUIRED {
+        match found.iter().filter(|(_, found)| *found == name).count() {
+            0 => return Err(Error::other(format!("the template is missing the placeholder '{{{{{}}}}}'!", name))),
+            1 => {},
+            _ => return Err(Error::other(format!("the placeholder '{{{{{}}}}}' appears more than once in the template!", name))),
+        }
+    }
+
+    Ok(found)
+}
+
+/// Fills in the placeholders of a template
+///
+/// Returns the filled template and the amount of lines in front of the line `{{BODY}}` was in.
+///
+/// # Arguments
+///
+/// * `template` - The contents of the template file, which `placeholders` accepted
+/// * `values` - The name and the text of each placeholder
+pub fn fill(template: &str, values: &[(&str, &str)]) -> Result<(String, usize)> {
+    let mut filled = String::new();
+    let mut body_line = 0;
+
+    let mut copied = 0;
+    for (range, name) in placeholders(template)? {
+        filled.push_str(&template[copied..range.start]);
+        if name == "BODY" {
+            body_line = filled.matches('\n').count();
+        }
+        let value = values.iter().find(|(value_name, _)| *value_name == name).map_or("", |(_, value)| value);
+        filled.push_str(value);
+        copied = range.end;
+    }
+    filled.push_str(&template[copied..]);
+
+    Ok((filled, body_line))
+}
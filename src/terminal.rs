@@ -0,0 +1,440 @@
+use std::io::{stdin, IsTerminal, Read, Result};
+
+/// How `,` treats stdin when interpreting
+#[derive(Clone, Copy, PartialEq)]
+pub enum StdinMode {
+    /// Like a terminal if stdin is one and like a pipe otherwise
+    Auto,
+    /// Lines typed at a terminal, whose line endings are read as `\n` and which `--raw-input` reads a key at a time
+    Terminal,
+    /// The bytes of a pipe or file as they are
+    Pipe,
+}
+
+impl StdinMode {
+    /// The names of the modes as accepted by `--stdin-mode`
+    pub const NAMES: [&'static str; 3] = ["auto", "terminal", "pipe"];
+
+    /// The mode with the given name
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the mode
+    pub fn from_name(name: &str) -> Option<StdinMode> {
+        match name {
+            "auto" => Some(StdinMode::Auto),
+            "terminal" => Some(StdinMode::Terminal),
+            "pipe" => Some(StdinMode::Pipe),
+            _ => None,
+        }
+    }
+
+    /// Returns whether stdin is treated like a terminal
+    pub fn is_terminal(self) -> bool {
+        match self {
+            StdinMode::Auto => stdin().is_terminal(),
+            StdinMode::Terminal => true,
+            StdinMode::Pipe => false,
+        }
+    }
+}
+
+/// Reads the input typed at a terminal with every line ending as `\n`
+///
+/// The Windows console ends lines with `\r\n` and raw mode may report Enter as `\r`, while brainfuck programs
+/// compare against 10. Both are replaced, so programs reading a line see the same byte on every platform.
+pub struct LineEndings<R: Read> {
+    /// The reader of the typed input
+    inner: R,
+    /// Whether the last byte read was a `\r`, whose `\n` is dropped
+    after_cr: bool,
+}
+
+impl<R: Read> LineEndings<R> {
+    /// Replaces the line endings of a reader
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The reader of the typed input
+    pub fn new(inner: R) -> LineEndings<R> {
+        LineEndings { inner, after_cr: false }
+    }
+}
+
+impl<R: Read> Read for LineEndings<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let read = self.inner.read(buf)?;
+
+            let mut kept = 0;
+            for i in 0..read {
+                let byte = buf[i];
+                if byte == b'\n' && self.after_cr {
+                    self.after_cr = false;
+                    continue;
+                }
+                self.after_cr = byte == b'\r';
+                buf[kept] = if byte == b'\r' { b'\n' } else { byte };
+                kept += 1;
+            }
+
+            // A read of nothing but the `\n` of a `\r\n` is no end of the input
+            if kept > 0 || read == 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+/// Reads stdin a key press at a time instead of a line at a time while it exists
+///
+/// The previous mode of the terminal is restored when it is dropped, which also happens when a panic unwinds, and by
+/// a handler when the process is interrupted with Ctrl-C. Handlers installed before, like the interpreter's, are kept
+/// and have to call `restore_original` themselves.
+pub struct RawInput {
+    /// Whether typed characters are shown
+    #[cfg_attr(not(windows), allow(dead_code))]
+    echo: bool,
+    /// The state of the terminal before switching to raw mode
+    saved: platform::Saved,
+}
+
+impl RawInput {
+    /// Switches the terminal of stdin to raw mode
+    ///
+    /// Returns `None` when stdin is not a terminal, e.g. because it is a pipe, or the terminal could not be switched,
+    /// so reads are simply buffered as usual.
+    ///
+    /// # Arguments
+    ///
+    /// * `echo` - Whether typed characters are shown
+    pub fn enable(echo: bool) -> Option<RawInput> {
+        if !stdin().is_terminal() {
+            return None;
+        }
+
+        platform::enable(echo).map(|saved| RawInput { echo, saved })
+    }
+}
+
+impl Read for RawInput {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = stdin().read(buf)?;
+
+        // The console only echoes whole lines, so the characters are echoed by hand
+        #[cfg(windows)]
+        if self.echo {
+            use std::io::{stdout, Write};
+
+            // Enter only returns the cursor to the start of the line
+            let mut out = stdout();
+            for byte in &buf[..read] {
+                out.write_all(if *byte == b'\r' { &b"\r\n"[..] } else { std::slice::from_ref(byte) })?;
+            }
+            out.flush()?;
+        }
+
+        Ok(read)
+    }
+}
+
+impl Drop for RawInput {
+    fn drop(&mut self) {
+        platform::restore(&self.saved);
+    }
+}
+
+/// Restores the mode the terminal had before raw mode was enabled for the first time, if it ever was
+///
+/// This is safe to call from signal handlers.
+pub fn restore_original() {
+    platform::restore_original();
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod platform {
+    use std::sync::OnceLock;
+
+    #[cfg(target_os = "linux")]
+    mod termios {
+        /// The settings of a terminal, laid out like `struct termios` of glibc and musl
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        pub struct Termios {
+            pub iflag: u32,
+            pub oflag: u32,
+            pub cflag: u32,
+            pub lflag: u32,
+            pub line: u8,
+            pub cc: [u8; 32],
+            pub ispeed: u32,
+            pub ospeed: u32,
+        }
+
+        pub const ICANON: u32 = 0o2;
+        pub const ECHO: u32 = 0o10;
+        pub const VTIME: usize = 5;
+        pub const VMIN: usize = 6;
+    }
+
+    #[cfg(target_os = "macos")]
+    mod termios {
+        /// The settings of a terminal, laid out like `struct termios` of macOS
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        pub struct Termios {
+            pub iflag: u64,
+            pub oflag: u64,
+            pub cflag: u64,
+            pub lflag: u64,
+            pub cc: [u8; 20],
+            pub ispeed: u64,
+            pub ospeed: u64,
+        }
+
+        pub const ICANON: u64 = 0x100;
+        pub const ECHO: u64 = 0x8;
+        pub const VMIN: usize = 16;
+        pub const VTIME: usize = 17;
+    }
+
+    use termios::*;
+
+    extern "C" {
+        fn tcgetattr(fd: i32, termios: *mut Termios) -> i32;
+        fn tcsetattr(fd: i32, action: i32, termios: *const Termios) -> i32;
+        fn signal(signum: i32, handler: usize) -> usize;
+        fn raise(signum: i32) -> i32;
+    }
+
+    const STDIN_FILENO: i32 = 0;
+    const TCSANOW: i32 = 0;
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+    const SIG_DFL: usize = 0;
+
+    /// The settings to restore when the process is interrupted, set once raw mode was enabled for the first time
+    static ORIGINAL: OnceLock<Termios> = OnceLock::new();
+
+    /// The state of the terminal and the signal handlers before switching to raw mode
+    pub struct Saved {
+        /// The settings of the terminal
+        termios: Termios,
+        /// The previous handlers of `SIGINT` and `SIGTERM`
+        handlers: [usize; 2],
+    }
+
+    /// Restores the settings the terminal had before raw mode was enabled for the first time
+    pub fn restore_original() {
+        if let Some(original) = ORIGINAL.get() {
+            // SAFETY: `tcsetattr` is async-signal-safe and the settings were fully initialized
+            unsafe {
+                tcsetattr(STDIN_FILENO, TCSANOW, original);
+            }
+        }
+    }
+
+    /// Restores the terminal and terminates the process like the default handler of the signal would
+    ///
+    /// # Arguments
+    ///
+    /// * `signum` - The number of the received signal
+    extern "C" fn restore_and_raise(signum: i32) {
+        restore_original();
+
+        // SAFETY: `signal` and `raise` are async-signal-safe
+        unsafe {
+            signal(signum, SIG_DFL);
+            raise(signum);
+        }
+    }
+
+    /// Installs a handler that restores the terminal for a signal unless it already has a handler
+    ///
+    /// Returns the previous handler.
+    ///
+    /// # Arguments
+    ///
+    /// * `signum` - The number of the signal
+    ///
+    /// # Safety
+    ///
+    /// The previous handler has to be reinstalled before the settings of the terminal are forgotten.
+    unsafe fn take_over(signum: i32) -> usize {
+        let previous = signal(signum, restore_and_raise as *const () as usize);
+        if previous != SIG_DFL {
+            signal(signum, previous);
+        }
+        previous
+    }
+
+    /// Switches the terminal of stdin to non-canonical mode, returning its previous state on success
+    ///
+    /// # Arguments
+    ///
+    /// * `echo` - Whether typed characters are shown
+    pub fn enable(echo: bool) -> Option<Saved> {
+        // SAFETY: `tcgetattr` fully initializes the settings when it succeeds, which is checked before they are used
+        let termios = unsafe {
+            let mut termios = std::mem::MaybeUninit::<Termios>::uninit();
+            if tcgetattr(STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+                return None;
+            }
+            termios.assume_init()
+        };
+        ORIGINAL.get_or_init(|| termios);
+
+        // Return every key press immediately, but keep Ctrl-C generating a signal
+        let mut raw = termios;
+        raw.lflag &= !ICANON;
+        if !echo {
+            raw.lflag &= !ECHO;
+        }
+        raw.cc[VMIN] = 1;
+        raw.cc[VTIME] = 0;
+
+        // SAFETY: The handlers only call async-signal-safe functions and are reinstalled when switching fails
+        unsafe {
+            let handlers = [take_over(SIGINT), take_over(SIGTERM)];
+            if tcsetattr(STDIN_FILENO, TCSANOW, &raw) != 0 {
+                signal(SIGINT, handlers[0]);
+                signal(SIGTERM, handlers[1]);
+                return None;
+            }
+
+            Some(Saved { termios, handlers })
+        }
+    }
+
+    /// Restores the state of the terminal and the signal handlers
+    ///
+    /// # Arguments
+    ///
+    /// * `saved` - The state before switching to raw mode
+    pub fn restore(saved: &Saved) {
+        // SAFETY: The settings and handlers were returned by `tcgetattr` and `signal`
+        unsafe {
+            tcsetattr(STDIN_FILENO, TCSANOW, &saved.termios);
+            signal(SIGINT, saved.handlers[0]);
+            signal(SIGTERM, saved.handlers[1]);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::sync::atomic::{AtomicIsize, AtomicU32, Ordering};
+
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> isize;
+        fn GetConsoleMode(console: isize, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: isize, mode: u32) -> i32;
+        fn SetConsoleCtrlHandler(handler: Option<extern "system" fn(u32) -> i32>, add: i32) -> i32;
+    }
+
+    const STD_INPUT_HANDLE: u32 = -10i32 as u32;
+    const ENABLE_LINE_INPUT: u32 = 0x2;
+    const ENABLE_ECHO_INPUT: u32 = 0x4;
+
+    /// The console and mode to restore when the process is interrupted, set once raw mode was enabled for the first time
+    static CONSOLE: AtomicIsize = AtomicIsize::new(0);
+    static MODE: AtomicU32 = AtomicU32::new(0);
+
+    /// The mode of the console before switching to raw mode
+    pub struct Saved {
+        /// The handle of stdin's console
+        console: isize,
+        /// The previous mode of the console
+        mode: u32,
+    }
+
+    /// Restores the console and lets the next handler, usually the one terminating the process, run
+    ///
+    /// # Arguments
+    ///
+    /// * `_event` - The kind of the received event
+    extern "system" fn restore_and_continue(_event: u32) -> i32 {
+        restore_original();
+        0
+    }
+
+    /// Restores the mode the console had before raw mode was enabled for the first time
+    pub fn restore_original() {
+        let console = CONSOLE.load(Ordering::SeqCst);
+        if console != 0 {
+            // SAFETY: The handle and mode were returned by `GetStdHandle` and `GetConsoleMode`
+            unsafe {
+                SetConsoleMode(console, MODE.load(Ordering::SeqCst));
+            }
+        }
+    }
+
+    /// Disables the line input of the console of stdin, returning its previous mode on success
+    ///
+    /// # Arguments
+    ///
+    /// * `_echo` - Whether typed characters are shown, which the console only supports with line input
+    pub fn enable(_echo: bool) -> Option<Saved> {
+        // SAFETY: The mode is only used when `GetConsoleMode` succeeded
+        unsafe {
+            let console = GetStdHandle(STD_INPUT_HANDLE);
+            let mut mode = 0;
+            if GetConsoleMode(console, &mut mode) == 0 {
+                return None;
+            }
+
+            if CONSOLE.load(Ordering::SeqCst) == 0 {
+                MODE.store(mode, Ordering::SeqCst);
+                CONSOLE.store(console, Ordering::SeqCst);
+            }
+            SetConsoleCtrlHandler(Some(restore_and_continue), 1);
+
+            if SetConsoleMode(console, mode & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT)) == 0 {
+                SetConsoleCtrlHandler(Some(restore_and_continue), 0);
+                return None;
+            }
+
+            Some(Saved { console, mode })
+        }
+    }
+
+    /// Restores the mode of the console and removes the handler
+    ///
+    /// # Arguments
+    ///
+    /// * `saved` - The mode before switching to raw mode
+    pub fn restore(saved: &Saved) {
+        // SAFETY: The handle and mode were returned by `GetStdHandle` and `GetConsoleMode`
+        unsafe {
+            SetConsoleMode(saved.console, saved.mode);
+            SetConsoleCtrlHandler(Some(restore_and_continue), 0);
+        }
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+mod platform {
+    /// Raw mode is not supported on this platform, so there never is a saved state
+    pub enum Saved {}
+
+    /// Reads stay buffered on this platform
+    ///
+    /// # Arguments
+    ///
+    /// * `_echo` - Whether typed characters are shown
+    pub fn enable(_echo: bool) -> Option<Saved> {
+        None
+    }
+
+    /// Nothing has to be restored as raw mode is never enabled
+    pub fn restore_original() {}
+
+    /// Never called as no state is ever saved
+    ///
+    /// # Arguments
+    ///
+    /// * `saved` - The state before switching to raw mode
+    pub fn restore(saved: &Saved) {
+        match *saved {}
+    }
+}
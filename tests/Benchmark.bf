@@ -0,0 +1,8 @@
+Benchmark: three nested counting loops of two hundred iterations each
+whose innermost body keeps the interpreter dispatching about fifty million operations
+Prints OK followed by a newline when done
+
+>[-]++++++++++[<++++++++++++++++++++>-]<[->>[-]++++++++++[<+
++++++++++++++++++++>-]<[->>[-]++++++++++[<++++++++++++++++++
+++>-]<[->[-]+<]<]<]++++++++[>++++++++++<-]>-.----.[-]+++++++
++++.
@@ -0,0 +1,25 @@
+Prints the factorials of 1 to 25 with one cell each
+Needs cells of arbitrary precision and numeric output as in bfasm run with cell size big and numeric io
+The last ones exceed 64 bits
+
+cells: #0 remaining count  #1 k  #2 factorial  #3 product  #4 and #5 scratch
++++++++++++++++++++++++++ 25 factorials
+>>+ start at 1
+<<
+[
+  -
+  >+ increase k
+  copy k to #4 using #5
+  [->>>+>+<<<<]>>>>[-<<<<+>>>>]
+  <
+  add the factorial to the product k times keeping it using #5
+  [
+    -
+    <<[->+>>+<<<]>>>[-<<<+>>>]
+    <
+  ]
+  move the product to the factorial
+  <<[-]>[-<+>]
+  <. print it
+  <<
+]
@@ -0,0 +1,16 @@
+Uppercases the lowercase ascii letters of the argument copied to the tape starting at cell 2
+Cells 0 and 1 have to stay free as each character uses the two cells before it as scratch space
+
+>>[
+  copy the character to the two cells before it and move it back from the farther one
+  [-<+<+>>]<<[->>+<<]>>
+  the copy counts down to tell whether the character is between 97 and 122 and the farther cell becomes a flag
+  <[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-
+  <+>[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-[-<->[-]]]]]]]]]]]]]]]]]]]]]]]]]]]
+  ]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]
+  subtract 32 from flagged characters then print and clear the character
+  <[->>--------------------------------<<]
+  >>.[-]>
+]
+print a newline
+++++++++++.